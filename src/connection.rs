@@ -0,0 +1,998 @@
+//! The public API: a [`Connection`] to a database file prepares
+//! [`Statement`]s, whose result [`Rows`] are read one [`Row`] at a time.
+
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use serde::de::DeserializeOwned;
+
+use crate::db::cache;
+use crate::db::db::Db;
+use crate::db::journal;
+use crate::de::RowDeserializer;
+use crate::error::{Error, Result};
+use crate::parser::parse_create_view;
+use crate::query::{Query, ResultRows};
+use crate::sql::schema::SchemaEntry;
+use crate::sql::value::Value;
+use crate::stats::{self, Stats};
+use crate::write;
+
+/// An open database file.
+pub struct Connection {
+    db: Db,
+    path: PathBuf,
+    read_only: bool,
+    interrupted: Arc<AtomicBool>,
+}
+
+impl Connection {
+    /// Opens the database at `path`, first rolling back a change that
+    /// didn't complete.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Connection> {
+        journal::recover(path.as_ref())?;
+        Ok(Connection {
+            db: Db::new(path.as_ref().to_string_lossy().into_owned())?,
+            path: path.as_ref().to_path_buf(),
+            read_only: false,
+            interrupted: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// Opens the database at `path` for reading only: creating tables and
+    /// inserting rows fail.
+    pub fn open_read_only<P: AsRef<Path>>(path: P) -> Result<Connection> {
+        Ok(Connection {
+            read_only: true,
+            ..Connection::open(path)?
+        })
+    }
+
+    /// Parses `sql` and binds it to the schema of the table it reads.
+    pub fn prepare(&self, sql: &str) -> Result<Statement<'_>> {
+        Ok(Statement {
+            connection: self,
+            query: Query::prepare(&self.db, sql)?,
+        })
+    }
+
+    /// Creates a table from `sql`, a `CREATE TABLE` statement. Tables that
+    /// would need an index, such as for a `UNIQUE` column, aren't
+    /// supported.
+    pub fn create_table(&mut self, sql: &str) -> Result<()> {
+        self.check_writable()?;
+        write::create_table(&self.path, sql)?;
+        self.reload()
+    }
+
+    /// Inserts `rows` into table `name`, each with a value for every column
+    /// in order, returning how many were inserted. The rows are written
+    /// together: if one breaks a constraint, none are.
+    pub fn insert<I>(&mut self, name: &str, rows: I) -> Result<usize>
+    where
+        I: IntoIterator<Item = Vec<Value>>,
+    {
+        self.insert_with(name, rows, |_, e| Err(e))
+    }
+
+    /// Like [`insert`](Self::insert), but a row that breaks a constraint is
+    /// passed to `failed` with its position, and left out if that returns
+    /// Ok rather than failing them all.
+    pub fn insert_with<I, F>(&mut self, name: &str, rows: I, failed: F) -> Result<usize>
+    where
+        I: IntoIterator<Item = Vec<Value>>,
+        F: FnMut(usize, Error) -> Result<()>,
+    {
+        self.check_writable()?;
+        let count = write::insert(&self.path, name, rows, failed)?;
+        self.reload()?;
+        Ok(count)
+    }
+
+    fn check_writable(&self) -> Result<()> {
+        match self.read_only {
+            true => Err(Error::Sql("attempt to write a readonly database".into())),
+            false => Ok(()),
+        }
+    }
+
+    /// Reads the schema again after a change.
+    fn reload(&mut self) -> Result<()> {
+        self.db = Db::new(self.path.to_string_lossy().into_owned())?;
+        Ok(())
+    }
+
+    /// What the statement run last on this thread read: pages, rows and
+    /// bytes, counted as its rows are read.
+    pub fn stats(&self) -> Stats {
+        stats::current()
+    }
+
+    /// A handle that stops the statement running on this connection from
+    /// another thread or a signal handler.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        InterruptHandle(self.interrupted.clone())
+    }
+
+    pub fn page_size(&self) -> usize {
+        self.db.get_page_size()
+    }
+
+    /// Number of entries in the schema table: tables, indexes, views and
+    /// triggers.
+    pub fn table_count(&self) -> usize {
+        self.db.get_table_count_schema_page()
+    }
+
+    /// The tables, indexes, views and triggers of the database, in the
+    /// order they are stored in the schema table.
+    pub fn schema(&self) -> Vec<SchemaEntry> {
+        self.db.get_schema_page().borrow().schema().to_vec()
+    }
+
+    /// Column names of table or view `name`. Those of a view without a
+    /// column list are the result columns of its SELECT.
+    pub fn table_columns(&self, name: &str) -> Result<Vec<String>> {
+        let view = self
+            .schema()
+            .into_iter()
+            .find(|entry| entry.kind() == "view" && entry.name() == name);
+        let Some(sql) = view.as_ref().and_then(|view| view.sql()) else {
+            let schema = self.db.get_schema_page().borrow().table_schema(name)?;
+            return Ok(schema.column_names());
+        };
+        Ok(match parse_create_view(sql)? {
+            (Some(columns), _) => columns,
+            (None, select) => {
+                let query = Query::prepare(&self.db, select)?;
+                query
+                    .columns()
+                    .iter()
+                    .map(|c| c.name().to_string())
+                    .collect()
+            }
+        })
+    }
+
+    /// Column names of table `name` that an INSERT gives values to: all
+    /// but the generated columns.
+    pub fn insert_columns(&self, name: &str) -> Result<Vec<String>> {
+        let schema = self.db.get_schema_page().borrow().table_schema(name)?;
+        let columns = schema.columns.into_iter().filter(|c| !c.is_generated());
+        Ok(columns.map(|c| c.name).collect())
+    }
+
+    /// Names of the tables and views, sorted, without the internal
+    /// `sqlite_` tables.
+    pub fn table_names(&self) -> Vec<String> {
+        let mut names = self
+            .schema()
+            .into_iter()
+            .filter(|entry| matches!(entry.kind(), "table" | "view"))
+            .map(|entry| entry.name().to_string())
+            .filter(|name| !name.starts_with("sqlite_"))
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+}
+
+/// Interrupts the statement running on a connection, which then fails with
+/// [`Error::Interrupted`]. An interrupt with no statement running is
+/// dropped when the next one starts.
+#[derive(Debug, Clone)]
+pub struct InterruptHandle(Arc<AtomicBool>);
+
+impl InterruptHandle {
+    pub fn interrupt(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// A prepared SELECT statement.
+pub struct Statement<'conn> {
+    connection: &'conn Connection,
+    query: Query,
+}
+
+impl Statement<'_> {
+    /// The result columns, with `*` expanded to the table's columns.
+    pub fn columns(&self) -> &[Column] {
+        self.query.columns()
+    }
+
+    pub fn column_count(&self) -> usize {
+        self.query.columns().len()
+    }
+
+    pub fn column_names(&self) -> Vec<&str> {
+        self.query.columns().iter().map(|c| c.name()).collect()
+    }
+
+    /// The largest parameter number: `?NNN` sets it explicitly, `?` and
+    /// `:name`, `@name` or `$name` take the next one.
+    pub fn parameter_count(&self) -> usize {
+        self.query.parameter_count()
+    }
+
+    /// Name of parameter `index` (from 1) including its prefix, None for
+    /// `?` and `?NNN`.
+    pub fn parameter_name(&self, index: usize) -> Option<&str> {
+        self.query.parameter_name(index)
+    }
+
+    /// Number of the parameter called `name`, prefix included.
+    pub fn parameter_index(&self, name: &str) -> Option<usize> {
+        self.query.parameter_index(name)
+    }
+
+    /// Binds `value` to parameter `index`, numbered from 1. Parameters
+    /// compare like literals of the same value; unbound ones are NULL.
+    pub fn bind<T: Into<Value>>(&mut self, index: usize, value: T) -> Result<()> {
+        self.query.bind(index, value.into())
+    }
+
+    /// Binds `value` to the parameter called `name`, such as `:id`.
+    pub fn bind_name<T: Into<Value>>(&mut self, name: &str, value: T) -> Result<()> {
+        match self.parameter_index(name) {
+            Some(index) => self.bind(index, value),
+            None => Err(Error::InvalidParameterName(name.to_string())),
+        }
+    }
+
+    /// Whether this is an `EXPLAIN QUERY PLAN` statement, whose rows are the
+    /// steps of the plan: `id`, `parent`, `notused` and `detail`.
+    pub fn is_explain(&self) -> bool {
+        self.query.is_explain()
+    }
+
+    /// Sets every parameter back to NULL.
+    pub fn clear_bindings(&mut self) {
+        self.query.clear_bindings()
+    }
+
+    /// Runs the statement; rows are produced as the result is iterated.
+    pub fn query(&self) -> Result<Rows<'_>> {
+        let interrupted = &self.connection.interrupted;
+        interrupted.store(false, Ordering::Relaxed);
+        stats::reset();
+        cache::clear();
+        Ok(Rows {
+            rows: self.query.run(&self.connection.db, interrupted)?,
+        })
+    }
+
+    /// Runs the statement, deserializing each row into a `T`: a struct
+    /// with fields named after the result columns, a tuple, or a single
+    /// column's value. See the [`de`](crate::de) module for conversions.
+    pub fn query_as<T: DeserializeOwned>(&self) -> Result<RowsAs<'_, T>> {
+        Ok(RowsAs {
+            rows: self.query()?,
+            columns: self.columns(),
+            marker: PhantomData,
+        })
+    }
+}
+
+/// Name and declared type of a result column.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    name: String,
+    decl_type: Option<String>,
+}
+
+impl Column {
+    pub(crate) fn new(name: String, decl_type: Option<String>) -> Column {
+        Column { name, decl_type }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The declared type of the table column the result column reads, as
+    /// written in `CREATE TABLE`; None for expressions and untyped columns.
+    pub fn decl_type(&self) -> Option<&str> {
+        self.decl_type.as_deref()
+    }
+}
+
+/// The result rows of a statement.
+pub struct Rows<'stmt> {
+    rows: ResultRows<'stmt>,
+}
+
+impl Iterator for Rows<'_> {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Result<Row>> {
+        let row = self.rows.next()?;
+        if row.is_ok() {
+            stats::record(|stats| stats.rows_returned += 1);
+        }
+        Some(row.map(|values| Row { values }))
+    }
+}
+
+/// The result rows of a statement, deserialized into `T`.
+pub struct RowsAs<'stmt, T> {
+    rows: Rows<'stmt>,
+    columns: &'stmt [Column],
+    marker: PhantomData<T>,
+}
+
+impl<T: DeserializeOwned> Iterator for RowsAs<'_, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Result<T>> {
+        self.rows.next().map(|row| {
+            let row = row?;
+            T::deserialize(RowDeserializer::new(self.columns, &row.values))
+        })
+    }
+}
+
+/// A result row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    values: Vec<Value>,
+}
+
+impl Row {
+    /// The value of the column at `index`, converted to `T`.
+    pub fn get<T: FromValue>(&self, index: usize) -> Result<T> {
+        match self.values.get(index) {
+            Some(value) => T::from_value(value),
+            None => Err(Error::InvalidColumnIndex(index)),
+        }
+    }
+
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+/// Conversion of a column value to a Rust type, failing when the value's
+/// storage class doesn't fit.
+pub trait FromValue: Sized {
+    fn from_value(value: &Value) -> Result<Self>;
+}
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Result<Self> {
+        Ok(value.clone())
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Integer(i) => Ok(*i),
+            _ => Err(invalid_type(value, "an integer")),
+        }
+    }
+}
+
+impl FromValue for i32 {
+    fn from_value(value: &Value) -> Result<Self> {
+        i32::try_from(i64::from_value(value)?).map_err(|_| invalid_type(value, "an i32"))
+    }
+}
+
+impl FromValue for usize {
+    fn from_value(value: &Value) -> Result<Self> {
+        usize::try_from(i64::from_value(value)?).map_err(|_| invalid_type(value, "a usize"))
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Result<Self> {
+        Ok(i64::from_value(value)? != 0)
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Real(r) => Ok(*r),
+            Value::Integer(i) => Ok(*i as f64),
+            _ => Err(invalid_type(value, "a real")),
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Text(s) => Ok(s.clone()),
+            _ => Err(invalid_type(value, "text")),
+        }
+    }
+}
+
+impl FromValue for Vec<u8> {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Blob(b) => Ok(b.clone()),
+            Value::Text(s) => Ok(s.as_bytes().to_vec()),
+            _ => Err(invalid_type(value, "a blob")),
+        }
+    }
+}
+
+fn invalid_type(value: &Value, target: &'static str) -> Error {
+    Error::InvalidColumnType {
+        value: format!("{value:?}"),
+        target,
+    }
+}
+
+/// NULL reads as None.
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Null => Ok(None),
+            value => Ok(Some(T::from_value(value)?)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_rows() {
+        let connection = Connection::open("sample.db").unwrap();
+        let statement = connection
+            .prepare("SELECT id, name, description, id * 1.5 AS half FROM oranges WHERE id < 3")
+            .unwrap();
+        assert_eq!(
+            statement.column_names(),
+            ["id", "name", "description", "half"]
+        );
+        let rows = statement
+            .query()
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1].get::<i64>(0).unwrap(), 2);
+        assert_eq!(rows[1].get::<String>(1).unwrap(), "Tangelo");
+        assert_eq!(rows[1].get::<f64>(3).unwrap(), 3.0);
+        assert_eq!(rows[1].get::<Option<i32>>(0).unwrap(), Some(2));
+        assert!(rows[1].get::<i64>(1).is_err());
+        assert!(rows[1].get::<i64>(4).is_err());
+    }
+
+    #[test]
+    fn test_column_metadata() {
+        let connection = Connection::open("sample.db").unwrap();
+        let statement = connection
+            .prepare("SELECT *, count(*) AS n FROM apples")
+            .unwrap();
+        let columns = statement
+            .columns()
+            .iter()
+            .map(|c| (c.name(), c.decl_type()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            columns,
+            [
+                ("id", Some("integer")),
+                ("name", Some("text")),
+                ("color", Some("text")),
+                ("n", None)
+            ]
+        );
+        assert!(connection.prepare("SELECT * FROM pears").is_err());
+        assert!(Connection::open("missing.db").is_err());
+    }
+
+    #[test]
+    fn test_schema() {
+        let connection = Connection::open("sample.db").unwrap();
+        let entries = connection
+            .schema()
+            .into_iter()
+            .map(|e| {
+                (
+                    e.kind().to_string(),
+                    e.name().to_string(),
+                    e.table_name().to_string(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let entry = |kind: &str, name: &str, table_name: &str| {
+            (kind.to_string(), name.to_string(), table_name.to_string())
+        };
+        assert_eq!(
+            entries,
+            [
+                entry("table", "apples", "apples"),
+                entry("table", "sqlite_sequence", "sqlite_sequence"),
+                entry("table", "oranges", "oranges"),
+                entry("index", "name_index", "oranges"),
+            ]
+        );
+        assert_eq!(
+            connection.schema()[3].sql(),
+            Some("CREATE INDEX name_index on oranges(name)")
+        );
+        assert_eq!(
+            connection.table_columns("oranges").unwrap(),
+            ["id", "name", "description"]
+        );
+        assert!(connection.table_columns("name_index").is_err());
+    }
+
+    #[test]
+    fn test_rows_are_lazy() {
+        let connection = Connection::open("sample.db").unwrap();
+        // the unknown function only fails on the rows that call it
+        let statement = connection
+            .prepare("SELECT name, CASE WHEN id = 3 THEN nosuch() END FROM oranges")
+            .unwrap();
+        let mut rows = statement.query().unwrap();
+        assert!(rows.next().unwrap().is_ok());
+        assert!(rows.next().unwrap().is_ok());
+        assert!(rows.next().unwrap().is_err());
+        assert!(rows.next().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_stats() {
+        let connection = Connection::open("sample.db").unwrap();
+        let statement = connection.prepare("SELECT name FROM oranges").unwrap();
+        let mut rows = statement.query().unwrap();
+        rows.next().unwrap().unwrap();
+        // table rows are only read as result rows are asked for
+        let stats = connection.stats();
+        assert_eq!((stats.rows_scanned, stats.rows_returned), (1, 1));
+        assert_eq!(rows.count(), 5);
+        let scan = connection.stats();
+        assert_eq!((scan.rows_scanned, scan.rows_returned), (6, 6));
+        // the table's only page is its root, kept with the schema
+        assert_eq!(scan.pages_read, 0);
+        assert!(scan.bytes_decoded > 0);
+
+        // through the index: an index leaf, then the table leaf holding the
+        // rows, read from the file for the first row and cached after it
+        let connection = Connection::open("indexes.db").unwrap();
+        let statement = connection
+            .prepare("SELECT a FROM t WHERE a BETWEEN 249 AND 251")
+            .unwrap();
+        assert_eq!(statement.query().unwrap().count(), 3);
+        let search = connection.stats();
+        assert_eq!(search.pages_read, 4);
+        assert_eq!((search.cache_hits, search.cache_misses), (2, 2));
+        assert_eq!((search.rows_scanned, search.rows_returned), (3, 3));
+
+        // the cache is emptied as a statement starts
+        assert_eq!(statement.query().unwrap().count(), 3);
+        assert_eq!(connection.stats().cache_misses, 2);
+    }
+
+    #[test]
+    fn test_explain_query_plan() {
+        let connection = Connection::open("sample.db").unwrap();
+        let plan = |sql: &str| {
+            let statement = connection.prepare(sql).unwrap();
+            assert!(statement.is_explain());
+            let rows = statement.query().unwrap().map(|row| row.unwrap());
+            rows.map(|row| row.get::<String>(3).unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            plan("EXPLAIN QUERY PLAN SELECT * FROM oranges"),
+            ["SCAN oranges"]
+        );
+        assert_eq!(
+            plan("explain query plan SELECT id FROM oranges WHERE name = 'Clementine' ORDER BY id"),
+            [
+                "SEARCH oranges USING INDEX name_index (name=?)",
+                "USE TEMP B-TREE FOR ORDER BY"
+            ]
+        );
+        assert_eq!(
+            plan("EXPLAIN QUERY PLAN SELECT name FROM oranges WHERE name > 'A' AND id = 2"),
+            ["SEARCH oranges USING INTEGER PRIMARY KEY (rowid=?)"]
+        );
+        assert_eq!(plan("EXPLAIN QUERY PLAN SELECT 1"), ["SCAN CONSTANT ROW"]);
+
+        let statement = connection
+            .prepare("EXPLAIN QUERY PLAN SELECT name FROM oranges WHERE name BETWEEN 'a' AND 'm'")
+            .unwrap();
+        assert_eq!(
+            statement.column_names(),
+            ["id", "parent", "notused", "detail"]
+        );
+        let row = statement.query().unwrap().next().unwrap().unwrap();
+        assert_eq!(row.get::<i64>(1).unwrap(), 0);
+        assert_eq!(
+            row.get::<String>(3).unwrap(),
+            "SEARCH oranges USING INDEX name_index (name>? AND name<?)"
+        );
+        assert!(!connection.prepare("SELECT 1").unwrap().is_explain());
+
+        // the rowid is looked up rather than scanned for
+        let statement = connection
+            .prepare("SELECT name FROM oranges WHERE id = 3")
+            .unwrap();
+        assert_eq!(statement.query().unwrap().count(), 1);
+        assert_eq!(connection.stats().rows_scanned, 1);
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let connection = Connection::open("sample.db").unwrap();
+        let handle = connection.interrupt_handle();
+        let statement = connection.prepare("SELECT name FROM oranges").unwrap();
+        let mut rows = statement.query().unwrap();
+        assert!(rows.next().unwrap().is_ok());
+        handle.interrupt();
+        assert!(matches!(rows.next(), Some(Err(Error::Interrupted))));
+
+        // an interrupt before the statement starts is dropped
+        handle.interrupt();
+        assert_eq!(statement.query().unwrap().count(), 6);
+    }
+
+    #[test]
+    fn test_errors() {
+        let connection = Connection::open("sample.db").unwrap();
+        assert!(matches!(
+            connection.prepare("SELECT * FROM pears"),
+            Err(Error::NoSuchTable(name)) if name == "pears"
+        ));
+        assert!(matches!(
+            connection.prepare("SELECT * FROM apples WHERE"),
+            Err(Error::Parse { position: 26, .. })
+        ));
+        let statement = connection.prepare("SELECT weight FROM apples").unwrap();
+        assert!(matches!(
+            statement.query().unwrap().next(),
+            Some(Err(Error::NoSuchColumn(name))) if name == "weight"
+        ));
+        let row = connection
+            .prepare("SELECT 'a'")
+            .unwrap()
+            .query()
+            .unwrap()
+            .next();
+        let row = row.unwrap().unwrap();
+        assert!(matches!(
+            row.get::<i64>(0),
+            Err(Error::InvalidColumnType { .. })
+        ));
+        assert!(matches!(
+            row.get::<Value>(1),
+            Err(Error::InvalidColumnIndex(1))
+        ));
+
+        assert!(matches!(Connection::open("missing.db"), Err(Error::Io(_))));
+        assert!(matches!(
+            Connection::open("Cargo.toml"),
+            Err(Error::Corrupt { page: 1, .. })
+        ));
+        // the root pages of the tables are past the end of a truncated copy
+        let truncated = std::env::temp_dir().join("codecrafters_sqlite_truncated.db");
+        let bytes = std::fs::read("sample.db").unwrap();
+        std::fs::write(&truncated, &bytes[..4096]).unwrap();
+        assert!(matches!(
+            Connection::open(&truncated),
+            Err(Error::Corrupt { page: 2, .. })
+        ));
+        std::fs::remove_file(truncated).unwrap();
+    }
+
+    #[test]
+    fn test_bind_parameters() {
+        let connection = Connection::open("sample.db").unwrap();
+        let names = |statement: &Statement| {
+            statement
+                .query()
+                .unwrap()
+                .map(|row| row.unwrap().get::<String>(0).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        let mut statement = connection
+            .prepare("SELECT name FROM oranges WHERE id > ? AND id < ?3 OR name = :name")
+            .unwrap();
+        assert_eq!(statement.parameter_count(), 4);
+        assert_eq!(statement.parameter_name(4), Some(":name"));
+        assert_eq!(statement.parameter_name(2), None);
+        assert_eq!(statement.parameter_index(":name"), Some(4));
+        assert!(names(&statement).is_empty());
+
+        // the text '2' is converted to the integer column's affinity
+        statement.bind(1, "2").unwrap();
+        statement.bind(3, 5).unwrap();
+        statement.bind_name(":name", "Mandarin").unwrap();
+        assert_eq!(names(&statement), ["Mandarin", "Tangerine", "Clementine"]);
+
+        // a value containing quotes is just a value
+        statement.bind_name(":name", "x' OR '1' = '1").unwrap();
+        assert_eq!(names(&statement), ["Tangerine", "Clementine"]);
+        statement.clear_bindings();
+        assert!(names(&statement).is_empty());
+
+        assert!(matches!(
+            statement.bind(5, 1),
+            Err(Error::InvalidParameterIndex(5))
+        ));
+        assert!(matches!(
+            statement.bind(0, 1),
+            Err(Error::InvalidParameterIndex(0))
+        ));
+        assert!(matches!(
+            statement.bind_name("name", 1),
+            Err(Error::InvalidParameterName(_))
+        ));
+
+        // a bound key can still be looked up through an index
+        let mut statement = connection
+            .prepare("SELECT description FROM oranges WHERE name = @name")
+            .unwrap();
+        statement.bind_name("@name", "Tangelo").unwrap();
+        assert_eq!(names(&statement), ["sweet and tart"]);
+        assert!(connection.prepare("SELECT ?0").is_err());
+    }
+
+    #[test]
+    fn test_btree_cycle() {
+        use std::io::{Seek, SeekFrom, Write};
+
+        let path = std::env::temp_dir().join("codecrafters_sqlite_cycle.db");
+        std::fs::copy("sample.db", &path).unwrap();
+        // apples (page 2), oranges (page 4) and name_index (page 5) become
+        // interior pages without cells whose right child is the page itself
+        let mut file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+        for (page, kind) in [(2u32, 0x05), (4, 0x05), (5, 0x02)] {
+            let mut header = [0u8; 12];
+            header[0] = kind;
+            header[8..].copy_from_slice(&page.to_be_bytes());
+            let offset = (page as u64 - 1) * 4096;
+            file.seek(SeekFrom::Start(offset)).unwrap();
+            file.write_all(&header).unwrap();
+        }
+        drop(file);
+
+        let mut connection = Connection::open(&path).unwrap();
+        for sql in [
+            "SELECT count(*) FROM oranges",
+            "SELECT name FROM oranges WHERE id = 1",
+            "SELECT id FROM oranges WHERE name = 'Mandarin'",
+        ] {
+            let statement = connection.prepare(sql).unwrap();
+            let error = statement
+                .query()
+                .and_then(|rows| rows.collect::<Result<Vec<_>>>());
+            assert!(
+                matches!(error, Err(Error::Corrupt { page, ref reason }) if page > 3 && reason == "cycle"),
+                "{sql}: {error:?}"
+            );
+        }
+        let error = connection.insert("apples", [vec![Value::Null, Value::Null, Value::Null]]);
+        assert!(
+            matches!(error, Err(Error::Corrupt { page: 2, .. })),
+            "{error:?}"
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_utf16_index_order() {
+        // UTF-16le, with an index on s: 'ā' is 01 01 and sorts before 'a'
+        let connection = Connection::open("utf16.db").unwrap();
+        let query = |sql: &str| {
+            let statement = connection.prepare(sql).unwrap();
+            let rows = statement.query().unwrap();
+            rows.map(|row| row.unwrap().values()[0].to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(query("SELECT s FROM t ORDER BY s"), ["ā", "a", "b", "c"]);
+        assert_eq!(query("SELECT s FROM t WHERE s = 'a'"), ["a"]);
+        assert_eq!(query("SELECT s FROM t WHERE s LIKE 'a%'"), ["a"]);
+        assert_eq!(query("SELECT s FROM t WHERE s LIKE 'ā%'"), ["ā"]);
+        assert_eq!(query("SELECT s FROM t WHERE s < 'b'"), ["ā", "a"]);
+        assert_eq!(query("SELECT min(s) FROM t"), ["ā"]);
+    }
+
+    #[test]
+    fn test_descending_index() {
+        // t(a) holds 1 to 500 and a NULL, indexed by a DESC, and w is a
+        // WITHOUT ROWID table with the first 50 of them as a DESC key
+        let connection = Connection::open("indexes.db").unwrap();
+        let query = |sql: &str| {
+            let statement = connection.prepare(sql).unwrap();
+            let rows = statement.query().unwrap();
+            rows.map(|row| row.unwrap().values()[0].to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(query("SELECT a FROM t WHERE a = 2"), ["2"]);
+        assert_eq!(query("SELECT a FROM t WHERE a < 3"), ["2", "1"]);
+        assert_eq!(
+            query("SELECT a FROM t WHERE a > 497"),
+            ["500", "499", "498"]
+        );
+        assert_eq!(query("SELECT count(*) FROM t WHERE a > 3"), ["497"]);
+        assert_eq!(
+            query("SELECT a FROM t WHERE a BETWEEN 249 AND 251"),
+            ["251", "250", "249"]
+        );
+        // only the rows within the range are read
+        assert_eq!(connection.stats().rows_scanned, 3);
+        assert_eq!(query("SELECT count(*) FROM t WHERE a IS NULL"), ["1"]);
+        assert_eq!(query("SELECT b FROM w WHERE a = 7"), ["14"]);
+        assert_eq!(query("SELECT b FROM w WHERE a < 3"), ["4", "2"]);
+        assert_eq!(query("SELECT b FROM w WHERE a >= 49"), ["100", "98"]);
+    }
+
+    #[test]
+    fn test_quoted_index_columns() {
+        // indexes on q("x y") and u("a,b"), next to columns x and a
+        let connection = Connection::open("indexes.db").unwrap();
+        let query = |sql: &str| {
+            let statement = connection.prepare(sql).unwrap();
+            let rows = statement.query().unwrap();
+            rows.map(|row| row.unwrap().values().to_vec())
+                .collect::<Vec<_>>()
+        };
+        let row = |a: i64, b: i64| vec![Value::Integer(a), Value::Integer(b)];
+        assert_eq!(query("SELECT * FROM q WHERE x = 1"), [row(1, 2)]);
+        assert_eq!(query("SELECT * FROM q WHERE \"x y\" = 4"), [row(3, 4)]);
+        assert_eq!(query("SELECT * FROM u WHERE a = 5"), [row(5, 6)]);
+        assert_eq!(query("SELECT * FROM u WHERE \"a,b\" = 8"), [row(7, 8)]);
+    }
+
+    #[test]
+    fn test_real_affinity() {
+        let path = std::env::temp_dir().join("codecrafters_sqlite_real.db");
+        std::fs::copy("sample.db", &path).unwrap();
+        let mut connection = Connection::open(&path).unwrap();
+        connection
+            .create_table("CREATE TABLE r (x REAL, y)")
+            .unwrap();
+        let rows = [
+            vec![Value::Integer(3), Value::Integer(3)],
+            vec![Value::Real(2.5), Value::Real(4.0)],
+        ];
+        connection.insert("r", rows).unwrap();
+        // 3 is stored as an integer, and read back as REAL
+        let statement = connection.prepare("SELECT x, x / 2, y FROM r").unwrap();
+        let rows = statement
+            .query()
+            .unwrap()
+            .map(|row| row.unwrap().values().to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rows,
+            [
+                [Value::Real(3.0), Value::Real(1.5), Value::Integer(3)],
+                [Value::Real(2.5), Value::Real(1.25), Value::Real(4.0)],
+            ]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_create_table_and_insert() {
+        let path = std::env::temp_dir().join("codecrafters_sqlite_insert.db");
+        std::fs::copy("sample.db", &path).unwrap();
+        let mut connection = Connection::open(&path).unwrap();
+        connection
+            .create_table(
+                "CREATE TABLE notes (id INTEGER PRIMARY KEY AUTOINCREMENT, body TEXT NOT NULL)",
+            )
+            .unwrap();
+        assert!(connection
+            .create_table("CREATE TABLE IF NOT EXISTS notes (a)")
+            .is_ok());
+        assert!(matches!(
+            connection.create_table("CREATE TABLE Notes (a)"),
+            Err(Error::Sql(_))
+        ));
+        assert!(matches!(
+            connection.create_table("CREATE TABLE u (a UNIQUE)"),
+            Err(Error::Unsupported(_))
+        ));
+
+        // enough rows, some past a page, to split the leaves and the root
+        let body = |i: usize| "x".repeat(i * 37 % 9000);
+        let rows = (1..=2000).map(|i| vec![Value::Null, Value::Text(body(i))]);
+        assert_eq!(connection.insert("notes", rows).unwrap(), 2000);
+        let statement = connection.prepare("SELECT id, body FROM notes").unwrap();
+        let mut count = 0;
+        for (i, row) in statement.query().unwrap().enumerate() {
+            let row = row.unwrap();
+            assert_eq!(row.get::<i64>(0).unwrap(), i as i64 + 1);
+            assert_eq!(row.get::<String>(1).unwrap(), body(i + 1));
+            count += 1;
+        }
+        assert_eq!(count, 2000);
+        assert!(!path.with_extension("db-journal").exists());
+
+        // a failed row leaves out the others, unless it's skipped
+        let rows = || {
+            vec![
+                vec![Value::Integer(9000), "a".into()],
+                vec![Value::Integer(1), "b".into()],
+                vec![Value::Null, Value::Null],
+            ]
+        };
+        assert!(matches!(
+            connection.insert("notes", rows()),
+            Err(Error::Constraint(e)) if e == "UNIQUE constraint failed: notes.id"
+        ));
+        let mut failed = vec![];
+        let inserted = connection.insert_with("notes", rows(), |i, e| {
+            failed.push((i, e.to_string()));
+            Ok(())
+        });
+        assert_eq!(inserted.unwrap(), 1);
+        assert_eq!(
+            failed,
+            [
+                (1, "UNIQUE constraint failed: notes.id".to_string()),
+                (2, "NOT NULL constraint failed: notes.body".to_string())
+            ]
+        );
+        let sequence = connection
+            .prepare("SELECT seq FROM sqlite_sequence WHERE name = 'notes'")
+            .unwrap();
+        let seq = sequence.query().unwrap().next().unwrap().unwrap();
+        assert_eq!(seq.get::<i64>(0).unwrap(), 9000);
+
+        assert!(matches!(
+            connection.insert("pears", vec![]),
+            Err(Error::NoSuchTable(_))
+        ));
+        let mut connection = Connection::open_read_only(&path).unwrap();
+        assert!(matches!(
+            connection.create_table("CREATE TABLE t (a)"),
+            Err(Error::Sql(_))
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_hot_journal() {
+        use std::io::{Seek, Write};
+
+        let path = std::env::temp_dir().join("codecrafters_sqlite_hot.db");
+        std::fs::copy("sample.db", &path).unwrap();
+        let original = std::fs::read(&path).unwrap();
+
+        // a change that stopped after overwriting the schema page
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .unwrap();
+        let page_count = (original.len() / 4096) as u32;
+        journal::write(&path, &mut file, 4096, page_count, &[1]).unwrap();
+        file.rewind().unwrap();
+        file.write_all(&[0xff; 4096]).unwrap();
+        drop(file);
+
+        let connection = Connection::open(&path).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), original);
+        assert!(!path.with_extension("db-journal").exists());
+        let statement = connection.prepare("SELECT count(*) FROM apples").unwrap();
+        let row = statement.query().unwrap().next().unwrap().unwrap();
+        assert_eq!(row.get::<i64>(0).unwrap(), 4);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,536 @@
+//! Writing to table B-trees. The pages a change touches are kept in memory
+//! and written to the file together once it's complete, so an error part
+//! way through leaves the database as it was. Their original content is
+//! saved to the rollback journal first, which undoes a crash part way
+//! through writing them (see `journal`).
+//!
+//! A page that no longer fits its cells is split into as many pages as the
+//! cells fill from the left, the last keeping its page number, and the new
+//! pages are added to its parent, which may split in turn. Rows appended in
+//! rowid order so leave full pages behind them. Pages are only ever added,
+//! at the end of the file: nothing is deleted, so the freelist is unused.
+
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::db::header::HEADER_BYTES_SIZE;
+use crate::db::journal;
+use crate::db::record::{read_varint, write_varint, TextEncoding};
+use crate::error::{Error, Result};
+
+const INTERIOR_TABLE: u8 = 0x05;
+const LEAF_TABLE: u8 = 0x0d;
+
+/// The cells of a table B-tree page, each as its bytes.
+struct Node {
+    kind: u8,
+    cells: Vec<Vec<u8>>,
+    /// The right-most child of an interior page.
+    right: u32,
+}
+
+impl Node {
+    fn leaf(cells: Vec<Vec<u8>>) -> Node {
+        Node {
+            kind: LEAF_TABLE,
+            cells,
+            right: 0,
+        }
+    }
+
+    /// Size of the page header: interior pages add the right-most child.
+    fn header_size(&self) -> usize {
+        match self.kind {
+            INTERIOR_TABLE => 12,
+            _ => 8,
+        }
+    }
+
+    /// Rowid of a leaf cell, or key of an interior cell.
+    fn key(&self, cell: &[u8]) -> i64 {
+        match self.kind {
+            INTERIOR_TABLE => read_varint(&cell[4..]).0 as i64,
+            _ => {
+                let (_, len) = read_varint(cell);
+                read_varint(&cell[len..]).0 as i64
+            }
+        }
+    }
+}
+
+/// An interior cell: the left child and the largest rowid under it.
+fn interior_cell(child: u32, key: i64) -> Vec<u8> {
+    let mut cell = child.to_be_bytes().to_vec();
+    write_varint(key as u64, &mut cell);
+    cell
+}
+
+fn left_child(cell: &[u8]) -> u32 {
+    u32::from_be_bytes([cell[0], cell[1], cell[2], cell[3]])
+}
+
+/// Fails when `child` is already on the `path` of pages from the root,
+/// which would make a walk down the tree endless.
+fn check_cycle(path: &[u32], child: u32) -> Result<()> {
+    match path.contains(&child) {
+        true => Err(Error::corrupt(child as usize, "cycle")),
+        false => Ok(()),
+    }
+}
+
+/// Where the B-tree header starts: after the database header on page 1.
+fn header_offset(number: u32) -> usize {
+    match number {
+        1 => HEADER_BYTES_SIZE as usize,
+        _ => 0,
+    }
+}
+
+/// Bytes of a payload of `size` bytes kept in a table leaf cell; the rest
+/// goes to overflow pages.
+fn local_size(size: usize, usable_size: usize) -> usize {
+    let max_local = usable_size - 35;
+    let min_local = (usable_size - 12) * 32 / 255 - 23;
+    if size <= max_local {
+        return size;
+    }
+    match min_local + (size - min_local) % (usable_size - 4) {
+        local if local <= max_local => local,
+        _ => min_local,
+    }
+}
+
+/// The pages of a database file being changed.
+pub struct Pager {
+    path: PathBuf,
+    file: File,
+    page_size: usize,
+    /// The page size less the bytes reserved at the end of each page.
+    usable_size: usize,
+    page_count: u32,
+    /// The page count before the change, the pages past which have nothing
+    /// to roll back.
+    original_count: u32,
+    encoding: TextEncoding,
+    /// Pages read or written so far, by number.
+    pages: BTreeMap<u32, Vec<u8>>,
+    schema_changed: bool,
+}
+
+impl Pager {
+    /// Opens the database at `path` for writing. Databases in WAL mode and
+    /// with auto-vacuum, which keeps a map of the pages, aren't supported.
+    ///
+    /// The database stays locked until the pager is dropped, and opening it
+    /// fails with `Error::Busy` while another connection has it locked. A hot
+    /// journal left by a change that didn't complete is rolled back first.
+    pub fn open(path: &Path) -> Result<Pager> {
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        journal::lock(&file)?;
+        journal::roll_back(path, &mut file)?;
+        file.rewind()?;
+        let mut header = [0; HEADER_BYTES_SIZE as usize];
+        file.read_exact(&mut header)
+            .map_err(|_| Error::corrupt(1, "file is not a database"))?;
+        if header[18] != 1 || header[19] != 1 {
+            return Err(Error::Unsupported(
+                "writing to a database in WAL mode".to_string(),
+            ));
+        }
+        if header[52..56] != [0; 4] {
+            return Err(Error::Unsupported(
+                "writing to an auto-vacuum database".to_string(),
+            ));
+        }
+        let page_size = match u16::from_be_bytes([header[16], header[17]]) {
+            1 => 65536,
+            size => size as usize,
+        };
+        // the page count in the header is only valid if written together
+        // with the change counter
+        let page_count = match u32::from_be_bytes([header[28], header[29], header[30], header[31]])
+        {
+            count if count > 0 && header[24..28] == header[92..96] => count,
+            _ => (file.metadata()?.len() / page_size as u64) as u32,
+        };
+        Ok(Pager {
+            path: path.to_path_buf(),
+            file,
+            page_size,
+            usable_size: page_size - header[20] as usize,
+            page_count,
+            original_count: page_count,
+            encoding: TextEncoding::from_header(u32::from_be_bytes([
+                header[56], header[57], header[58], header[59],
+            ])),
+            pages: BTreeMap::new(),
+            schema_changed: false,
+        })
+    }
+
+    pub fn text_encoding(&self) -> TextEncoding {
+        self.encoding
+    }
+
+    /// The bytes of page `number`, read from the file the first time.
+    fn page(&mut self, number: u32) -> Result<&mut Vec<u8>> {
+        if number == 0 || number > self.page_count {
+            return Err(Error::corrupt(number as usize, "invalid page number"));
+        }
+        match self.pages.entry(number) {
+            Entry::Occupied(page) => Ok(page.into_mut()),
+            Entry::Vacant(entry) => {
+                let mut page = vec![0; self.page_size];
+                let offset = (number as u64 - 1) * self.page_size as u64;
+                self.file.seek(SeekFrom::Start(offset))?;
+                self.file.read_exact(&mut page)?;
+                Ok(entry.insert(page))
+            }
+        }
+    }
+
+    /// A new zeroed page at the end of the file.
+    fn allocate(&mut self) -> u32 {
+        self.page_count += 1;
+        self.pages.insert(self.page_count, vec![0; self.page_size]);
+        self.page_count
+    }
+
+    fn read_node(&mut self, number: u32) -> Result<Node> {
+        let usable_size = self.usable_size;
+        let header = header_offset(number);
+        let page = self.page(number)?;
+        let mut node = match page[header] {
+            INTERIOR_TABLE => Node {
+                kind: INTERIOR_TABLE,
+                cells: vec![],
+                right: left_child(&page[header + 8..]),
+            },
+            LEAF_TABLE => Node::leaf(vec![]),
+            kind => {
+                return Err(Error::corrupt(
+                    number as usize,
+                    format!("invalid table page type {kind:#04x}"),
+                ))
+            }
+        };
+        let count = u16::from_be_bytes([page[header + 3], page[header + 4]]) as usize;
+        let pointers = header + node.header_size();
+        for i in 0..count {
+            let pointer = pointers + i * 2;
+            let start = u16::from_be_bytes([page[pointer], page[pointer + 1]]) as usize;
+            let size = match node.kind {
+                INTERIOR_TABLE => 4 + read_varint(&page[start + 4..]).1,
+                _ => {
+                    let (payload_size, len) = read_varint(&page[start..]);
+                    let len = len + read_varint(&page[start + len..]).1;
+                    let local = local_size(payload_size as usize, usable_size);
+                    match local < payload_size as usize {
+                        true => len + local + 4,
+                        false => len + local,
+                    }
+                }
+            };
+            match page.get(start..start + size) {
+                Some(cell) if start + size <= usable_size => node.cells.push(cell.to_vec()),
+                _ => {
+                    return Err(Error::corrupt(
+                        number as usize,
+                        format!("cell {i} past the end of the page"),
+                    ))
+                }
+            }
+        }
+        Ok(node)
+    }
+
+    /// Whether `node` fits in page `number`.
+    fn fits(&self, number: u32, node: &Node) -> bool {
+        let cells = node.cells.iter().map(|cell| cell.len() + 2).sum::<usize>();
+        header_offset(number) + node.header_size() + cells <= self.usable_size
+    }
+
+    /// Lays out `node` on page `number`, its cells from the end of the page
+    /// in reverse order, with no free space in between.
+    fn write_node(&mut self, number: u32, node: &Node) -> Result<()> {
+        let usable_size = self.usable_size;
+        let header = header_offset(number);
+        let page = self.page(number)?;
+        page[header..usable_size].fill(0);
+        page[header] = node.kind;
+        page[header + 3..header + 5].copy_from_slice(&(node.cells.len() as u16).to_be_bytes());
+        if node.kind == INTERIOR_TABLE {
+            page[header + 8..header + 12].copy_from_slice(&node.right.to_be_bytes());
+        }
+        let mut content = usable_size;
+        for (i, cell) in node.cells.iter().enumerate() {
+            content -= cell.len();
+            page[content..content + cell.len()].copy_from_slice(cell);
+            let pointer = header + node.header_size() + i * 2;
+            page[pointer..pointer + 2].copy_from_slice(&(content as u16).to_be_bytes());
+        }
+        // a content area starting at 65536 is written as 0
+        page[header + 5..header + 7].copy_from_slice(&(content as u16).to_be_bytes());
+        Ok(())
+    }
+
+    /// A leaf cell for `payload` under `rowid`, with what doesn't fit in the
+    /// page written to a chain of overflow pages.
+    fn leaf_cell(&mut self, rowid: i64, payload: &[u8]) -> Result<Vec<u8>> {
+        let mut cell = vec![];
+        write_varint(payload.len() as u64, &mut cell);
+        write_varint(rowid as u64, &mut cell);
+        let local = local_size(payload.len(), self.usable_size);
+        cell.extend_from_slice(&payload[..local]);
+        if local < payload.len() {
+            let chunks = payload[local..]
+                .chunks(self.usable_size - 4)
+                .collect::<Vec<_>>();
+            let numbers = chunks.iter().map(|_| self.allocate()).collect::<Vec<_>>();
+            for (i, chunk) in chunks.into_iter().enumerate() {
+                let next = numbers.get(i + 1).copied().unwrap_or(0);
+                let page = self.page(numbers[i])?;
+                page[..4].copy_from_slice(&next.to_be_bytes());
+                page[4..4 + chunk.len()].copy_from_slice(chunk);
+            }
+            cell.extend_from_slice(&numbers[0].to_be_bytes());
+        }
+        Ok(cell)
+    }
+
+    /// Creates an empty table B-tree, returning its root page.
+    pub fn create_tree(&mut self) -> Result<u32> {
+        let root = self.allocate();
+        self.write_node(root, &Node::leaf(vec![]))?;
+        self.schema_changed = true;
+        Ok(root)
+    }
+
+    /// The largest rowid in the table B-tree at `root`, if it has rows.
+    pub fn max_rowid(&mut self, root: u32) -> Result<Option<i64>> {
+        let mut path = vec![root];
+        let mut node = self.read_node(root)?;
+        while node.kind == INTERIOR_TABLE {
+            check_cycle(&path, node.right)?;
+            path.push(node.right);
+            node = self.read_node(node.right)?;
+        }
+        Ok(node.cells.last().map(|cell| node.key(cell)))
+    }
+
+    /// Every row of the table B-tree at `root` as its rowid and payload.
+    pub fn rows(&mut self, root: u32) -> Result<Vec<(i64, Vec<u8>)>> {
+        let mut rows = vec![];
+        self.collect_rows(root, &mut vec![root], &mut rows)?;
+        Ok(rows)
+    }
+
+    /// Adds the rows of the subtree at the last page of `path` to `rows`.
+    fn collect_rows(
+        &mut self,
+        number: u32,
+        path: &mut Vec<u32>,
+        rows: &mut Vec<(i64, Vec<u8>)>,
+    ) -> Result<()> {
+        let node = self.read_node(number)?;
+        if node.kind == INTERIOR_TABLE {
+            let children = node.cells.iter().map(|cell| left_child(cell));
+            for child in children.chain([node.right]).collect::<Vec<_>>() {
+                check_cycle(path, child)?;
+                path.push(child);
+                self.collect_rows(child, path, rows)?;
+                path.pop();
+            }
+            return Ok(());
+        }
+        for cell in &node.cells {
+            let (size, len) = read_varint(cell);
+            let (rowid, rowid_len) = read_varint(&cell[len..]);
+            let start = len + rowid_len;
+            let local = local_size(size as usize, self.usable_size);
+            let mut payload = cell[start..start + local].to_vec();
+            let mut next = match local < size as usize {
+                true => left_child(&cell[start + local..]),
+                false => 0,
+            };
+            while next != 0 && payload.len() < size as usize {
+                let remaining = size as usize - payload.len();
+                let usable_size = self.usable_size;
+                let page = self.page(next)?;
+                let chunk = remaining.min(usable_size - 4);
+                payload.extend_from_slice(&page[4..4 + chunk]);
+                next = left_child(page);
+            }
+            rows.push((rowid as i64, payload));
+        }
+        Ok(())
+    }
+
+    /// Inserts a row into the table B-tree at `root`. With `replace` it
+    /// takes the place of the row with the same rowid, whose overflow pages
+    /// if any are left unused; otherwise such a row leaves the tree as it is
+    /// and false is returned.
+    pub fn insert(&mut self, root: u32, rowid: i64, payload: &[u8], replace: bool) -> Result<bool> {
+        // the interior pages down to the leaf, with the child taken in each
+        let mut path = vec![];
+        let mut number = root;
+        let mut node = self.read_node(number)?;
+        while node.kind == INTERIOR_TABLE {
+            let i = node.cells.partition_point(|cell| node.key(cell) < rowid);
+            let child = node
+                .cells
+                .get(i)
+                .map_or(node.right, |cell| left_child(cell));
+            path.push((number, node, i));
+            if path.iter().any(|(number, ..)| *number == child) {
+                return Err(Error::corrupt(child as usize, "cycle"));
+            }
+            number = child;
+            node = self.read_node(number)?;
+        }
+
+        let i = node.cells.partition_point(|cell| node.key(cell) < rowid);
+        let exists = node
+            .cells
+            .get(i)
+            .is_some_and(|cell| node.key(cell) == rowid);
+        if exists && !replace {
+            return Ok(false);
+        }
+        let cell = self.leaf_cell(rowid, payload)?;
+        match exists {
+            true => node.cells[i] = cell,
+            false => node.cells.insert(i, cell),
+        }
+        self.store(number, node, path)?;
+        Ok(true)
+    }
+
+    /// Writes `node` to page `number`, splitting it first if it doesn't
+    /// fit, and the interior pages of `path` above it in turn.
+    fn store(
+        &mut self,
+        mut number: u32,
+        mut node: Node,
+        mut path: Vec<(u32, Node, usize)>,
+    ) -> Result<()> {
+        while !self.fits(number, &node) {
+            let (parent_number, mut parent, i) = match path.pop() {
+                Some(parent) => parent,
+                None => {
+                    // the root keeps its page: its cells move to a new
+                    // child, which is split below it
+                    let child = self.allocate();
+                    let root = Node {
+                        kind: INTERIOR_TABLE,
+                        cells: vec![],
+                        right: child,
+                    };
+                    let root_number = number;
+                    number = child;
+                    (root_number, root, 0)
+                }
+            };
+
+            let capacity = self.usable_size - node.header_size();
+            let mut groups = vec![];
+            let mut last = vec![];
+            let mut used = 0;
+            for cell in node.cells.drain(..) {
+                if used + cell.len() + 2 > capacity && !last.is_empty() {
+                    groups.push(std::mem::take(&mut last));
+                    used = 0;
+                }
+                used += cell.len() + 2;
+                last.push(cell);
+            }
+
+            // each group but the last goes to a new page, whose largest key
+            // is added to the parent
+            let mut dividers = vec![];
+            for mut cells in groups {
+                let page = self.allocate();
+                let divider = match node.kind {
+                    INTERIOR_TABLE => {
+                        // the last cell's child becomes the right-most one
+                        let cell = cells.pop().unwrap_or_default();
+                        let key = node.key(&cell);
+                        self.write_node(
+                            page,
+                            &Node {
+                                kind: INTERIOR_TABLE,
+                                cells,
+                                right: left_child(&cell),
+                            },
+                        )?;
+                        key
+                    }
+                    _ => {
+                        let key = cells.last().map_or(0, |cell| node.key(cell));
+                        self.write_node(page, &Node::leaf(cells))?;
+                        key
+                    }
+                };
+                dividers.push(interior_cell(page, divider));
+            }
+            node.cells = last;
+            self.write_node(number, &node)?;
+
+            parent.cells.splice(i..i, dividers);
+            number = parent_number;
+            node = parent;
+        }
+        self.write_node(number, &node)?;
+        // the pages above are unchanged
+        Ok(())
+    }
+
+    /// Marks the change as one to the schema, so that connections reading
+    /// it know to read the schema again.
+    pub fn change_schema(&mut self) {
+        self.schema_changed = true;
+    }
+
+    /// Writes the pages and the header: the change counter, the page count
+    /// and, when the schema changed, the schema cookie. The pages the file
+    /// already had are journaled first, and the change is committed once
+    /// the journal is deleted.
+    pub fn commit(mut self) -> Result<()> {
+        let page_count = self.page_count;
+        let schema_changed = self.schema_changed;
+        let first = self.page(1)?;
+        let counter = u32::from_be_bytes([first[24], first[25], first[26], first[27]]);
+        let counter = counter.wrapping_add(1).to_be_bytes();
+        first[24..28].copy_from_slice(&counter);
+        first[28..32].copy_from_slice(&page_count.to_be_bytes());
+        first[92..96].copy_from_slice(&counter);
+        if schema_changed {
+            let cookie = u32::from_be_bytes([first[40], first[41], first[42], first[43]]);
+            first[40..44].copy_from_slice(&cookie.wrapping_add(1).to_be_bytes());
+        }
+
+        let journaled: Vec<u32> = self
+            .pages
+            .keys()
+            .copied()
+            .take_while(|number| *number <= self.original_count)
+            .collect();
+        journal::write(
+            &self.path,
+            &mut self.file,
+            self.page_size,
+            self.original_count,
+            &journaled,
+        )?;
+        for (number, page) in &self.pages {
+            let offset = (*number as u64 - 1) * self.page_size as u64;
+            self.file.seek(SeekFrom::Start(offset))?;
+            self.file.write_all(page)?;
+        }
+        self.file.sync_all()?;
+        journal::delete(&self.path)
+    }
+}
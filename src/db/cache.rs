@@ -0,0 +1,95 @@
+//! The pages a statement has read, kept in memory so that a page read
+//! again, such as the interior pages of a table that each rowid found by an
+//! index search is looked up through, doesn't go back to the file. Like the
+//! stats, the cache is kept per thread since pages are read without a
+//! handle on the connection, and it's emptied as each statement starts so
+//! that it never serves pages from before a change to the file.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::rc::Rc;
+use std::sync::{Arc, Weak};
+
+use crate::error::Result;
+
+/// Pages kept at most; the one cached first makes room for the next.
+const CAPACITY: usize = 2000;
+
+/// The address of a file and a page number in it.
+type Key = (usize, usize);
+
+struct Entry {
+    page: Rc<[u8]>,
+    /// Keeps the address of the file from being reused by another one
+    /// while its pages are cached, without keeping it open.
+    _file: Weak<File>,
+}
+
+#[derive(Default)]
+struct Cache {
+    pages: HashMap<Key, Entry>,
+    order: VecDeque<Key>,
+}
+
+thread_local! {
+    static CACHE: RefCell<Cache> = RefCell::default();
+}
+
+/// The bytes of page `number` of `file`, fewer at the end of the file, and
+/// whether they were cached rather than read.
+pub(crate) fn page(file: &Arc<File>, number: usize, page_size: usize) -> Result<(Rc<[u8]>, bool)> {
+    let key = (Arc::as_ptr(file) as usize, number);
+    let cached = CACHE.with_borrow(|cache| cache.pages.get(&key).map(|entry| entry.page.clone()));
+    if let Some(page) = cached {
+        return Ok((page, true));
+    }
+    let mut page = vec![];
+    let mut reader = &**file;
+    reader.seek(SeekFrom::Start((number as u64 - 1) * page_size as u64))?;
+    reader.take(page_size as u64).read_to_end(&mut page)?;
+    let page = Rc::<[u8]>::from(page);
+    CACHE.with_borrow_mut(|cache| {
+        if cache.order.len() == CAPACITY {
+            if let Some(first) = cache.order.pop_front() {
+                cache.pages.remove(&first);
+            }
+        }
+        cache.order.push_back(key);
+        let entry = Entry {
+            page: page.clone(),
+            _file: Arc::downgrade(file),
+        };
+        cache.pages.insert(key, entry);
+    });
+    Ok((page, false))
+}
+
+/// Drops every cached page, as a statement starts.
+pub(crate) fn clear() {
+    CACHE.with_borrow_mut(|cache| *cache = Cache::default());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page() {
+        clear();
+        let file = Arc::new(File::open("sample.db").unwrap());
+        let (first, cached) = page(&file, 2, 4096).unwrap();
+        assert_eq!((first.len(), cached), (4096, false));
+        let (again, cached) = page(&file, 2, 4096).unwrap();
+        assert!(cached && Rc::ptr_eq(&first, &again));
+
+        // another handle on the file has pages of its own
+        let other = Arc::new(File::open("sample.db").unwrap());
+        assert!(!page(&other, 2, 4096).unwrap().1);
+        // past the end of the file
+        assert!(page(&file, 1000, 4096).unwrap().0.is_empty());
+        clear();
+        assert!(!page(&file, 2, 4096).unwrap().1);
+    }
+}
@@ -1,5 +1,7 @@
 use crate::db::header::DatabaseHeader;
 use crate::db::page::Page;
+use crate::db::record::TextEncoding;
+use crate::error::Result;
 use std::cell::{RefCell, RefMut};
 use std::fs::File;
 use std::sync::Arc;
@@ -13,10 +15,10 @@ pub struct Db {
 }
 
 impl Db {
-    pub fn new(db_path: String) -> Self {
-        let mut file = Arc::new(File::open(db_path).expect("Unable to open file"));
+    pub fn new(db_path: String) -> Result<Self> {
+        let mut file = Arc::new(File::open(db_path)?);
         let page_number = 1usize;
-        let header = Arc::new(DatabaseHeader::new_(&mut file));
+        let header = Arc::new(DatabaseHeader::new_(&mut file)?);
         let page_size = header.page_info().0;
 
         // read head
@@ -24,15 +26,20 @@ impl Db {
             &mut file.clone(),
             page_number,
             page_size as usize,
-        )));
+            header.text_encoding(),
+        )?));
         let pages = Arc::new(vec![]);
 
-        Self {
+        Ok(Self {
             file,
             header,
             schema_page,
             pages,
-        }
+        })
+    }
+
+    pub fn text_encoding(&self) -> TextEncoding {
+        self.header.text_encoding()
     }
 
     pub fn get_page_size(&self) -> usize {
@@ -52,10 +59,11 @@ impl Db {
     }
 
     #[allow(dead_code)]
-    pub fn display_columns(&mut self, _columns: &[&str], table_name: String) {
+    pub fn display_columns(&mut self, _columns: &[&str], table_name: String) -> Result<()> {
         self.schema_page
             .borrow_mut()
-            .get_table_data(&mut self.file, table_name);
+            .get_table_data(&mut self.file, table_name)?;
+        Ok(())
     }
 
     #[allow(dead_code)]
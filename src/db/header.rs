@@ -1,12 +1,15 @@
 use std::io::*;
-use std::str;
 
 use std::fs::File;
 use std::sync::Arc;
 
+use crate::db::record::TextEncoding;
+use crate::error::{Error, Result};
+
 pub struct DatabaseHeader {
-    header_str: String, // 16 bytes
-    page_size: u16,     // 2 bytes offset 16
+    header_str: String,          // 16 bytes
+    page_size: u16,              // 2 bytes offset 16
+    text_encoding: TextEncoding, // 4 bytes offset 56
 }
 
 pub const HEADER_BYTES_SIZE: u8 = 100;
@@ -15,57 +18,91 @@ impl DatabaseHeader {
     pub const SIZE_HEAD_STR: u8 = 15; //Bytes
     pub const SIZE_PAGE_SIZE: u8 = 2; //Bytes
     pub const OFFSET_PAGE_SIZE: u8 = 16;
+    pub const SIZE_TEXT_ENCODING: u8 = 4; //Bytes
+    pub const OFFSET_TEXT_ENCODING: u8 = 56;
 
     #[allow(dead_code)]
-    pub fn new(database_header: &[u8]) -> Self {
-        let header_str = str::from_utf8(&database_header[0..15])
-            .expect("AN ISSUE HAPPENED")
-            .to_string();
+    pub fn new(database_header: &[u8]) -> Result<Self> {
+        if database_header.len() < HEADER_BYTES_SIZE as usize {
+            return Err(Error::corrupt(1, "file is not a database"));
+        }
+        let header_str = String::from_utf8_lossy(&database_header[0..15]).to_string();
 
         let page_size: u16 = u16::from_be_bytes([database_header[16], database_header[17]]);
-        Self {
-            header_str,
-            page_size,
-        }
+        let text_encoding = TextEncoding::from_header(u32::from_be_bytes([
+            database_header[56],
+            database_header[57],
+            database_header[58],
+            database_header[59],
+        ]));
+        DatabaseHeader::validate(header_str, page_size, text_encoding)
     }
 
-    pub fn new_(file: &mut Arc<File>) -> Self {
-        let header_str = str::from_utf8(&DatabaseHeader::buffer_read(
-            DatabaseHeader::SIZE_HEAD_STR as usize,
-            0,
-            file,
-        ))
-        .expect("AN ISSUE HAPPENED")
-        .to_string();
+    pub fn new_(file: &mut Arc<File>) -> Result<Self> {
+        // a file shorter than the header can't be a database
+        let buff_header_str =
+            DatabaseHeader::buffer_read(DatabaseHeader::SIZE_HEAD_STR as usize, 0, file)
+                .map_err(|_| Error::corrupt(1, "file is not a database"))?;
+        let header_str = String::from_utf8_lossy(&buff_header_str).to_string();
 
         let buff_page_size: Vec<u8> = DatabaseHeader::buffer_read(
             DatabaseHeader::SIZE_PAGE_SIZE as usize,
             DatabaseHeader::OFFSET_PAGE_SIZE as usize,
             file,
-        );
+        )?;
         let page_size: u16 = u16::from_be_bytes([buff_page_size[0], buff_page_size[1]]);
 
-        Self {
+        let buff_text_encoding: Vec<u8> = DatabaseHeader::buffer_read(
+            DatabaseHeader::SIZE_TEXT_ENCODING as usize,
+            DatabaseHeader::OFFSET_TEXT_ENCODING as usize,
+            file,
+        )?;
+        let text_encoding = TextEncoding::from_header(u32::from_be_bytes([
+            buff_text_encoding[0],
+            buff_text_encoding[1],
+            buff_text_encoding[2],
+            buff_text_encoding[3],
+        ]));
+
+        DatabaseHeader::validate(header_str, page_size, text_encoding)
+    }
+
+    /// Checks the magic string and the page size, a power of two from 512
+    /// to 32768. The value 1, meaning 65536, doesn't fit the u16 page size.
+    fn validate(header_str: String, page_size: u16, text_encoding: TextEncoding) -> Result<Self> {
+        if header_str != "SQLite format 3" {
+            return Err(Error::corrupt(1, "file is not a database"));
+        }
+        if page_size == 1 {
+            return Err(Error::Unsupported("page size of 65536 bytes".to_string()));
+        }
+        if page_size < 512 || !page_size.is_power_of_two() {
+            return Err(Error::corrupt(1, format!("invalid page size {page_size}")));
+        }
+        Ok(Self {
             header_str,
             page_size,
-        }
+            text_encoding,
+        })
     }
 
-    fn buffer_read(buff_size: usize, offset: usize, file: &mut Arc<File>) -> Vec<u8> {
+    fn buffer_read(buff_size: usize, offset: usize, file: &mut Arc<File>) -> Result<Vec<u8>> {
         let mut buff = vec![0; buff_size];
 
-        file.seek(SeekFrom::Start(offset as u64))
-            .expect("seek failed from buffer_read() ");
+        file.seek(SeekFrom::Start(offset as u64))?;
 
-        file.read_exact(&mut buff)
-            .expect("read_exact failed from buffer_read() ");
+        file.read_exact(&mut buff)?;
 
-        buff
+        Ok(buff)
     }
 
     pub fn page_info(&self) -> (u16, String) {
         (self.page_size, self.header_str.to_string())
     }
+
+    pub fn text_encoding(&self) -> TextEncoding {
+        self.text_encoding
+    }
 }
 
 #[cfg(test)]
@@ -74,12 +111,32 @@ mod tests {
     #[test]
     fn test_header_info() {
         let file = Arc::new(
-            File::open("/Users/ollaid/codecrafters/codecrafters-sqlite-rust/sample.db")
+            File::open("sample.db")
                 .expect("FAILED TO OPEN"),
         );
-        let dbheader: DatabaseHeader = DatabaseHeader::new_(&mut file.clone());
+        let dbheader: DatabaseHeader = DatabaseHeader::new_(&mut file.clone()).unwrap();
         let (page_size, string_header) = dbheader.page_info();
         assert_eq!(string_header, "SQLite format 3");
         assert_eq!(page_size, 4096);
+        assert_eq!(dbheader.text_encoding(), TextEncoding::Utf8);
+    }
+
+    #[test]
+    fn test_header_validation() {
+        let mut header = vec![0u8; 100];
+        header[..16].copy_from_slice(b"SQLite format 3\0");
+        header[16..18].copy_from_slice(&4096u16.to_be_bytes());
+        assert!(DatabaseHeader::new(&header).is_ok());
+        assert!(DatabaseHeader::new(&header[..50]).is_err());
+
+        header[16..18].copy_from_slice(&1000u16.to_be_bytes());
+        assert!(matches!(
+            DatabaseHeader::new(&header),
+            Err(Error::Corrupt { page: 1, .. })
+        ));
+
+        header[16..18].copy_from_slice(&4096u16.to_be_bytes());
+        header[..6].copy_from_slice(b"MySQL ");
+        assert!(DatabaseHeader::new(&header).is_err());
     }
 }
@@ -0,0 +1,255 @@
+//! The rollback journal and the file lock that keep a change safe from
+//! crashes and from other connections. Both follow SQLite's formats, so
+//! either can undo a change the other didn't complete.
+//!
+//! Before any page of the database is overwritten, its original content is
+//! written to `<database>-journal` and synced. Deleting the journal commits
+//! the change. A journal that is still there when a connection opens the
+//! database or the next change starts is hot: its pages are written back,
+//! undoing the change that was cut short. Only writing takes a lock.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::Result;
+
+const MAGIC: [u8; 8] = [0xd9, 0xd5, 0x05, 0xf9, 0x20, 0xa1, 0x63, 0xd7];
+/// Size of the journal header, padded to a disk sector.
+const SECTOR_SIZE: usize = 512;
+
+/// `<database>-journal`, next to the database.
+fn journal_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push("-journal");
+    PathBuf::from(name)
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Sum of every 200th byte of `page` from the end, the first excluded,
+/// starting from the random `nonce` of the journal.
+fn checksum(nonce: u32, page: &[u8]) -> u32 {
+    (200..page.len()).step_by(200).fold(nonce, |sum, back| {
+        sum.wrapping_add(page[page.len() - back] as u32)
+    })
+}
+
+/// Writes the journal of the database at `path` and syncs it: the content
+/// of `pages` as they are in `file`, which has `page_count` pages.
+pub fn write(
+    path: &Path,
+    file: &mut File,
+    page_size: usize,
+    page_count: u32,
+    pages: &[u32],
+) -> Result<()> {
+    let nonce = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.subsec_nanos());
+    let mut header = MAGIC.to_vec();
+    for field in [
+        pages.len() as u32,
+        nonce,
+        page_count,
+        SECTOR_SIZE as u32,
+        page_size as u32,
+    ] {
+        header.extend_from_slice(&field.to_be_bytes());
+    }
+    header.resize(SECTOR_SIZE, 0);
+
+    let journal = File::create(journal_path(path))?;
+    let mut out = BufWriter::new(&journal);
+    out.write_all(&header)?;
+    let mut page = vec![0; page_size];
+    for &number in pages {
+        file.seek(SeekFrom::Start((number as u64 - 1) * page_size as u64))?;
+        io::Read::read_exact(file, &mut page)?;
+        out.write_all(&number.to_be_bytes())?;
+        out.write_all(&page)?;
+        out.write_all(&checksum(nonce, &page).to_be_bytes())?;
+    }
+    out.flush()?;
+    drop(out);
+    journal.sync_all()?;
+    Ok(())
+}
+
+/// Deletes the journal of the database at `path`, which commits the change.
+pub fn delete(path: &Path) -> Result<()> {
+    Ok(fs::remove_file(journal_path(path))?)
+}
+
+/// Undoes the change a hot journal of the database at `path` was kept for:
+/// writes its pages back to `file`, truncates the file to its size before
+/// the change and deletes the journal. The journal may have several
+/// segments, each with a header starting a sector, as SQLite starts a new
+/// one whenever it syncs part way through a change. Pages from the first
+/// whose checksum doesn't match were never synced, and are left out. A
+/// journal without a valid header has nothing to undo.
+pub fn roll_back(path: &Path, file: &mut File) -> Result<()> {
+    let journal = match fs::read(journal_path(path)) {
+        Ok(journal) => journal,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    let valid =
+        |size: usize, range: RangeInclusive<usize>| size.is_power_of_two() && range.contains(&size);
+    // the page count and size from the first header, before the change
+    let mut original = None;
+    let mut offset = 0;
+    'segments: while let Some(header) = journal.get(offset..offset + 28) {
+        let sector_size = read_u32(&header[20..]) as usize;
+        if header[..8] != MAGIC || !valid(sector_size, 32..=65536) {
+            break;
+        }
+        let (page_count, page_size) = match original {
+            Some(original) => original,
+            None => match read_u32(&header[24..]) as usize {
+                size if valid(size, 512..=65536) => {
+                    *original.insert((read_u32(&header[16..]), size))
+                }
+                _ => break,
+            },
+        };
+        let nonce = read_u32(&header[12..]);
+        offset += sector_size;
+        let records = journal.get(offset..).unwrap_or_default();
+        let records = records.chunks_exact(page_size + 8);
+        // a count not written yet covers the rest of the file
+        let count = match read_u32(&header[8..]) {
+            u32::MAX => records.len(),
+            count => count as usize,
+        };
+        for record in records.take(count) {
+            let number = read_u32(record);
+            let page = &record[4..4 + page_size];
+            if checksum(nonce, page) != read_u32(&record[4 + page_size..]) {
+                break 'segments;
+            }
+            if (1..=page_count).contains(&number) {
+                file.seek(SeekFrom::Start((number as u64 - 1) * page_size as u64))?;
+                file.write_all(page)?;
+            }
+            offset += page_size + 8;
+        }
+        offset = offset.next_multiple_of(sector_size);
+    }
+    if let Some((page_count, page_size)) = original {
+        file.set_len(page_count as u64 * page_size as u64)?;
+        file.sync_all()?;
+    }
+    delete(path)
+}
+
+/// Locks the database at `path` and rolls back its hot journal, if it has
+/// one, so that it can be read.
+pub fn recover(path: &Path) -> Result<()> {
+    if !journal_path(path).exists() {
+        return Ok(());
+    }
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    lock(&file)?;
+    roll_back(path, &mut file)
+}
+
+/// Where SQLite's lock bytes are in the file: PENDING, RESERVED and then
+/// the 510 bytes readers take their SHARED lock on.
+#[cfg(unix)]
+const LOCK_BYTES: (i64, i64) = (0x4000_0000, 512);
+
+/// Takes the EXCLUSIVE lock SQLite writers take, a write lock on all of its
+/// lock bytes, until `file` is closed. Fails with `Error::Busy` while
+/// another connection holds a lock on the database.
+#[cfg(unix)]
+pub fn lock(file: &File) -> Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    // SAFETY: flock is a plain C struct, valid when zeroed
+    let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+    lock.l_type = libc::F_WRLCK as _;
+    lock.l_whence = libc::SEEK_SET as _;
+    lock.l_start = LOCK_BYTES.0 as _;
+    lock.l_len = LOCK_BYTES.1 as _;
+    // SAFETY: F_SETLK only reads the flock it's given
+    match unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETLK, &lock) } {
+        -1 => match io::Error::last_os_error() {
+            e if matches!(e.raw_os_error(), Some(libc::EAGAIN | libc::EACCES)) => {
+                Err(crate::error::Error::Busy)
+            }
+            e => Err(e.into()),
+        },
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+pub fn lock(_file: &File) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roll_back() {
+        let path = std::env::temp_dir().join("codecrafters_sqlite_journal.db");
+        std::fs::copy("sample.db", &path).unwrap();
+        let original = std::fs::read(&path).unwrap();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .unwrap();
+        let page_count = (original.len() / 4096) as u32;
+        write(&path, &mut file, 4096, page_count, &[1, 3]).unwrap();
+
+        // a change cut short: page 3 overwritten and a page added
+        file.seek(SeekFrom::Start(2 * 4096)).unwrap();
+        file.write_all(&[0xff; 4096]).unwrap();
+        file.seek(SeekFrom::End(0)).unwrap();
+        file.write_all(&[0; 4096]).unwrap();
+        roll_back(&path, &mut file).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), original);
+        assert!(!journal_path(&path).exists());
+
+        // a segment for each sync, the second starting a sector
+        write(&path, &mut file, 4096, page_count, &[3]).unwrap();
+        let second = std::fs::read(journal_path(&path)).unwrap();
+        write(&path, &mut file, 4096, page_count, &[1]).unwrap();
+        let mut journal = std::fs::read(journal_path(&path)).unwrap();
+        journal.resize(journal.len().next_multiple_of(SECTOR_SIZE), 0);
+        journal.extend_from_slice(&second);
+        std::fs::write(journal_path(&path), journal).unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.write_all(&[0xff; 4096]).unwrap();
+        file.seek(SeekFrom::Start(2 * 4096)).unwrap();
+        file.write_all(&[0xff; 4096]).unwrap();
+        roll_back(&path, &mut file).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), original);
+
+        // nothing to undo without a journal, or with one of another format
+        roll_back(&path, &mut file).unwrap();
+        std::fs::write(journal_path(&path), b"not a journal").unwrap();
+        roll_back(&path, &mut file).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), original);
+        assert!(!journal_path(&path).exists());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_checksum() {
+        let mut page = vec![0u8; 512];
+        page[312] = 3;
+        page[112] = 4;
+        // page[512 - 600] doesn't exist, and page[0] isn't summed
+        page[0] = 9;
+        assert_eq!(checksum(1, &page), 8);
+    }
+}
@@ -1,3 +1,7 @@
+pub mod btree;
+pub mod cache;
 pub mod db;
 pub mod header;
+pub mod journal;
 pub mod page;
+pub mod record;
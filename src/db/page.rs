@@ -1,31 +1,20 @@
+use crate::db::cache;
 use crate::db::header::HEADER_BYTES_SIZE;
-use anyhow::bail;
-use core::panic;
+use crate::db::record::{decode_record, read_varint, TextEncoding};
+use crate::error::{Error, Result};
+use crate::parser::parse_create_table;
+use crate::sql::collation::Collation;
+use crate::sql::planner::KeyRange;
+use crate::sql::schema::{SchemaEntry, TableSchema};
+use crate::sql::value::Value;
+use crate::stats;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fs::File;
-use std::io::prelude::*;
+use std::ops::Bound;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
-const OFFSET: usize = 100;
-#[derive(Debug, Copy, Clone, Default)]
-#[allow(dead_code)]
-enum RecordFieldType {
-    #[default]
-    Null,
-    I8,
-    I16,
-    I24,
-    I32,
-    I48,
-    I64,
-    Float,
-    Zero,
-    One,
-    STRING(usize),
-    BLOB(usize),
-}
-
 #[derive(Debug, Clone, PartialEq)]
 enum PageType {
     LEAFINDEX,
@@ -36,6 +25,11 @@ enum PageType {
 }
 
 type Rows = HashMap<String, Arc<(usize, RefCell<Page>)>>;
+/// A row as column name and value text pairs.
+type TextRow = Vec<(String, String)>;
+/// Rows of a table as column name and value pairs, read from the file as
+/// they are iterated.
+pub type RowIter = Box<dyn Iterator<Item = Result<Vec<(String, Value)>>>>;
 
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
@@ -45,8 +39,12 @@ pub struct Page {
     table_count: u16,       // two bytes
     cell_content_area: u16, // two bytess
     rows: Rows,
+    /// The rows of the schema table in order; only on the schema page.
+    entries: Vec<SchemaEntry>,
     sql_schema: String,
     right_page_number: u32,
+    page_size: usize,
+    encoding: TextEncoding,
 }
 
 impl Page {
@@ -54,13 +52,11 @@ impl Page {
         file: &mut Arc<File>,
         page_number: usize,
         page_size: usize,
+        encoding: TextEncoding,
         sql_schema: String,
-    ) -> Self {
-        let mut database_page = vec![0; page_size];
-        let page_offset = Page::get_offset_page(page_number, page_size);
-        file.seek(std::io::SeekFrom::Start(page_offset as u64))
-            .expect("SEEK_FILED!!");
-        file.read_exact(&mut database_page).expect("READ FAILED!!");
+    ) -> Result<Self> {
+        let database_page = Page::read_page(file, page_number, page_size)?;
+        stats::record(|stats| stats.pages_read += 1);
 
         let page_type = Page::get_page_type(database_page[0]);
         let right_page_number = match page_type {
@@ -73,28 +69,32 @@ impl Page {
             _ => 0u32,
         };
 
-        Self {
+        Ok(Self {
             offset: Page::get_offset_page(page_number, page_size), // offset default to schema page
             type_page: page_type,
             table_count: u16::from_be_bytes([database_page[3], database_page[4]]),
             cell_content_area: u16::from_be_bytes([database_page[5], database_page[6]]),
             rows: HashMap::default(),
+            entries: vec![],
             sql_schema: sql_schema,
             right_page_number: right_page_number,
-        }
+            page_size,
+            encoding,
+        })
     }
     /*
 
 
     */
-    pub fn new__(file: &mut Arc<File>, page_number: usize, page_size: usize) -> Self {
-        assert!(page_number >= 1);
+    pub fn new__(
+        file: &mut Arc<File>,
+        page_number: usize,
+        page_size: usize,
+        encoding: TextEncoding,
+    ) -> Result<Self> {
+        let database_page = Page::read_page(file, page_number, page_size)?;
+        stats::record(|stats| stats.pages_read += 1);
         let offset_page = Page::get_offset_page(page_number, page_size);
-        file.seek(std::io::SeekFrom::Start(offset_page as u64))
-            .expect("SEEK FAILED!!");
-        let mut database_page = vec![0u8; page_size as usize];
-        file.read_exact(&mut database_page[..])
-            .expect("BUFFER READ FAILED!!");
 
         let page_type = Page::get_page_type(database_page[0]);
         let right_page_number = match page_type {
@@ -113,53 +113,99 @@ impl Page {
             table_count: u16::from_be_bytes([database_page[3], database_page[4]]),
             cell_content_area: u16::from_be_bytes([database_page[5], database_page[6]]),
             rows: HashMap::default(), // rows
+            entries: vec![],
             sql_schema: String::default(),
             right_page_number,
+            page_size,
+            encoding,
         }
         .fill_cell_vec(file, page_size)
     }
 
-    #[allow(dead_code)]
-    pub fn get_rows_colum_names(&self, _table_name: String, schema: bool) -> Vec<Vec<String>> {
-        match self.sql_schema.eq("") {
-            true => match schema {
-                true => {
-                    vec![
-                        vec!["type".into(), "text".into()],
-                        vec!["name".into(), "text".into()],
-                        vec!["tbl_name".into(), "text".into()],
-                        vec!["rootpage".into(), "integer".into()],
-                        vec!["sql".into(), "text".into()],
-                    ]
-                }
-                _ => {
-                    let page = self
-                        .rows
-                        .get(&_table_name)
-                        .expect("Page::get_rows_colum_names():GET TABLE NAME FAILED!!");
-                    page.1.borrow().get_rows_colum_names(_table_name, false)
-                }
-            },
-            _ => self
-                .sql_schema
-                .split("(")
-                .map(|c| c.to_string())
-                .last()
-                .unwrap()
-                .replace(")", "")
-                .replace("\n", "")
-                .replace("\t", "")
-                .trim()
-                .split(",")
-                .map(|s| s.trim().to_string())
-                .map(|s| {
-                    s.split(" ")
-                        .map(|s| s.trim().to_string())
-                        .collect::<Vec<String>>()
-                })
-                .collect::<Vec<Vec<String>>>(),
+    /// A schema object without a B-tree of its own, such as a view.
+    fn rootless(page_size: usize, encoding: TextEncoding, sql_schema: String) -> Self {
+        Self {
+            offset: 0,
+            type_page: PageType::UNKNOWNTYPE,
+            table_count: 0,
+            cell_content_area: 0,
+            rows: HashMap::default(),
+            entries: vec![],
+            sql_schema,
+            right_page_number: 0,
+            page_size,
+            encoding,
+        }
+    }
+
+    /// The bytes of page `page_number`, starting with its B-tree header
+    /// (after the database header on page 1), from the page cache if it's
+    /// there. Fails on a page number outside the file or a page that isn't
+    /// a B-tree page.
+    fn read_page(file: &mut Arc<File>, page_number: usize, page_size: usize) -> Result<Vec<u8>> {
+        if page_number == 0 {
+            return Err(Error::corrupt(0, "invalid page number"));
+        }
+        let (page, cached) = cache::page(file, page_number, page_size)?;
+        stats::record(|stats| match cached {
+            true => stats.cache_hits += 1,
+            _ => stats.cache_misses += 1,
+        });
+        if page.len() < page_size {
+            return Err(Error::corrupt(page_number, "page past the end of the file"));
+        }
+        let offset = Page::get_offset_page(page_number, page_size);
+        let database_page = page[offset % page_size..].to_vec();
+        match Page::get_page_type(database_page[0]) {
+            PageType::UNKNOWNTYPE => Err(Error::corrupt(
+                page_number,
+                format!("invalid page type {:#04x}", database_page[0]),
+            )),
+            _ => Ok(database_page),
+        }
+    }
+
+    /// Number of this page in the file, for error messages.
+    fn page_number(&self) -> usize {
+        match self.offset == HEADER_BYTES_SIZE as usize {
+            true => 1,
+            _ => self.offset / self.page_size + 1,
+        }
+    }
+
+    /// Definition of the table whose B-tree this page belongs to; the
+    /// schema table for the schema page itself.
+    fn own_table_schema(&self) -> Result<TableSchema> {
+        match self.sql_schema.is_empty() {
+            true => Ok(TableSchema::sqlite_schema()),
+            _ => parse_create_table(&self.sql_schema),
+        }
+    }
+
+    /// Parsed `CREATE TABLE` statement of `table_name`. Only meaningful on
+    /// the schema page.
+    pub fn table_schema(&self, table_name: &str) -> Result<TableSchema> {
+        let sql = match self.rows.get(table_name) {
+            Some(table_page) => table_page.1.borrow().sql_schema.clone(),
+            None => return Err(Error::NoSuchTable(table_name.to_string())),
+        };
+        let words = sql
+            .split_whitespace()
+            .take(3)
+            .map(|word| word.to_ascii_uppercase())
+            .collect::<Vec<_>>();
+        let word = |w: &str| words.iter().any(|word| word == w);
+        match (word("TABLE"), word("VIRTUAL"), word("VIEW")) {
+            (true, true, _) => Err(Error::Unsupported(format!(
+                "reading from virtual table {table_name}"
+            ))),
+            (true, _, _) => parse_create_table(&sql),
+            (_, _, true) => Err(Error::Unsupported(format!(
+                "reading from view {table_name}"
+            ))),
+            // indexes and triggers
+            _ => Err(Error::NoSuchTable(table_name.to_string())),
         }
-        // THIS ONLY WORK FOR NON SCHEMA PAGES
     }
 
     fn get_page_type(byte: u8) -> PageType {
@@ -172,178 +218,65 @@ impl Page {
         }
     }
 
-    fn add_page(&mut self, file: &mut Arc<File>, row_offset: u16, page_size: usize) -> () {
-        let row_data = self.parse_row_data(row_offset as u64, " ".into(), file, true);
-        //println!("add_page()::page:{:?}", row_data);
-        let table_number = row_data
-            .iter()
-            .find(|col| col.0.eq("rootpage"))
-            .unwrap()
-            .1
-            .chars()
-            //.inspect(|c| println!("ABOUT TO FILTER: {:?}", c))
-            .filter(|c| c.is_ascii_digit())
-            .collect::<String>();
-
-        //println!("add_page():: TABLE_NUMBER::{:?}", table_number);
-        let table_number = &table_number.parse::<usize>().unwrap();
+    fn add_page(
+        &mut self,
+        file: &mut Arc<File>,
+        row_data: Vec<(String, Value)>,
+        page_size: usize,
+    ) -> Result<()> {
+        let column = |name: &str| {
+            row_data
+                .iter()
+                .find(|col| col.0.eq(name))
+                .map(|col| col.1.clone())
+                .unwrap_or_default()
+        };
 
-        let table_name = row_data
-            .iter()
-            .find(|col| col.0.eq("name"))
-            .unwrap()
-            .1
-            .clone();
+        let table_number = match column("rootpage") {
+            Value::Integer(n) if n >= 0 => n as usize,
+            other => {
+                return Err(Error::corrupt(
+                    self.page_number(),
+                    format!("invalid root page {other:?}"),
+                ))
+            }
+        };
+        let table_name = column("name").to_string();
+        let sql = column("sql").to_string();
+        self.entries.push(SchemaEntry::new(
+            column("type").to_string(),
+            table_name.clone(),
+            column("tbl_name").to_string(),
+            match column("sql") {
+                Value::Null => None,
+                _ => Some(sql.clone()),
+            },
+        ));
 
-        let sql = row_data
-            .iter()
-            .find(|col| col.0.eq("sql"))
-            .unwrap()
-            .1
-            .clone();
-        //println!("row_data---->: {:?}", row_data);
-
-        self.rows.entry(table_name).or_insert(Arc::new((
-            *table_number,
-            RefCell::new(Page::new_(file, *table_number, page_size, sql)),
-        )));
+        // views, triggers and virtual tables have no B-tree
+        let page = match table_number {
+            0 => Page::rootless(page_size, self.encoding, sql),
+            _ => Page::new_(file, table_number, page_size, self.encoding, sql)?,
+        };
+        self.rows
+            .entry(table_name)
+            .or_insert(Arc::new((table_number, RefCell::new(page))));
+        Ok(())
     }
 
-    pub fn fill_cell_vec(mut self, file: &mut Arc<File>, page_size: usize) -> Self {
-        let schema_header_offeset = 8;
-        let mut init_offset = OFFSET + schema_header_offeset;
-        let res = file.seek(std::io::SeekFrom::Start(init_offset as u64));
-        match res {
-            Ok(_res) => {
-                let mut i = 1;
-                let step = 2;
-
-                while i <= self.table_count {
-                    // iterate throught the cells of the schema table
-                    let mut buffer: [u8; 2] = [0; 2];
-                    file.read_exact(&mut buffer).expect("READ EXACT FAILED!!");
-                    let row_offset = u16::from_be_bytes([buffer[0], buffer[1]]);
-
-                    self.add_page(file, row_offset, page_size);
-                    // add the cells vector
-                    //seek to the row data using the the current
-                    //let offse_row_data = file.seek_relative(offset)
-                    init_offset = init_offset + step;
-
-                    file.seek(std::io::SeekFrom::Start(init_offset as u64))
-                        .expect("SEEK FAILED!!"); //
-
-                    i += 1;
-                }
-            }
-            Err(x) => {
-                println!("SOMTHING WENT WRONG!! {}", x)
-            }
+    pub fn fill_cell_vec(mut self, file: &mut Arc<File>, page_size: usize) -> Result<Self> {
+        // iterate throught the rows of the schema table
+        for row_data in self.parse_page(file, &Arc::default())? {
+            self.add_page(file, row_data?, page_size)?;
         }
 
-        //panic!("STOP!!");
-
-        self
-    }
-
-    pub fn display_cells(&self) {
-        let mut _res = self
-            .rows
-            .iter()
-            .filter_map(|c| match **c.0 != "sqlite_sequence".to_string() {
-                true => Some(c.clone().0.clone()),
-                _ => None,
-            })
-            .collect::<Vec<String>>();
-        _res.sort();
-        print!("{}", _res.join(" "));
+        Ok(self)
     }
 
     pub fn get_table_count(&self) -> u16 {
         self.table_count
     }
 
-    #[allow(dead_code)]
-    fn get_varint_buffer(file: &mut File, offset: usize, size: usize) -> Vec<u8> {
-        let mut buffer = vec![0; size];
-        let _ = file.seek(std::io::SeekFrom::Start(offset as u64));
-        file.read_exact(&mut buffer).expect("READ_FAILED");
-        buffer
-    }
-
-    fn decode_var_int(&self, offset: usize, file: &mut Arc<File>) -> Option<(Vec<u8>, usize)> {
-        file.seek(std::io::SeekFrom::Start(offset as u64))
-            .expect("SEEK FAILED!!");
-        let mut payload = vec![0; 9];
-        file.take(9)
-            .read(&mut payload)
-            .expect("READ FILED VARINT BUFFER");
-
-        let mut res = vec![];
-        let mut it = 0;
-        let mut value: u64 = 0;
-        let mut found = false;
-        while !found && it < 9 {
-            res.push(payload[it]);
-            // construct the final value by joining bits
-            let data_bits = (payload[it] & 0b0111_1111) as u64;
-            value = (value << 7) | data_bits;
-            // test if last bit is set
-            if !(payload[it] & 0b1000_0000 == 0b1000_0000) {
-                // test if last bit is set
-                found = true
-            }
-            it += 1;
-        }
-        Some((res, value as usize))
-    }
-
-    fn get_size_from_varint(&self, serialtype: usize) -> (RecordFieldType, usize) {
-        let (field_type, field_size) = match serialtype {
-            0 => (RecordFieldType::Null, 0),
-            1 => (RecordFieldType::I8, 1),
-            2 => (RecordFieldType::I16, 2),
-            3 => (RecordFieldType::I24, 3),
-            4 => (RecordFieldType::I32, 4),
-            5 => (RecordFieldType::I48, 6),
-            6 => (RecordFieldType::I64, 8),
-            7 => (RecordFieldType::Float, 8),
-            8 => (RecordFieldType::Zero, 0),
-            9 => (RecordFieldType::One, 0),
-            n if n >= 12 && n % 2 == 0 => {
-                let size = ((n - 12) / 2) as usize;
-                (RecordFieldType::BLOB(size), size)
-            }
-            n if n >= 13 && n % 2 == 1 => {
-                let size = ((n - 13) / 2) as usize;
-                (RecordFieldType::STRING(size), size)
-            }
-            _ => {
-                //
-                //println!("TYPE: {:?}", serialtype);
-                panic!("NOT SUPORTED TYPE")
-            }
-        };
-
-        (field_type, field_size)
-    }
-
-    fn read_bytes_to_utf8(&self, file: &mut Arc<File>, offset: usize, size: usize) -> String {
-        let mut buff = vec![0; size];
-        file.seek(std::io::SeekFrom::Start(offset as u64))
-            .expect("SEEK read_bytes() failed");
-        file.read_exact(&mut buff)
-            .expect("read_exact() from read_bytes() failed ");
-        //println!("READ EXACT: BUFF read_bytes_to_utf8:: {:x?}", buff);
-
-        let res = match buff.len() {
-            1  => u8::from_be(buff[0]).to_string(),
-            2 => u16::from_be_bytes([buff[0], buff[1]]).to_string(),
-            _ => String::from_utf8_lossy(&buff).to_string(),
-        };
-        res
-    }
-
     pub fn get_offset_page(page_number: usize, page_size: usize) -> usize {
         let offset_page = match page_number - 1 {
             0 => 0 + HEADER_BYTES_SIZE as usize,
@@ -366,903 +299,645 @@ impl Page {
         }
     }
 
-    pub fn order_row_columns(
-        columns: &[&str],
-        row: Vec<&(String, String)>,
-    ) -> Vec<(String, String)> {
-        //order the columns to match the select
-        let mut response: Vec<(String, String)> = vec![];
-
-        for column in columns {
-            let aux = row.iter().find(|c| c.0.eq(column));
-            match aux {
-                Some(res) => {
-                    response.push((*res).clone());
-                }
-                _ => (),
-            }
-        }
-        response
-    }
-
-    pub fn filter_columns(columns: &[&str], table: Vec<Vec<(String, String)>>) -> Vec<Vec<String>> {
-        table
-            .iter()
-            .map(|row| {
-                //return only columns especified in columns parameter
-                let res = row
-                    .iter()
-                    .filter(|col| columns.as_ref().contains(&&col.0.as_str()))
-                    .collect::<Vec<_>>();
-                Page::order_row_columns(columns, res)
-            })
-            .map(|row| row.iter().map(|col| col.1.clone()).collect::<Vec<String>>())
-            .collect::<Vec<Vec<String>>>()
-    }
-
     pub fn get_table_data(
         &mut self,
         file: &mut Arc<File>,
         table_name: String,
-    ) -> Vec<Vec<Vec<(String, String)>>> {
-        let table: Vec<Vec<Vec<(String, String)>>> = self
-            .rows
-            .iter_mut()
-            .filter(|table| (*(*table).0).eq(&table_name))
-            .map(|c| {
-                let (table_name, table_data) = c;
-                table_data
-                    .1
-                    .borrow_mut()
-                    .parse_page(table_name.clone(), file)
+    ) -> Result<Vec<Vec<TextRow>>> {
+        let table = self
+            .scan_table(file, &table_name, &Arc::default())?
+            .map(|row| {
+                Ok(row?
+                    .into_iter()
+                    .map(|(name, value)| (name, value.to_string()))
+                    .collect())
             })
-            .collect();
+            .collect::<Result<_>>()?;
 
-        table
+        Ok(vec![table])
     }
 
-    pub fn get_varints_byte_array(
+    /// Decodes the table leaf cell at `row_offset` (relative to the start of
+    /// the page) into column name and value pairs. Rows stored before an
+    /// `ALTER TABLE ADD COLUMN` lack the trailing columns, which take their
+    /// default value. VIRTUAL generated columns aren't stored, and are
+    /// computed from the others.
+    pub fn parse_row_values(
         &self,
-        file: &mut Arc<File>,
-        byte_array_header_size: usize,
         row_offset: u64,
-    ) -> Vec<usize> {
-        file.seek(std::io::SeekFrom::Start(row_offset))
-            .expect("FAILED TO SEEK!!");
-
-        let mut buffer_header: Vec<u8> = vec![0; byte_array_header_size];
-
-        file.read_exact(&mut buffer_header)
-            .expect("failed to read!!");
-
-        //println!("BUFFER HEADE: {:x?}", buffer_header);
-
-        let mut current_bytes_reads = 0;
-        let mut end = false;
-        let mut offset: usize = (row_offset) as usize;
-        let mut response: Vec<usize> = vec![];
-        while !end {
-            let res = self.decode_var_int(offset, file);
-            //println!("RES: {:?}", res);
-            response.push(res.clone().unwrap().1);
-            current_bytes_reads += res.clone().unwrap().0.len();
-
-            if current_bytes_reads < byte_array_header_size {
-                offset += res.clone().unwrap().0.len();
-                file.seek(std::io::SeekFrom::Start(offset as u64))
-                    .expect("FAILED TO SEEK!!");
-            } else {
-                end = true;
-            }
-        }
-
-        response
-    }
-
-    pub fn read_bytes_to_usize(&self, file: &mut Arc<File>, offset: usize, size: usize) -> usize {
-        let mut buff = vec![0; size];
-        file.seek(std::io::SeekFrom::Start((offset + 1) as u64))
-            .expect("SEEK read_bytes() failed");
-
-        file.read_exact(&mut buff)
-            .expect("read_exact() from read_bytes() failed ");
-        buff.reverse();
-        let mut arr = [0u8; std::mem::size_of::<usize>()];
-
-        // Copy bytes into the beginning of arr (lowest bytes)
-        let len = buff.len().min(arr.len());
-        arr[..len].copy_from_slice(&buff[..len]);
-        arr.reverse();
-
-        //println!("BUFF:{:X?},POS: {:?}", arr, file.stream_position());
-        //panic!("STOP::");
-        usize::from_be_bytes(arr)
-    }
-    pub fn parse_payload_field_index(
-        &self,
-        _row_offset: u64,
-        _page_offset: u64,
+        schema: &TableSchema,
         file: &mut Arc<File>,
-    ) -> Vec<String> {
-
-
-
-        let row_offset_relative_current_page = file.stream_position().unwrap() as usize;
-        let row_size = self.decode_var_int(row_offset_relative_current_page, file);
-
-        
-        let offset_size_header_byte_array =
-            row_offset_relative_current_page + row_size.as_ref().unwrap().0.iter().len();
-        let size_header_byte_array = self
-            .decode_var_int(offset_size_header_byte_array, file)
-            .as_ref()
-            .unwrap()
-            .1;
-        //let mut buffer_header_byter_array_cell_serial_types = vec![0; size_header_byte_array];
-
-        file.seek(std::io::SeekFrom::Start(
-            offset_size_header_byte_array as u64,
-        ))
-        .expect(format!("Failed to seek to offset").as_str());
-        let resp = self.get_varints_byte_array(
+    ) -> Result<Vec<(String, Value)>> {
+        let offset = self.page_start() + row_offset as usize;
+        let header = Page::read_at(file, offset, 18, self.page_size)?;
+        let (payload_size, size_len) = read_varint(&header);
+        let (row_id, row_id_len) = read_varint(&header[size_len..]);
+        let payload = Page::read_payload(
             file,
-            size_header_byte_array,
-            offset_size_header_byte_array as u64,
-        );
-
-        let sizes_fields = resp
-            .iter()
-            .skip(1)
-            .map(|value| self.get_size_from_varint(*value).1)
-            .collect::<Vec<_>>();
-
-        // heady + data =  row_size
-
-        assert!(
-            sizes_fields.iter().sum::<usize>() + size_header_byte_array
-                == row_size.as_ref().unwrap().1
-        ); // check that the header size and the sum of fields is equal to the row size.
-
-        // seek to offset data
-        let data_offset_byte_array = offset_size_header_byte_array + size_header_byte_array;
-        file.seek(std::io::SeekFrom::Start(data_offset_byte_array as u64))
-            .expect("seek failed");
+            offset + size_len + row_id_len,
+            payload_size as usize,
+            self.page_size,
+            false,
+        )?;
+        stats::record(|stats| stats.bytes_decoded += payload.len() as u64);
+        let record = decode_record(&payload, self.encoding);
+        Page::table_row(schema, row_id as i64, record)
+    }
 
-        let mut offset = data_offset_byte_array;
+    /// Names the values of a table record with the given rowid, in
+    /// declaration order.
+    fn table_row(
+        schema: &TableSchema,
+        row_id: i64,
+        record: Vec<Value>,
+    ) -> Result<Vec<(String, Value)>> {
+        let mut row_data = record.into_iter();
+        // the rowid alias column is stored as NULL, its value is the rowid
+        let rowid_alias = schema.rowid_alias();
 
-        let row_data: Vec<String> = sizes_fields
+        // join colum names and data of the current row
+        let mut row = schema
+            .columns
             .iter()
             .enumerate()
-            .map(|size| {
-                //
-
-                let res = match size.0 {
-                    0 => self.read_bytes_to_utf8(file, offset, *size.1),
-                    _ => self
-                        .read_bytes_to_usize(file, offset - 1, *size.1)
-                        .to_string(),
+            .map(|(i, column)| {
+                // computed once the stored values are read
+                if column.virtual_expr().is_some() {
+                    return (column.name.clone(), Value::Null);
+                }
+                let value = match row_data.next() {
+                    Some(value) => column.stored_value(value),
+                    None => column.default_value(),
                 };
-                //res = res.replace(" ", row_id.as_ref().unwrap().1.to_string().as_str());
-
-                offset += *size.1;
-
-                res
+                let value = match rowid_alias == Some(i) {
+                    true => Value::Integer(row_id),
+                    _ => value,
+                };
+                (column.name.clone(), value)
             })
-            .collect();
-
-        row_data
+            .collect::<Vec<_>>();
+        schema.compute_virtual_columns(&mut row)?;
+        Ok(row)
     }
-    pub fn parse_row_data(
-        &self,
-        row_offset: u64,
-        table_name: String,
-        file: &mut Arc<File>,
-        schema: bool,
-    ) -> Vec<(String, String)> {
-        let page_offset = match self.sql_schema.eq("") {
-            true => 0,
-            _ => self.offset as u64,
-        };
-
-        //println!("PAGE_OFFSET: {}. SELF:{:?}",page_offset,&self);
 
+    /// The rows of the table whose B-tree is rooted at this page. Reading
+    /// them fails with `Error::Interrupted` once `interrupted` is set.
+    fn parse_page(&self, file: &mut Arc<File>, interrupted: &Arc<AtomicBool>) -> Result<RowIter> {
+        let interrupted = interrupted.clone();
+        match self.type_page {
+            PageType::INTERIORTABLE | PageType::LEAFTABLE => {
+                let schema = self.own_table_schema()?;
+                Ok(Box::new(TableRows::new(
+                    file.clone(),
+                    self.clone(),
+                    schema,
+                    interrupted,
+                )?))
+            }
+            // a WITHOUT ROWID table is stored as an index on its primary key
+            PageType::INTERIORINDEX | PageType::LEAFINDEX => {
+                match parse_create_table(&self.sql_schema) {
+                    Ok(schema) if schema.without_rowid => {
+                        let all = KeyRange {
+                            lower: Bound::Unbounded,
+                            upper: Bound::Unbounded,
+                            collation: Collation::Binary,
+                            descending: false,
+                        };
+                        let records =
+                            IndexEntries::new(file.clone(), self.clone(), all, interrupted)?;
+                        Ok(Box::new(
+                            records.map(move |record| Page::clustered_row(&schema, record?)),
+                        ))
+                    }
+                    _ => Ok(Box::new(std::iter::empty())),
+                }
+            }
+            // only pages of schema objects without a B-tree, whose type is
+            // never read
+            PageType::UNKNOWNTYPE => Ok(Box::new(std::iter::empty())),
+        }
+    }
 
-
-        let mut aux_buffer:Vec<u8> = Vec::new();
-
-        file.read_exact(&mut aux_buffer);
-
-
-        println!("WHOLE ROW BUFFER: {:?}",aux_buffer);
-
-
-        let row_offset_relative_current_page = (page_offset + row_offset) as usize;
-        let row_size = self.decode_var_int(row_offset_relative_current_page, file);
-        //--------------------------------------
-        file.seek(std::io::SeekFrom::Start(
-            row_offset_relative_current_page as u64,
-        ))
-        .expect(format!("Failed to seek to offset{row_offset_relative_current_page:?}").as_str());
-        let mut aux_buffer:Vec<u8> = vec![0u8;row_size.as_ref().unwrap().1 as usize];
-        file.read_exact(&mut aux_buffer);
-        println!("WHOLE ROW BUFFER: {:x?}",aux_buffer);
-        //--------------------------------------
-
-        let row_id_offeset = row_offset_relative_current_page + row_size.as_ref().unwrap().0.len(); // offset row id
-        let row_id = self.decode_var_int(row_id_offeset, file);
-        //println!("ROW OFFSET: {:?} ROWSIZE: {:?}. ROW:ID:{:?}",row_offset_relative_current_page, row_size, row_id );
-
-        let offset_size_header_byte_array =
-            row_id_offeset + row_id.as_ref().unwrap().0.iter().len();
-        let size_header_byte_array = self
-            .decode_var_int(offset_size_header_byte_array, file)
-            .as_ref()
-            .unwrap()
-            .1;
-        //let mut buffer_header_byter_array_cell_serial_types = vec![0; size_header_byte_array];
-
-        file.seek(std::io::SeekFrom::Start(
-            offset_size_header_byte_array as u64,
-        ))
-        .expect(format!("Failed to seek to offset{row_offset:?}").as_str());
-
-        let resp = self.get_varints_byte_array(
-            file,
-            size_header_byte_array,
-            (offset_size_header_byte_array) as u64,
-        );
-
-        //println!("VAR_INTS: {:?}", resp);
-
-        let sizes_fields = resp
-            .iter()
-            .skip(1) // skeep the size of the header
-            .map(|value| self.get_size_from_varint(*value).1)
+    /// Names the values of a `WITHOUT ROWID` table record, in declaration
+    /// order, with the VIRTUAL generated columns computed.
+    fn clustered_row(schema: &TableSchema, record: Vec<Value>) -> Result<Vec<(String, Value)>> {
+        let mut stored = schema
+            .record_columns()
+            .into_iter()
+            .zip(record)
             .collect::<Vec<_>>();
-
-        //println!("SIZES_FIELDS: {:?}", sizes_fields);
-
-        // heady + data =  row_size
-
-        assert!(
-            sizes_fields.iter().sum::<usize>() + size_header_byte_array
-                == row_size.as_ref().unwrap().1
-        ); // check that the header size and the sum of fields is equal to the row size.
-
-        // seek to offset data
-        let data_offset_byte_array = offset_size_header_byte_array + size_header_byte_array;
-        file.seek(std::io::SeekFrom::Start(data_offset_byte_array as u64))
-            .expect("seek failed");
-
-        let mut offset = data_offset_byte_array;
-
-        let row_data: Vec<String> = sizes_fields
+        let mut row = schema
+            .columns
             .iter()
-            //.inspect(|c| println!("BOAUT TO CONVERT TO UT8F8 : {:?}", c))
-            .map(|size| {
-                //
-                let res = self.read_bytes_to_utf8(file, offset, *size);
-                //res = res.replace(" ", row_id.as_ref().unwrap().1.to_string().as_str());
-
-                offset += *size;
-
-                match res.eq("") {
-                    true => row_id.as_ref().unwrap().1.to_string(),
-                    _ => res,
-                }
+            .map(|column| {
+                let value = match stored.iter().position(|(name, _)| *name == column.name) {
+                    Some(i) => column.stored_value(stored.swap_remove(i).1),
+                    None => column.default_value(),
+                };
+                (column.name.clone(), value)
             })
-            .collect();
-
-        //println!("ROW DATA: {:?}",row_data);
-
-        let column_names = self.get_rows_colum_names(table_name.clone(), schema);
-
-        //println!("DATA_ {:?}. rowlen: {:?} colleng: {:?}",row_data,row_data.len(),column_names.len());
-
-        assert!(row_data.len() == column_names.len()); // assert colum data length and colum types are equal
-                                                       //println!("ROW DATA: {:?}",row_data);
-                                                       /*
-                                                        println!(
-                                                           "ROW_SIZE {:?} ROW_ID {:?} HEADER_SIZE: {:?} BUFFER_S_TYPES: {:?} SIZE_FIEDLS: {:?}, ROW_DATA: {:?}",
-                                                           row_size,
-                                                           row_id,
-                                                           size_header_byte_array,
-                                                           resp,
-                                                           sizes_fields,
-                                                           row_data
-                                                       );
-                                                        */
-        // join colum names and data of the current row
-        let res = column_names
-            .iter()
-            .zip(row_data.iter())
-            .map(|c| (c.0[0].clone(), c.1.clone()))
             .collect::<Vec<_>>();
-
-        //println!("ROW_ DATA:{:?}",res);
-
-        res
+        schema.compute_virtual_columns(&mut row)?;
+        Ok(row)
     }
 
-    fn get_page(
+    /// Every row of `table_name`, in rowid order (primary key order for a
+    /// `WITHOUT ROWID` table), until `interrupted` is set.
+    pub fn scan_table(
         &self,
-        cell_offset: u64,
         file: &mut Arc<File>,
-        is_index_page: (PageType, usize),
-        sql_schema: String,
-    ) -> (String, Arc<(usize, RefCell<Page>)>) {
-        match is_index_page.0 {
-            PageType::INTERIORINDEX | PageType::INTERIORTABLE => {
-                file.seek(std::io::SeekFrom::Start(is_index_page.1 as u64))
-                    .expect("seeking failed!!"); //seek to the start of the paage
-            }
-            _ => {
-                file.seek(std::io::SeekFrom::Start(self.offset as u64))
-                    .expect("seeking failed!!"); //seek to the start of the paage
-            }
+        table_name: &str,
+        interrupted: &Arc<AtomicBool>,
+    ) -> Result<RowIter> {
+        match self.rows.get(table_name) {
+            Some(table_page) => table_page.1.borrow().parse_page(file, interrupted),
+            _ => Err(Error::NoSuchTable(table_name.to_string())),
         }
-
-        file.seek_relative(cell_offset as i64)
-            .expect("FAILED TO SEEK!!");
-
-        let mut row_buffer: Vec<u8> = vec![0u8; 5];
-
-        file.read_exact(&mut row_buffer)
-            .expect("READ EXACT FAILED!!");
-
-        let page_number =
-            u32::from_be_bytes([row_buffer[0], row_buffer[1], row_buffer[2], row_buffer[3]]);
-
-        //panic!("stop:::");
-
-        let payload: String = match is_index_page.0 {
-            PageType::INTERIORINDEX | PageType::INTERIORTABLE => {
-                match is_index_page.0 {
-                    PageType::INTERIORINDEX => {
-                        let stream_pos = file.stream_position().unwrap() - 1;
-                        file.seek(std::io::SeekFrom::Start(stream_pos))
-                            .expect("Somthing went wrong using seek");
-                        self.parse_payload_field_index(cell_offset, is_index_page.1 as u64, file)
-                            .join("|")
-                    }
-                    _ => {
-                        let stream_pos = file.stream_position().unwrap() - 1;
-                        file.seek(std::io::SeekFrom::Start(stream_pos))
-                            .expect("Somthing went wrong using seek");
-
-                        let row_id =
-                            self.decode_var_int(file.stream_position().unwrap() as usize, file);
-                        //READ VARINT
-                        format!("{}|{}", row_id.clone().unwrap().1, page_number)
-                    }
-                }
-            }
-            _ => u8::from_be_bytes([row_buffer[4]]).to_string(),
-        };
-
-        (
-            payload,
-            Arc::new((
-                page_number as usize,
-                RefCell::new(Page::new_(
-                    file,
-                    page_number as usize,
-                    4096,
-                    sql_schema, //self.sql_schema.clone(),
-                )),
-            )),
-        )
     }
-    pub fn get_right_child_page(
+
+    /// The row of `table_name` whose rowid is `rowid`, if there is one.
+    pub fn search_table_rowid(
         &self,
         file: &mut Arc<File>,
-    ) -> anyhow::Result<Arc<(usize, RefCell<Page>)>> {
-        let page_size = 4096;
-        match self.type_page {
-            PageType::INTERIORINDEX | PageType::INTERIORTABLE => anyhow::Ok(Arc::new((
-                self.right_page_number as usize,
-                RefCell::new(Page::new_(
-                    file,
-                    self.right_page_number as usize,
-                    page_size,
-                    self.sql_schema.clone(),
-                )),
-            ))),
-            _ => bail!("THIS PAGE TYPE DOES NOT HAVE RIGHT CHIELD"),
+        table_name: &str,
+        rowid: i64,
+    ) -> Result<Option<Vec<(String, Value)>>> {
+        match self.rows.get(table_name) {
+            Some(table_page) => table_page.1.borrow().search_rowid(file, rowid),
+            _ => Err(Error::NoSuchTable(table_name.to_string())),
         }
     }
-    pub fn add_page_to_rows(
-        &mut self,
-        row_offset: u64,
-        file: &mut Arc<File>,
-    ) -> Vec<(String, String)> {
-        let (_, page) = self.get_page(
-            row_offset,
-            file,
-            (PageType::UNKNOWNTYPE, 0),
-            self.sql_schema.clone(),
-        );
-        let page_number = page.0;
-        self.rows.entry(format!("{page_number}")).or_insert(page);
-        vec![("".into(), "".into())]
-    }
 
-    fn parse_page(
-        &mut self,
-        table_name: String,
-        file: &mut Arc<File>,
-    ) -> Vec<Vec<(String, String)>> {
-        let offset_page_header = 8; // offset for leaf pages
-
-        let size_cell_pointer = 2;
-        match self.type_page {
-            PageType::INTERIORINDEX => panic!("INTERIOR INDEX NOT IMPLEMENTED YET"),
-            PageType::INTERIORTABLE => {
-                //
-                //root page
-
-                let offset_page_header = 12;
-
-                file.seek(std::io::SeekFrom::Start(
-                    (self.offset + offset_page_header) as u64,
-                ))
-                .expect("SEEK FAILED!!");
-
-                let mut buffer = vec![0u8; (self.table_count * size_cell_pointer) as usize];
-                file.read_exact(&mut buffer).expect("READ EXACT FAILED!!");
-
-                let _res: Vec<Vec<(String, String)>> = buffer
-                    .chunks(2) // cell size
-                    .map(|cell| u16::from_be_bytes([cell[0], cell[1]]))
-                    .map(|offeset_cell| self.add_page_to_rows(offeset_cell as u64, file))
-                    .collect();
-
-                //println!("PAGES {:?}",_res);
-
-                let res_ = self
-                    .rows
-                    .iter_mut()
-                    .flat_map(|data| {
-                        let (_, page) = data;
-                        page.1.borrow_mut().parse_page(table_name.clone(), file)
-                        //println!("table_data {:?}",data);
-                    })
-                    .collect::<Vec<_>>();
-
-                res_
-            }
-            PageType::LEAFTABLE | PageType::LEAFINDEX => {
-                file.seek(std::io::SeekFrom::Start(
-                    (self.offset + offset_page_header) as u64,
-                ))
-                .expect("SEEK FAILED!!");
-                let mut buffer = vec![0u8; (self.table_count * size_cell_pointer) as usize];
-                file.read_exact(&mut buffer).expect("READ EXACT FAILED!!");
-                //seek from the start of the page
-                let res: Vec<Vec<(String, String)>> = buffer
-                    .chunks(2) // cell size
-                    .map(|cell| u16::from_be_bytes([cell[0], cell[1]]))
-                    .map(|offeset_cell| match self.type_page {
-                        PageType::LEAFINDEX => {
-                            file.seek(std::io::SeekFrom::Start(
-                                (self.offset + offeset_cell as usize) as u64,
-                            ))
-                            .expect("PARSING");
-
-                            //println!("Stream_pos: {:?}", file.stream_position());
-                            let res = self.parse_payload_field_index(0, 0, file);
-
-                            //panic!("STOPS: GET PAGE!!");
-
-                            vec![(res[0].clone(), res[1].clone())]
-                        }
-                        PageType::LEAFTABLE => self.parse_row_data(
-                            offeset_cell as u64,
-                            table_name.clone(),
-                            file,
-                            false,
-                        ),
-
-                        _ => panic!("DOES NOT APPLY!!"),
-                    })
-                    .collect();
-                res
-            }
-            PageType::UNKNOWNTYPE => panic!("unknow page type!! File might be corrupted!!"),
-        }
+    /// Name and `CREATE` statement of every object in the schema, sorted by
+    /// name. Only meaningful on the schema page.
+    pub fn schema_entries(&self) -> Vec<(String, String)> {
+        let mut entries = self
+            .rows
+            .iter()
+            .map(|(name, page)| (name.clone(), page.1.borrow().sql_schema.clone()))
+            .collect::<Vec<_>>();
+        entries.sort();
+        entries
+    }
 
-        //TODO -> CHECK FOR PAGE TYPE FOR NON LEAF PAGES THERE MIGHT BE RIGHT POINTER BEFORE THE CELLS START.
-        // USE A MATCH TO CHECK THE TYPE OF THE PAGE
+    /// The rows of the schema table in the order they are stored. Only
+    /// meaningful on the schema page.
+    pub fn schema(&self) -> &[SchemaEntry] {
+        &self.entries
     }
 
-    pub fn search_index_country(
+    /// Rows of `table_name` whose entry in `index_name` has a first column
+    /// within `range`, in index order. An index named after the table is the
+    /// primary key of a `WITHOUT ROWID` table, whose entries are the rows.
+    pub fn search_index_range(
         &self,
         file: &mut Arc<File>,
         (table_name, index_name): (String, String),
-        key: String,
-    ) -> Vec<Vec<(String, String)>> {
-        //
-        let index_name: String = index_name;
-        let search_name = &key;
-        let index_page = self
-            .rows
-            .iter()
-            .find(|(name, _)| (*name).eq(&index_name))
-            .unwrap()
-            .1;
+        range: &KeyRange,
+        interrupted: &Arc<AtomicBool>,
+    ) -> Result<RowIter> {
+        let (index_page, table_page) =
+            match (self.rows.get(&index_name), self.rows.get(&table_name)) {
+                (Some(index_page), Some(table_page)) => (index_page, table_page),
+                (_, None) => return Err(Error::NoSuchTable(table_name)),
+                (None, _) => return Err(Error::Sql(format!("no such index: {index_name}"))),
+            };
+        let index_page = index_page.1.borrow().clone();
+        let entries =
+            IndexEntries::new(file.clone(), index_page, range.clone(), interrupted.clone())?;
+        if index_name == table_name {
+            let schema = table_page.1.borrow().own_table_schema()?;
+            return Ok(Box::new(
+                entries.map(move |record| Page::clustered_row(&schema, record?)),
+            ));
+        }
+        let table_page = table_page.1.borrow().clone();
+        let mut file = file.clone();
+        Ok(Box::new(entries.filter_map(move |entry| match entry {
+            Ok(entry) => match entry.last() {
+                Some(Value::Integer(rowid)) => {
+                    table_page.search_rowid(&mut file, *rowid).transpose()
+                }
+                _ => None,
+            },
+            Err(e) => Some(Err(e)),
+        })))
+    }
 
-        let table_page = self
-            .rows
-            .iter()
-            .find(|(name, _)| (*name).eq(&table_name))
-            .unwrap()
-            .1;
+    /// Offset of the first byte of the page. On page 1 the B-tree header
+    /// (`offset`) comes after the database header, but cell pointers are
+    /// still relative to the start of the file.
+    fn page_start(&self) -> usize {
+        match self.offset == HEADER_BYTES_SIZE as usize {
+            true => 0,
+            _ => self.offset,
+        }
+    }
 
-        let ids_list: Vec<(String, String)> = self.i_search_index(
-            &index_page.1.borrow(),
-            search_name,
-            "".into(),
+    /// Offsets of the cells of this page, relative to the page start.
+    fn cell_pointers(&self, file: &mut Arc<File>) -> Result<Vec<usize>> {
+        let offset_page_header = match self.type_page {
+            PageType::INTERIORINDEX | PageType::INTERIORTABLE => 12,
+            _ => 8,
+        };
+        let buffer = Page::read_at(
             file,
-            Arc::new(vec![]),
-        );
-        let final_response = ids_list
-            .iter()
-            .map(|id| {
-                let row: Vec<(String, String)> = self.i_search_index(
-                    &table_page.1.borrow(),
-                    id.1.as_str(),
-                    "".into(),
-                    file,
-                    Arc::new(vec![]),
-                );
-                row
-            })
-            .collect::<Vec<_>>();
-
-        final_response
+            self.offset + offset_page_header,
+            self.table_count as usize * 2,
+            self.page_size,
+        )?;
+        if buffer.len() < self.table_count as usize * 2 {
+            return Err(Error::corrupt(
+                self.page_number(),
+                "truncated cell pointers",
+            ));
+        }
+        buffer
+            .chunks_exact(2)
+            .map(
+                |cell| match u16::from_be_bytes([cell[0], cell[1]]) as usize {
+                    cell if cell < self.page_size => Ok(cell),
+                    cell => Err(Error::corrupt(
+                        self.page_number(),
+                        format!("cell offset {cell} past the end of the page"),
+                    )),
+                },
+            )
+            .collect()
     }
-    #[allow(dead_code)]
-    pub fn search_by_id(
-        &self,
+
+    /// Reads up to `size` bytes at `offset`, fewer at the end of the file,
+    /// from the pages they're on in the page cache.
+    fn read_at(
         file: &mut Arc<File>,
-        table_name: String,
-        key: String,
-    ) -> Vec<(String, String)> {
-        //
-        let search_name = &key;
+        offset: usize,
+        size: usize,
+        page_size: usize,
+    ) -> Result<Vec<u8>> {
+        let mut buffer = Vec::with_capacity(size);
+        while buffer.len() < size {
+            let start = offset + buffer.len();
+            let (page, _) = cache::page(file, start / page_size + 1, page_size)?;
+            let bytes = page.get(start % page_size..).unwrap_or_default();
+            let bytes = &bytes[..bytes.len().min(size - buffer.len())];
+            if bytes.is_empty() {
+                break;
+            }
+            buffer.extend_from_slice(bytes);
+        }
+        Ok(buffer)
+    }
 
-        let table_page = self
-            .rows
-            .iter()
-            .find(|(name, _)| (*name).eq(&table_name))
-            .unwrap()
-            .1;
+    /// The whole payload of a cell whose payload starts at `offset`,
+    /// following the overflow page chain when it doesn't fit in the page.
+    fn read_payload(
+        file: &mut Arc<File>,
+        offset: usize,
+        payload_size: usize,
+        page_size: usize,
+        index: bool,
+    ) -> Result<Vec<u8>> {
+        let max_local = match index {
+            true => (page_size - 12) * 64 / 255 - 23,
+            _ => page_size - 35,
+        };
+        let min_local = (page_size - 12) * 32 / 255 - 23;
+        let local = match payload_size <= max_local {
+            true => payload_size,
+            _ => match min_local + (payload_size - min_local) % (page_size - 4) {
+                size if size <= max_local => size,
+                _ => min_local,
+            },
+        };
+        let mut payload = Page::read_at(file, offset, local, page_size)?;
+        if local < payload_size {
+            let next = Page::read_at(file, offset + local, 4, page_size)?;
+            let mut next = u32::from_be_bytes(next.try_into().unwrap_or([0; 4])) as usize;
+            while next != 0 && payload.len() < payload_size {
+                let page = Page::read_at(file, (next - 1) * page_size, page_size, page_size)?;
+                let size = (payload_size - payload.len()).min(page_size - 4);
+                if page.len() < 4 + size {
+                    return Err(Error::corrupt(
+                        next,
+                        "overflow page past the end of the file",
+                    ));
+                }
+                next = u32::from_be_bytes([page[0], page[1], page[2], page[3]]) as usize;
+                payload.extend_from_slice(&page[4..4 + size]);
+            }
+        }
+        Ok(payload)
+    }
 
-        let row: Vec<(String, String)> = self.i_search_index(
-            &table_page.1.borrow(),
-            &search_name,
-            "".into(),
+    /// Left child page number (0 on leaf pages) and key of an index cell.
+    fn index_cell(&self, file: &mut Arc<File>, cell: usize) -> Result<(u32, Vec<Value>)> {
+        let offset = self.page_start() + cell;
+        let header = Page::read_at(file, offset, 13, self.page_size)?;
+        let (left_child, start) = match self.type_page {
+            PageType::INTERIORINDEX => (self.left_child(&header)?, 4),
+            _ => (0, 0),
+        };
+        let (payload_size, len) = read_varint(&header[start..]);
+        let payload = Page::read_payload(
             file,
-            Arc::new(vec![]),
-        );
+            offset + start + len,
+            payload_size as usize,
+            self.page_size,
+            true,
+        )?;
+        stats::record(|stats| stats.bytes_decoded += payload.len() as u64);
+        Ok((left_child, decode_record(&payload, self.encoding)))
+    }
 
-        row
+    /// Rowid of a table cell, together with the left child page number on
+    /// interior pages.
+    fn table_cell(&self, file: &mut Arc<File>, cell: usize) -> Result<(u32, i64)> {
+        let header = Page::read_at(file, self.page_start() + cell, 18, self.page_size)?;
+        Ok(match self.type_page {
+            PageType::INTERIORTABLE => (
+                self.left_child(&header)?,
+                read_varint(&header[4..]).0 as i64,
+            ),
+            _ => {
+                let (_, len) = read_varint(&header);
+                (0, read_varint(&header[len..]).0 as i64)
+            }
+        })
     }
 
-    #[allow(dead_code)]
-    fn page_number(&self) -> usize {
-        (self.offset / 4096) + 1
+    /// The left child page number an interior cell starts with.
+    fn left_child(&self, cell: &[u8]) -> Result<u32> {
+        match cell.get(..4) {
+            Some(bytes) => Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+            None => Err(Error::corrupt(self.page_number(), "truncated cell")),
+        }
     }
-    fn i_search_index(
+
+    /// Looks a row up by rowid in the table B-tree rooted at this page.
+    pub fn search_rowid(
         &self,
-        page: &Page,
-        search_key: &str,
-        payload: String,
         file: &mut Arc<File>,
-        pages: Arc<Vec<(&String, &Vec<(String, Arc<(usize, RefCell<Page>)>)>)>>,
-    ) -> Vec<(String, String)> {
-        if page.type_page == PageType::LEAFINDEX || page.type_page == PageType::LEAFTABLE {
-            let compare_keys = |search_key: &str, payload: String| match (
-                search_key.parse::<usize>(),
-                payload.parse::<usize>(),
-            ) {
-                (Ok(search_key), Ok(payload)) => search_key.le(&payload),
-                _ => payload.clone().le(&search_key.to_string()),
-            };
-
-            if compare_keys(search_key, payload) {
-                //if payload.clone().le(&search_key.to_string()) {
-                //
-                //let res = page.clone().
-                //("companies".into(), file);
-                //println!("---------------------------------------------------------------------");
-                //println!("PAGE: {:?} NUM_PAGE: {}", page, page.page_number());
-                /*
-                let page1 = dbg!(Page::new_(file, page.page_number() + 1, 4096, "".into()))
-                    .parse_page("".into(), file);
-                let page2 = dbg!(Page::new_(file, page.page_number() + 2, 4096, "".into()))
-                    .parse_page("".into(), file);
-
-                let page3 = dbg!(Page::new_(file, page.page_number() - 1, 4096, "".into()))
-                    .parse_page("".into(), file);
-                let res = page.clone().parse_page("".into(), file);
-                 */
-
-                //let mut response = vec![];
-
-                let mut response = match pages.len() {
-                    0 => {
-                        let res = page.clone().parse_page("".into(), file);
-
-                        let reso = vec![res];
-                        reso
-                    }
-                    _ => match page.type_page {
-                        PageType::LEAFTABLE => vec![page.clone().parse_page("".into(), file)],
-                        _ => vec![page.clone().parse_page("".into(), file)],
-                    },
-                };
-
-                //println!("PAGES LEN: {:?}",response);
-                /*
-                let right_page = page
-                    .get_right_child_page(file)
-                    .1
-                    .borrow_mut()
-                    .parse_page("".into(), file);
-                    println!("RIGHT PAGE: {:?} ", right_page);
-                 */
-                /*
-                for page in pages.iter() {
-                    let _pr_key = page.0.split("|").collect::<Vec<_>>()[0].to_owned();
-
-                    for repe in page.1 {
-                        let res = repe.1 .1.borrow().clone().parse_page("".into(), file);
-                        response.push(res);
-                    }
+        rowid: i64,
+    ) -> Result<Option<Vec<(String, Value)>>> {
+        // the pages below this one, read on the way down to the leaf
+        let mut child: Option<Page> = None;
+        let mut path = vec![self.page_number()];
+        loop {
+            let page = child.as_ref().unwrap_or(self);
+            let cells = page.cell_pointers(file)?;
+            // binary search for the first cell whose rowid isn't below `rowid`
+            let (mut low, mut high) = (0, cells.len());
+            while low < high {
+                let middle = (low + high) / 2;
+                match page.table_cell(file, cells[middle])?.1 < rowid {
+                    true => low = middle + 1,
+                    _ => high = middle,
                 }
-                 */
-
-                let mut total_records = 0;
-                let mut final_response: Vec<Vec<(String, String)>> = vec![];
-                let response = match page.type_page {
-                    PageType::LEAFTABLE => {
-                        //println!("RESPONSE: {:?} TYPE: {:?}",response[0],page.type_page);
-
-                        let id = match search_key {
-                            "0" => "1",
-                            _ => search_key,
-                        };
-
-                        /*
-                           println!("RESPONSE: {:?}",response[0].iter().map(|v| {
-                               v.iter().find(|(key, _)| key == "id")
-                                   .map(|(_, value)| (*value).clone()).unwrap()
-                           }).collect::<Vec<_>>());
-                        */
-
-                        //panic!("STOP {}",search_key);
-                        let new_response = response[0].clone();
-                        let rep = new_response.binary_search_by(|probe| {
-                            let id_value = probe
-                                .iter()
-                                .find(|(key, _)| key == "id")
-                                .map(|(_, value)| (*value).clone())
-                                .unwrap_or("".into());
-                            id_value.cmp(&id.to_string())
-                        });
-
-                        let row = response[0][rep.unwrap()].clone();
-                        //println!("RESP: {:?} ROW: {:?}",rep.expect("NO RESPONSE"),row);
-                        final_response.push(row);
-                        final_response
-                            .iter()
-                            .flatten()
-                            .map(|tup| tup.clone())
-                            .collect::<Vec<_>>()
+            }
+            match page.type_page {
+                PageType::INTERIORTABLE => {
+                    let number = match cells.get(low) {
+                        Some(cell) => page.table_cell(file, *cell)?.0,
+                        _ => page.right_page_number,
+                    } as usize;
+                    // a page already on the path would make the search endless
+                    if path.contains(&number) {
+                        return Err(Error::corrupt(number, "cycle"));
                     }
-                    _ => {
-                        //println!("RESPONSE: {:?} TYPE: {:?}",response,page.type_page);
-
-                        response.iter().for_each(|res| {
-                            let filtered = res
-                                .iter()
-                                .flat_map(|c| {
-                                    c.iter()
-                                        .filter(|tuple| {
-                                            //
-                                            let (key, _) = tuple;
-                                            (*key).eq(search_key)
-                                        })
-                                        .collect::<Vec<_>>()
-                                })
-                                .map(|tup| tup.clone())
-                                .collect::<Vec<_>>();
-                            /*
-                            println!(
-                                "FILTERED LENGTH: {:?} ITEMS: {:?}",
-                                filtered.len(),
-                                filtered
-                            );
-                            */
-                            total_records += filtered.len();
-                            final_response.push(filtered);
-                            /*
-                                println!(
-                                    "---------------------------------------------------------------------"
-                                );
-                            */
-                            //panic!("STOP!!! -->");
-                        });
-
-                        //println!("TOTAL RECORDS: {:?}",total_records);
-
-                        final_response
-                            .iter()
-                            .flatten()
-                            .map(|tup| tup.clone())
-                            .collect::<Vec<_>>()
+                    path.push(number);
+                    child = Some(Page::new_(
+                        file,
+                        number,
+                        page.page_size,
+                        page.encoding,
+                        page.sql_schema.clone(),
+                    )?);
+                }
+                PageType::LEAFTABLE => {
+                    return match cells.get(low) {
+                        Some(cell) if page.table_cell(file, *cell)?.1 == rowid => {
+                            let schema = page.own_table_schema()?;
+                            Ok(Some(page.parse_row_values(*cell as u64, &schema, file)?))
+                        }
+                        _ => Ok(None),
                     }
-                };
-
-                return response;
+                }
+                _ => return Ok(None),
             }
-            return vec![];
         }
+    }
+}
 
-        let size_cell_pointer = 2;
-        let cell_count = page.table_count;
-
-        let offset_page_header = 12;
-
-        file.seek(std::io::SeekFrom::Start(
-            (page.offset + offset_page_header) as u64,
-        ))
-        .expect("SEEK FAILED!!");
+/// A page on the path from the root of a B-tree to the cell being read.
+struct PathPage {
+    page: Page,
+    /// Offsets of the cells of the page.
+    cells: Vec<usize>,
+    /// Position of the next cell to read.
+    next: usize,
+    /// Key of the index cell at `next`, kept while its left subtree is
+    /// walked.
+    key: Option<Vec<Value>>,
+}
 
-        let mut buffer = vec![0u8; (cell_count * size_cell_pointer) as usize];
-        file.read_exact(&mut buffer).expect("READ EXACT FAILED!!");
+impl PathPage {
+    fn new(file: &mut Arc<File>, page: Page) -> Result<PathPage> {
+        let cells = page.cell_pointers(file)?;
+        Ok(PathPage {
+            page,
+            cells,
+            next: 0,
+            key: None,
+        })
+    }
 
-        // TODO:  get left chield an right chield!!
+    /// Reads page `number`, a child of the last page of `path`, onto the
+    /// path. A child already on the path would make the walk endless.
+    fn descend(path: &mut Vec<PathPage>, file: &mut Arc<File>, number: u32) -> Result<()> {
+        if path
+            .iter()
+            .any(|level| level.page.page_number() == number as usize)
+        {
+            return Err(Error::corrupt(number as usize, "cycle"));
+        }
+        if let Some(parent) = path.last() {
+            let child = Page::new_(
+                file,
+                number as usize,
+                parent.page.page_size,
+                parent.page.encoding,
+                String::new(),
+            )?;
+            path.push(PathPage::new(file, child)?);
+        }
+        Ok(())
+    }
+}
 
-        let right_chield = page.get_right_child_page(file);
-        let mut pages_final: HashMap<String, Vec<(String, Arc<(usize, RefCell<Page>)>)>> =
-            HashMap::new();
+/// Walks a table B-tree in rowid order, decoding a leaf cell for each row.
+/// Only the pages from the root to the current leaf are held.
+struct TableRows {
+    file: Arc<File>,
+    schema: TableSchema,
+    path: Vec<PathPage>,
+    interrupted: Arc<AtomicBool>,
+}
 
-        let _ = buffer
-            .chunks(2) // cell size
-            .map(|cell| u16::from_be_bytes([cell[0], cell[1]]))
-            .map(|cell_offset| {
-                let left_chield = match page.type_page {
-                    PageType::INTERIORINDEX => self.get_page(
-                        cell_offset as u64,
-                        file,
-                        (PageType::INTERIORINDEX, page.offset),
-                        page.sql_schema.clone(),
-                    ),
-                    PageType::INTERIORTABLE => {
-                        /*
-                        let mut COPY_PAGE = page.clone();
-                        COPY_PAGE.add_page_to_rows(cell_offset as u64, file);
-                        let rows = &page.rows;
-                        println!(" ROWS PAGE {:?}",rows);
-                        panic!("not")
-                         */
-
-                        assert!(
-                            !page.sql_schema.clone().is_empty(),
-                            "SQL SQUEMA FOR INTERIOR INDEX IS EMPTY"
-                        );
-
-                        self.get_page(
-                            cell_offset as u64,
-                            file,
-                            (PageType::INTERIORTABLE, page.offset),
-                            page.sql_schema.clone(),
-                        )
-                    }
-                    _ => panic!("NOT SUPORTED"),
-                };
+impl TableRows {
+    fn new(
+        mut file: Arc<File>,
+        root: Page,
+        schema: TableSchema,
+        interrupted: Arc<AtomicBool>,
+    ) -> Result<TableRows> {
+        let root = PathPage::new(&mut file, root)?;
+        Ok(TableRows {
+            file,
+            schema,
+            path: vec![root],
+            interrupted,
+        })
+    }
 
-                //let left_chield = self.get_page(cell_offset as u64, file, (true, page.offset));
-                left_chield
-            })
-            .for_each(|value| {
-                let pr_key = value.0.split("|").collect::<Vec<_>>()[0].to_owned();
-                pages_final
-                    .entry(pr_key)
-                    .and_modify(|vec| vec.push(value.clone()))
-                    .or_insert(vec![value.clone()]);
-            });
-        //.collect::<Vec<_>>();
-
-        //println!("LENGTH: BEFORE SHORT {:?}", pages_final);
-        let mut pages_vec: Vec<(&String, &Vec<(String, Arc<(usize, RefCell<Page>)>)>)> =
-            pages_final.iter().map(|c| c).collect();
-
-        if let Some((key, _)) = pages_vec.first() {
-            match key.parse::<usize>() {
-                Ok(_) => {
-                    pages_vec.sort_by_key(|c| (*c.0).parse::<usize>().unwrap());
+    fn next_row(&mut self) -> Result<Option<Vec<(String, Value)>>> {
+        loop {
+            let Some(level) = self.path.last_mut() else {
+                return Ok(None);
+            };
+            if self.interrupted.load(Ordering::Relaxed) {
+                return Err(Error::Interrupted);
+            }
+            let cell = level.cells.get(level.next).copied();
+            let right = level.next == level.cells.len();
+            level.next += 1;
+            let child = match (&level.page.type_page, cell) {
+                (PageType::LEAFTABLE, Some(cell)) => {
+                    let (schema, file) = (&self.schema, &mut self.file);
+                    return level
+                        .page
+                        .parse_row_values(cell as u64, schema, file)
+                        .map(Some);
+                }
+                (PageType::INTERIORTABLE, Some(cell)) => {
+                    level.page.table_cell(&mut self.file, cell)?.0
+                }
+                // the right most child is read after the cells
+                (PageType::INTERIORTABLE, None) if right => level.page.right_page_number,
+                (PageType::INTERIORTABLE | PageType::LEAFTABLE, None) => {
+                    self.path.pop();
+                    continue;
                 }
                 _ => {
-                    pages_vec.sort_by_key(|c| c.0.clone());
+                    return Err(Error::corrupt(
+                        level.page.page_number(),
+                        "index page in a table B-tree",
+                    ))
                 }
-            }
-        };
+            };
+            PathPage::descend(&mut self.path, &mut self.file, child)?;
+        }
+    }
+}
 
-        let pages: Arc<Vec<(&String, &Vec<(String, Arc<(usize, RefCell<Page>)>)>)>> =
-            Arc::new(pages_vec);
+impl Iterator for TableRows {
+    type Item = Result<Vec<(String, Value)>>;
 
-        let mut debug = pages_final
-            .iter()
-            .map(|res| res.0.clone().split("|").collect::<Vec<_>>()[0].to_owned())
-            .collect::<Vec<_>>();
-        debug.sort();
-
-        /*
-           if page.type_page == PageType::INTERIORTABLE {
-               println!("--------INTERIOR PAGE--------");
-               //println!("VEC_PAGES INTERIOR PAGE:{:?}",pages_final);
-               //return rows of table
-               //let res = page.clone().parse_page("companies".into(), file);
-               //println!("TEST: {:?}",res);
-               println!("--------INTERIOR PAGE--------");
-               //println!("{:?}", debug);
-               panic!("STOP!!!");
-           }else {
-           }
-        */
-        //println!("{:?}", debug);
-        let mut found = false;
-        let mut resp_final: Vec<(String, String)> = vec![];
-
-        let compare_keys = |search_key: &str, pr_key: &str| match (
-            search_key.parse::<usize>(),
-            pr_key.parse::<usize>(),
-        ) {
-            (Ok(search_key), Ok(pr_key)) => !search_key.gt(&pr_key),
-            _ => !search_key.gt(pr_key),
-        };
+    fn next(&mut self) -> Option<Self::Item> {
+        let row = self.next_row();
+        // nothing is read past an error
+        if row.is_err() {
+            self.path.clear();
+        }
+        row.transpose()
+    }
+}
 
-        for page in pages.iter() {
-            let pr_key = page.0.split("|").collect::<Vec<_>>()[0].to_owned();
-            if compare_keys(search_key, &pr_key) {
-                let repeated_pages = page.1;
-
-                //println!("REAPEATED PAGES LENGTH: {:?}", repeated_pages.len());
-                let _repeated_pages_len = page.0.len();
-                for j in repeated_pages {
-                    //println!("<-----------GOING LEFT KEY|PAGENUMBER {} SEARCH KEY: {search_key}. LEN: {repeated_pages_len} ",page.0);
-                    //
-                    let res = self.i_search_index(
-                        &j.1 .1.borrow(),
-                        search_key,
-                        pr_key.clone(),
-                        file,
-                        pages.clone(),
-                    );
-                    res.iter().for_each(|c| resp_final.push(c.clone()));
-                }
-                found = true;
+/// Walks an index B-tree in key order, yielding the entries whose first
+/// column is within `range`. Subtrees that only hold keys before the range
+/// are skipped, and the walk ends at the first key past it.
+struct IndexEntries {
+    file: Arc<File>,
+    range: KeyRange,
+    path: Vec<PathPage>,
+    interrupted: Arc<AtomicBool>,
+}
 
-                break;
+impl IndexEntries {
+    fn new(
+        mut file: Arc<File>,
+        root: Page,
+        range: KeyRange,
+        interrupted: Arc<AtomicBool>,
+    ) -> Result<IndexEntries> {
+        let root = PathPage::new(&mut file, root)?;
+        Ok(IndexEntries {
+            file,
+            range,
+            path: vec![root],
+            interrupted,
+        })
+    }
+
+    fn next_entry(&mut self) -> Result<Option<Vec<Value>>> {
+        loop {
+            let Some(level) = self.path.last_mut() else {
+                return Ok(None);
+            };
+            if self.interrupted.load(Ordering::Relaxed) {
+                return Err(Error::Interrupted);
+            }
+            let interior = level.page.type_page == PageType::INTERIORINDEX;
+            // back from the left subtree of the cell holding `key`
+            if let Some(key) = level.key.take() {
+                level.next += 1;
+                let first = key.first().cloned().unwrap_or_default();
+                if self.range.is_after(&first) {
+                    self.path.clear();
+                    return Ok(None);
+                }
+                if self.range.contains(&first) {
+                    return Ok(Some(key));
+                }
+                continue;
             }
+            let child = match level.cells.get(level.next) {
+                Some(&cell) => {
+                    let (left_child, key) = level.page.index_cell(&mut self.file, cell)?;
+                    let first = key.first().cloned().unwrap_or_default();
+                    let before = self.range.is_before(&first);
+                    level.key = Some(key);
+                    // interior cells hold index entries too, after their
+                    // left subtree
+                    match interior && !before {
+                        true => left_child,
+                        _ => continue,
+                    }
+                }
+                // the right most child is read after the cells
+                None if interior && level.next == level.cells.len() => {
+                    level.next += 1;
+                    level.page.right_page_number
+                }
+                None => {
+                    self.path.pop();
+                    continue;
+                }
+            };
+            PathPage::descend(&mut self.path, &mut self.file, child)?;
         }
+    }
+}
 
-        if !found && right_chield.is_ok() {
-            //println!(" GOING RIGHT {payload} ----------> SEARCH KEY: {search_key}");
-            let res = self.i_search_index(
-                &right_chield.unwrap().1.borrow(),
-                search_key,
-                payload.clone(),
-                file,
-                pages.clone(),
-            );
-            return res;
-        } else {
-            resp_final
+impl Iterator for IndexEntries {
+    type Item = Result<Vec<Value>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.next_entry();
+        if entry.is_err() {
+            self.path.clear();
         }
+        entry.transpose()
     }
 }
 
@@ -1272,11 +947,13 @@ mod tests {
 
     use super::*;
     use crate::db::db::Db;
+    use crate::db::record::serial_type_size;
+    use crate::sql::schema::ColumnConstraint;
 
     fn get_db_instance(db_name: String) -> Db {
         let db_file_path: String =
-            format!("/Users/ollaid/codecrafters/codecrafters-sqlite-rust/{db_name}.db");
-        let db = Db::new(db_file_path.clone());
+            format!("{db_name}.db");
+        let db = Db::new(db_file_path.clone()).expect("open database");
         db
     }
 
@@ -1345,73 +1022,44 @@ mod tests {
     fn get_column_names_db_sample() {
         let db = get_db_instance("sample".into());
 
-        let table_name: String = "oranges".into();
-
-        let res: Vec<Vec<String>> = [
-            [
-                "id".into(),
-                "integer".into(),
-                "primary".into(),
-                "key".into(),
-                "autoincrement".into(),
-            ]
-            .to_vec(),
-            ["name".into(), "text".into()].to_vec(),
-            ["description".into(), "text".into()].to_vec(),
-        ]
-        .to_vec();
-
-        let col_names_db = db
+        let schema = db
             .get_schema_page()
             .borrow()
-            .rows
-            .get("oranges")
-            .unwrap()
-            .1
-            .borrow()
-            .get_rows_colum_names(table_name, false);
-        println!("{:?}", col_names_db);
+            .table_schema("oranges")
+            .expect("oranges table schema");
+        println!("{:?}", schema);
 
-        assert!(col_names_db.iter().eq(res.iter()));
+        assert_eq!(schema.column_names(), ["id", "name", "description"]);
+        let types = schema.columns.iter().map(|c| c.type_name.as_str());
+        assert!(types.eq(["integer", "text", "text"]));
+        assert_eq!(
+            schema.columns[0].constraints,
+            [ColumnConstraint::PrimaryKey {
+                descending: false,
+                autoincrement: true
+            }]
+        );
+        assert!(db.get_schema_page().borrow().table_schema("apples").is_ok());
+        assert!(db.get_schema_page().borrow().table_schema("pears").is_err());
     }
 
     #[test]
     fn test_decode_varint() {
         let offset_oranges_cell_1_size_record = 3779;
         let offset_oranges_cell_sql_squema = 3786;
-        let db = get_db_instance("sample".into());
-        let mut file = db.get_file();
-        let schema_page = db.get_schema_page();
-        let (bytes, value) = schema_page
-            .borrow()
-            .decode_var_int(offset_oranges_cell_1_size_record, &mut file)
-            .expect("DECODE VARINT FAILED");
-        assert_eq!(value, 120);
-        assert_eq!(bytes.len(), 1);
-
-        let (bytes, value) = schema_page
-            .borrow()
-            .decode_var_int(offset_oranges_cell_sql_squema, &mut file)
-            .expect("DECODE VARINT FAILED");
-
-        assert_eq!(value, 199);
-        assert_eq!(bytes.len(), 2);
+        let bytes = std::fs::read("sample.db").unwrap();
+        let (value, size) = read_varint(&bytes[offset_oranges_cell_1_size_record..]);
+        assert_eq!((value, size), (120, 1));
+        let (value, size) = read_varint(&bytes[offset_oranges_cell_sql_squema..]);
+        assert_eq!((value, size), (199, 2));
     }
 
     #[test]
-    fn test_get_size_from_varint() {
+    fn test_serial_type_size() {
         let offset_oranges_cell_sql_squema = 3786;
-        let db = get_db_instance("sample".into());
-        let mut file = db.get_file();
-        let schema_page = db.get_schema_page();
-        let size = schema_page.borrow().get_size_from_varint(
-            schema_page
-                .borrow()
-                .decode_var_int(offset_oranges_cell_sql_squema, &mut file)
-                .expect("DECODE VARINT FAILED")
-                .1,
-        );
-        assert_eq!(size.1, 93);
+        let bytes = std::fs::read("sample.db").unwrap();
+        let (serial_type, _) = read_varint(&bytes[offset_oranges_cell_sql_squema..]);
+        assert_eq!(serial_type_size(serial_type), 93);
     }
 
     #[test]
@@ -1442,7 +1090,8 @@ mod tests {
         ];
         let actual_rows = schema_page
             .borrow_mut()
-            .get_table_data(&mut file, table_name.to_string());
+            .get_table_data(&mut file, table_name.to_string())
+            .unwrap();
 
         let mut actual_sorted = actual_rows.clone();
         let mut expected_sorted = expected_rows.clone();
@@ -1470,7 +1119,8 @@ mod tests {
 
         let actual_rows = schema_page
             .borrow_mut()
-            .get_table_data(&mut file, table_name.to_string());
+            .get_table_data(&mut file, table_name.to_string())
+            .unwrap();
 
         let mut actual_sorted = actual_rows.clone();
         let mut expected_sorted = expected_rows.clone();
@@ -1481,65 +1131,44 @@ mod tests {
         //assert_eq!(actual_sorted, expected_sorted);
     }
 
-    #[test]
-    fn test_order_columns() {
-        let db = get_db_instance("sample".into());
-        let _schema_page = db.get_schema_page();
-
-        // Create a row with columns in random order
-        let unordered_row = vec![
-            ("description".to_string(), "great for snacking".to_string()),
-            ("name".to_string(), "Mandarin".to_string()),
-            ("id".to_string(), "1".to_string()),
-        ];
-
-        let unordered_row = unordered_row.iter().map(|cl| cl).collect::<Vec<_>>();
-
-        // Define desired column order
-        let column_order = &["name", "description", "id"];
-
-        // Expected ordered result
-        let expected_ordered = vec![
-            ("name".to_string(), "Mandarin".to_string()),
-            ("description".to_string(), "great for snacking".to_string()),
-            ("id".to_string(), "1".to_string()),
-        ];
-
-        // Get actual ordered result
-        let actual_ordered = Page::order_row_columns(column_order, unordered_row);
-
-        assert_eq!(
-            actual_ordered, expected_ordered,
-            "Columns were not ordered correctly according to specified order"
-        );
-    }
-    #[test]
-    fn test_get_varints_byte_array() {
-        let db = get_db_instance("superheroes".into());
-        let file = &mut db.get_file();
-        let schema_page = db.get_schema_page();
-        let page = schema_page.borrow();
-        let row_offset = 3728;
-        let byte_array_header_size = 7usize;
-        let actual_res: Vec<usize> =
-            page.get_varints_byte_array(file, byte_array_header_size, row_offset);
-        let expected = vec![7, 17, 23, 23, 1, 193];
-        //assert!(actual_res.iter().eq(expected.iter()))
-        assert!(true)
+    /// Rows of `table_name` whose `index_name` key is `key`, with their
+    /// values as text.
+    fn search_index_key(
+        db: &Db,
+        table_name: &str,
+        index_name: &str,
+        key: &str,
+    ) -> Vec<Vec<(String, String)>> {
+        let key = Value::Text(key.into());
+        let range = KeyRange {
+            lower: Bound::Included(key.clone()),
+            upper: Bound::Included(key),
+            collation: Collation::Binary,
+            descending: false,
+        };
+        db.get_schema_page()
+            .borrow()
+            .search_index_range(
+                &mut db.get_file(),
+                (table_name.into(), index_name.into()),
+                &range,
+                &Arc::default(),
+            )
+            .unwrap()
+            .map(|row| {
+                row.unwrap()
+                    .into_iter()
+                    .map(|(name, value)| (name, value.to_string()))
+                    .collect()
+            })
+            .collect()
     }
 
     #[test]
     fn test_index_search_oranges() {
         //
         let db = get_db_instance("sample".into());
-        let file = &mut db.get_file();
-        let schema_page = db.get_schema_page();
-        let page = schema_page.borrow();
-        let res = page.search_index_country(
-            file,
-            ("oranges".into(), "name_index".into()),
-            "Mandarin".into(),
-        );
+        let res = search_index_key(&db, "oranges", "name_index", "Mandarin");
 
         // Expect at least one matching row and that it contains name and description for Mandarin
         assert_eq!(res.len(), 1, "Expected exactly one row for 'Mandarin'");
@@ -1558,14 +1187,7 @@ mod tests {
     fn test_index_search_companies() {
         //
         let db = get_db_instance("companies".into());
-        let file = &mut db.get_file();
-        let schema_page = db.get_schema_page();
-        let page = schema_page.borrow();
-        let res = page.search_index_country(
-            file,
-            ("companies".into(), "idx_companies_country".into()),
-            "eritrea".into(),
-        );
+        let res = search_index_key(&db, "companies", "idx_companies_country", "eritrea");
 
         // Expect at least one matching row where the country column equals "eritrea"
         assert!(
@@ -1586,18 +1208,94 @@ mod tests {
         let schema_page = db.get_schema_page();
         let page = schema_page.borrow();
 
-        // use search_by_id instead of calling i_search_index directly
-        let result = page.search_by_id(file, "oranges".into(), "1".into());
+        let result = page
+            .search_table_rowid(file, "oranges", 1)
+            .unwrap()
+            .expect("Expected a row for id = 1");
 
         // Expect at least the name and description columns for id = 1
-        assert!(!result.is_empty(), "Expected a row for id = 1");
-        let has_name = result.iter().any(|(k, v)| k == "name" && v == "Mandarin");
+        let has_name = result
+            .iter()
+            .any(|(k, v)| k == "name" && *v == Value::Text("Mandarin".into()));
         let has_description = result
             .iter()
-            .any(|(k, v)| k == "description" && v == "great for snacking");
+            .any(|(k, v)| k == "description" && *v == Value::Text("great for snacking".into()));
         assert!(
             has_name && has_description,
             "Row for id=1 did not contain expected name/description"
         );
+        assert_eq!(page.search_table_rowid(file, "oranges", 99).unwrap(), None);
+    }
+
+    #[test]
+    fn test_interrupted_walk() {
+        let db = get_db_instance("sample".into());
+        let file = &mut db.get_file();
+        let schema_page = db.get_schema_page();
+        let page = schema_page.borrow();
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let mut rows = page.scan_table(file, "oranges", &interrupted).unwrap();
+        assert!(rows.next().unwrap().is_ok());
+        interrupted.store(true, Ordering::Relaxed);
+        assert!(matches!(rows.next(), Some(Err(Error::Interrupted))));
+        assert!(rows.next().is_none());
+
+        let all = KeyRange {
+            lower: Bound::Unbounded,
+            upper: Bound::Unbounded,
+            collation: Collation::Binary,
+            descending: false,
+        };
+        let names = ("oranges".into(), "name_index".into());
+        let mut rows = page
+            .search_index_range(file, names, &all, &interrupted)
+            .unwrap();
+        assert!(matches!(rows.next(), Some(Err(Error::Interrupted))));
+    }
+
+    #[test]
+    fn test_virtual_generated_columns() {
+        let schema = parse_create_table(
+            "CREATE TABLE p (id INTEGER PRIMARY KEY, price REAL, \
+             total GENERATED ALWAYS AS (price * 2) VIRTUAL, ref INT, \
+             tag AS (ref || '/' || total), stored AS (ref + 1) STORED)",
+        )
+        .unwrap();
+        // the record has no value for total and tag
+        let record = vec![
+            Value::Null,
+            Value::Integer(2),
+            Value::Integer(3),
+            Value::Integer(4),
+        ];
+        let row = Page::table_row(&schema, 7, record).unwrap();
+        let values = row.into_iter().map(|(_, value)| value).collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            [
+                Value::Integer(7),
+                Value::Real(2.0),
+                Value::Real(4.0),
+                Value::Integer(3),
+                Value::Text("3/4.0".into()),
+                Value::Integer(4),
+            ]
+        );
+
+        let schema = parse_create_table(
+            "CREATE TABLE w (k TEXT PRIMARY KEY, d AS (v * 10), v INT) WITHOUT ROWID",
+        )
+        .unwrap();
+        assert_eq!(schema.record_columns(), ["k", "v"]);
+        let row = Page::clustered_row(&schema, vec![Value::Text("x".into()), Value::Integer(4)]);
+        let values = row.unwrap().into_iter().map(|(_, value)| value);
+        assert_eq!(
+            values.collect::<Vec<_>>(),
+            [
+                Value::Text("x".into()),
+                Value::Integer(40),
+                Value::Integer(4)
+            ]
+        );
     }
 }
@@ -0,0 +1,255 @@
+//! The record format stored in table and index B-tree cells: decoding, and
+//! encoding for the rows written to a table.
+
+use crate::sql::value::Value;
+
+/// Encoding of the text values of a database, set in its header.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TextEncoding {
+    #[default]
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+impl TextEncoding {
+    /// The encoding for the header field value: 1 for UTF-8, 2 for UTF-16le
+    /// and 3 for UTF-16be.
+    pub fn from_header(value: u32) -> TextEncoding {
+        match value {
+            2 => TextEncoding::Utf16Le,
+            3 => TextEncoding::Utf16Be,
+            _ => TextEncoding::Utf8,
+        }
+    }
+
+    pub fn decode(self, bytes: &[u8]) -> String {
+        let units = |from_bytes: fn([u8; 2]) -> u16| {
+            bytes
+                .chunks_exact(2)
+                .map(|pair| from_bytes([pair[0], pair[1]]))
+                .collect::<Vec<_>>()
+        };
+        match self {
+            TextEncoding::Utf8 => String::from_utf8_lossy(bytes).to_string(),
+            TextEncoding::Utf16Le => String::from_utf16_lossy(&units(u16::from_le_bytes)),
+            TextEncoding::Utf16Be => String::from_utf16_lossy(&units(u16::from_be_bytes)),
+        }
+    }
+
+    pub fn encode(self, text: &str) -> Vec<u8> {
+        match self {
+            TextEncoding::Utf8 => text.as_bytes().to_vec(),
+            TextEncoding::Utf16Le => text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+            TextEncoding::Utf16Be => text.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+        }
+    }
+}
+
+/// Reads the varint at the start of `bytes`, returning its value and length.
+pub fn read_varint(bytes: &[u8]) -> (u64, usize) {
+    let mut value = 0u64;
+    for (i, byte) in bytes.iter().take(9).enumerate() {
+        // the ninth byte contributes all of its 8 bits
+        if i == 8 {
+            return ((value << 8) | *byte as u64, 9);
+        }
+        value = (value << 7) | (byte & 0x7f) as u64;
+        if byte & 0x80 == 0 {
+            return (value, i + 1);
+        }
+    }
+    (value, bytes.len().min(9))
+}
+
+/// Appends `value` as a varint: 7 bits per byte, high bits first, and all 8
+/// bits of a ninth byte.
+pub fn write_varint(value: u64, out: &mut Vec<u8>) {
+    if value >> 56 != 0 {
+        for i in (1..=8).rev() {
+            out.push((value >> (i * 7 + 1)) as u8 | 0x80);
+        }
+        out.push(value as u8);
+        return;
+    }
+    let len = (1..=8).find(|len| value >> (len * 7) == 0).unwrap_or(8);
+    for i in (1..len).rev() {
+        out.push((value >> (i * 7)) as u8 | 0x80);
+    }
+    out.push(value as u8 & 0x7f);
+}
+
+/// Number of bytes used by a value with the given serial type.
+pub fn serial_type_size(serial_type: u64) -> usize {
+    match serial_type {
+        0 | 8 | 9 | 10 | 11 => 0,
+        1 => 1,
+        2 => 2,
+        3 => 3,
+        4 => 4,
+        5 => 6,
+        6 | 7 => 8,
+        n => ((n - 12) / 2) as usize,
+    }
+}
+
+fn decode_value(serial_type: u64, bytes: &[u8], encoding: TextEncoding) -> Value {
+    match serial_type {
+        0 | 10 | 11 => Value::Null,
+        1..=6 => {
+            // big endian two's complement, sign extended from the first byte
+            let init = match bytes.first() {
+                Some(byte) if byte & 0x80 != 0 => -1i64,
+                _ => 0,
+            };
+            Value::Integer(bytes.iter().fold(init, |acc, b| (acc << 8) | *b as i64))
+        }
+        7 => Value::Real(f64::from_be_bytes(bytes.try_into().unwrap_or([0; 8]))),
+        8 => Value::Integer(0),
+        9 => Value::Integer(1),
+        n if n % 2 == 0 => Value::Blob(bytes.to_vec()),
+        _ => Value::Text(encoding.decode(bytes)),
+    }
+}
+
+/// Decodes a record: a header of serial types followed by the values, with
+/// text in the database's encoding. Values cut short by a truncated payload
+/// decode as NULL.
+pub fn decode_record(payload: &[u8], encoding: TextEncoding) -> Vec<Value> {
+    let (header_size, mut header_offset) = read_varint(payload);
+    let header_size = (header_size as usize).min(payload.len());
+    let mut data_offset = header_size;
+    let mut values = vec![];
+    while header_offset < header_size {
+        let (serial_type, len) = read_varint(&payload[header_offset..header_size]);
+        header_offset += len;
+        let size = serial_type_size(serial_type);
+        values.push(match payload.get(data_offset..data_offset + size) {
+            Some(bytes) => decode_value(serial_type, bytes, encoding),
+            _ => Value::Null,
+        });
+        data_offset += size;
+    }
+    values
+}
+
+/// Encodes `values` as a record, integers in the fewest bytes that hold
+/// them and text in the database's encoding.
+pub fn encode_record(values: &[Value], encoding: TextEncoding) -> Vec<u8> {
+    let mut header = vec![];
+    let mut body = vec![];
+    for value in values {
+        let serial_type = match value {
+            Value::Null => 0,
+            Value::Integer(i) => {
+                let (serial_type, size) = match *i {
+                    -0x80..=0x7f => (1, 1),
+                    -0x8000..=0x7fff => (2, 2),
+                    -0x80_0000..=0x7f_ffff => (3, 3),
+                    -0x8000_0000..=0x7fff_ffff => (4, 4),
+                    -0x8000_0000_0000..=0x7fff_ffff_ffff => (5, 6),
+                    _ => (6, 8),
+                };
+                body.extend_from_slice(&i.to_be_bytes()[8 - size..]);
+                serial_type
+            }
+            Value::Real(r) => {
+                body.extend_from_slice(&r.to_be_bytes());
+                7
+            }
+            Value::Text(s) => {
+                let bytes = encoding.encode(s);
+                body.extend_from_slice(&bytes);
+                bytes.len() as u64 * 2 + 13
+            }
+            Value::Blob(b) => {
+                body.extend_from_slice(b);
+                b.len() as u64 * 2 + 12
+            }
+        };
+        write_varint(serial_type, &mut header);
+    }
+    // the header size counts itself, which may take a byte more
+    let mut size = header.len() + 1;
+    let mut record = vec![];
+    write_varint(size as u64, &mut record);
+    if record.len() > 1 {
+        size += record.len() - 1;
+        record.clear();
+        write_varint(size as u64, &mut record);
+    }
+    record.extend(header);
+    record.extend(body);
+    record
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_varint() {
+        assert_eq!(read_varint(&[0x01]), (1, 1));
+        assert_eq!(read_varint(&[0x81, 0x00]), (128, 2));
+        assert_eq!(read_varint(&[0xff; 9]), (u64::MAX, 9));
+    }
+
+    #[test]
+    fn test_write_varint() {
+        for value in [0, 1, 127, 128, 16383, 16384, 1 << 56, u64::MAX] {
+            let mut bytes = vec![];
+            write_varint(value, &mut bytes);
+            assert_eq!(read_varint(&bytes), (value, bytes.len()));
+        }
+        let mut bytes = vec![];
+        write_varint(128, &mut bytes);
+        assert_eq!(bytes, [0x81, 0x00]);
+    }
+
+    #[test]
+    fn test_encode_record() {
+        let values = vec![
+            Value::Null,
+            Value::Integer(-2),
+            Value::Integer(1 << 40),
+            Value::Real(0.5),
+            Value::Text("abc".into()),
+            Value::Blob(vec![1, 2]),
+        ];
+        let record = encode_record(&values, TextEncoding::Utf8);
+        assert_eq!(record[..7], [7, 0, 1, 5, 7, 19, 16]);
+        assert_eq!(decode_record(&record, TextEncoding::Utf8), values);
+        let record = encode_record(&values[4..], TextEncoding::Utf16Be);
+        assert_eq!(decode_record(&record, TextEncoding::Utf16Be), values[4..]);
+    }
+
+    #[test]
+    fn test_decode_record() {
+        // header: size 5, NULL, 1 byte int, text of 3 bytes, constant 1
+        let payload = [5, 0, 1, 19, 9, 0xfe, b'a', b'b', b'c'];
+        assert_eq!(
+            decode_record(&payload, TextEncoding::Utf8),
+            vec![
+                Value::Null,
+                Value::Integer(-2),
+                Value::Text("abc".into()),
+                Value::Integer(1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_utf16_text() {
+        // header: size 2, text of 4 bytes
+        let le = [2, 21, b'h', 0, 0xe9, 0];
+        let be = [2, 21, 0, b'h', 0, 0xe9];
+        let text = Value::Text("hé".into());
+        assert_eq!(
+            decode_record(&le, TextEncoding::Utf16Le),
+            vec![text.clone()]
+        );
+        assert_eq!(decode_record(&be, TextEncoding::Utf16Be), vec![text]);
+        assert_eq!(TextEncoding::from_header(1), TextEncoding::Utf8);
+        assert_eq!(TextEncoding::from_header(3), TextEncoding::Utf16Be);
+    }
+}
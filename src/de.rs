@@ -0,0 +1,372 @@
+//! Deserialization of result rows with serde. A row deserializes as a map
+//! from column names to values, so into a struct whose fields are named
+//! after the columns, as a sequence of values, so into a tuple, or, when it
+//! has a single column, as the value of that column.
+//!
+//! Values convert like [`FromValue`](crate::FromValue): integers to any
+//! integer type they fit in, integers and reals to floats, text to strings,
+//! blobs and text to bytes and NULL to None. Text also deserializes into a
+//! unit enum variant of the same name.
+
+use std::fmt::Display;
+use std::slice;
+
+use serde::de::{self, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess, Visitor};
+use serde::forward_to_deserialize_any;
+
+use crate::connection::Column;
+use crate::error::{Error, Result};
+use crate::sql::value::Value;
+
+impl de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Error {
+        Error::Deserialize(msg.to_string())
+    }
+}
+
+/// Prefixes a deserialization error with the column it comes from.
+fn in_column(error: Error, column: impl Display) -> Error {
+    match error {
+        Error::Deserialize(msg) => Error::Deserialize(format!("column {column}: {msg}")),
+        error => error,
+    }
+}
+
+pub(crate) struct RowDeserializer<'a> {
+    columns: &'a [Column],
+    values: &'a [Value],
+}
+
+impl<'a> RowDeserializer<'a> {
+    pub(crate) fn new(columns: &'a [Column], values: &'a [Value]) -> Self {
+        RowDeserializer { columns, values }
+    }
+
+    fn single_value(&self) -> Result<ValueDeserializer<'a>> {
+        match self.values {
+            [value] => Ok(ValueDeserializer(value)),
+            values => Err(Error::Deserialize(format!(
+                "expected a single column, got {}",
+                values.len()
+            ))),
+        }
+    }
+}
+
+macro_rules! single_value {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+                self.single_value()?.$method(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for RowDeserializer<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_map(RowMap {
+            columns: self.columns.iter(),
+            values: self.values.iter(),
+            column: None,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_seq(RowSeq {
+            values: self.values.iter(),
+            position: 0,
+        })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.single_value()?.deserialize_unit_struct(name, visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.single_value()?
+            .deserialize_enum(name, variants, visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    single_value! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_f32
+        deserialize_f64 deserialize_char deserialize_str deserialize_string deserialize_bytes
+        deserialize_byte_buf deserialize_option deserialize_unit deserialize_identifier
+    }
+}
+
+struct RowMap<'a> {
+    columns: slice::Iter<'a, Column>,
+    values: slice::Iter<'a, Value>,
+    column: Option<&'a str>,
+}
+
+impl<'de> MapAccess<'de> for RowMap<'_> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        match self.columns.next() {
+            Some(column) => {
+                self.column = Some(column.name());
+                seed.deserialize(column.name().into_deserializer())
+                    .map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        let value = self.values.next().unwrap_or(&Value::Null);
+        seed.deserialize(ValueDeserializer(value))
+            .map_err(|e| in_column(e, self.column.unwrap_or_default()))
+    }
+}
+
+struct RowSeq<'a> {
+    values: slice::Iter<'a, Value>,
+    position: usize,
+}
+
+impl<'de> SeqAccess<'de> for RowSeq<'_> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        match self.values.next() {
+            Some(value) => {
+                self.position += 1;
+                seed.deserialize(ValueDeserializer(value))
+                    .map(Some)
+                    .map_err(|e| in_column(e, self.position))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.values.len())
+    }
+}
+
+struct ValueDeserializer<'a>(&'a Value);
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Value::Null => visitor.visit_unit(),
+            Value::Integer(i) => visitor.visit_i64(*i),
+            Value::Real(r) => visitor.visit_f64(*r),
+            Value::Text(s) => visitor.visit_str(s),
+            Value::Blob(b) => visitor.visit_bytes(b),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Value::Integer(i) => visitor.visit_bool(*i != 0),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_f64(visitor)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Value::Integer(i) => visitor.visit_f64(*i as f64),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Value::Text(s) => visitor.visit_bytes(s.as_bytes()),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_bytes(visitor)
+    }
+
+    /// `Vec<u8>` deserializes as a sequence of bytes.
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let bytes = match self.0 {
+            Value::Blob(b) => b.as_slice(),
+            Value::Text(s) => s.as_bytes(),
+            _ => return self.deserialize_any(visitor),
+        };
+        visitor.visit_seq(de::value::SeqDeserializer::new(bytes.iter().copied()))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        match self.0 {
+            Value::Text(s) => visitor.visit_enum(s.as_str().into_deserializer()),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 char str string unit unit_struct
+        tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use crate::connection::Connection;
+    use crate::error::Error;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    enum Color {
+        Red,
+        Yellow,
+        #[serde(other)]
+        Other,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Apple {
+        id: u32,
+        name: String,
+        color: Option<String>,
+        // not a column of the result
+        weight: Option<f64>,
+    }
+
+    #[test]
+    fn test_query_as_struct() {
+        let connection = Connection::open("sample.db").unwrap();
+        let statement = connection
+            .prepare("SELECT * FROM apples ORDER BY id")
+            .unwrap();
+        let apples = statement
+            .query_as::<Apple>()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(apples.len(), 4);
+        assert_eq!(
+            apples[0],
+            Apple {
+                id: 1,
+                name: "Granny Smith".into(),
+                color: Some("Light Green".into()),
+                weight: None,
+            }
+        );
+
+        let statement = connection
+            .prepare("SELECT color FROM apples ORDER BY id")
+            .unwrap();
+        let colors = statement
+            .query_as::<Color>()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            colors,
+            [Color::Other, Color::Red, Color::Other, Color::Yellow]
+        );
+    }
+
+    #[test]
+    fn test_query_as_tuples_and_values() {
+        let connection = Connection::open("sample.db").unwrap();
+        let statement = connection
+            .prepare("SELECT id, name, id * 0.5 FROM oranges WHERE id = 2")
+            .unwrap();
+        let rows = statement
+            .query_as::<(i64, String, f64)>()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(rows, [(2, "Tangelo".to_string(), 1.0)]);
+
+        let statement = connection.prepare("SELECT count(*) FROM oranges").unwrap();
+        let count = statement.query_as::<usize>().unwrap().next().unwrap();
+        assert_eq!(count.unwrap(), 6);
+
+        let statement = connection.prepare("SELECT name FROM oranges").unwrap();
+        let error = statement.query_as::<(i64,)>().unwrap().next().unwrap();
+        assert!(matches!(error, Err(Error::Deserialize(msg)) if msg.starts_with("column 1: ")));
+        let error = statement.query_as::<Apple>().unwrap().next().unwrap();
+        assert!(
+            matches!(error, Err(Error::Deserialize(msg)) if msg.contains("missing field `id`"))
+        );
+    }
+}
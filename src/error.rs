@@ -0,0 +1,70 @@
+//! The error type returned by opening a database and running statements.
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// The file is not a database, or a page of it can't be decoded.
+    #[error("database disk image is malformed: page {page}: {reason}")]
+    Corrupt { page: usize, reason: String },
+    /// A statement that doesn't parse; `position` is the byte offset where
+    /// parsing stopped.
+    #[error("syntax error at position {position}: expected {expected}")]
+    Parse { position: usize, expected: String },
+    #[error("no such table: {0}")]
+    NoSuchTable(String),
+    #[error("no such column: {0}")]
+    NoSuchColumn(String),
+    /// Valid SQL or file contents this reader doesn't handle.
+    #[error("unsupported: {0}")]
+    Unsupported(String),
+    /// Any other error raised preparing or running a statement, such as an
+    /// unknown function or collation.
+    #[error("{0}")]
+    Sql(String),
+    /// A row that breaks a `NOT NULL`, `UNIQUE` or `STRICT` column type
+    /// constraint of its table.
+    #[error("{0}")]
+    Constraint(String),
+    /// The database is locked by another connection.
+    #[error("database is locked")]
+    Busy,
+    /// The statement was stopped through an `InterruptHandle`.
+    #[error("interrupted")]
+    Interrupted,
+    #[error("column index {0} out of range")]
+    InvalidColumnIndex(usize),
+    /// A parameter number not between 1 and the statement's parameter count.
+    #[error("parameter index {0} out of range")]
+    InvalidParameterIndex(usize),
+    #[error("no such parameter: {0}")]
+    InvalidParameterName(String),
+    /// A value read as a Rust type its storage class doesn't convert to.
+    #[error("cannot read {value} as {target}")]
+    InvalidColumnType { value: String, target: &'static str },
+    /// A row that doesn't fit the type given to `Statement::query_as`.
+    #[error("deserialization failed: {0}")]
+    Deserialize(String),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    pub(crate) fn corrupt(page: usize, reason: impl Into<String>) -> Error {
+        Error::Corrupt {
+            page,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl From<peg::error::ParseError<peg::str::LineCol>> for Error {
+    fn from(e: peg::error::ParseError<peg::str::LineCol>) -> Error {
+        Error::Parse {
+            position: e.location.offset,
+            expected: e.expected.to_string(),
+        }
+    }
+}
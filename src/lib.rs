@@ -0,0 +1,38 @@
+//! A reader for SQLite database files.
+//!
+//! ```no_run
+//! use codecrafters_sqlite::Connection;
+//!
+//! let connection = Connection::open("sample.db")?;
+//! let mut statement = connection.prepare("SELECT id, name FROM apples WHERE color = :color")?;
+//! statement.bind_name(":color", "Red")?;
+//! for row in statement.query()? {
+//!     let row = row?;
+//!     let name: String = row.get(1)?;
+//!     println!("{}: {name}", row.get::<i64>(0)?);
+//! }
+//! # Ok::<(), codecrafters_sqlite::Error>(())
+//! ```
+//!
+//! [`Statement::query_as`] deserializes rows into any `serde::Deserialize`
+//! type instead, such as a struct with fields named after the columns.
+
+mod connection;
+mod db;
+pub mod de;
+mod error;
+mod parser;
+mod query;
+mod sql;
+mod stats;
+mod write;
+
+pub use connection::{
+    Column, Connection, FromValue, InterruptHandle, Row, Rows, RowsAs, Statement,
+};
+pub use error::{Error, Result};
+pub use sql::collation::register_collation;
+pub use sql::datetime::set_localtime_offset;
+pub use sql::schema::SchemaEntry;
+pub use sql::value::Value;
+pub use stats::Stats;
@@ -1,187 +1,225 @@
-use std::sync::Arc;
-
-use anyhow::{bail, Ok, Result};
-use std::result::Result::Ok as OK;
-
-mod db;
-use db::db::Db;
-mod parser;
-use parser::parse_sql_;
-
-use crate::db::page::Page;
-use std::cell::RefCell;
-
-fn search_by_index(condition: &Option<String>) -> Option<(bool, String)> {
-    match condition {
-        Some(cond) => {
-            let vec = cond
-                .split("=")
-                .map(|s| s.trim().replace("'", ""))
-                .collect::<Vec<_>>();
-
-            let col_name = vec.get(0).unwrap();
-            let col_value = vec.get(1).unwrap();
+mod shell;
+
+use std::io::{IsTerminal, Read};
+
+use anyhow::{anyhow, bail, Result};
+
+use codecrafters_sqlite::{set_localtime_offset, Connection};
+use shell::{Action, Shell};
+
+const OPTIONS: &[(&str, &str)] = &[
+    ("--", "treat no subsequent arguments as options"),
+    ("-bail", "stop after hitting an error"),
+    ("-box", "set output mode to 'box'"),
+    ("-cmd COMMAND", "run \"COMMAND\" before reading stdin"),
+    ("-column", "set output mode to 'column'"),
+    ("-csv", "set output mode to 'csv'"),
+    ("-echo", "print inputs before execution"),
+    ("-init FILENAME", "read/process named file"),
+    ("-[no]header", "turn headers on or off"),
+    ("-help", "show this message"),
+    ("-html", "set output mode to HTML"),
+    ("-json", "set output mode to 'json'"),
+    ("-line", "set output mode to 'line'"),
+    ("-list", "set output mode to 'list'"),
+    ("-markdown", "set output mode to 'markdown'"),
+    ("-mode MODE", "set output mode to MODE"),
+    ("-ndjson", "set output mode to 'ndjson'"),
+    ("-newline SEP", "set output row separator. Default: '\\n'"),
+    (
+        "-nullvalue TEXT",
+        "set text string for NULL values. Default ''",
+    ),
+    ("-quote", "set output mode to 'quote'"),
+    ("-readonly", "open the database read-only"),
+    (
+        "-separator SEP",
+        "set output column separator. Default: '|'",
+    ),
+    ("-table", "set output mode to 'table'"),
+    ("-tabs", "set output mode to 'tabs'"),
+    (
+        "-utcoffset MINUTES",
+        "offset of 'localtime' from UTC. Default: 0",
+    ),
+    ("-version", "show the version"),
+];
+
+/// An output option, applied in the order given.
+enum Setting {
+    Mode(String),
+    /// `-csv`, which unlike `.mode csv` keeps the row separator.
+    Csv,
+    Headers(bool),
+    Separator(String),
+    Newline(String),
+    NullValue(String),
+}
 
-            Some((col_name.eq("country"), col_value.clone()))
-        }
-        _ => None,
+fn main() -> Result<()> {
+    match run(std::env::args())? {
+        0 => Ok(()),
+        code => std::process::exit(code),
     }
 }
 
-fn get_column_data(
-    table_name: String,
-    columns: Vec<String>,
-    schema_page: Arc<RefCell<Page>>,
-    db: &mut Arc<Db>,
-    condition: &Option<String>,
-) -> Result<String> {
-    let columns_refs: Vec<&str> = columns.iter().map(AsRef::as_ref).collect();
-    let columns_names = match search_by_index(condition) {
-        Some((true, key)) => {
-            let res = schema_page.borrow_mut().search_index_country(
-                &mut db.get_file(),
-                (table_name, "idx_companies_country".into()),
-                key,
-            );
-            vec![res]
+/// Runs the shell as the command line `args` ask, returning the exit status.
+fn run(mut args: impl Iterator<Item = String>) -> Result<i32> {
+    let program = args.next().unwrap_or_default();
+
+    // options can come anywhere, up to a `--`
+    let mut positional = vec![];
+    let mut settings = vec![];
+    let mut init = None;
+    let mut commands = vec![];
+    let (mut bail, mut echo, mut read_only) = (false, false, false);
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            positional.extend(args.by_ref());
+            break;
         }
-        _ => schema_page
-            .borrow_mut()
-            .get_table_data(&mut db.get_file(), table_name.clone()),
-    };
-
-    let res = columns_names[0]
-        .iter()
-        .filter(|row| match condition {
-            // filter for where clause
-            Some(cond) => {
-                // only when where clause is available
-                let cond = cond
-                    .split("=")
-                    .map(|s| s.trim().replace("'", ""))
-                    .collect::<Vec<_>>();
-
-                let col_name = cond.get(0).unwrap();
-                let col_value = cond.get(1).unwrap();
-
-                let (index, colum_name) = row
-                    .iter()
-                    .enumerate()
-                    .find(|c| c.1 .0.eq(col_name))
-                    .unwrap();
-                assert!(colum_name.0.eq(col_name));
-                row[index].1.eq(col_value)
+        let option = arg.strip_prefix("--").or(arg.strip_prefix('-'));
+        let Some(option) = option.filter(|option| !option.is_empty()) else {
+            positional.push(arg);
+            continue;
+        };
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| anyhow!("Missing argument to {arg}"))
+        };
+        match option {
+            "bail" => bail = true,
+            "echo" => echo = true,
+            "readonly" => read_only = true,
+            "header" => settings.push(Setting::Headers(true)),
+            "noheader" => settings.push(Setting::Headers(false)),
+            "csv" => settings.push(Setting::Csv),
+            "box" | "column" | "html" | "json" | "line" | "list" | "markdown" | "ndjson"
+            | "quote" | "table" | "tabs" => settings.push(Setting::Mode(option.to_string())),
+            "mode" => settings.push(Setting::Mode(value()?)),
+            "separator" => settings.push(Setting::Separator(value()?)),
+            "newline" => settings.push(Setting::Newline(value()?)),
+            "nullvalue" => settings.push(Setting::NullValue(value()?)),
+            "init" => init = Some(value()?),
+            "cmd" => commands.push(value()?),
+            "utcoffset" => {
+                let minutes = value()?;
+                match minutes.parse() {
+                    Ok(minutes) => set_localtime_offset(minutes),
+                    Err(_) => bail!("invalid offset from UTC: {minutes}"),
+                }
             }
-            _ => true,
-        })
-        .map(|row| row.clone())
-        .collect::<Vec<_>>();
-
-    let filtered = Page::filter_columns(&columns_refs.as_ref(), res);
-    let resp = filtered.iter().map(|c| c.join("|")).collect::<Vec<_>>();
-    let out = resp.join("\n");
-    Ok(out)
-}
-
-fn handle_sql_query(sql_query: String, db: &mut Arc<Db>) -> Result<String> {
-    let is_select = sql_query.to_ascii_lowercase().starts_with("select");
-    assert!(is_select); // must be a select
-    let (columns, table_name, condition) = parse_sql_(sql_query).expect("FAILED TO PARSE SQL");
-    let count: String = "count(*)".into();
-
-    let schema_page = db.get_schema_page();
-    match columns.join("").to_ascii_lowercase().contains(&count) {
-        true => {
-            let res = schema_page.borrow().get_cell_count_page_schema(table_name);
-            Ok(res.unwrap().to_string())
-        }
-        _ => match columns.iter().any(|c| c.eq("*")) {
-            true => {
-                let columns: Vec<String> = schema_page
-                    .borrow()
-                    .get_rows_colum_names(table_name.clone(), false)
-                    .iter()
-                    .map(|c| {
-                        let col = c[0].clone();
-                        col
-                    })
-                    .collect();
-
-                get_column_data(table_name, columns, schema_page, db, &condition)
-                // display all columns
+            "version" => {
+                println!("{}", env!("CARGO_PKG_VERSION"));
+                return Ok(0);
+            }
+            "help" => {
+                println!("Usage: {program} [OPTIONS] FILENAME [SQL...]");
+                println!("FILENAME is the name of an SQLite database. The SQL statements and");
+                println!("dot-commands after it run in order; without any, they are read");
+                println!("from stdin.");
+                println!("OPTIONS include:");
+                for (option, description) in OPTIONS {
+                    println!("   {option:<20} {description}");
+                }
+                return Ok(0);
             }
-            _ => {
-                let res = get_column_data(table_name, columns, schema_page, db, &condition);
-                res
-            } // just for some columns
-        },
+            _ => bail!("unknown option: {arg}\nUse -help for a list of options."),
+        }
     }
-}
+    let Some((path, sql)) = positional.split_first() else {
+        bail!("Missing <database path>");
+    };
 
-fn main() -> Result<()> {
-    //Parse arguments
-    let args = std::env::args().collect::<Vec<_>>();
-    match args.len() {
-        0 | 1 => bail!("Missing <database path> and <command>"),
-        2 => bail!("Missing <command>"),
-        _ => {}
+    let connection = match read_only {
+        true => Connection::open_read_only(path)?,
+        false => Connection::open(path)?,
+    };
+    let mut shell = Shell::new(connection);
+    let mut newline = "\n".to_string();
+    for setting in settings {
+        match setting {
+            Setting::Mode(mode) => shell.set_mode(&mode)?,
+            Setting::Csv => {
+                shell.set_mode("csv")?;
+                shell.set_separators(None, Some(&newline));
+            }
+            Setting::Headers(headers) => shell.set_headers(headers),
+            Setting::Separator(separator) => shell.set_separators(Some(&separator), None),
+            Setting::Newline(separator) => {
+                shell.set_separators(None, Some(&separator));
+                newline = separator;
+            }
+            Setting::NullValue(text) => shell.set_null_value(&text),
+        }
     }
-
-    // Parse command and act accordingly
-    let command = &args[2];
-
-    let mut db: Arc<Db> = Arc::new(Db::new(args[1].clone()));
-
-    match command.as_str() {
-        ".dbinfo" => {
-            println!("database page size: {:?}", db.get_page_size());
-
-            println!("number of tables: {}", db.get_table_count_schema_page());
+    shell.set_bail(bail);
+    shell.set_echo(echo);
+
+    // the init script, then the -cmd commands, run before any other input
+    if let Some(init) = init {
+        let Ok(script) = std::fs::read_to_string(&init) else {
+            bail!("cannot open \"{init}\"");
+        };
+        if let Action::Exit(code) = shell.run_script(&script)? {
+            return Ok(code);
         }
-        ".tables" => {
-            db.get_schema_page().borrow().display_cells();
+    }
+    for command in commands {
+        if let Action::Exit(code) = shell.execute(&command)? {
+            return Ok(code);
         }
-        _ => {
-            let res = handle_sql_query(command.to_string(), &mut db).unwrap();
+    }
 
-            match res.parse::<usize>() {
-                OK(count) => {
-                    println!("{:?}", count);
-                }
-                Err(_) => {
-                    // Simplified printing without quotes
-                    for row in res.split('\n') {
-                        println!("{}", row);
-                    }
-                }
+    // Run the arguments if there are any, otherwise read stdin: as a script
+    // when it's piped, or else with the interactive shell
+    if !sql.is_empty() {
+        for input in sql {
+            if let Action::Exit(code) = shell.execute(input)? {
+                return Ok(code);
+            }
+            // a script read by `.read` reports its own errors
+            if shell.failed() {
+                return Ok(1);
             }
         }
+    } else if std::io::stdin().is_terminal() {
+        if let Action::Exit(code) = shell.run_interactive()? {
+            return Ok(code);
+        }
+    } else {
+        let mut script = String::new();
+        std::io::stdin().read_to_string(&mut script)?;
+        if let Action::Exit(code) = shell.run_script(&script)? {
+            return Ok(code);
+        }
+        if shell.failed() {
+            return Ok(1);
+        }
     }
-    Ok(())
+    Ok(0)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    #[test]
-    fn test_handle_query() {
-        // Setup
-        let mut db = Arc::new(Db::new("sample.db".to_string()));
-
-        // Test COUNT(*) query
-        let count_query = "SELECT COUNT(*) FROM oranges;".to_string();
-        let count_result =
-            handle_sql_query(count_query, &mut db).expect("Failed to handle COUNT query");
-        assert_eq!(count_result, "6");
-
-        // Test SELECT * query
-        let select_query = "SELECT * FROM oranges;".to_string();
-        let select_result =
-            handle_sql_query(select_query, &mut db).expect("Failed to handle SELECT query");
+    fn args(input: &str) -> impl Iterator<Item = String> {
+        ["sqlite", "sample.db", input].map(String::from).into_iter()
+    }
 
-        // Split result into lines and verify content
-        let rows: Vec<&str> = select_result.split('\n').collect();
-        assert!(rows.len() == 6)
+    #[test]
+    fn test_exit_status() {
+        assert_eq!(run(args("SELECT 1")).unwrap(), 0);
+        assert_eq!(run(args(".exit 3")).unwrap(), 3);
+
+        // a script nested past the limit fails the run
+        let script = std::env::temp_dir().join("codecrafters_sqlite_nested.sql");
+        let read = format!(".read {}", script.display());
+        std::fs::write(&script, &read).unwrap();
+        assert_eq!(run(args(&read)).unwrap(), 1);
+        std::fs::write(&script, "SELECT 1;").unwrap();
+        assert_eq!(run(args(&read)).unwrap(), 0);
+        std::fs::remove_file(script).unwrap();
     }
 }
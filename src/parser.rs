@@ -1,66 +1,458 @@
-use regex::Regex;
+use crate::error::Result;
+use crate::sql::ast::{
+    BinaryOp, Expr, OrderingTerm, Parameter, PatternOp, ResultColumn, Select, UnaryOp,
+};
+use crate::sql::schema::{
+    ColumnConstraint, ColumnDef, ForeignKey, IndexedColumn, TableConstraint, TableSchema,
+};
+use crate::sql::value::Value;
 
-pub fn parse_sql_(sql: String) -> Option<(Vec<String>, String, Option<String>)> {
-    // Regex to capture columns and table name
-    let re =
-        Regex::new(r"(?i)select\s+(?P<columns>[\w\s,\(\)\*]+)\s+from\s+(?P<table>\w+)(?:\s+where\s+(?P<condition>[\w\s=\']+))?").unwrap();
+/// Index name, table name and indexed columns.
+pub type IndexDefinition = (String, String, Vec<IndexedColumn>);
 
-    if let Some(caps) = re.captures(&sql) {
-        let columns = caps.name("columns").unwrap().as_str();
-        let table = caps.name("table").unwrap().as_str();
+/// Reads `CREATE [UNIQUE] INDEX name ON table (columns)`. Partial indexes
+/// and indexes on expressions are not accepted since they can't answer
+/// plain column terms.
+pub fn parse_create_index(sql: &str) -> Result<IndexDefinition> {
+    Ok(sql_grammar::create_index(sql)?)
+}
+
+pub fn parse_select(sql: &str) -> Result<Select> {
+    Ok(sql_grammar::select(sql)?)
+}
+
+/// The statement after `EXPLAIN QUERY PLAN`, None if `sql` doesn't start
+/// with it.
+pub fn parse_explain_query_plan(sql: &str) -> Option<&str> {
+    sql_grammar::explain_query_plan(sql).ok()
+}
+
+pub fn parse_create_table(sql: &str) -> Result<TableSchema> {
+    Ok(sql_grammar::create_table(sql)?)
+}
+
+/// Reads `CREATE VIEW name [(columns)] AS select`, returning the column
+/// names if listed and the text of the SELECT.
+pub fn parse_create_view(sql: &str) -> Result<(Option<Vec<String>>, &str)> {
+    Ok(sql_grammar::create_view(sql)?)
+}
+
+peg::parser! {
+    grammar sql_grammar() for str {
+        pub rule select() -> Select
+            = _ kw("SELECT") _ columns:(result_column() ++ (_ "," _))
+              from:(_ kw("FROM") _ t:identifier() { t })?
+              where_clause:(_ kw("WHERE") _ e:expr() { e })?
+              order_by:(_ kw("ORDER") _ kw("BY") _ t:(ordering_term() ++ (_ "," _)) { t })?
+              _ (";" _)? ![_]
+            {
+                Select { columns, from, where_clause, order_by: order_by.unwrap_or_default() }
+            }
+
+        pub rule explain_query_plan() -> &'input str
+            = _ kw("EXPLAIN") _ kw("QUERY") _ kw("PLAN") statement:$([_]*) { statement }
+
+        rule result_column() -> ResultColumn
+            = "*" { ResultColumn::Star }
+            / expr:&expr() text:$(expr()) alias:(_ (kw("AS") _)? a:identifier() { a })? {
+                ResultColumn::Expr { expr, alias, text: text.to_string() }
+            }
+
+        rule ordering_term() -> OrderingTerm
+            = expr:expr() descending:(_ d:sort_order() { d })? {
+                OrderingTerm { expr, descending: descending.unwrap_or(false) }
+            }
+
+        rule sort_order() -> bool
+            = kw("ASC") { false }
+            / kw("DESC") { true }
+
+        pub rule create_table() -> TableSchema
+            = _ kw("CREATE") _ ((kw("TEMP") / kw("TEMPORARY")) _)? kw("TABLE") _
+              (kw("IF") _ kw("NOT") _ kw("EXISTS") _)? (identifier() _ "." _)? name:identifier() _
+              "(" _ columns:(column_def() ++ (_ "," _))
+              constraints:(_ "," _ c:table_constraint() { c })* _ ")"
+              options:(_ o:(table_option() ++ (_ "," _)) { o })?
+              _ (";" _)? ![_]
+            {
+                let options = options.unwrap_or_default();
+                TableSchema {
+                    name,
+                    columns,
+                    constraints,
+                    without_rowid: options.contains(&"WITHOUT ROWID"),
+                    strict: options.contains(&"STRICT"),
+                }
+            }
+
+        pub rule create_view() -> (Option<Vec<String>>, &'input str)
+            = _ kw("CREATE") _ ((kw("TEMP") / kw("TEMPORARY")) _)? kw("VIEW") _
+              (kw("IF") _ kw("NOT") _ kw("EXISTS") _)? (identifier() _ "." _)? identifier() _
+              columns:("(" _ c:(identifier() ++ (_ "," _)) _ ")" _ { c })?
+              kw("AS") _ select:$([_]*)
+            {
+                (columns, select)
+            }
+
+        pub rule create_index() -> IndexDefinition
+            = _ kw("CREATE") _ (kw("UNIQUE") _)? kw("INDEX") _
+              (kw("IF") _ kw("NOT") _ kw("EXISTS") _)? (identifier() _ "." _)? name:identifier() _
+              kw("ON") _ table:identifier() _ "(" _ columns:indexed_columns() _ ")"
+              _ (";" _)? ![_]
+            {
+                (name, table, columns)
+            }
+
+        rule table_option() -> &'static str
+            = kw("WITHOUT") _ kw("ROWID") { "WITHOUT ROWID" }
+            / kw("STRICT") { "STRICT" }
+
+        rule column_def() -> ColumnDef
+            = name:identifier() type_name:(_ t:type_name() { t })?
+              constraints:(_ c:column_constraint() { c })* {
+                ColumnDef {
+                    name,
+                    type_name: type_name.unwrap_or_default(),
+                    constraints: constraints.into_iter().flatten().collect(),
+                }
+            }
+
+        // `NULL` is accepted as a constraint but doesn't constrain anything
+        rule column_constraint() -> Option<ColumnConstraint>
+            = (kw("CONSTRAINT") _ identifier() _)? c:(
+                kw("PRIMARY") _ kw("KEY") descending:(_ d:sort_order() { d })?
+                  (_ conflict_clause())? autoincrement:(_ kw("AUTOINCREMENT"))? {
+                    Some(ColumnConstraint::PrimaryKey {
+                        descending: descending.unwrap_or(false),
+                        autoincrement: autoincrement.is_some(),
+                    })
+                }
+                / kw("NOT") _ kw("NULL") (_ conflict_clause())? { Some(ColumnConstraint::NotNull) }
+                / kw("NULL") (_ conflict_clause())? { None }
+                / kw("UNIQUE") (_ conflict_clause())? { Some(ColumnConstraint::Unique) }
+                / kw("CHECK") _ "(" _ e:expr() _ ")" { Some(ColumnConstraint::Check(e)) }
+                / kw("DEFAULT") _ e:default_value() { Some(ColumnConstraint::Default(e)) }
+                / kw("COLLATE") _ name:identifier() { Some(ColumnConstraint::Collate(name)) }
+                / fk:foreign_key_clause() { Some(ColumnConstraint::References(fk)) }
+                / (kw("GENERATED") _ kw("ALWAYS") _)? kw("AS") _ "(" _ expr:expr() _ ")"
+                  stored:(_ s:(kw("STORED") { true } / kw("VIRTUAL") { false }) { s })? {
+                    Some(ColumnConstraint::Generated { expr, stored: stored.unwrap_or(false) })
+                }
+            ) { c }
+
+        // a full expression would take `DEFAULT 0 NOT NULL` as `0 NOT NULL`
+        rule default_value() -> Expr
+            = "(" _ e:expr() _ ")" { e }
+            / "-" _ n:number() { Expr::unary(UnaryOp::Negate, Expr::Literal(n)) }
+            / "+" _ n:number() { Expr::Literal(n) }
+            / literal()
+            / name:identifier() { Expr::Literal(Value::Text(name)) }
+
+        rule conflict_clause() = kw("ON") _ kw("CONFLICT") _ identifier()
+
+        rule table_constraint() -> TableConstraint
+            = (kw("CONSTRAINT") _ identifier() _)? c:(
+                kw("PRIMARY") _ kw("KEY") _ "(" _ columns:indexed_columns() _ ")"
+                  (_ conflict_clause())? {
+                    TableConstraint::PrimaryKey(columns)
+                }
+                / kw("UNIQUE") _ "(" _ columns:indexed_columns() _ ")" (_ conflict_clause())? {
+                    TableConstraint::Unique(columns)
+                }
+                / kw("CHECK") _ "(" _ e:expr() _ ")" { TableConstraint::Check(e) }
+                / kw("FOREIGN") _ kw("KEY") _ "(" _ columns:(identifier() ++ (_ "," _)) _ ")" _
+                  references:foreign_key_clause() {
+                    TableConstraint::ForeignKey { columns, references }
+                }
+            ) { c }
+
+        rule indexed_columns() -> Vec<IndexedColumn>
+            = (name:identifier() collation:(_ kw("COLLATE") _ c:identifier() { c })?
+               descending:(_ d:sort_order() { d })? {
+                IndexedColumn { name, collation, descending: descending.unwrap_or(false) }
+              }) ++ (_ "," _)
+
+        rule foreign_key_clause() -> ForeignKey
+            = kw("REFERENCES") _ table:identifier()
+              columns:(_ "(" _ c:(identifier() ++ (_ "," _)) _ ")" { c })?
+              actions:(_ a:foreign_key_action() { a })* {
+                let action = |on: &str| {
+                    actions.iter().rev().find_map(|(event, action)| match event == &on {
+                        true => action.clone(),
+                        _ => None,
+                    })
+                };
+                ForeignKey {
+                    table,
+                    columns: columns.unwrap_or_default(),
+                    on_delete: action("DELETE"),
+                    on_update: action("UPDATE"),
+                }
+            }
+
+        // (event, action) for ON DELETE / ON UPDATE, nothing for the
+        // MATCH and DEFERRABLE clauses
+        rule foreign_key_action() -> (&'static str, Option<String>)
+            = kw("ON") _ event:(kw("DELETE") { "DELETE" } / kw("UPDATE") { "UPDATE" }) _
+              action:$(kw("SET") _ kw("NULL") / kw("SET") _ kw("DEFAULT") / kw("CASCADE")
+                       / kw("RESTRICT") / kw("NO") _ kw("ACTION")) {
+                (event, Some(action.split_whitespace().collect::<Vec<_>>().join(" ").to_uppercase()))
+            }
+            / kw("MATCH") _ identifier() { ("", None) }
+            / (kw("NOT") _)? kw("DEFERRABLE")
+              (_ kw("INITIALLY") _ (kw("DEFERRED") / kw("IMMEDIATE")))? { ("", None) }
+
+        pub rule expr() -> Expr = precedence! {
+            x:(@) _ kw("OR") _ y:@ { Expr::binary(x, BinaryOp::Or, y) }
+            --
+            x:(@) _ kw("AND") _ y:@ { Expr::binary(x, BinaryOp::And, y) }
+            --
+            kw("NOT") _ x:@ { Expr::unary(UnaryOp::Not, x) }
+            --
+            x:(@) _ ("==" / "=") _ y:@ { Expr::binary(x, BinaryOp::Eq, y) }
+            x:(@) _ ("!=" / "<>") _ y:@ { Expr::binary(x, BinaryOp::NotEq, y) }
+            x:(@) _ kw("IS") _ kw("NOT") _ y:@ { Expr::binary(x, BinaryOp::IsNot, y) }
+            x:(@) _ kw("IS") _ y:@ { Expr::binary(x, BinaryOp::Is, y) }
+            x:@ _ kw("ISNULL") { Expr::binary(x, BinaryOp::Is, Expr::Literal(Value::Null)) }
+            x:@ _ (kw("NOTNULL") / kw("NOT") _ kw("NULL")) {
+                Expr::binary(x, BinaryOp::IsNot, Expr::Literal(Value::Null))
+            }
+            x:@ _ negated:(kw("NOT") _)? kw("IN") _ "(" _ list:(expr() ** (_ "," _)) _ ")" {
+                Expr::InList { expr: Box::new(x), list, negated: negated.is_some() }
+            }
+            x:@ _ negated:(kw("NOT") _)? kw("BETWEEN") _ low:comparison() _ kw("AND") _
+              high:comparison() {
+                Expr::Between {
+                    expr: Box::new(x),
+                    low: Box::new(low),
+                    high: Box::new(high),
+                    negated: negated.is_some(),
+                }
+            }
+            x:@ _ negated:(kw("NOT") _)? op:pattern_op() _ y:comparison()
+              escape:(_ kw("ESCAPE") _ e:primary() { Box::new(e) })? {
+                Expr::Pattern {
+                    op,
+                    negated: negated.is_some(),
+                    expr: Box::new(x),
+                    pattern: Box::new(y),
+                    escape,
+                }
+            }
+            --
+            c:comparison() { c }
+        }
+
+        // everything binding tighter than the equality operators, so that
+        // the pattern of a LIKE can be parsed on its own before ESCAPE
+        rule comparison() -> Expr = precedence! {
+            x:(@) _ "<=" _ y:@ { Expr::binary(x, BinaryOp::LtEq, y) }
+            x:(@) _ ">=" _ y:@ { Expr::binary(x, BinaryOp::GtEq, y) }
+            x:(@) _ "<" _ y:@ { Expr::binary(x, BinaryOp::Lt, y) }
+            x:(@) _ ">" _ y:@ { Expr::binary(x, BinaryOp::Gt, y) }
+            --
+            x:(@) _ "+" _ y:@ { Expr::binary(x, BinaryOp::Add, y) }
+            x:(@) _ "-" _ y:@ { Expr::binary(x, BinaryOp::Subtract, y) }
+            --
+            x:(@) _ "*" _ y:@ { Expr::binary(x, BinaryOp::Multiply, y) }
+            x:(@) _ "/" _ y:@ { Expr::binary(x, BinaryOp::Divide, y) }
+            x:(@) _ "%" _ y:@ { Expr::binary(x, BinaryOp::Modulo, y) }
+            --
+            x:(@) _ "||" _ y:@ { Expr::binary(x, BinaryOp::Concat, y) }
+            --
+            "-" _ x:@ { Expr::unary(UnaryOp::Negate, x) }
+            "+" _ x:@ { Expr::unary(UnaryOp::Plus, x) }
+            --
+            x:@ _ kw("COLLATE") _ name:identifier() { Expr::Collate(Box::new(x), name) }
+            --
+            p:primary() { p }
+        }
+
+        rule pattern_op() -> PatternOp
+            = kw("LIKE") { PatternOp::Like }
+            / kw("GLOB") { PatternOp::Glob }
+            / kw("REGEXP") { PatternOp::Regexp }
+
+        rule primary() -> Expr
+            = literal()
+            / parameter()
+            / "(" _ e:expr() _ ")" { e }
+            / case()
+            / kw("CAST") _ "(" _ e:expr() _ kw("AS") _ type_name:type_name() _ ")" {
+                Expr::Cast { expr: Box::new(e), type_name }
+            }
+            / function_call()
+            / (identifier() _ "." _)? c:identifier() { Expr::Column(c) }
+
+        rule case() -> Expr
+            = kw("CASE") _ operand:(e:expr() _ { Box::new(e) })?
+              branches:(kw("WHEN") _ w:expr() _ kw("THEN") _ t:expr() _ { (w, t) })+
+              else_expr:(kw("ELSE") _ e:expr() _ { Box::new(e) })? kw("END") {
+                Expr::Case { operand, branches, else_expr }
+            }
+
+        // one or more names with optional size arguments: VARCHAR(10),
+        // DOUBLE PRECISION, DECIMAL(10, 2)
+        rule type_name() -> String
+            = t:$(type_word() ++ _ (_ "(" _ ['+' | '-']? number() (_ "," _ ['+' | '-']? number())? _ ")")?) {
+                t.to_string()
+            }
+
+        // GENERATED isn't reserved but starts a column constraint
+        rule type_word() = !kw("GENERATED") identifier()
+
+        rule function_call() -> Expr
+            = name:identifier() _ "(" _ "*" _ ")" {
+                Expr::Function { name: name.to_ascii_lowercase(), args: vec![], star: true }
+            }
+            / name:function_name() _ "(" _ args:(expr() ** (_ "," _)) _ ")" {
+                Expr::function(&name, args)
+            }
+
+        // like(), glob() and regexp() are also callable as plain functions
+        rule function_name() -> String
+            = identifier()
+            / name:$(pattern_op()) { name.to_string() }
+
+        rule literal() -> Expr
+            = v:(number() / string() / blob()) { Expr::Literal(v) }
+            / kw("NULL") { Expr::Literal(Value::Null) }
+            / kw("TRUE") { Expr::Literal(Value::Integer(1)) }
+            / kw("FALSE") { Expr::Literal(Value::Integer(0)) }
+            / kw("CURRENT_TIMESTAMP") { Expr::function("datetime", vec![]) }
+            / kw("CURRENT_DATE") { Expr::function("date", vec![]) }
+            / kw("CURRENT_TIME") { Expr::function("time", vec![]) }
+
+        rule parameter() -> Expr
+            = "?" n:$(['0'..='9']+) {?
+                match n.parse::<usize>() {
+                    Ok(n) if (1..=32766).contains(&n) => Ok(Expr::Parameter(Parameter::Numbered(n))),
+                    _ => Err("parameter number between 1 and 32766"),
+                }
+            }
+            / "?" { Expr::Parameter(Parameter::Anonymous) }
+            / name:$([':' | '@' | '$'] ident_char()+) {
+                Expr::Parameter(Parameter::Named(name.to_string()))
+            }
+
+        rule number() -> Value
+            = ("0x" / "0X") h:$(['0'..='9' | 'a'..='f' | 'A'..='F']+) {?
+                u64::from_str_radix(h, 16).map(|v| Value::Integer(v as i64)).or(Err("hex literal"))
+            }
+            / n:$((['0'..='9']+ ("." ['0'..='9']*)? / "." ['0'..='9']+)
+                  (['e' | 'E'] ['+' | '-']? ['0'..='9']+)?) !ident_char() {
+                match n.parse::<i64>() {
+                    Ok(i) => Value::Integer(i),
+                    Err(_) => Value::Real(n.parse::<f64>().unwrap_or(f64::INFINITY)),
+                }
+            }
+
+        rule string() -> Value
+            = "'" s:$(("''" / [^'\''])*) "'" { Value::Text(s.replace("''", "'")) }
+
+        rule blob() -> Value
+            = ['x' | 'X'] "'" h:$(['0'..='9' | 'a'..='f' | 'A'..='F']*) "'" {?
+                match h.len() % 2 {
+                    0 => Ok(Value::Blob(
+                        (0..h.len()).step_by(2)
+                            .map(|i| u8::from_str_radix(&h[i..i + 2], 16).unwrap_or(0))
+                            .collect(),
+                    )),
+                    _ => Err("blob literal with an even number of hex digits"),
+                }
+            }
 
-        let condition = caps.name("condition").map(|c| c.as_str().to_string());
+        rule identifier() -> String
+            = quiet!{
+                "\"" s:$(("\"\"" / [^'"'])*) "\"" { s.replace("\"\"", "\"") }
+                / "[" s:$([^']']*) "]" { s.to_string() }
+                / "`" s:$(("``" / [^'`'])*) "`" { s.replace("``", "`") }
+                / s:$(['a'..='z' | 'A'..='Z' | '_'] ident_char()*) {?
+                    match is_reserved(s) {
+                        true => Err("identifier"),
+                        _ => Ok(s.to_string()),
+                    }
+                }
+            } / expected!("identifier")
 
-        // Split columns by comma and trim whitespace
-        let columns: Vec<String> = columns.split(',').map(|s| s.trim().to_string()).collect();
+        rule ident_char() = ['a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '$']
 
-        Some((columns, String::from(table), condition))
-    } else {
-        None
+        rule kw(k: &'static str) = quiet!{
+            w:$(['a'..='z' | 'A'..='Z' | '_']+) !ident_char() {?
+                match w.eq_ignore_ascii_case(k) {
+                    true => Ok(()),
+                    _ => Err(k),
+                }
+            }
+        } / expected!(k)
+
+        rule _ = quiet!{ ([' ' | '\t' | '\n' | '\r'] / comment())* }
+
+        rule comment()
+            = "--" (!"\n" [_])*
+            / "/*" (!"*/" [_])* "*/"
     }
 }
+
+/// Keywords that can't be used as bare identifiers because they would make a
+/// result column alias, a column reference or a declared type ambiguous.
+fn is_reserved(word: &str) -> bool {
+    const RESERVED: &[&str] = &[
+        "AND",
+        "AS",
+        "BETWEEN",
+        "BY",
+        "CASE",
+        "CAST",
+        "CHECK",
+        "COLLATE",
+        "CONSTRAINT",
+        "DEFAULT",
+        "ELSE",
+        "END",
+        "ESCAPE",
+        "FOREIGN",
+        "FROM",
+        "GLOB",
+        "GROUP",
+        "HAVING",
+        "IN",
+        "IS",
+        "ISNULL",
+        "LIKE",
+        "LIMIT",
+        "NOT",
+        "NOTNULL",
+        "NULL",
+        "OFFSET",
+        "OR",
+        "ORDER",
+        "PRIMARY",
+        "REFERENCES",
+        "REGEXP",
+        "SELECT",
+        "THEN",
+        "UNIQUE",
+        "WHEN",
+        "WHERE",
+    ];
+    RESERVED.iter().any(|k| k.eq_ignore_ascii_case(word))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_parser() {
-        let sql_apples: String = "SELECT COUNT(*) FROM apples;".into();
-        let sql_oranges: String = "SELECT COUNT(*) FROM oranges;".into();
-
-        let (columns_apples, table_name_apples, _) =
-            parse_sql_(sql_apples).expect("fn parser():SQL PARSE FAILED");
-
-        assert_eq!(columns_apples, vec!["COUNT(*)".to_string()]);
-        assert_eq!(table_name_apples, "apples");
-        let (columns_oranges, table_name_oranges, _) =
-            parse_sql_(sql_oranges.to_string()).expect("PARSE FAILED");
-        assert_eq!(columns_oranges, vec!["COUNT(*)".to_string()]);
-        assert_eq!(table_name_oranges, "oranges");
-    }
-
-    #[test]
-    fn test_parser_sql_superhoreos() {
-        let db_name: String = "superheroes".into();
-        let sql_ = format!("SELECT COUNT(*) FROM {db_name};");
-
-        // Test parsing
-        let (columns, table, _) =
-            parse_sql_(sql_.clone()).expect("Failed to parse superheroes query");
-        assert_eq!(columns, vec!["COUNT(*)".to_string()]);
-        assert_eq!(table, db_name);
-    }
-
-    #[test]
-    fn test_parser_sql_companies() {
-        let db_name: String = "companies".into();
-        let sql_ = format!("SELECT COUNT(*) FROM {db_name};");
-
-        // Test parsing
-        let (columns, table, _) =
-            parse_sql_(sql_.clone()).expect("Failed to parse companies query");
-        assert_eq!(columns, vec!["COUNT(*)".to_string()]);
-        assert_eq!(table, db_name);
+        for table in ["apples", "oranges", "superheroes", "companies"] {
+            let select = parse_select(&format!("SELECT COUNT(*) FROM {table};"))
+                .expect("fn parser():SQL PARSE FAILED");
+            let columns = select.columns.iter().map(ResultColumn::name);
+            assert_eq!(columns.collect::<Vec<_>>(), ["COUNT(*)"]);
+            assert_eq!(select.from.as_deref(), Some(table));
+            assert_eq!(select.where_clause, None);
+        }
     }
 
     #[test]
@@ -81,33 +473,342 @@ mod tests {
         ];
 
         for (sql, expected_columns, expected_table) in test_cases {
-            if let Some((columns, table, _)) = parse_sql_(sql.to_string()) {
-                assert_eq!(columns, expected_columns);
-                assert_eq!(table, expected_table);
-            } else {
-                panic!("Failed to parse SQL query: {}", sql);
-            }
+            let select = parse_select(sql).expect("Failed to parse SQL query");
+            let columns = select.columns.iter().map(ResultColumn::name);
+            assert_eq!(columns.collect::<Vec<_>>(), expected_columns);
+            assert_eq!(select.from.as_deref(), Some(expected_table));
         }
     }
 
     #[test]
     fn test_parser_with_where() {
-        let sql_apples: String = "SELECT COUNT(*) FROM apples;".into();
+        let select = parse_select("SELECT name FROM users WHERE id = 5;")
+            .expect("Failed to parse WHERE clause");
 
-        let (columns_apples, table_name_apples, condition) =
-            parse_sql_(sql_apples).expect("fn parser():SQL PARSE FAILED");
+        assert_eq!(select.columns[0].name(), "name");
+        assert_eq!(select.from.as_deref(), Some("users"));
+        assert_eq!(
+            select.where_clause,
+            Some(Expr::binary(
+                Expr::Column("id".into()),
+                BinaryOp::Eq,
+                Expr::Literal(Value::Integer(5))
+            ))
+        );
+    }
 
-        assert_eq!(columns_apples, vec!["COUNT(*)".to_string()]);
-        assert_eq!(table_name_apples, "apples");
-        assert_eq!(condition, None);
+    #[test]
+    fn test_parse_select_expressions() {
+        let select = parse_select(
+            "SELECT name, date(created_at, '+1 day') AS due FROM tasks WHERE created_at >= '2024-01-01' AND done = 0;",
+        )
+        .expect("Failed to parse select");
 
-        // Test with WHERE clause
-        let sql_with_where = "SELECT name FROM users WHERE id = 5;".into();
-        let (columns, table, condition) =
-            parse_sql_(sql_with_where).expect("Failed to parse WHERE clause");
+        assert_eq!(select.from, Some("tasks".to_string()));
+        assert_eq!(select.columns.len(), 2);
+        assert_eq!(select.columns[0].name(), "name");
+        assert_eq!(select.columns[1].name(), "due");
+        match &select.columns[1] {
+            ResultColumn::Expr { expr, .. } => assert_eq!(
+                *expr,
+                Expr::function(
+                    "date",
+                    vec![
+                        Expr::Column("created_at".into()),
+                        Expr::Literal(Value::Text("+1 day".into()))
+                    ]
+                )
+            ),
+            _ => panic!("expected an expression column"),
+        }
+        match select.where_clause {
+            Some(Expr::Binary(_, BinaryOp::And, _)) => {}
+            other => panic!("unexpected WHERE clause {:?}", other),
+        }
+    }
 
-        assert_eq!(columns, vec!["name".to_string()]);
-        assert_eq!(table, "users");
-        assert_eq!(condition, Some("id = 5".to_string()));
+    #[test]
+    fn test_parse_select_precedence() {
+        let select = parse_select("SELECT 1 + 2 * 3, count(*), CURRENT_DATE").unwrap();
+        assert_eq!(select.from, None);
+        match &select.columns[0] {
+            ResultColumn::Expr { expr, text, .. } => {
+                assert_eq!(text, "1 + 2 * 3");
+                assert_eq!(
+                    *expr,
+                    Expr::binary(
+                        Expr::Literal(Value::Integer(1)),
+                        BinaryOp::Add,
+                        Expr::binary(
+                            Expr::Literal(Value::Integer(2)),
+                            BinaryOp::Multiply,
+                            Expr::Literal(Value::Integer(3))
+                        )
+                    )
+                );
+            }
+            _ => panic!("expected an expression column"),
+        }
+        assert_eq!(select.columns[1].name(), "count(*)");
+        assert!(parse_select("SELECT FROM apples").is_err());
+    }
+
+    #[test]
+    fn test_parse_create_index() {
+        let column = |name: &str, collation: Option<&str>, descending| IndexedColumn {
+            name: name.into(),
+            collation: collation.map(|c| c.into()),
+            descending,
+        };
+        assert_eq!(
+            parse_create_index("CREATE INDEX idx_companies_country\n\ton companies (country)")
+                .unwrap(),
+            (
+                "idx_companies_country".into(),
+                "companies".into(),
+                vec![column("country", None, false)]
+            )
+        );
+        assert_eq!(
+            parse_create_index(
+                "CREATE UNIQUE INDEX IF NOT EXISTS \"i\" ON \"t\"(a COLLATE NOCASE, [b] DESC)"
+            )
+            .unwrap(),
+            (
+                "i".into(),
+                "t".into(),
+                vec![column("a", Some("NOCASE"), false), column("b", None, true)]
+            )
+        );
+        // quoted names may hold spaces and commas
+        assert_eq!(
+            parse_create_index("CREATE INDEX i ON t(\"x y\", \"a,b\" ASC)")
+                .unwrap()
+                .2,
+            [column("x y", None, false), column("a,b", None, false)]
+        );
+        assert!(parse_create_index("CREATE INDEX i ON t(a) WHERE a > 0").is_err());
+        assert!(parse_create_index("CREATE INDEX i ON t(a + 1)").is_err());
+        assert!(parse_create_index("CREATE TABLE t(a)").is_err());
+    }
+
+    #[test]
+    fn test_parse_select_in_between_case_cast() {
+        let select = parse_select(
+            "SELECT CASE WHEN a IN (1, 2) THEN 'low' ELSE 'high' END AS level, \
+             CAST(b AS VARCHAR(10)) FROM t WHERE c NOT BETWEEN 1 + 1 AND 5 AND d = 1",
+        )
+        .unwrap();
+        assert_eq!(select.columns[0].name(), "level");
+        match &select.columns[0] {
+            ResultColumn::Expr {
+                expr:
+                    Expr::Case {
+                        operand,
+                        branches,
+                        else_expr,
+                    },
+                ..
+            } => {
+                assert!(operand.is_none());
+                assert_eq!(branches.len(), 1);
+                assert!(matches!(branches[0].0, Expr::InList { negated: false, .. }));
+                assert!(else_expr.is_some());
+            }
+            other => panic!("unexpected column {:?}", other),
+        }
+        match &select.columns[1] {
+            ResultColumn::Expr {
+                expr: Expr::Cast { type_name, .. },
+                ..
+            } => assert_eq!(type_name, "VARCHAR(10)"),
+            other => panic!("unexpected column {:?}", other),
+        }
+        match select.where_clause {
+            Some(Expr::Binary(left, BinaryOp::And, _)) => {
+                assert!(matches!(*left, Expr::Between { negated: true, .. }))
+            }
+            other => panic!("unexpected WHERE clause {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_select_patterns() {
+        let select = parse_select(
+            "SELECT name FROM users WHERE email NOT LIKE '%!_%' ESCAPE '!' AND name GLOB 'A*'",
+        )
+        .unwrap();
+        let pattern =
+            |op, negated, expr: &str, pattern: &str, escape: Option<&str>| Expr::Pattern {
+                op,
+                negated,
+                expr: Box::new(Expr::Column(expr.into())),
+                pattern: Box::new(Expr::Literal(Value::Text(pattern.into()))),
+                escape: escape.map(|e| Box::new(Expr::Literal(Value::Text(e.into())))),
+            };
+        assert_eq!(
+            select.where_clause,
+            Some(Expr::binary(
+                pattern(PatternOp::Like, true, "email", "%!_%", Some("!")),
+                BinaryOp::And,
+                pattern(PatternOp::Glob, false, "name", "A*", None)
+            ))
+        );
+
+        let select = parse_select("SELECT glob('a*', name), name REGEXP '^a' FROM t").unwrap();
+        assert_eq!(select.columns[0].name(), "glob('a*', name)");
+        match &select.columns[1] {
+            ResultColumn::Expr {
+                expr: Expr::Pattern { op, .. },
+                ..
+            } => assert_eq!(*op, PatternOp::Regexp),
+            other => panic!("unexpected column {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_select_collate_order_by() {
+        let select = parse_select(
+            "SELECT name FROM t WHERE -a COLLATE nocase = 'x' ORDER BY name COLLATE RTRIM DESC, 2",
+        )
+        .unwrap();
+        assert_eq!(
+            select.where_clause,
+            Some(Expr::binary(
+                Expr::unary(
+                    UnaryOp::Negate,
+                    Expr::Collate(Box::new(Expr::Column("a".into())), "nocase".into())
+                ),
+                BinaryOp::Eq,
+                Expr::Literal(Value::Text("x".into()))
+            ))
+        );
+        assert_eq!(
+            select.order_by,
+            vec![
+                OrderingTerm {
+                    expr: Expr::Collate(Box::new(Expr::Column("name".into())), "RTRIM".into()),
+                    descending: true,
+                },
+                OrderingTerm {
+                    expr: Expr::Literal(Value::Integer(2)),
+                    descending: false,
+                },
+            ]
+        );
+        assert!(parse_select("SELECT a FROM t ORDER BY").is_err());
+    }
+
+    #[test]
+    fn test_parse_create_table() {
+        let schema = parse_create_table(
+            "CREATE TABLE IF NOT EXISTS main.products (
+                -- quoted names and type arguments
+                \"size range\" VARCHAR(255) NOT NULL,
+                price DECIMAL(10, 2) DEFAULT 0.5 CHECK (price >= 0),
+                label text default 'a, b' collate nocase, /* comment */
+                id integer primary key desc autoincrement,
+                vendor_id INT REFERENCES vendors(id) ON DELETE CASCADE,
+                total REAL GENERATED ALWAYS AS (price * 2) STORED,
+                untyped,
+                CONSTRAINT pk PRIMARY KEY (\"size range\", label COLLATE rtrim DESC),
+                UNIQUE (label),
+                FOREIGN KEY (vendor_id) REFERENCES vendors (id) ON UPDATE SET NULL
+            ) WITHOUT ROWID, STRICT;",
+        )
+        .unwrap();
+        assert_eq!(schema.name, "products");
+        assert_eq!(
+            schema.column_names(),
+            [
+                "size range",
+                "price",
+                "label",
+                "id",
+                "vendor_id",
+                "total",
+                "untyped"
+            ]
+        );
+        let types = schema.columns.iter().map(|c| c.type_name.as_str());
+        assert!(types.eq([
+            "VARCHAR(255)",
+            "DECIMAL(10, 2)",
+            "text",
+            "integer",
+            "INT",
+            "REAL",
+            ""
+        ]));
+        assert_eq!(schema.columns[0].constraints, [ColumnConstraint::NotNull]);
+        assert_eq!(
+            schema.columns[1].constraints[0],
+            ColumnConstraint::Default(Expr::Literal(Value::Real(0.5)))
+        );
+        assert_eq!(
+            schema.columns[2].constraints,
+            [
+                ColumnConstraint::Default(Expr::Literal(Value::Text("a, b".into()))),
+                ColumnConstraint::Collate("nocase".into()),
+            ]
+        );
+        assert_eq!(
+            schema.columns[3].constraints,
+            [ColumnConstraint::PrimaryKey {
+                descending: true,
+                autoincrement: true
+            }]
+        );
+        assert_eq!(
+            schema.columns[4].constraints,
+            [ColumnConstraint::References(ForeignKey {
+                table: "vendors".into(),
+                columns: vec!["id".into()],
+                on_delete: Some("CASCADE".into()),
+                on_update: None,
+            })]
+        );
+        assert!(matches!(
+            schema.columns[5].constraints[..],
+            [ColumnConstraint::Generated { stored: true, .. }]
+        ));
+        let column = |name: &str, collation: Option<&str>, descending| IndexedColumn {
+            name: name.into(),
+            collation: collation.map(|c| c.into()),
+            descending,
+        };
+        assert_eq!(
+            schema.constraints[..2],
+            [
+                TableConstraint::PrimaryKey(vec![
+                    column("size range", None, false),
+                    column("label", Some("rtrim"), true),
+                ]),
+                TableConstraint::Unique(vec![column("label", None, false)]),
+            ]
+        );
+        assert!(matches!(
+            &schema.constraints[2],
+            TableConstraint::ForeignKey { references, .. }
+                if references.on_update.as_deref() == Some("SET NULL")
+        ));
+        assert!(schema.without_rowid && schema.strict);
+        assert!(parse_create_table("CREATE TABLE t (a,)").is_err());
+        assert!(parse_create_table("CREATE INDEX i ON t (a)").is_err());
+    }
+    #[test]
+    fn test_parse_create_view() {
+        assert_eq!(
+            parse_create_view("CREATE VIEW v AS SELECT a FROM t").unwrap(),
+            (None, "SELECT a FROM t")
+        );
+        assert_eq!(
+            parse_create_view(
+                "create temp view if not exists main.\"v 2\" ( p, q )\nas select 1, 2"
+            )
+            .unwrap(),
+            (Some(vec!["p".into(), "q".into()]), "select 1, 2")
+        );
+        assert!(parse_create_view("CREATE TABLE v (a)").is_err());
     }
 }
@@ -0,0 +1,549 @@
+//! Execution of SELECT statements: binding a statement to the schema of the
+//! table it reads, choosing how to scan the table and computing the result
+//! rows.
+
+use std::cmp::Ordering;
+use std::ops::Bound;
+use std::sync::atomic::{self, AtomicBool};
+use std::sync::Arc;
+
+use crate::connection::Column;
+use crate::db::db::Db;
+use crate::db::page::{Page, RowIter};
+use crate::error::{Error, Result};
+use crate::parser::{parse_create_index, parse_explain_query_plan, parse_select};
+use crate::sql::affinity::bind_affinities;
+use crate::sql::ast::{Expr, OrderingTerm, ResultColumn, Select};
+use crate::sql::collation::{self, bind_collations, expr_collation, Collation};
+use crate::sql::eval::{eval, eval_aggregate};
+use crate::sql::parameter::{number_parameters, substitute_parameters};
+use crate::sql::planner::{plan_scan, IndexInfo, KeyRange, ScanPlan};
+use crate::sql::schema::TableSchema;
+use crate::sql::value::Value;
+use crate::stats;
+
+/// Result rows. When the query neither aggregates nor sorts, each is read
+/// from the table and computed as it is iterated.
+pub type ResultRows<'a> = Box<dyn Iterator<Item = Result<Vec<Value>>> + 'a>;
+
+/// A SELECT statement bound to the schema of the table it reads.
+#[derive(Debug, Clone)]
+pub struct Query {
+    /// The statement as parsed, with its parameters numbered.
+    parsed: Select,
+    /// The statement as run: `parsed` with the bound values in place of the
+    /// parameters.
+    select: Select,
+    schema: Option<TableSchema>,
+    collations: Vec<(String, Collation)>,
+    columns: Vec<Column>,
+    /// Name of each parameter, None for `?` and `?NNN`.
+    parameters: Vec<Option<String>>,
+    values: Vec<Value>,
+    /// Whether the statement is `EXPLAIN QUERY PLAN`, whose rows describe
+    /// how the SELECT would run instead.
+    explain: bool,
+}
+
+impl Query {
+    pub fn prepare(db: &Db, sql: &str) -> Result<Query> {
+        let (explain, sql) = match parse_explain_query_plan(sql) {
+            Some(select) => (true, select),
+            None => (false, sql),
+        };
+        let mut parsed = parse_select(sql)?;
+        let mut parameters = vec![];
+        for expr in parsed.exprs_mut() {
+            number_parameters(expr, &mut parameters);
+        }
+        let schema = match &parsed.from {
+            Some(table_name) => Some(db.get_schema_page().borrow().table_schema(table_name)?),
+            _ => None,
+        };
+        let collations = match &schema {
+            Some(schema) => schema.collations()?,
+            _ => vec![],
+        };
+
+        let columns = match explain {
+            true => ["id", "parent", "notused", "detail"]
+                .map(|name| Column::new(name.to_string(), None))
+                .into(),
+            false => result_columns(&parsed, schema.as_ref()),
+        };
+        let mut query = Query {
+            select: parsed.clone(),
+            parsed,
+            schema,
+            collations,
+            columns,
+            values: vec![Value::Null; parameters.len()],
+            parameters,
+            explain,
+        };
+        query.bind_select();
+        Ok(query)
+    }
+
+    /// Rebuilds the statement to run from the parsed one and the bound
+    /// values. The values are substituted first so that they are treated
+    /// like literals written in the statement.
+    fn bind_select(&mut self) {
+        let affinities = match &self.schema {
+            Some(schema) => schema.affinities(),
+            _ => vec![],
+        };
+        let mut select = self.parsed.clone();
+        for expr in select.exprs_mut() {
+            substitute_parameters(expr, &self.values);
+            // comparisons convert their operands to the columns' affinity
+            // and compare text with the columns' collation
+            bind_affinities(expr, &affinities);
+            bind_collations(expr, &self.collations);
+        }
+        self.select = select;
+    }
+
+    /// Whether this is an `EXPLAIN QUERY PLAN` statement.
+    pub fn is_explain(&self) -> bool {
+        self.explain
+    }
+
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    /// The largest parameter number, which is the number of parameters
+    /// unless some `?NNN` numbers are skipped.
+    pub fn parameter_count(&self) -> usize {
+        self.parameters.len()
+    }
+
+    /// Name of parameter `index` (from 1), with its `:`, `@` or `$` prefix.
+    pub fn parameter_name(&self, index: usize) -> Option<&str> {
+        self.parameters.get(index.checked_sub(1)?)?.as_deref()
+    }
+
+    pub fn parameter_index(&self, name: &str) -> Option<usize> {
+        self.parameters
+            .iter()
+            .position(|n| n.as_deref() == Some(name))
+            .map(|i| i + 1)
+    }
+
+    pub fn bind(&mut self, index: usize, value: Value) -> Result<()> {
+        match index.checked_sub(1).and_then(|i| self.values.get_mut(i)) {
+            Some(slot) => *slot = value,
+            None => return Err(Error::InvalidParameterIndex(index)),
+        }
+        self.bind_select();
+        Ok(())
+    }
+
+    /// Sets every parameter back to NULL.
+    pub fn clear_bindings(&mut self) {
+        self.values.fill(Value::Null);
+        self.bind_select();
+    }
+
+    /// Runs the query. A query without aggregates or ORDER BY reads, filters
+    /// and computes its rows one at a time as the result is iterated; others
+    /// collect the matching rows when they start. Rows fail with
+    /// `Error::Interrupted` once `interrupted` is set.
+    pub fn run<'a>(&'a self, db: &Db, interrupted: &'a Arc<AtomicBool>) -> Result<ResultRows<'a>> {
+        let encoding = db.text_encoding();
+        collation::set_text_encoding(encoding);
+        if self.explain {
+            return Ok(Box::new(self.query_plan(db).into_iter().map(Ok)));
+        }
+        let mut rows: RowIter = match (&self.select.from, &self.schema) {
+            (Some(table_name), Some(schema)) => {
+                let condition = &self.select.where_clause;
+                get_table_rows(table_name, db, schema, condition, interrupted)?
+            }
+            // SELECT without FROM works on a single empty row
+            _ => Box::new(std::iter::once(Ok(vec![]))),
+        };
+        // index keys are compared as the next row is read, and another
+        // statement may have run on this thread in between
+        let mut rows = std::iter::from_fn(move || {
+            collation::set_text_encoding(encoding);
+            let row = rows.next()?;
+            if row.is_ok() {
+                stats::record(|stats| stats.rows_scanned += 1);
+            }
+            Some(row)
+        });
+        let check_interrupt = move || match interrupted.load(atomic::Ordering::Relaxed) {
+            true => Err(Error::Interrupted),
+            false => Ok(()),
+        };
+
+        if !self.is_aggregate() && self.select.order_by.is_empty() {
+            return Ok(Box::new(std::iter::from_fn(move || loop {
+                if let Err(e) = check_interrupt() {
+                    return Some(Err(e));
+                }
+                let row = match rows.next()? {
+                    Ok(row) => row,
+                    Err(e) => return Some(Err(e)),
+                };
+                match self.matches(&row) {
+                    Ok(true) => return Some(self.project(&row)),
+                    Ok(false) => {}
+                    Err(e) => return Some(Err(e)),
+                }
+            })));
+        }
+
+        let mut matching = vec![];
+        for row in rows {
+            check_interrupt()?;
+            let row = row?;
+            if self.matches(&row)? {
+                matching.push(row);
+            }
+        }
+        let result = match self.is_aggregate() {
+            true => vec![self.aggregate(&matching)?],
+            _ => {
+                let outputs = self
+                    .select
+                    .columns
+                    .iter()
+                    .flat_map(|column| match column {
+                        ResultColumn::Star => self
+                            .schema
+                            .iter()
+                            .flat_map(|schema| schema.column_names())
+                            .map(|name| (None, Expr::Column(name)))
+                            .collect(),
+                        ResultColumn::Expr { expr, alias, .. } => {
+                            vec![(alias.clone(), expr.clone())]
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                sort_rows(
+                    &matching,
+                    |row| self.project(row),
+                    &self.select.order_by,
+                    &outputs,
+                    &self.collations,
+                )?
+            }
+        };
+        Ok(Box::new(result.into_iter().map(Ok)))
+    }
+
+    /// The rows of `EXPLAIN QUERY PLAN`: an id, the id of the parent step
+    /// (0 at the top), an unused 0 and a description of each step, in
+    /// SQLite's words.
+    fn query_plan(&self, db: &Db) -> Vec<Vec<Value>> {
+        let mut steps = vec![];
+        match (&self.select.from, &self.schema) {
+            (Some(table_name), Some(schema)) => {
+                let (plan, indexes) = scan_plan(table_name, db, schema, &self.select.where_clause);
+                steps.push(match plan {
+                    ScanPlan::FullScan => format!("SCAN {table_name}"),
+                    ScanPlan::Rowid(_) => {
+                        format!("SEARCH {table_name} USING INTEGER PRIMARY KEY (rowid=?)")
+                    }
+                    ScanPlan::IndexRange { index, range } => {
+                        let info = indexes.iter().find(|info| info.name == index);
+                        let column = info.and_then(|info| info.columns.first());
+                        let column = column.map_or("", |(column, _)| column.as_str());
+                        let using = match index == *table_name {
+                            true => "PRIMARY KEY".to_string(),
+                            false => format!("INDEX {index}"),
+                        };
+                        let constraint = range_constraint(column, &range);
+                        format!("SEARCH {table_name} USING {using} ({constraint})")
+                    }
+                });
+            }
+            _ => steps.push("SCAN CONSTANT ROW".to_string()),
+        }
+        if !self.is_aggregate() && !self.select.order_by.is_empty() {
+            steps.push("USE TEMP B-TREE FOR ORDER BY".to_string());
+        }
+        steps
+            .into_iter()
+            .enumerate()
+            .map(|(i, step)| {
+                let id = Value::Integer(i as i64 + 1);
+                vec![id, Value::Integer(0), Value::Integer(0), Value::Text(step)]
+            })
+            .collect()
+    }
+
+    fn is_aggregate(&self) -> bool {
+        self.select.columns.iter().any(|c| match c {
+            ResultColumn::Expr { expr, .. } => expr.is_aggregate(),
+            _ => false,
+        })
+    }
+
+    fn matches(&self, row: &[(String, Value)]) -> Result<bool> {
+        Ok(match &self.select.where_clause {
+            Some(cond) => eval(cond, row)?.as_bool() == Some(true),
+            _ => true,
+        })
+    }
+
+    fn project(&self, row: &[(String, Value)]) -> Result<Vec<Value>> {
+        let mut values = vec![];
+        for column in self.select.columns.iter() {
+            match column {
+                ResultColumn::Star => values.extend(row.iter().map(|c| c.1.clone())),
+                ResultColumn::Expr { expr, .. } => values.push(eval(expr, row)?),
+            }
+        }
+        Ok(values)
+    }
+
+    fn aggregate(&self, rows: &[Vec<(String, Value)>]) -> Result<Vec<Value>> {
+        let mut values = vec![];
+        for column in self.select.columns.iter() {
+            match column {
+                ResultColumn::Star => {
+                    if let Some(row) = rows.last() {
+                        values.extend(row.iter().map(|c| c.1.clone()))
+                    }
+                }
+                ResultColumn::Expr { expr, .. } => values.push(eval_aggregate(expr, rows)?),
+            }
+        }
+        Ok(values)
+    }
+}
+
+/// Name and declared type of each result column, with `*` expanded to the
+/// columns of the table.
+fn result_columns(select: &Select, schema: Option<&TableSchema>) -> Vec<Column> {
+    let declared = |name: &str| {
+        schema
+            .and_then(|schema| schema.column(name))
+            .map(|column| column.type_name.clone())
+            .filter(|type_name| !type_name.is_empty())
+    };
+    select
+        .columns
+        .iter()
+        .flat_map(|column| match column {
+            ResultColumn::Star => schema
+                .iter()
+                .flat_map(|schema| schema.columns.iter())
+                .map(|c| Column::new(c.name.clone(), declared(&c.name)))
+                .collect(),
+            ResultColumn::Expr { expr, .. } => {
+                let decl_type = match expr {
+                    Expr::Column(name) => declared(name),
+                    _ => None,
+                };
+                vec![Column::new(column.name(), decl_type)]
+            }
+        })
+        .collect()
+}
+
+/// Indexes that can be searched to read `table_name`, from their `CREATE
+/// INDEX` statements. An indexed column is sorted by its declared collation
+/// unless the index names another one. A `WITHOUT ROWID` table is searched
+/// through its primary key instead, listed under the table's own name.
+fn table_indexes(schema_page: &Page, table_name: &str, schema: &TableSchema) -> Vec<IndexInfo> {
+    let collation = |column: &str, collation: Option<String>| match collation {
+        Some(collation) => Collation::from_name(&collation).ok(),
+        None => match schema.column(column) {
+            Some(column) => column.collation().ok(),
+            None => Some(Collation::Binary),
+        },
+    };
+
+    if schema.without_rowid {
+        let key = schema.primary_key();
+        let descending = key.first().is_some_and(|column| column.descending);
+        let columns = key
+            .into_iter()
+            .map(|column| {
+                Some((
+                    column.name.clone(),
+                    collation(&column.name, column.collation)?,
+                ))
+            })
+            .collect::<Option<Vec<_>>>();
+        return columns
+            .map(|columns| IndexInfo {
+                name: table_name.to_string(),
+                columns,
+                descending,
+            })
+            .into_iter()
+            .collect();
+    }
+
+    schema_page
+        .schema_entries()
+        .iter()
+        .filter_map(|(_, sql)| parse_create_index(sql).ok())
+        .filter(|(_, table, _)| table.eq_ignore_ascii_case(table_name))
+        .filter_map(|(name, _, columns)| {
+            let descending = columns.first().is_some_and(|column| column.descending);
+            let columns = columns
+                .into_iter()
+                .map(|column| {
+                    Some((
+                        column.name.clone(),
+                        collation(&column.name, column.collation)?,
+                    ))
+                })
+                .collect::<Option<Vec<_>>>()?;
+            Some(IndexInfo {
+                name,
+                columns,
+                descending,
+            })
+        })
+        .collect()
+}
+
+/// How `table_name` is read for rows matching `condition`, and the indexes
+/// that were considered.
+fn scan_plan(
+    table_name: &str,
+    db: &Db,
+    schema: &TableSchema,
+    condition: &Option<Expr>,
+) -> (ScanPlan, Vec<IndexInfo>) {
+    let indexes = table_indexes(&db.get_schema_page().borrow(), table_name, schema);
+    let alias = schema
+        .rowid_alias()
+        .map(|i| schema.columns[i].name.as_str());
+    let plan = plan_scan(&schema.affinities(), alias, &indexes, condition.as_ref());
+    (plan, indexes)
+}
+
+/// How an index search is shown in a query plan: `column=?` for a single
+/// key, otherwise `column>?` and `column<?` for its bounds.
+fn range_constraint(column: &str, range: &KeyRange) -> String {
+    if let (Bound::Included(lower), Bound::Included(upper)) = (&range.lower, &range.upper) {
+        if lower == upper {
+            return format!("{column}=?");
+        }
+    }
+    let mut bounds = vec![];
+    // the bound excluding NULL only keeps NULLs out of a `<` range
+    if !matches!(range.lower, Bound::Unbounded | Bound::Excluded(Value::Null)) {
+        bounds.push(format!("{column}>?"));
+    }
+    if !matches!(range.upper, Bound::Unbounded) {
+        bounds.push(format!("{column}<?"));
+    }
+    bounds.join(" AND ")
+}
+
+/// Rows of `table_name` that may match `condition`, read through the rowid
+/// or an index when one can narrow the scan.
+fn get_table_rows(
+    table_name: &str,
+    db: &Db,
+    schema: &TableSchema,
+    condition: &Option<Expr>,
+    interrupted: &Arc<AtomicBool>,
+) -> Result<RowIter> {
+    let (plan, _) = scan_plan(table_name, db, schema, condition);
+    let schema_page = db.get_schema_page();
+    let schema_page = schema_page.borrow();
+
+    match plan {
+        ScanPlan::Rowid(rowid) => {
+            let row = schema_page.search_table_rowid(&mut db.get_file(), table_name, rowid)?;
+            Ok(Box::new(row.into_iter().map(Ok)))
+        }
+        ScanPlan::IndexRange { index, range } => schema_page.search_index_range(
+            &mut db.get_file(),
+            (table_name.to_string(), index),
+            &range,
+            interrupted,
+        ),
+        ScanPlan::FullScan => schema_page.scan_table(&mut db.get_file(), table_name, interrupted),
+    }
+}
+
+/// "1st", "2nd", ... as used in error messages about ORDER BY terms.
+fn ordinal(n: usize) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{n}{suffix}")
+}
+
+/// The expression an ORDER BY term sorts by: a number picks a result column
+/// by position, and a name can refer to a result column alias.
+fn ordering_expr(term: &Expr, outputs: &[(Option<String>, Expr)], position: usize) -> Result<Expr> {
+    Ok(match term {
+        Expr::Collate(expr, name) => Expr::Collate(
+            Box::new(ordering_expr(expr, outputs, position)?),
+            name.clone(),
+        ),
+        Expr::Literal(Value::Integer(i)) => match usize::try_from(*i)
+            .ok()
+            .filter(|i| (1..=outputs.len()).contains(i))
+        {
+            Some(i) => outputs[i - 1].1.clone(),
+            None => {
+                return Err(Error::Sql(format!(
+                    "{} ORDER BY term out of range - should be between 1 and {}",
+                    ordinal(position + 1),
+                    outputs.len()
+                )))
+            }
+        },
+        Expr::Column(name) => outputs
+            .iter()
+            .find(|(alias, _)| alias.as_ref().is_some_and(|a| a.eq_ignore_ascii_case(name)))
+            .map_or(term.clone(), |(_, expr)| expr.clone()),
+        _ => term.clone(),
+    })
+}
+
+/// Sorts the result rows by the ORDER BY terms, each compared with its
+/// collation. Rows that compare equal keep their scan order.
+fn sort_rows(
+    rows: &[Vec<(String, Value)>],
+    project: impl Fn(&[(String, Value)]) -> Result<Vec<Value>>,
+    order_by: &[OrderingTerm],
+    outputs: &[(Option<String>, Expr)],
+    collations: &[(String, Collation)],
+) -> Result<Vec<Vec<Value>>> {
+    let mut ordering = vec![];
+    for (position, term) in order_by.iter().enumerate() {
+        let expr = ordering_expr(&term.expr, outputs, position)?;
+        let collation = expr_collation(&expr, collations)?;
+        ordering.push((expr, collation, term.descending));
+    }
+
+    let mut keyed = vec![];
+    for row in rows {
+        let keys = ordering
+            .iter()
+            .map(|(expr, _, _)| eval(expr, row))
+            .collect::<Result<Vec<_>>>()?;
+        keyed.push((keys, project(row)?));
+    }
+    keyed.sort_by(|(a, _), (b, _)| {
+        ordering
+            .iter()
+            .zip(a.iter().zip(b))
+            .map(|((_, collation, descending), (a, b))| match descending {
+                true => collation.compare(a, b).reverse(),
+                _ => collation.compare(a, b),
+            })
+            .find(|order| order.is_ne())
+            .unwrap_or(Ordering::Equal)
+    });
+    Ok(keyed.into_iter().map(|(_, values)| values).collect())
+}
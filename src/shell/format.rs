@@ -0,0 +1,705 @@
+//! Output modes: how the shell writes the rows of a result, as delimited
+//! values, JSON, SQL or HTML as they come, or aligned in columns once they
+//! are all read.
+
+use std::fmt::{self, Alignment};
+use std::io::Write;
+
+use anyhow::{bail, Result};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use codecrafters_sqlite::{Rows, Value};
+
+use super::{quote_name, sql_literal, text_literal};
+
+const MODES: &str = "box column csv html insert json line list markdown ndjson quote table tabs";
+
+/// An output mode, set with `.mode`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mode {
+    /// Values separated by the column separator.
+    List,
+    /// Comma-separated values, quoted as in RFC 4180.
+    Csv,
+    /// Tab-separated values.
+    Tabs,
+    /// An array with an object per row.
+    Json,
+    /// An object per row, one per line.
+    Ndjson,
+    /// A Markdown table.
+    Markdown,
+    /// A table framed with box-drawing characters.
+    Box,
+    /// A table framed with `+`, `-` and `|`.
+    Table,
+    /// Columns separated by two spaces.
+    Column,
+    /// A `name = value` line per value, a blank line between rows.
+    Line,
+    /// Rows of an HTML table.
+    Html,
+    /// INSERT statements into the table.
+    Insert(String),
+    /// Values as SQL literals.
+    Quote,
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Mode::List => "list",
+            Mode::Csv => "csv",
+            Mode::Tabs => "tabs",
+            Mode::Json => "json",
+            Mode::Ndjson => "ndjson",
+            Mode::Markdown => "markdown",
+            Mode::Box => "box",
+            Mode::Table => "table",
+            Mode::Column => "column",
+            Mode::Line => "line",
+            Mode::Html => "html",
+            Mode::Insert(table) => return write!(f, "insert {table}"),
+            Mode::Quote => "quote",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// How results are written: the mode and the settings it uses.
+pub struct Format {
+    pub mode: Mode,
+    /// Whether the column names come before the rows.
+    pub headers: bool,
+    pub column_separator: String,
+    pub row_separator: String,
+    /// Text shown for NULL, except where modes write it as a literal.
+    pub null_value: String,
+    /// Widths of the columns in the columnar modes, from the first: 0 for as
+    /// wide as the values, negative to align the values right.
+    pub widths: Vec<i64>,
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Format {
+            mode: Mode::List,
+            headers: false,
+            column_separator: "|".to_string(),
+            row_separator: "\n".to_string(),
+            null_value: String::new(),
+            widths: vec![],
+        }
+    }
+}
+
+impl Format {
+    /// Switches to mode `name`, which for `insert` takes the table name as
+    /// argument. The delimited modes set their separators, and `list` puts
+    /// back `|` in place of theirs.
+    pub fn set_mode(&mut self, name: &str, args: &[String]) -> Result<()> {
+        let separators = |column: &str, row: &str| (column.to_string(), row.to_string());
+        let (mode, separators) = match (name, args) {
+            ("list", []) => match self.column_separator.as_str() {
+                "," | "\t" => (Mode::List, Some(separators("|", "\n"))),
+                column => (Mode::List, Some(separators(column, "\n"))),
+            },
+            ("csv", []) => (Mode::Csv, Some(separators(",", "\r\n"))),
+            ("tabs", []) => (Mode::Tabs, Some(separators("\t", "\n"))),
+            ("quote", []) => (Mode::Quote, Some(separators(",", "\n"))),
+            ("json", []) => (Mode::Json, None),
+            ("ndjson", []) => (Mode::Ndjson, None),
+            ("markdown", []) => (Mode::Markdown, None),
+            ("box", []) => (Mode::Box, None),
+            ("table", []) => (Mode::Table, None),
+            ("column", []) => (Mode::Column, None),
+            ("line", []) => (Mode::Line, None),
+            ("html", []) => (Mode::Html, None),
+            ("insert", []) => (Mode::Insert("table".to_string()), None),
+            ("insert", [table]) => (Mode::Insert(table.clone()), None),
+            _ => bail!("mode should be one of: {MODES}"),
+        };
+        self.mode = mode;
+        if let Some((column, row)) = separators {
+            self.column_separator = column;
+            self.row_separator = row;
+        }
+        Ok(())
+    }
+
+    /// Writes the rows of a result whose columns are named `columns`. The
+    /// columnar modes read all rows first to size the columns; the others
+    /// write each row as it comes.
+    pub fn write_rows(&self, out: &mut dyn Write, columns: &[&str], rows: Rows<'_>) -> Result<()> {
+        if matches!(
+            self.mode,
+            Mode::Markdown | Mode::Box | Mode::Table | Mode::Column
+        ) {
+            let mut table = vec![];
+            for row in rows {
+                let row = row?;
+                table.push(row.values().iter().map(|value| self.show(value)).collect());
+            }
+            return self.write_columns(out, columns, &table);
+        }
+
+        let mut first = true;
+        for row in rows {
+            self.write_row(out, columns, row?.values(), first)?;
+            first = false;
+        }
+        if !first && self.mode == Mode::Json {
+            writeln!(out, "]")?;
+        }
+        Ok(())
+    }
+
+    /// Writes one row in a mode that doesn't align columns, after the
+    /// headers if it's the `first`.
+    fn write_row(
+        &self,
+        out: &mut dyn Write,
+        columns: &[&str],
+        values: &[Value],
+        first: bool,
+    ) -> Result<()> {
+        let (separator, end) = (&self.column_separator, &self.row_separator);
+        let headers = first && self.headers;
+        match &self.mode {
+            Mode::List | Mode::Tabs => {
+                if headers {
+                    write!(out, "{}{end}", columns.join(separator))?;
+                }
+                let values = values.iter().map(|value| self.show(value));
+                write!(out, "{}{end}", values.collect::<Vec<_>>().join(separator))?;
+            }
+            Mode::Csv => {
+                if headers {
+                    let names = columns.iter().map(|name| csv_field(name, separator));
+                    write!(out, "{}{end}", names.collect::<Vec<_>>().join(separator))?;
+                }
+                let values = values.iter().map(|value| match value {
+                    Value::Null if self.null_value.is_empty() => String::new(),
+                    value => csv_field(&self.show(value), separator),
+                });
+                write!(out, "{}{end}", values.collect::<Vec<_>>().join(separator))?;
+            }
+            Mode::Quote => {
+                if headers {
+                    let names = columns.iter().map(|name| text_literal(name, false));
+                    write!(out, "{}{end}", names.collect::<Vec<_>>().join(separator))?;
+                }
+                let values = values.iter().map(|value| match value {
+                    Value::Text(s) => text_literal(s, false),
+                    value => sql_literal(value),
+                });
+                write!(out, "{}{end}", values.collect::<Vec<_>>().join(separator))?;
+            }
+            Mode::Json | Mode::Ndjson => {
+                let fields = columns
+                    .iter()
+                    .zip(values)
+                    .map(|(name, value)| format!("{}:{}", json_string(name), json_value(value)));
+                let object = format!("{{{}}}", fields.collect::<Vec<_>>().join(","));
+                match (&self.mode, first) {
+                    (Mode::Json, true) => write!(out, "[{object}")?,
+                    (Mode::Json, false) => write!(out, ",\n{object}")?,
+                    _ => writeln!(out, "{object}")?,
+                }
+            }
+            Mode::Line => {
+                // names are right-aligned to at least 5 columns
+                let width = columns.iter().map(|name| name.width()).max();
+                let width = width.unwrap_or_default().max(5);
+                if !first {
+                    writeln!(out)?;
+                }
+                for (name, value) in columns.iter().zip(values) {
+                    let name = pad(name, width, Alignment::Right);
+                    writeln!(out, "{name} = {}", self.show(value))?;
+                }
+            }
+            Mode::Html => {
+                if headers {
+                    write_html_row(out, "TH", columns.iter().map(|name| name.to_string()))?;
+                }
+                write_html_row(out, "TD", values.iter().map(|value| self.show(value)))?;
+            }
+            Mode::Insert(table) => {
+                let names = match self.headers {
+                    true => {
+                        let names = columns.iter().map(|name| quote_name(name));
+                        format!("({})", names.collect::<Vec<_>>().join(","))
+                    }
+                    false => String::new(),
+                };
+                let values = values.iter().map(sql_literal).collect::<Vec<_>>();
+                writeln!(
+                    out,
+                    "INSERT INTO {}{names} VALUES({});",
+                    quote_name(table),
+                    values.join(",")
+                )?;
+            }
+            Mode::Markdown | Mode::Box | Mode::Table | Mode::Column => {
+                unreachable!("columnar modes are written by write_columns")
+            }
+        }
+        Ok(())
+    }
+
+    /// `value` as the text modes show it: NULL as the null text.
+    fn show(&self, value: &Value) -> String {
+        match value {
+            Value::Null => self.null_value.clone(),
+            value => display(value),
+        }
+    }
+
+    /// Writes `rows` in columns as wide as their longest value or name, the
+    /// names centered in a frame, or above a rule in column mode when
+    /// headers are on. A column given a width wraps its values and cuts its
+    /// name to fit. Rows are set apart when a value spans several lines.
+    /// Widths are counted in terminal columns, two for a wide character.
+    fn write_columns(
+        &self,
+        out: &mut dyn Write,
+        columns: &[&str],
+        rows: &[Vec<String>],
+    ) -> Result<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let fixed = |i: usize| self.widths.get(i).copied().unwrap_or(0);
+        let align = |i: usize| match fixed(i) < 0 {
+            true => Alignment::Right,
+            false => Alignment::Left,
+        };
+        let rows = rows
+            .iter()
+            .map(|row| {
+                let cells = row.iter().enumerate();
+                cells
+                    .map(|(i, value)| cell_lines(value, fixed(i).unsigned_abs() as usize))
+                    .collect()
+            })
+            .collect::<Vec<Vec<_>>>();
+        let mut widths = columns
+            .iter()
+            .enumerate()
+            .map(|(i, name)| match fixed(i) {
+                0 => name.width(),
+                width => width.unsigned_abs() as usize,
+            })
+            .collect::<Vec<_>>();
+        let columns = columns
+            .iter()
+            .zip(&widths)
+            .map(|(name, width)| cut(name, *width))
+            .collect::<Vec<_>>();
+        for (i, name) in columns.iter().enumerate() {
+            widths[i] = widths[i].max(name.width());
+        }
+        for lines in rows.iter().flat_map(|row| row.iter().enumerate()) {
+            let (i, lines) = lines;
+            for line in lines {
+                widths[i] = widths[i].max(line.width());
+            }
+        }
+        let multi_line = rows.iter().flatten().any(|lines| lines.len() > 1);
+
+        let rule = |ends: [&str; 3], fill: &str| {
+            let fills = widths.iter().map(|width| fill.repeat(width + 2));
+            format!(
+                "{}{}{}",
+                ends[0],
+                fills.collect::<Vec<_>>().join(ends[1]),
+                ends[2]
+            )
+        };
+        let line = |cells: Vec<String>, bar: &str| match bar {
+            "" => cells.join("  "),
+            bar => format!("{bar} {} {bar}", cells.join(&format!(" {bar} "))),
+        };
+        let (top, middle, bottom, bar) = match self.mode {
+            Mode::Box => (
+                Some(rule(["┌", "┬", "┐"], "─")),
+                Some(rule(["├", "┼", "┤"], "─")),
+                Some(rule(["└", "┴", "┘"], "─")),
+                "│",
+            ),
+            Mode::Table => {
+                let rule = rule(["+", "+", "+"], "-");
+                (Some(rule.clone()), Some(rule.clone()), Some(rule), "|")
+            }
+            Mode::Markdown => (None, Some(rule(["|", "|", "|"], "-")), None, "|"),
+            _ => (None, None, None, ""),
+        };
+
+        if let Some(top) = &top {
+            writeln!(out, "{top}")?;
+        }
+        if bar.is_empty() {
+            if self.headers {
+                let names = columns.iter().zip(&widths).enumerate();
+                let names = names.map(|(i, (name, width))| pad(name, *width, align(i)));
+                writeln!(out, "{}", line(names.collect(), bar))?;
+                let dashes = widths.iter().map(|width| "-".repeat(*width));
+                writeln!(out, "{}", line(dashes.collect(), bar))?;
+            }
+        } else {
+            let names = columns.iter().zip(&widths);
+            let names = names.map(|(name, width)| pad(name, *width, Alignment::Center));
+            writeln!(out, "{}", line(names.collect(), bar))?;
+            if let Some(middle) = &middle {
+                writeln!(out, "{middle}")?;
+            }
+        }
+        for (i, row) in rows.iter().enumerate() {
+            if i > 0 && multi_line {
+                match (&self.mode, &middle) {
+                    (Mode::Markdown, _) => {}
+                    (_, Some(middle)) => writeln!(out, "{middle}")?,
+                    (_, None) => writeln!(out)?,
+                }
+            }
+            let height = row.iter().map(Vec::len).max().unwrap_or(1);
+            for k in 0..height {
+                let cells = row.iter().zip(&widths).enumerate();
+                let cells = cells.map(|(i, (lines, width))| {
+                    pad(lines.get(k).map_or("", String::as_str), *width, align(i))
+                });
+                writeln!(out, "{}", line(cells.collect(), bar))?;
+            }
+        }
+        if let Some(bottom) = &bottom {
+            writeln!(out, "{bottom}")?;
+        }
+        Ok(())
+    }
+}
+
+/// `value` as text to read, with control characters other than tabs and
+/// line feeds written as `^A` to `^_`, and `^?`. As in sqlite3, the text
+/// ends at the first NUL, which blobs especially may have.
+fn display(value: &Value) -> String {
+    let mut text = String::new();
+    for c in value.to_string().chars().take_while(|&c| c != '\0') {
+        match c {
+            '\t' | '\n' => text.push(c),
+            '\u{7f}' => text += "^?",
+            c if c.is_ascii_control() => {
+                text.push('^');
+                text.push(char::from(c as u8 + b'@'));
+            }
+            c => text.push(c),
+        }
+    }
+    text
+}
+
+/// The lines of a value in a column, with tabs expanded to the next
+/// multiple of 8 columns, cut into lines `width` columns wide unless
+/// `width` is 0. As in sqlite3, a tab stops at the end of a line and is
+/// dropped right after it, and a wide character that starts before the end
+/// is kept, going past it.
+fn cell_lines(value: &str, width: usize) -> Vec<String> {
+    let mut lines = vec![];
+    for text in value.split('\n') {
+        let start = lines.len();
+        let mut line = String::new();
+        let mut used = 0;
+        for c in text.chars() {
+            if width > 0 && used >= width {
+                lines.push(std::mem::take(&mut line));
+                used = 0;
+                if c == '\t' {
+                    continue;
+                }
+            }
+            match c {
+                '\t' => loop {
+                    line.push(' ');
+                    used += 1;
+                    if used % 8 == 0 || used == width {
+                        break;
+                    }
+                },
+                c => {
+                    line.push(c);
+                    used += c.width().unwrap_or(0);
+                }
+            }
+        }
+        if !line.is_empty() || lines.len() == start {
+            lines.push(line);
+        }
+    }
+    lines
+}
+
+/// The start of `text` that fills `width` columns, a wide character that
+/// starts before the width is reached kept as in `cell_lines`.
+fn cut(text: &str, width: usize) -> &str {
+    let mut used = 0;
+    for (i, c) in text.char_indices() {
+        if used >= width {
+            return &text[..i];
+        }
+        used += c.width().unwrap_or(0);
+    }
+    text
+}
+
+/// `text` padded with spaces to `width` terminal columns.
+fn pad(text: &str, width: usize, align: Alignment) -> String {
+    let fill = width.saturating_sub(text.width());
+    let (left, right) = match align {
+        Alignment::Left => (0, fill),
+        Alignment::Right => (fill, 0),
+        Alignment::Center => (fill / 2, fill - fill / 2),
+    };
+    format!("{}{text}{}", " ".repeat(left), " ".repeat(right))
+}
+
+/// `s` as a CSV field: in double quotes, with `"` doubled, when it's empty
+/// or has the separator, a quote, a space, or a control or non-ASCII
+/// character.
+fn csv_field(s: &str, separator: &str) -> String {
+    let quoted = s.is_empty()
+        || !separator.is_empty() && s.contains(separator)
+        || s.chars()
+            .any(|c| matches!(c, '"' | '\'' | ' ') || c.is_ascii_control() || !c.is_ascii());
+    match quoted {
+        true => format!("\"{}\"", s.replace('"', "\"\"")),
+        false => s.to_string(),
+    }
+}
+
+/// `s` as a JSON string.
+fn json_string(s: &str) -> String {
+    let mut json = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => json += "\\\"",
+            '\\' => json += "\\\\",
+            '\n' => json += "\\n",
+            '\r' => json += "\\r",
+            '\t' => json += "\\t",
+            '\u{8}' => json += "\\b",
+            '\u{c}' => json += "\\f",
+            c if c < ' ' => json += &format!("\\u{:04x}", c as u32),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// `value` as JSON: numbers, strings and null, and a blob as the string
+/// with a character per byte.
+fn json_value(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Integer(i) => i.to_string(),
+        Value::Real(_) => sql_literal(value),
+        Value::Text(s) => json_string(s),
+        Value::Blob(b) => json_string(&b.iter().map(|&byte| char::from(byte)).collect::<String>()),
+    }
+}
+
+/// Writes a row of an HTML table, with `tag` cells on lines of their own.
+fn write_html_row(
+    out: &mut dyn Write,
+    tag: &str,
+    cells: impl Iterator<Item = String>,
+) -> Result<()> {
+    write!(out, "<TR>")?;
+    for cell in cells {
+        let cell = cell
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+            .replace('\'', "&#39;");
+        writeln!(out, "<{tag}>{cell}</{tag}>")?;
+    }
+    writeln!(out, "</TR>")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use codecrafters_sqlite::Connection;
+
+    use super::*;
+
+    /// The result of `sql` on sample.db in mode `name`, with headers.
+    fn write(name: &str, sql: &str) -> String {
+        let connection = Connection::open("sample.db").unwrap();
+        let statement = connection.prepare(sql).unwrap();
+        let mut format = Format {
+            headers: true,
+            ..Format::default()
+        };
+        format.set_mode(name, &[]).unwrap();
+        let mut out = vec![];
+        let columns = statement.column_names();
+        format
+            .write_rows(&mut out, &columns, statement.query().unwrap())
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_delimited_modes() {
+        let sql = "SELECT id, name, NULL AS \"no value\" FROM apples WHERE id < 3";
+        assert_eq!(
+            write("list", sql),
+            "id|name|no value\n1|Granny Smith|\n2|Fuji|\n"
+        );
+        assert_eq!(
+            write("csv", sql),
+            "id,name,\"no value\"\r\n1,\"Granny Smith\",\r\n2,Fuji,\r\n"
+        );
+        assert_eq!(
+            write("tabs", sql),
+            "id\tname\tno value\n1\tGranny Smith\t\n2\tFuji\t\n"
+        );
+        assert_eq!(
+            write("quote", sql),
+            "'id','name','no value'\n1,'Granny Smith',NULL\n2,'Fuji',NULL\n"
+        );
+        assert_eq!(
+            write("json", sql),
+            "[{\"id\":1,\"name\":\"Granny Smith\",\"no value\":null},\n\
+             {\"id\":2,\"name\":\"Fuji\",\"no value\":null}]\n"
+        );
+        assert_eq!(
+            write("ndjson", "SELECT 'say \"hi\"\n' AS s, 0.5 AS r"),
+            "{\"s\":\"say \\\"hi\\\"\\n\",\"r\":0.5}\n"
+        );
+        assert_eq!(
+            write("line", "SELECT id, name AS n FROM apples WHERE id = 1"),
+            "   id = 1\n    n = Granny Smith\n"
+        );
+        assert_eq!(
+            write("html", "SELECT '<b>' AS tag"),
+            "<TR><TH>tag</TH>\n</TR>\n<TR><TD>&lt;b&gt;</TD>\n</TR>\n"
+        );
+        assert_eq!(
+            write(
+                "insert",
+                "SELECT id, 'it''s' AS \"order\" FROM apples WHERE id = 1"
+            ),
+            "INSERT INTO \"table\"(id,\"order\") VALUES(1,'it''s');\n"
+        );
+        assert_eq!(write("json", "SELECT * FROM apples WHERE 0"), "");
+    }
+
+    #[test]
+    fn test_columnar_modes() {
+        let sql = "SELECT id, name FROM apples WHERE id < 3";
+        assert_eq!(
+            write("column", sql),
+            "id  name        \n--  ------------\n1   Granny Smith\n2   Fuji        \n"
+        );
+        assert_eq!(
+            write("table", sql),
+            "+----+--------------+\n\
+             | id |     name     |\n\
+             +----+--------------+\n\
+             | 1  | Granny Smith |\n\
+             | 2  | Fuji         |\n\
+             +----+--------------+\n"
+        );
+        assert_eq!(
+            write("box", "SELECT 'a\tb' AS x"),
+            "┌───────────┐\n│     x     │\n├───────────┤\n│ a       b │\n└───────────┘\n"
+        );
+        assert_eq!(
+            write("markdown", "SELECT 1 AS n, 'two\nlines' AS s"),
+            "| n |   s   |\n|---|-------|\n| 1 | two   |\n|   | lines |\n"
+        );
+        assert_eq!(write("box", "SELECT * FROM apples WHERE 0"), "");
+    }
+
+    #[test]
+    fn test_column_widths() {
+        let connection = Connection::open("sample.db").unwrap();
+        let statement = connection
+            .prepare("SELECT id, name FROM apples WHERE id < 3")
+            .unwrap();
+        let mut format = Format {
+            headers: true,
+            widths: vec![-3, 4],
+            ..Format::default()
+        };
+        let mut write = |mode: &str| {
+            format.set_mode(mode, &[]).unwrap();
+            let mut out = vec![];
+            let columns = statement.column_names();
+            format
+                .write_rows(&mut out, &columns, statement.query().unwrap())
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(
+            write("column"),
+            " id  name\n---  ----\n  1  Gran\n     ny S\n     mith\n\n  2  Fuji\n"
+        );
+        assert_eq!(
+            write("table"),
+            "+-----+------+\n\
+             | id  | name |\n\
+             +-----+------+\n\
+             |   1 | Gran |\n\
+             |     | ny S |\n\
+             |     | mith |\n\
+             +-----+------+\n\
+             |   2 | Fuji |\n\
+             +-----+------+\n"
+        );
+    }
+
+    #[test]
+    fn test_wide_characters() {
+        let sql = "SELECT '漢字テスト' AS name, id FROM apples WHERE id < 3";
+        assert_eq!(
+            write("box", sql),
+            "┌────────────┬────┐\n\
+             │    name    │ id │\n\
+             ├────────────┼────┤\n\
+             │ 漢字テスト │ 1  │\n\
+             │ 漢字テスト │ 2  │\n\
+             └────────────┴────┘\n"
+        );
+        // a wide character that starts within the width is kept
+        assert_eq!(cell_lines("漢字テスト", 3), ["漢字", "テス", "ト"]);
+        assert_eq!(cell_lines("a\t漢b", 0), ["a       漢b"]);
+        assert_eq!(cell_lines("ab\tc\t\td", 3), ["ab ", "c  ", "d"]);
+        assert_eq!(pad("漢", 5, Alignment::Center), " 漢  ");
+    }
+
+    #[test]
+    fn test_nul() {
+        // values end at the first NUL
+        let sql = "SELECT x'41004243' AS b, 'c' AS c FROM apples WHERE id = 1";
+        assert_eq!(write("list", sql), "b|c\nA|c\n");
+        assert_eq!(write("json", sql), "[{\"b\":\"A\\u0000BC\",\"c\":\"c\"}]\n");
+    }
+
+    #[test]
+    fn test_set_mode() {
+        let mut format = Format::default();
+        format.set_mode("csv", &[]).unwrap();
+        assert_eq!(format.column_separator, ",");
+        format.set_mode("list", &[]).unwrap();
+        assert_eq!(format.column_separator, "|");
+        assert_eq!(format.row_separator, "\n");
+        format.set_mode("insert", &["fruit".to_string()]).unwrap();
+        assert_eq!(format.mode.to_string(), "insert fruit");
+        assert!(format.set_mode("bogus", &[]).is_err());
+        assert!(format.set_mode("csv", &["extra".to_string()]).is_err());
+    }
+}
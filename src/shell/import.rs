@@ -0,0 +1,230 @@
+//! Reading the records `.import` inserts: fields split by a column and a row
+//! separator, with CSV's double quotes unless the input is ASCII-separated.
+
+use std::str::Chars;
+
+pub struct Reader<'a> {
+    /// The file read, for warnings.
+    name: &'a str,
+    chars: Chars<'a>,
+    column: char,
+    row: char,
+    quoted: bool,
+    line: usize,
+    /// Warnings about malformed quotes not yet shown.
+    pub warnings: Vec<String>,
+}
+
+impl<'a> Reader<'a> {
+    /// Reads `input`, splitting it on `column` and `row`. With `quoted`, a
+    /// field can be in double quotes, doubled within it, and a row ending in
+    /// `\r\n` ends before the `\r`.
+    pub fn new(name: &'a str, input: &'a str, column: char, row: char, quoted: bool) -> Self {
+        Reader {
+            name,
+            chars: input.chars(),
+            column,
+            row,
+            quoted,
+            line: 1,
+            warnings: vec![],
+        }
+    }
+
+    /// The fields of the next record and the line it starts on, or None at
+    /// the end of the input.
+    pub fn next_record(&mut self) -> Option<(usize, Vec<String>)> {
+        let line = self.line;
+        let (field, mut end) = self.field()?;
+        let mut fields = vec![field];
+        while end == Some(self.column) {
+            // a separator just before the end of the input ends an empty field
+            let (field, next) = self.field().unwrap_or_default();
+            fields.push(field);
+            end = next;
+        }
+        Some((line, fields))
+    }
+
+    /// The next field and the character that ended it, None for the end of
+    /// the input; None if the input ends before the field starts.
+    fn field(&mut self) -> Option<(String, Option<char>)> {
+        let first = self.chars.next()?;
+        let mut field = String::new();
+        if self.quoted && first == '"' {
+            return Some(self.quoted_field());
+        }
+        let mut c = Some(first);
+        while let Some(next) = c.filter(|&next| next != self.column && next != self.row) {
+            field.push(next);
+            c = self.chars.next();
+        }
+        if c == Some(self.row) {
+            self.line += 1;
+            if self.quoted && c == Some('\n') && field.ends_with('\r') {
+                field.pop();
+            }
+        }
+        Some((field, c))
+    }
+
+    /// A field after its opening quote. A quote that doesn't close the field
+    /// or start a doubled one is kept, as is the rest of the input if the
+    /// field isn't closed.
+    fn quoted_field(&mut self) -> (String, Option<char>) {
+        let start = self.line;
+        let mut field = String::new();
+        // the last two characters read
+        let (mut previous, mut before) = (None, None);
+        loop {
+            let c = self.chars.next();
+            if c == Some(self.row) {
+                self.line += 1;
+            }
+            if c == Some('"') && previous == Some('"') {
+                previous = None;
+                continue;
+            }
+            let closed = previous == Some('"')
+                && (c == Some(self.column) || c == Some(self.row) || c.is_none())
+                || c == Some(self.row) && previous == Some('\r') && before == Some('"');
+            if closed {
+                // drop the closing quote and the `\r` after it
+                let end = field.rfind('"').unwrap_or(0);
+                field.truncate(end);
+                return (field, c);
+            }
+            if previous == Some('"') && c != Some('\r') {
+                self.warnings.push(format!(
+                    "{}:{}: unescaped \" character",
+                    self.name, self.line
+                ));
+            }
+            let Some(c) = c else {
+                self.warnings.push(format!(
+                    "{}:{start}: unterminated \"-quoted field",
+                    self.name
+                ));
+                return (field, None);
+            };
+            field.push(c);
+            before = previous;
+            previous = Some(c);
+        }
+    }
+}
+
+/// Column names for a new table from a header record: an empty name
+/// becomes `?`, and names used more than once, ignoring case, are numbered
+/// `_1`, `_2` and so on. Also returns what was renamed.
+pub fn column_names(header: &[String]) -> (Vec<String>, Vec<(String, String)>) {
+    let names = header
+        .iter()
+        .map(|name| match name.is_empty() {
+            true => "?".to_string(),
+            false => name.clone(),
+        })
+        .collect::<Vec<_>>();
+    let mut renamed = vec![];
+    let mut columns = names.clone();
+    for (i, name) in names.iter().enumerate() {
+        let same = |other: &String| other.eq_ignore_ascii_case(name);
+        if names.iter().filter(|other| same(other)).count() > 1 {
+            let number = names[..=i].iter().filter(|other| same(other)).count();
+            columns[i] = format!("{name}_{number}");
+            renamed.push((name.clone(), columns[i].clone()));
+        }
+    }
+    (columns, renamed)
+}
+
+/// The declared type a column of `values` is given with `--infer-types`:
+/// INTEGER if every one is an integer, REAL if every one is a number and
+/// TEXT otherwise. Empty values, which become NULL, don't count.
+pub fn infer_type<'v>(values: impl IntoIterator<Item = &'v str>) -> &'static str {
+    let is_number = |value: &str| {
+        value.parse::<f64>().is_ok()
+            && value
+                .chars()
+                .all(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '+' | '-'))
+    };
+    let mut inferred = None;
+    for value in values.into_iter().filter(|value| !value.is_empty()) {
+        inferred = match inferred {
+            _ if !is_number(value) => return "TEXT",
+            Some("REAL") => Some("REAL"),
+            _ if value.parse::<i64>().is_ok() => Some("INTEGER"),
+            _ => Some("REAL"),
+        };
+    }
+    inferred.unwrap_or("TEXT")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(input: &str, quoted: bool) -> (Vec<(usize, Vec<String>)>, Vec<String>) {
+        let (column, row) = match quoted {
+            true => (',', '\n'),
+            false => ('\x1f', '\x1e'),
+        };
+        let mut reader = Reader::new("f.csv", input, column, row, quoted);
+        let records = std::iter::from_fn(|| reader.next_record()).collect();
+        (records, reader.warnings)
+    }
+
+    #[test]
+    fn test_reader() {
+        let record = |line: usize, fields: &[&str]| {
+            (
+                line,
+                fields.iter().map(|f| f.to_string()).collect::<Vec<_>>(),
+            )
+        };
+        let (read, warnings) = records("a,b\r\n1,\"x \"\"q\"\" y\"\n\n\"multi\nline\",2\n3,", true);
+        assert_eq!(
+            read,
+            [
+                record(1, &["a", "b"]),
+                record(2, &["1", "x \"q\" y"]),
+                record(3, &[""]),
+                record(4, &["multi\nline", "2"]),
+                record(6, &["3", ""]),
+            ]
+        );
+        assert!(warnings.is_empty());
+
+        let (read, warnings) = records("5,\"ab\"cd,6\n", true);
+        assert_eq!(read, [record(1, &["5", "ab\"cd,6\n"])]);
+        assert_eq!(
+            warnings,
+            [
+                "f.csv:1: unescaped \" character",
+                "f.csv:1: unterminated \"-quoted field"
+            ]
+        );
+
+        let (read, _) = records("\"a\"\x1fb,c\x1e1\x1f2\x1e", false);
+        assert_eq!(read, [record(1, &["\"a\"", "b,c"]), record(2, &["1", "2"])]);
+    }
+
+    #[test]
+    fn test_column_names() {
+        let header = ["a", "A", "", "b"].map(String::from);
+        let (columns, renamed) = column_names(&header);
+        assert_eq!(columns, ["a_1", "A_2", "?", "b"]);
+        assert_eq!(
+            renamed,
+            [("a".into(), "a_1".into()), ("A".into(), "A_2".into())]
+        );
+    }
+
+    #[test]
+    fn test_infer_type() {
+        assert_eq!(infer_type(["1", "", "-20"]), "INTEGER");
+        assert_eq!(infer_type(["1", "2.5", "1e3"]), "REAL");
+        assert_eq!(infer_type(["1", "inf"]), "TEXT");
+        assert_eq!(infer_type(["", ""]), "TEXT");
+    }
+}
@@ -0,0 +1,2022 @@
+//! The command-line shell: runs dot-commands and SQL statements given on
+//! the command line, or read interactively with line editing and history.
+
+mod format;
+mod import;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+#[cfg(unix)]
+use std::mem::MaybeUninit;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+
+use codecrafters_sqlite::{Connection, Value};
+
+use format::{Format, Mode};
+use import::{column_names, infer_type, Reader};
+
+const PROMPT: &str = "sqlite> ";
+const CONTINUATION_PROMPT: &str = "   ...> ";
+const HISTORY_FILE: &str = ".codecrafters_sqlite_history";
+/// How many scripts can run inside each other through `.read`.
+const MAX_INPUT_NESTING: usize = 25;
+
+const HELP: &[(&str, &str)] = &[
+    (".dbinfo", "Show status information about the database"),
+    (
+        ".dump ?OPTIONS? ?OBJECTS?",
+        "Render database content as SQL: --data-only, --newlines, --nosys",
+    ),
+    (".exit ?CODE?", "Exit this program with return-code CODE"),
+    (
+        ".fullschema ?--indent?",
+        "Show schema and the content of sqlite_stat tables",
+    ),
+    (".headers on|off", "Turn display of headers on or off"),
+    (".help", "Show help text"),
+    (
+        ".import FILE TABLE",
+        "Import data from FILE into TABLE: --ascii, --csv, --infer-types, --skip N",
+    ),
+    (".indexes ?TABLE?", "Show names of indexes"),
+    (
+        ".mode MODE ?TABLE?",
+        "Set output mode: box, column, csv, html, insert, json, line, list, \
+         markdown, ndjson, quote, table or tabs",
+    ),
+    (".nullvalue STRING", "Use STRING in place of NULL values"),
+    (".once FILE", "Output for the next command only to FILE"),
+    (
+        ".output ?FILE?",
+        "Send output to FILE or stdout if FILE is omitted",
+    ),
+    (".quit", "Exit this program"),
+    (".read FILE", "Read input from FILE"),
+    (
+        ".schema ?--indent? ?--nosys? ?PATTERN?",
+        "Show the CREATE statements matching PATTERN",
+    ),
+    (
+        ".separator COL ?ROW?",
+        "Change the column and row separators",
+    ),
+    (
+        ".stats ?on|off?",
+        "Show what the last statement read, or show it after every statement",
+    ),
+    (
+        ".tables ?TABLE?",
+        "List names of tables matching LIKE pattern TABLE",
+    ),
+    (".timer on|off", "Turn SQL timer on or off"),
+    (
+        ".width NUM1 NUM2 ...",
+        "Set minimum column widths for columnar output",
+    ),
+];
+
+/// SQLite's keywords, sorted, which need quotes to be used as names.
+const KEYWORDS: &[&str] = &[
+    "ABORT",
+    "ACTION",
+    "ADD",
+    "AFTER",
+    "ALL",
+    "ALTER",
+    "ALWAYS",
+    "ANALYZE",
+    "AND",
+    "AS",
+    "ASC",
+    "ATTACH",
+    "AUTOINCREMENT",
+    "BEFORE",
+    "BEGIN",
+    "BETWEEN",
+    "BY",
+    "CASCADE",
+    "CASE",
+    "CAST",
+    "CHECK",
+    "COLLATE",
+    "COLUMN",
+    "COMMIT",
+    "CONFLICT",
+    "CONSTRAINT",
+    "CREATE",
+    "CROSS",
+    "CURRENT",
+    "CURRENT_DATE",
+    "CURRENT_TIME",
+    "CURRENT_TIMESTAMP",
+    "DATABASE",
+    "DEFAULT",
+    "DEFERRABLE",
+    "DEFERRED",
+    "DELETE",
+    "DESC",
+    "DETACH",
+    "DISTINCT",
+    "DO",
+    "DROP",
+    "EACH",
+    "ELSE",
+    "END",
+    "ESCAPE",
+    "EXCEPT",
+    "EXCLUDE",
+    "EXCLUSIVE",
+    "EXISTS",
+    "EXPLAIN",
+    "FAIL",
+    "FILTER",
+    "FIRST",
+    "FOLLOWING",
+    "FOR",
+    "FOREIGN",
+    "FROM",
+    "FULL",
+    "GENERATED",
+    "GLOB",
+    "GROUP",
+    "GROUPS",
+    "HAVING",
+    "IF",
+    "IGNORE",
+    "IMMEDIATE",
+    "IN",
+    "INDEX",
+    "INDEXED",
+    "INITIALLY",
+    "INNER",
+    "INSERT",
+    "INSTEAD",
+    "INTERSECT",
+    "INTO",
+    "IS",
+    "ISNULL",
+    "JOIN",
+    "KEY",
+    "LAST",
+    "LEFT",
+    "LIKE",
+    "LIMIT",
+    "MATCH",
+    "MATERIALIZED",
+    "NATURAL",
+    "NO",
+    "NOT",
+    "NOTHING",
+    "NOTNULL",
+    "NULL",
+    "NULLS",
+    "OF",
+    "OFFSET",
+    "ON",
+    "OR",
+    "ORDER",
+    "OTHERS",
+    "OUTER",
+    "OVER",
+    "PARTITION",
+    "PLAN",
+    "PRAGMA",
+    "PRECEDING",
+    "PRIMARY",
+    "QUERY",
+    "RAISE",
+    "RANGE",
+    "RECURSIVE",
+    "REFERENCES",
+    "REGEXP",
+    "REINDEX",
+    "RELEASE",
+    "RENAME",
+    "REPLACE",
+    "RESTRICT",
+    "RETURNING",
+    "RIGHT",
+    "ROLLBACK",
+    "ROW",
+    "ROWS",
+    "SAVEPOINT",
+    "SELECT",
+    "SET",
+    "TABLE",
+    "TEMP",
+    "TEMPORARY",
+    "THEN",
+    "TIES",
+    "TO",
+    "TRANSACTION",
+    "TRIGGER",
+    "UNBOUNDED",
+    "UNION",
+    "UNIQUE",
+    "UPDATE",
+    "USING",
+    "VACUUM",
+    "VALUES",
+    "VIEW",
+    "VIRTUAL",
+    "WHEN",
+    "WHERE",
+    "WINDOW",
+    "WITH",
+    "WITHOUT",
+];
+
+/// What the shell does after a command.
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    Continue,
+    Exit(i32),
+}
+
+pub struct Shell {
+    connection: Connection,
+    out: Box<dyn Write>,
+    /// The output to go back to after the command following `.once`.
+    once: Option<Box<dyn Write>>,
+    format: Format,
+    /// Whether headers were turned on or off, which `.mode column` then
+    /// leaves as they are.
+    headers_set: bool,
+    /// Whether a script stops at its first error.
+    bail: bool,
+    /// Whether input is printed before it runs.
+    echo: bool,
+    /// Whether a statement or command of a script failed.
+    failed: bool,
+    /// Whether the time each statement takes is shown after it.
+    timer: bool,
+    /// Whether what each statement read is shown after it.
+    stats: bool,
+    /// How many scripts are running, each read by the one before.
+    nesting: usize,
+}
+
+impl Shell {
+    pub fn new(connection: Connection) -> Shell {
+        Shell {
+            connection,
+            out: Box::new(io::stdout()),
+            once: None,
+            format: Format::default(),
+            headers_set: false,
+            bail: false,
+            echo: false,
+            failed: false,
+            timer: false,
+            stats: false,
+            nesting: 0,
+        }
+    }
+
+    /// Sets the output mode, as `.mode` does.
+    pub fn set_mode(&mut self, mode: &str) -> Result<()> {
+        self.format.set_mode(mode, &[])
+    }
+
+    /// Turns the column names before the rows on or off, as `.headers` does.
+    pub fn set_headers(&mut self, headers: bool) {
+        self.format.headers = headers;
+        self.headers_set = true;
+    }
+
+    /// Sets the column separator, or the row separator, of the delimited
+    /// modes.
+    pub fn set_separators(&mut self, column: Option<&str>, row: Option<&str>) {
+        if let Some(column) = column {
+            self.format.column_separator = column.to_string();
+        }
+        if let Some(row) = row {
+            self.format.row_separator = row.to_string();
+        }
+    }
+
+    /// Sets the text shown for NULL.
+    pub fn set_null_value(&mut self, text: &str) {
+        self.format.null_value = text.to_string();
+    }
+
+    /// Makes a script stop at its first error, with exit status 1.
+    pub fn set_bail(&mut self, bail: bool) {
+        self.bail = bail;
+    }
+
+    /// Prints each command, or group of statements read together, before
+    /// running it.
+    pub fn set_echo(&mut self, echo: bool) {
+        self.echo = echo;
+    }
+
+    /// Whether a statement or command of a script has failed.
+    pub fn failed(&self) -> bool {
+        self.failed
+    }
+
+    /// Runs a dot-command, or the SQL statements in `input`; the last one
+    /// doesn't need a terminating `;`.
+    pub fn execute(&mut self, input: &str) -> Result<Action> {
+        if self.echo {
+            writeln!(self.out, "{}", input.trim_end_matches('\n'))?;
+        }
+        self.run_redirected(input)
+    }
+
+    /// Runs `input` with its output sent where a `.once` just before it
+    /// says.
+    fn run_redirected(&mut self, input: &str) -> Result<Action> {
+        let Some(previous) = self.once.take() else {
+            return self.run_input(input);
+        };
+        // `.once` redirects this command, unless it redirects again
+        let command = input.trim_start().strip_prefix('.').map(split_arguments);
+        let command = command.as_ref().and_then(|args| args.first());
+        if matches!(command.map(String::as_str), Some("once" | "output")) {
+            self.out = previous;
+            return self.run_input(input);
+        }
+        let result = self.run_input(input);
+        self.out = previous;
+        result
+    }
+
+    fn run_input(&mut self, input: &str) -> Result<Action> {
+        let action = match input.trim_start().starts_with('.') {
+            true => self.dot_command(input.trim()),
+            false => {
+                let (mut statements, rest) = split_statements(input);
+                statements.extend(rest);
+                statements
+                    .into_iter()
+                    .try_for_each(|sql| self.run_query(sql))
+                    .map(|_| Action::Continue)
+            }
+        };
+        // written before the output is replaced, or the process exits
+        self.out.flush()?;
+        action
+    }
+
+    /// Runs the statements and dot-commands of a script, as `.read` does. A
+    /// statement that fails is reported with the line it starts on, and the
+    /// script goes on unless bailing; the last statement doesn't need a `;`.
+    /// Scripts can only be nested `MAX_INPUT_NESTING` deep, which stops a
+    /// script that reads itself.
+    pub fn run_script(&mut self, script: &str) -> Result<Action> {
+        if self.nesting == MAX_INPUT_NESTING {
+            bail!("Input nesting limit ({MAX_INPUT_NESTING}) reached. Check recursion.");
+        }
+        self.nesting += 1;
+        let action = self.run_script_lines(script);
+        self.nesting -= 1;
+        action
+    }
+
+    fn run_script_lines(&mut self, script: &str) -> Result<Action> {
+        let mut buffer = String::new();
+        // the line the statements in the buffer start on
+        let mut line = 1;
+        for (i, text) in script.lines().enumerate() {
+            if buffer.trim().is_empty() {
+                buffer.clear();
+                line = i + 1;
+                if text.trim_start().starts_with('.') {
+                    match self.execute(text) {
+                        Ok(Action::Continue) => {}
+                        Ok(exit) => return Ok(exit),
+                        Err(e) => {
+                            eprintln!("Error: {e}");
+                            if let Some(exit) = self.fail() {
+                                return Ok(exit);
+                            }
+                        }
+                    }
+                    continue;
+                }
+            }
+            buffer.push_str(text);
+            buffer.push('\n');
+            let (statements, rest) = split_statements(&buffer);
+            if rest.is_none() {
+                if let Action::Exit(code) = self.run_statements(line, &buffer, &statements)? {
+                    return Ok(Action::Exit(code));
+                }
+                buffer.clear();
+            }
+        }
+        let (mut statements, rest) = split_statements(&buffer);
+        statements.extend(rest);
+        self.run_statements(line, &buffer, &statements)
+    }
+
+    /// Runs `statements`, read from a script as `text` starting on `line`,
+    /// reporting errors with the line of the statement.
+    fn run_statements(
+        &mut self,
+        mut line: usize,
+        text: &str,
+        statements: &[&str],
+    ) -> Result<Action> {
+        if self.echo && !statements.is_empty() {
+            writeln!(self.out, "{}", text.trim_end_matches('\n'))?;
+        }
+        for sql in statements {
+            let sql_line = line
+                + sql[..sql.len() - sql.trim_start().len()]
+                    .matches('\n')
+                    .count();
+            if let Err(e) = self.run_redirected(sql) {
+                eprintln!("Error: near line {sql_line}: {e}");
+                if let Some(exit) = self.fail() {
+                    return Ok(exit);
+                }
+            }
+            line += sql.matches('\n').count();
+        }
+        Ok(Action::Continue)
+    }
+
+    /// Notes that part of a script failed: the script ends if bailing.
+    fn fail(&mut self) -> Option<Action> {
+        self.failed = true;
+        self.bail.then_some(Action::Exit(1))
+    }
+
+    /// Reads statements and dot-commands from the terminal until `.quit` or
+    /// end of input. A statement runs once a line completes it with `;`;
+    /// Ctrl-C discards a partly typed statement, or stops a running one.
+    pub fn run_interactive(&mut self) -> Result<Action> {
+        let mut editor = DefaultEditor::new()?;
+        // after the editor, which replaces the SIGINT handler while it lives
+        let interrupt = self.connection.interrupt_handle();
+        ctrlc::set_handler(move || interrupt.interrupt())?;
+        let history = history_path();
+        // there's no history file before the first session ends
+        let _ = editor.load_history(&history);
+
+        let mut buffer = String::new();
+        let action = loop {
+            let prompt = match buffer.is_empty() {
+                true => PROMPT,
+                false => CONTINUATION_PROMPT,
+            };
+            let line = match editor.readline(prompt) {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => {
+                    buffer.clear();
+                    continue;
+                }
+                Err(ReadlineError::Eof) => break Action::Continue,
+                Err(e) => return Err(e.into()),
+            };
+
+            if buffer.is_empty() && line.trim_start().starts_with('.') {
+                editor.add_history_entry(line.as_str())?;
+                match self.execute(&line) {
+                    Ok(Action::Continue) => {}
+                    Ok(exit) => break exit,
+                    Err(e) => eprintln!("Error: {e}"),
+                }
+                continue;
+            }
+
+            buffer.push_str(&line);
+            buffer.push('\n');
+            let (statements, rest) = split_statements(&buffer);
+            if rest.is_some() {
+                continue;
+            }
+            if !statements.is_empty() {
+                editor.add_history_entry(buffer.trim())?;
+                if let Err(e) = self.execute(&buffer) {
+                    eprintln!("Error: {e}");
+                }
+            }
+            buffer.clear();
+        };
+
+        editor.save_history(&history)?;
+        Ok(action)
+    }
+
+    fn dot_command(&mut self, line: &str) -> Result<Action> {
+        let args = split_arguments(&line[1..]);
+        let Some((command, args)) = args.split_first() else {
+            bail!("unknown command or invalid arguments: \"\". Enter \".help\" for help");
+        };
+        match (command.as_str(), args) {
+            ("dbinfo", []) => {
+                writeln!(
+                    self.out,
+                    "database page size: {:?}",
+                    self.connection.page_size()
+                )?;
+                writeln!(
+                    self.out,
+                    "number of tables: {}",
+                    self.connection.table_count()
+                )?;
+            }
+            ("dump", args) => self.dump(args)?,
+            ("exit", []) | ("quit", []) => return Ok(Action::Exit(0)),
+            ("exit", [code]) => return Ok(Action::Exit(code.parse().unwrap_or(0))),
+            ("headers", [flag]) => self.set_headers(parse_bool(flag)?),
+            ("headers", _) => bail!("Usage: .headers on|off"),
+            ("help", []) => {
+                let width = HELP.iter().map(|(usage, _)| usage.len()).max();
+                for (usage, description) in HELP {
+                    let width = width.unwrap_or_default();
+                    writeln!(self.out, "{usage:width$}  {description}")?;
+                }
+            }
+            ("fullschema", []) => self.full_schema(false)?,
+            ("fullschema", [flag]) if flag == "--indent" => self.full_schema(true)?,
+            ("fullschema", _) => bail!("Usage: .fullschema ?--indent?"),
+            ("import", args) => self.import(args)?,
+            ("indexes" | "indices", [] | [_]) => {
+                let pattern = args.first().map_or("%", |table| table.as_str());
+                let mut names = vec![];
+                for entry in self.connection.schema() {
+                    if entry.kind() == "index"
+                        && self.matches("LIKE", pattern, entry.table_name())?
+                    {
+                        names.push(entry.name().to_string());
+                    }
+                }
+                names.sort();
+                self.write_columns(&names)?;
+            }
+            ("indexes" | "indices", _) => bail!("Usage: .indexes ?LIKE-PATTERN?"),
+            ("mode", []) => writeln!(self.out, "current output mode: {}", self.format.mode)?,
+            ("mode", [mode, args @ ..]) => {
+                self.format.set_mode(mode, args)?;
+                // as in sqlite3, column mode turns headers on unless they
+                // were set
+                if self.format.mode == Mode::Column && !self.headers_set {
+                    self.format.headers = true;
+                }
+            }
+            ("nullvalue", [text]) => self.set_null_value(text),
+            ("nullvalue", _) => bail!("Usage: .nullvalue STRING"),
+            ("once", [file]) => {
+                let file = create_output(file)?;
+                self.once = Some(std::mem::replace(&mut self.out, file));
+            }
+            ("once", _) => bail!("Usage: .once FILE"),
+            ("output", []) => self.out = Box::new(io::stdout()),
+            ("output", [file]) => {
+                self.out = match file.as_str() {
+                    "stdout" => Box::new(io::stdout()),
+                    file => create_output(file)?,
+                }
+            }
+            ("output", _) => bail!("Usage: .output ?FILE?"),
+            ("read", [file]) => {
+                let Ok(script) = std::fs::read_to_string(file) else {
+                    bail!("cannot open \"{file}\"");
+                };
+                return self.run_script(&script);
+            }
+            ("read", _) => bail!("Usage: .read FILE"),
+            ("schema", args) => self.schema(args)?,
+            ("separator", [column]) => self.set_separators(Some(column), None),
+            ("separator", [column, row]) => self.set_separators(Some(column), Some(row)),
+            ("separator", _) => bail!("Usage: .separator COL ?ROW?"),
+            ("stats", []) => self.write_stats()?,
+            ("stats", [flag]) => self.stats = parse_bool(flag)?,
+            ("stats", _) => bail!("Usage: .stats ?on|off?"),
+            ("tables", [] | [_]) => {
+                let pattern = args.first().map_or("%", |table| table.as_str());
+                let mut names = vec![];
+                for name in self.connection.table_names() {
+                    if self.matches("LIKE", pattern, &name)? {
+                        names.push(name);
+                    }
+                }
+                self.write_columns(&names)?;
+            }
+            ("tables", _) => bail!("Usage: .tables ?LIKE-PATTERN?"),
+            ("timer", [flag]) => self.timer = parse_bool(flag)?,
+            ("timer", _) => bail!("Usage: .timer on|off"),
+            ("width", widths) => {
+                self.format.widths = widths
+                    .iter()
+                    .map(|width| width.parse().unwrap_or(0))
+                    .collect()
+            }
+            (command, _) => bail!(
+                "unknown command or invalid arguments: \"{command}\". Enter \".help\" for help"
+            ),
+        }
+        Ok(Action::Continue)
+    }
+
+    /// `.schema`: the CREATE statements of the tables whose name matches
+    /// the pattern, or of all tables, with their indexes and triggers, and
+    /// of the matching views.
+    fn schema(&mut self, args: &[String]) -> Result<()> {
+        let mut indent = false;
+        let mut no_system = false;
+        let mut pattern = None;
+        for arg in args {
+            match arg.as_str() {
+                "--indent" => indent = true,
+                "--nosys" => no_system = true,
+                _ if pattern.is_none() => pattern = Some(arg),
+                _ => bail!("Usage: .schema ?--indent? ?--nosys? ?LIKE-PATTERN?"),
+            }
+        }
+
+        let mut statements = vec![];
+        // the schema table isn't in itself
+        let schema_table = |name: &&String| {
+            name.eq_ignore_ascii_case("sqlite_master") || name.eq_ignore_ascii_case("sqlite_schema")
+        };
+        if let Some(name) = pattern.filter(schema_table) {
+            statements.push(format!(
+                "CREATE TABLE {name} (\n  type text,\n  name text,\n  tbl_name text,\n  \
+                 rootpage integer,\n  sql text\n)"
+            ));
+        }
+        for entry in self.connection.schema() {
+            let Some(sql) = entry.sql() else {
+                continue;
+            };
+            if no_system && entry.name().to_lowercase().starts_with("sqlite_") {
+                continue;
+            }
+            if let Some(pattern) = &pattern {
+                let op = match pattern.contains(['*', '?', '[']) {
+                    true => "GLOB",
+                    false => "LIKE",
+                };
+                if !self.matches(op, pattern, &entry.table_name().to_lowercase())? {
+                    continue;
+                }
+            }
+            let mut sql = sql.to_string();
+            // a view's columns are only known once its SELECT is bound
+            if entry.kind() == "view" {
+                if let Ok(columns) = self.connection.table_columns(entry.name()) {
+                    let columns = columns.iter().map(|c| quote_name(c));
+                    let columns = columns.collect::<Vec<_>>().join(",");
+                    sql += &format!("\n/* {}({columns}) */", quote_name(entry.name()));
+                }
+            }
+            statements.push(sql);
+        }
+
+        self.write_schema(&statements, indent)
+    }
+
+    /// `.fullschema`: the CREATE statements of all but the internal tables,
+    /// then statements restoring the statistics collected by ANALYZE.
+    fn full_schema(&mut self, indent: bool) -> Result<()> {
+        let schema = self.connection.schema();
+        let statements = schema
+            .iter()
+            .filter(|entry| !entry.name().to_lowercase().starts_with("sqlite_"))
+            .filter_map(|entry| entry.sql().map(String::from))
+            .collect::<Vec<_>>();
+        self.write_schema(&statements, indent)?;
+
+        let has_table = |name: &str| schema.iter().any(|entry| entry.name() == name);
+        if !["sqlite_stat1", "sqlite_stat3", "sqlite_stat4"]
+            .into_iter()
+            .any(has_table)
+        {
+            writeln!(self.out, "/* No STAT tables available */")?;
+            return Ok(());
+        }
+        // the statistics are only read when the schema is
+        writeln!(self.out, "ANALYZE sqlite_schema;")?;
+        for table in ["sqlite_stat1", "sqlite_stat4"] {
+            if !has_table(table) {
+                continue;
+            }
+            self.dump_rows(table, false)?;
+        }
+        writeln!(self.out, "ANALYZE sqlite_schema;")?;
+        Ok(())
+    }
+
+    /// `.dump`: a script that recreates the tables whose names match one of
+    /// the patterns, or all tables, with their rows, then the matching
+    /// views, triggers and indexes, in one transaction.
+    fn dump(&mut self, args: &[String]) -> Result<()> {
+        let mut data_only = false;
+        let mut newlines = false;
+        let mut no_system = false;
+        let mut patterns = vec![];
+        for arg in args {
+            match arg.as_str() {
+                "--data-only" => data_only = true,
+                "--newlines" => newlines = true,
+                "--nosys" => no_system = true,
+                option if option.starts_with('-') => {
+                    bail!("Unknown option \"{option}\" on \".dump\"")
+                }
+                pattern => patterns.push(pattern),
+            }
+        }
+
+        let mut entries = vec![];
+        for entry in self.connection.schema() {
+            let mut selected = patterns.is_empty();
+            for pattern in &patterns {
+                selected = selected || self.matches("LIKE", pattern, entry.name())?;
+            }
+            if selected && entry.sql().is_some() {
+                entries.push(entry);
+            }
+        }
+        // tables first, sqlite_sequence last among them so that it isn't
+        // filled by the AUTOINCREMENT inserts, then views, triggers and
+        // indexes, each in schema order
+        entries.sort_by_key(|entry| match entry.kind() {
+            "table" => (0, entry.name() == "sqlite_sequence"),
+            "view" => (1, false),
+            "trigger" => (2, false),
+            _ => (3, false),
+        });
+
+        if !data_only {
+            writeln!(self.out, "PRAGMA foreign_keys=OFF;")?;
+            writeln!(self.out, "BEGIN TRANSACTION;")?;
+        }
+        let mut writable_schema = false;
+        let mut errors = 0;
+        for entry in &entries {
+            let (name, sql) = (entry.name(), entry.sql().unwrap_or_default());
+            if entry.kind() != "table" {
+                if !data_only {
+                    // a `--` comment would swallow the `;`
+                    let end = if sql.contains("--") { "\n;" } else { ";" };
+                    writeln!(self.out, "{sql}{end}")?;
+                }
+                continue;
+            }
+            let is_stat = name.len() == 12 && name.starts_with("sqlite_stat");
+            if name == "sqlite_sequence" && !no_system {
+                // the data alone still replaces the sequences
+                if !writable_schema {
+                    writeln!(self.out, "PRAGMA writable_schema=ON;")?;
+                    writable_schema = true;
+                }
+                writeln!(
+                    self.out,
+                    "CREATE TABLE IF NOT EXISTS sqlite_sequence(name,seq);"
+                )?;
+                writeln!(self.out, "DELETE FROM sqlite_sequence;")?;
+            } else if is_stat && !no_system {
+                if !data_only {
+                    writeln!(self.out, "ANALYZE sqlite_schema;")?;
+                }
+            } else if name.starts_with("sqlite_") {
+                continue;
+            } else if sql.starts_with("CREATE VIRTUAL TABLE") {
+                // its rows live in its shadow tables
+                if !data_only {
+                    if !writable_schema {
+                        writeln!(self.out, "PRAGMA writable_schema=ON;")?;
+                        writable_schema = true;
+                    }
+                    let [name, sql] = [name, sql].map(|text| text.replace('\'', "''"));
+                    writeln!(
+                        self.out,
+                        "INSERT INTO sqlite_schema(type,name,tbl_name,rootpage,sql)\
+                         VALUES('table','{name}','{name}',0,'{sql}');"
+                    )?;
+                }
+                continue;
+            } else if !data_only {
+                write!(self.out, "{}", schema_line(sql, ";\n"))?;
+            }
+            // a table we can't read is left out, and the script rolls back
+            if let Err(e) = self.dump_rows(name, newlines) {
+                writeln!(self.out, "/****** {e} ******/")?;
+                errors += 1;
+            }
+        }
+        if writable_schema {
+            writeln!(self.out, "PRAGMA writable_schema=OFF;")?;
+        }
+        if !data_only {
+            match errors {
+                0 => writeln!(self.out, "COMMIT;")?,
+                _ => writeln!(self.out, "ROLLBACK; -- due to errors")?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes an INSERT statement for each row of `table`, read with the
+    /// table scan, with the values of all but the generated columns. Line
+    /// breaks in text are kept as they are with `newlines`, or else written
+    /// with `replace()`.
+    fn dump_rows(&mut self, table: &str, newlines: bool) -> Result<()> {
+        let columns = self.connection.insert_columns(table)?;
+        let columns = columns.iter().map(|c| quote_name(c)).collect::<Vec<_>>();
+        let table = quote_name(table);
+        let sql = format!("SELECT {} FROM {table}", columns.join(","));
+        let statement = self.connection.prepare(&sql)?;
+        for row in statement.query()? {
+            let row = row?;
+            let values = row.values().iter().map(|value| match value {
+                Value::Text(s) => text_literal(s, !newlines),
+                value => sql_literal(value),
+            });
+            let values = values.collect::<Vec<_>>().join(",");
+            writeln!(self.out, "INSERT INTO {table} VALUES({values});")?;
+        }
+        Ok(())
+    }
+
+    /// `.import`: inserts the records of a file into a table, creating it
+    /// with columns named by the first record if it doesn't exist. Fields
+    /// are split as CSV by the separators of the output mode, unless
+    /// `--csv` or `--ascii` say otherwise.
+    fn import(&mut self, args: &[String]) -> Result<()> {
+        let mut separators = None;
+        let mut skip = 0;
+        let mut infer_types = false;
+        let mut positional = vec![];
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--csv" => separators = Some((",".to_string(), "\n".to_string(), true)),
+                "--ascii" => separators = Some(("\x1f".to_string(), "\x1e".to_string(), false)),
+                "--skip" => match args.next().map(|count| count.parse()) {
+                    Some(Ok(count)) => skip = count,
+                    _ => bail!("Usage: .import ?OPTIONS? FILE TABLE"),
+                },
+                "--infer-types" => infer_types = true,
+                option if option.starts_with('-') => bail!("unknown option: \"{option}\""),
+                arg => positional.push(arg),
+            }
+        }
+        let [file, table] = positional[..] else {
+            bail!("Usage: .import ?OPTIONS? FILE TABLE");
+        };
+        let (column, row, quoted) = separators.unwrap_or_else(|| {
+            let row = match self.format.row_separator.as_str() {
+                "\r\n" => "\n".to_string(),
+                row => row.to_string(),
+            };
+            (self.format.column_separator.clone(), row, true)
+        });
+        let single = |separator: &str| match separator.chars().collect::<Vec<_>>()[..] {
+            [c] => Some(c),
+            _ => None,
+        };
+        let Some(column) = single(&column) else {
+            bail!("multi-character column separators not allowed for import");
+        };
+        let Some(row) = single(&row) else {
+            bail!("multi-character row separators not allowed for import");
+        };
+        let Ok(bytes) = std::fs::read(file) else {
+            bail!("cannot open \"{file}\"");
+        };
+        let input = String::from_utf8_lossy(&bytes);
+
+        let mut reader = Reader::new(file, &input, column, row, quoted);
+        for _ in 0..skip {
+            reader.next_record();
+        }
+        let exists = self.connection.schema().iter().any(|entry| {
+            matches!(entry.kind(), "table" | "view") && entry.name().eq_ignore_ascii_case(table)
+        });
+        let header = match exists {
+            true => None,
+            false => match reader.next_record() {
+                Some((_, header)) => Some(header),
+                None => bail!("{file}: empty file"),
+            },
+        };
+        let columns = header.as_deref().map(|header| {
+            let (columns, renamed) = column_names(header);
+            if !renamed.is_empty() {
+                let renamed = renamed
+                    .iter()
+                    .map(|(name, new_name)| format!("\"{name}\" to \"{new_name}\""));
+                eprintln!(
+                    "Columns renamed during .import {file} due to duplicates:\n{}",
+                    renamed.collect::<Vec<_>>().join(",\n")
+                );
+            }
+            columns
+        });
+        let count = match &columns {
+            Some(columns) => columns.len(),
+            None => self.connection.table_columns(table)?.len(),
+        };
+
+        let mut rows = vec![];
+        // the line each row starts on, for insert errors
+        let mut lines = vec![];
+        loop {
+            let record = reader.next_record();
+            for warning in reader.warnings.drain(..) {
+                eprintln!("{warning}");
+            }
+            let Some((line, fields)) = record else {
+                break;
+            };
+            let found = fields.len();
+            if found < count {
+                eprintln!(
+                    "{file}:{line}: expected {count} columns but found {found} - \
+                     filling the rest with NULL"
+                );
+            } else if found > count {
+                eprintln!(
+                    "{file}:{line}: expected {count} columns but found {found} - extras ignored"
+                );
+            }
+            let mut values = fields
+                .into_iter()
+                .take(count)
+                .map(|field| match infer_types && field.is_empty() {
+                    true => Value::Null,
+                    false => Value::Text(field),
+                })
+                .collect::<Vec<_>>();
+            values.resize(count, Value::Null);
+            rows.push(values);
+            lines.push(line);
+        }
+
+        if let Some(columns) = columns {
+            let quote = |name: &str| format!("\"{}\"", name.replace('"', "\"\""));
+            let definitions = columns.iter().enumerate().map(|(i, name)| {
+                let type_name = match infer_types {
+                    true => infer_type(rows.iter().filter_map(|row| match &row[i] {
+                        Value::Text(value) => Some(value.as_str()),
+                        _ => None,
+                    })),
+                    false => "TEXT",
+                };
+                format!("{} {type_name}", quote(name))
+            });
+            let definitions = definitions.collect::<Vec<_>>().join(", ");
+            let sql = format!("CREATE TABLE {}(\n{definitions})", quote(table));
+            self.connection.create_table(&sql)?;
+        }
+        self.connection.insert_with(table, rows, |i, e| {
+            eprintln!("{file}:{}: INSERT failed: {e}", lines[i]);
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    /// Writes CREATE statements as `.schema` does, formatted by
+    /// `indent_schema` with `--indent`.
+    fn write_schema(&mut self, statements: &[String], indent: bool) -> Result<()> {
+        for sql in statements {
+            let text = match indent {
+                true => indent_schema(sql),
+                false => schema_line(sql, ";\n"),
+            };
+            write!(self.out, "{text}")?;
+        }
+        Ok(())
+    }
+
+    /// Writes what the last statement read, a count per line.
+    fn write_stats(&mut self) -> Result<()> {
+        let stats = self.connection.stats();
+        let counts = [
+            ("Pages read:", stats.pages_read),
+            ("Page cache hits:", stats.cache_hits),
+            ("Page cache misses:", stats.cache_misses),
+            ("Rows scanned:", stats.rows_scanned),
+            ("Rows returned:", stats.rows_returned),
+            ("Bytes decoded:", stats.bytes_decoded),
+        ];
+        for (name, count) in counts {
+            writeln!(self.out, "{name:<36} {count}")?;
+        }
+        Ok(())
+    }
+
+    /// Writes `names` in columns down the lines, as many as fit in 80
+    /// characters.
+    fn write_columns(&mut self, names: &[String]) -> Result<()> {
+        let width = names.iter().map(|name| name.chars().count()).max();
+        let width = width.unwrap_or_default();
+        let columns = (80 / (width + 2)).max(1);
+        let lines = names.len().div_ceil(columns);
+        for line in 0..lines {
+            let row = names.iter().skip(line).step_by(lines);
+            let row = row.map(|name| format!("{name:width$}"));
+            writeln!(self.out, "{}", row.collect::<Vec<_>>().join("  "))?;
+        }
+        Ok(())
+    }
+
+    /// Whether `text` matches `pattern` with `op`, `LIKE` or `GLOB`. In a
+    /// LIKE pattern `\\` escapes `%` and `_`.
+    fn matches(&self, op: &str, pattern: &str, text: &str) -> Result<bool> {
+        let escape = match op {
+            "LIKE" => " ESCAPE '\\'",
+            _ => "",
+        };
+        let mut statement = self
+            .connection
+            .prepare(&format!("SELECT ?1 {op} ?2{escape}"))?;
+        statement.bind(1, text)?;
+        statement.bind(2, pattern)?;
+        let row = statement.query()?.next().transpose()?;
+        Ok(row.map_or(Ok(false), |row| row.get(0))?)
+    }
+
+    /// Runs `sql` and writes the result rows in the output mode, then how
+    /// long it took and what it read if the timer and stats are on, even
+    /// when it fails.
+    fn run_query(&mut self, sql: &str) -> Result<()> {
+        let start = (Instant::now(), cpu_times());
+        let result = self.write_query(sql);
+        if self.timer {
+            let (user, system) = cpu_times();
+            writeln!(
+                self.out,
+                "Run Time: real {:.6} user {:.6} sys {:.6}",
+                start.0.elapsed().as_secs_f64(),
+                (user - start.1 .0).as_secs_f64(),
+                (system - start.1 .1).as_secs_f64()
+            )?;
+        }
+        if self.stats {
+            self.write_stats()?;
+        }
+        result
+    }
+
+    /// Writes the result rows of `sql` in the output mode, or the tree of
+    /// steps of a query plan.
+    fn write_query(&mut self, sql: &str) -> Result<()> {
+        let statement = self.connection.prepare(sql)?;
+        if statement.is_explain() {
+            let mut steps = vec![];
+            for row in statement.query()? {
+                let row = row?;
+                steps.push((row.get(0)?, row.get(1)?, row.get(3)?));
+            }
+            writeln!(self.out, "QUERY PLAN")?;
+            write_plan_steps(&mut self.out, &steps, 0, "")?;
+            self.out.flush()?;
+            return Ok(());
+        }
+        let columns = statement.column_names();
+        self.format
+            .write_rows(&mut self.out, &columns, statement.query()?)?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes the steps of a query plan under the step `parent`, each on a
+/// branch after `indent`, and the steps under them further in.
+fn write_plan_steps(
+    out: &mut dyn Write,
+    steps: &[(i64, i64, String)],
+    parent: i64,
+    indent: &str,
+) -> Result<()> {
+    let children = steps
+        .iter()
+        .filter(|(_, step_parent, _)| *step_parent == parent);
+    let mut children = children.peekable();
+    while let Some((id, _, detail)) = children.next() {
+        let (branch, below) = match children.peek() {
+            Some(_) => ("|--", "|  "),
+            None => ("`--", "   "),
+        };
+        writeln!(out, "{indent}{branch}{detail}")?;
+        write_plan_steps(out, steps, *id, &format!("{indent}{below}"))?;
+    }
+    Ok(())
+}
+
+/// Time the process has spent on the CPU so far, in user and in system
+/// mode.
+#[cfg(unix)]
+fn cpu_times() -> (Duration, Duration) {
+    let mut usage = MaybeUninit::<libc::rusage>::zeroed();
+    // SAFETY: getrusage only writes to the struct it's given, and a zeroed
+    // rusage is valid even if it fails
+    let usage = unsafe {
+        libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr());
+        usage.assume_init()
+    };
+    let time = |time: libc::timeval| {
+        Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+    };
+    (time(usage.ru_utime), time(usage.ru_stime))
+}
+
+#[cfg(not(unix))]
+fn cpu_times() -> (Duration, Duration) {
+    (Duration::ZERO, Duration::ZERO)
+}
+
+/// A file that `.output` or `.once` writes to, replacing what it holds.
+fn create_output(path: &str) -> Result<Box<dyn Write>> {
+    match File::create(path) {
+        Ok(file) => Ok(Box::new(BufWriter::new(file))),
+        Err(_) => bail!("cannot write to \"{path}\""),
+    }
+}
+
+/// The history file in the home directory, or in the working directory
+/// when there is no home.
+fn history_path() -> PathBuf {
+    match std::env::var_os("HOME") {
+        Some(home) => PathBuf::from(home).join(HISTORY_FILE),
+        None => PathBuf::from(HISTORY_FILE),
+    }
+}
+
+/// Splits `input` after each `;` that ends a statement, skipping those in
+/// string literals, quoted identifiers and comments. Returns the statements
+/// and the text after the last one, if it has more than whitespace and
+/// comments.
+fn split_statements(input: &str) -> (Vec<&str>, Option<&str>) {
+    let bytes = input.as_bytes();
+    let closing = |from: usize, end: &str| {
+        input[from..]
+            .find(end)
+            .map_or(bytes.len(), |i| from + i + end.len() - 1)
+    };
+
+    let mut statements = vec![];
+    let mut start = 0;
+    let mut has_content = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'-' if bytes.get(i + 1) == Some(&b'-') => i = closing(i, "\n"),
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = closing(i + 2, "*/");
+                // an unterminated comment waits for its end
+                has_content |= i >= bytes.len();
+            }
+            b';' => {
+                if has_content {
+                    statements.push(&input[start..=i]);
+                }
+                start = i + 1;
+                has_content = false;
+            }
+            quote @ (b'\'' | b'"' | b'`' | b'[') => {
+                let end = if quote == b'[' { "]" } else { &input[i..=i] };
+                i = closing(i + 1, end);
+                has_content = true;
+            }
+            byte => has_content |= !byte.is_ascii_whitespace(),
+        }
+        i += 1;
+    }
+    (statements, has_content.then(|| &input[start..]))
+}
+
+/// Whether `sql` is one or more statements ended by `;`.
+fn is_complete(sql: &str) -> bool {
+    matches!(split_statements(sql), (statements, None) if !statements.is_empty())
+}
+
+/// `value` as an SQL literal. Reals keep a fractional part or exponent so
+/// they read back as reals, with as many digits as they need to read back
+/// exactly.
+fn sql_literal(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::Integer(i) => i.to_string(),
+        Value::Real(r) if r.is_infinite() => match *r > 0.0 {
+            true => "9.0e+999".to_string(),
+            false => "-9.0e+999".to_string(),
+        },
+        Value::Real(r) => real_literal(*r),
+        Value::Text(s) => text_literal(s, true),
+        Value::Blob(b) => {
+            let hex = b.iter().map(|byte| format!("{byte:02x}"));
+            format!("X'{}'", hex.collect::<String>())
+        }
+    }
+}
+
+/// `s` in single quotes. With `escape_line_breaks`, the statement stays on
+/// one line: `\r` and `\n` are written as the text `\r` and `\n`, or other
+/// text that isn't in `s`, and put back with `replace()`. NUL characters,
+/// which would end the script, always are.
+fn text_literal(s: &str, escape_line_breaks: bool) -> String {
+    let mut literal = s.replace('\'', "''");
+    let mut replaced = vec![];
+    for (c, name, code) in [('\0', '0', 0), ('\r', 'r', 13), ('\n', 'n', 10)] {
+        if !s.contains(c) || !escape_line_breaks && c != '\0' {
+            continue;
+        }
+        let mut escape = format!("\\{name}");
+        if s.contains(&escape) {
+            escape = format!("\\{code:03o}");
+        }
+        let mut i = 0;
+        while s.contains(&escape) {
+            escape = format!("(\\{name}{i})");
+            i += 1;
+        }
+        literal = literal.replace(c, &escape);
+        replaced.push((escape, code));
+    }
+    let mut literal = format!("'{literal}'");
+    for (escape, code) in replaced {
+        literal = format!("replace({literal},'{escape}',char({code}))");
+    }
+    literal
+}
+
+/// The shortest digits that read back as `r`, laid out like `%!.20g` in
+/// SQLite's printf: an exponent below -4 or from 20 up is written out,
+/// and there is always a digit after the point.
+fn real_literal(r: f64) -> String {
+    let scientific = format!("{r:e}");
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let exponent = exponent.parse::<i32>().unwrap_or_default();
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(mantissa) => ("-", mantissa),
+        None => ("", mantissa),
+    };
+    let digits = mantissa.replace('.', "");
+    let (whole, fraction) = if !(-4..20).contains(&exponent) {
+        let (first, rest) = digits.split_at(1);
+        let exponent_sign = if exponent < 0 { '-' } else { '+' };
+        let fraction = format!("{rest:0<1}e{exponent_sign}{:02}", exponent.abs());
+        (first.to_string(), fraction)
+    } else if exponent < 0 {
+        let zeros = "0".repeat(exponent.unsigned_abs() as usize - 1);
+        ("0".to_string(), format!("{zeros}{digits}"))
+    } else {
+        let point = exponent as usize + 1;
+        let whole = format!("{digits:0<point$}");
+        let (whole, fraction) = whole.split_at(point);
+        (whole.to_string(), format!("{fraction:0<1}"))
+    };
+    format!("{sign}{whole}.{fraction}")
+}
+
+/// `name`, in double quotes unless it's a plain identifier and not a
+/// keyword.
+fn quote_name(name: &str) -> String {
+    let plain = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && KEYWORDS
+            .binary_search(&name.to_ascii_uppercase().as_str())
+            .is_err();
+    match plain {
+        true => name.to_string(),
+        false => format!("\"{}\"", name.replace('"', "\"\"")),
+    }
+}
+
+/// `sql` followed by `tail` as sqlite3 prints schema statements: a `;` tail
+/// is kept out of a trailing comment, and a table whose name is quoted gets
+/// `IF NOT EXISTS`.
+fn schema_line(sql: &str, tail: &str) -> String {
+    let mut sql = sql.to_string();
+    if tail.starts_with(';') && (sql.contains("/*") || sql.contains("--")) {
+        if let Some(end) = ["", "*/", "\n"]
+            .into_iter()
+            .find(|end| is_complete(&format!("{sql}{end};")))
+        {
+            sql += end;
+        }
+    }
+    match sql.strip_prefix("CREATE TABLE ") {
+        Some(rest) if rest.starts_with(['\'', '"']) => {
+            format!("CREATE TABLE IF NOT EXISTS {rest}{tail}")
+        }
+        _ => format!("{sql}{tail}"),
+    }
+}
+
+/// A `CREATE` statement formatted as by `.schema --indent` in sqlite3. Runs
+/// of whitespace become one character, and none is left after `(` or
+/// before `(` and `)`. A statement still 79 bytes or longer then gets one
+/// column or constraint per line, and an index one `AND` term of its
+/// `WHERE` per line. Views and triggers are left as they are.
+fn indent_schema(sql: &str) -> String {
+    let upper = sql.to_ascii_uppercase();
+    if upper.starts_with("CREATE VIEW") || upper.starts_with("CREATE TRIG") {
+        return format!("{sql};\n");
+    }
+    let is_index = upper.starts_with("CREATE INDEX") || upper.starts_with("CREATE UNIQUE INDEX");
+
+    let mut z: Vec<char> = vec![];
+    for c in sql.trim_start().chars() {
+        if c.is_ascii_whitespace() {
+            if let Some(last @ '\r') = z.last_mut() {
+                *last = '\n';
+            }
+            if z.last()
+                .is_some_and(|&last| last.is_ascii_whitespace() || last == '(')
+            {
+                continue;
+            }
+        } else if (c == '(' || c == ')') && z.last().is_some_and(|c| c.is_ascii_whitespace()) {
+            z.pop();
+        }
+        z.push(c);
+    }
+    while z.last().is_some_and(|c| c.is_ascii_whitespace()) {
+        z.pop();
+    }
+    if z.iter().collect::<String>().len() < 79 {
+        return schema_line(&z.iter().collect::<String>(), ";\n");
+    }
+
+    let keyword_at = |i: usize, keyword: &str| {
+        let word = z[i..].iter().take(keyword.len()).collect::<String>();
+        word.eq_ignore_ascii_case(keyword)
+            && !z
+                .get(i + keyword.len())
+                .is_some_and(|&c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    // whether only whitespace or a comment follows on the line from `i`
+    let ends_line = |i: usize| {
+        let rest = z[i..].iter().collect::<String>();
+        let rest = rest.trim_start_matches(|c: char| c.is_ascii_whitespace() && c != '\n');
+        rest.is_empty() || rest.starts_with('\n') || rest.starts_with("--")
+    };
+
+    let mut out = String::new();
+    let mut line = String::new();
+    let mut depth = 0;
+    let mut lines = 0;
+    let mut end = None;
+    let mut in_where = false;
+    let mut i = 0;
+    while i < z.len() {
+        let c = z[i];
+        // quotes and comments only stop lines being broken inside them
+        if Some(c) == end {
+            end = None;
+        } else if matches!(c, '"' | '\'' | '`') {
+            end = Some(c);
+        } else if c == '[' {
+            end = Some(']');
+        } else if c == '-' && z.get(i + 1) == Some(&'-') {
+            end = Some('\n');
+        } else if c == '(' {
+            depth += 1;
+        } else if c == ')' {
+            depth -= 1;
+            if lines > 0 && depth == 0 && !line.is_empty() && !in_where {
+                out += &schema_line(&line, "\n");
+                line.clear();
+            }
+        } else if depth == 0 && is_index && keyword_at(i, "WHERE") {
+            in_where = true;
+        } else if depth == 0 && in_where && keyword_at(i, "AND") {
+            out += &schema_line(&line, "\n    ");
+            line.clear();
+        }
+        line.push(c);
+        if depth == 1
+            && end.is_none()
+            && !in_where
+            && (c == '(' || c == '\n' || c == ',' && !ends_line(i + 1))
+        {
+            if c == '\n' {
+                line.pop();
+            }
+            out += &schema_line(&line, "\n  ");
+            line.clear();
+            lines += 1;
+            while z.get(i + 1).is_some_and(|c| c.is_ascii_whitespace()) {
+                i += 1;
+            }
+        }
+        i += 1;
+    }
+    out + &schema_line(&line, ";\n")
+}
+
+/// `arg` as the value of an on/off setting: `on`, `yes` or `true`, `off`,
+/// `no` or `false`, or a number that is true unless 0.
+fn parse_bool(arg: &str) -> Result<bool> {
+    match arg.to_ascii_lowercase().as_str() {
+        "on" | "yes" | "true" => Ok(true),
+        "off" | "no" | "false" => Ok(false),
+        number => match number.parse::<i64>() {
+            Ok(number) => Ok(number != 0),
+            Err(_) => bail!("Not a boolean value: \"{arg}\""),
+        },
+    }
+}
+
+/// Splits the arguments of a dot-command on whitespace, except inside
+/// single or double quotes, which are removed. Backslash escapes such as
+/// `\t` are resolved in double quotes.
+fn split_arguments(line: &str) -> Vec<String> {
+    let mut args = vec![];
+    let mut chars = line.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let mut arg = String::new();
+        if c == '\'' {
+            chars.next();
+            arg.extend(chars.by_ref().take_while(|&next| next != c));
+        } else if c == '"' {
+            chars.next();
+            while let Some(next) = chars.next().filter(|&next| next != '"') {
+                match next {
+                    '\\' => arg.push(unescape(&mut chars)),
+                    next => arg.push(next),
+                }
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                arg.push(c);
+            }
+        }
+        args.push(arg);
+    }
+    args
+}
+
+/// The character a backslash escape in a dot-command argument stands for,
+/// reading what follows the backslash: a letter as in C, up to three octal
+/// digits, or `x` and up to two hex digits.
+fn unescape(chars: &mut std::iter::Peekable<std::str::Chars>) -> char {
+    let Some(c) = chars.next() else {
+        return '\\';
+    };
+    match c {
+        'a' => '\x07',
+        'b' => '\x08',
+        'f' => '\x0c',
+        'n' => '\n',
+        'r' => '\r',
+        't' => '\t',
+        'v' => '\x0b',
+        '0'..='7' => {
+            let mut code = c.to_digit(8).unwrap_or(0);
+            for _ in 0..2 {
+                let Some(digit) = chars.next_if(|c| c.is_digit(8)) else {
+                    break;
+                };
+                code = code * 8 + digit.to_digit(8).unwrap_or(0);
+            }
+            char::from_u32(code).unwrap_or('\0')
+        }
+        'x' => {
+            let mut code = 0;
+            for _ in 0..2 {
+                let Some(digit) = chars.next_if(|c| c.is_ascii_hexdigit()) else {
+                    break;
+                };
+                code = code * 16 + digit.to_digit(16).unwrap_or(0);
+            }
+            char::from_u32(code).unwrap_or('\0')
+        }
+        c => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    /// Output of a shell, kept readable after the shell takes it.
+    #[derive(Clone, Default)]
+    struct Capture(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Runs `input` in a shell on `db` and returns what it printed, without
+    /// the final newline.
+    fn run(db: &str, input: &str) -> Result<String> {
+        let capture = Capture::default();
+        let mut shell = Shell::new(Connection::open(db)?);
+        shell.out = Box::new(capture.clone());
+        shell.execute(input)?;
+        let out = String::from_utf8(capture.0.take())?;
+        Ok(out.strip_suffix('\n').unwrap_or(&out).to_string())
+    }
+
+    #[test]
+    fn test_handle_query() {
+        // Test COUNT(*) query
+        let count_query = "SELECT COUNT(*) FROM oranges;".to_string();
+        let count_result = run("sample.db", &count_query).expect("Failed to handle COUNT query");
+        assert_eq!(count_result, "6");
+
+        // Test SELECT * query
+        let select_query = "SELECT * FROM oranges;".to_string();
+        let select_result = run("sample.db", &select_query).expect("Failed to handle SELECT query");
+
+        // Split result into lines and verify content
+        let rows: Vec<&str> = select_result.split('\n').collect();
+        assert!(rows.len() == 6)
+    }
+
+    #[test]
+    fn test_handle_query_patterns() {
+        let query = |sql: &str, db: &str| run(db, sql).expect("Failed to handle query");
+        let db = "sample.db";
+
+        // name_index on oranges(name) answers the prefix terms
+        assert_eq!(
+            query("SELECT name FROM oranges WHERE name LIKE 'ma%'", db),
+            "Mandarin"
+        );
+        assert_eq!(
+            query("SELECT id FROM oranges WHERE name GLOB 'T*'", db),
+            "2\n3"
+        );
+        assert_eq!(
+            query("SELECT id FROM oranges WHERE name = 'Tangelo'", db),
+            "2"
+        );
+        assert_eq!(
+            query("SELECT name FROM apples WHERE color REGEXP 'Red$'", db),
+            "Fuji\nHoneycrisp"
+        );
+        assert_eq!(
+            query(
+                "SELECT count(*) FROM oranges WHERE description NOT LIKE '%great%'",
+                db
+            ),
+            "3"
+        );
+    }
+
+    #[test]
+    fn test_handle_query_affinity() {
+        let query = |sql: &str, db: &str| run(db, sql).expect("Failed to handle query");
+        let db = "sample.db";
+
+        assert_eq!(
+            query("SELECT name FROM oranges WHERE id = '3'", db),
+            query("SELECT name FROM oranges WHERE id = 3", db)
+        );
+        assert_eq!(
+            query("SELECT count(*) FROM oranges WHERE id < '10'", db),
+            "6"
+        );
+        // unary plus drops the column affinity: INTEGER never equals TEXT
+        assert_eq!(
+            query("SELECT count(*) FROM oranges WHERE +id = '3'", db),
+            "0"
+        );
+        assert_eq!(
+            query("SELECT count(*) FROM oranges WHERE name = 3", db),
+            "0"
+        );
+    }
+
+    #[test]
+    fn test_handle_query_order_by() {
+        let query = |sql: &str, db: &str| run(db, sql).expect("Failed to handle query");
+        let db = "sample.db";
+
+        assert_eq!(
+            query("SELECT id FROM oranges ORDER BY name DESC", db),
+            "5\n3\n2\n6\n1\n4"
+        );
+        assert_eq!(
+            query("SELECT name FROM apples ORDER BY color, 1", db),
+            "Honeycrisp\nGranny Smith\nFuji\nGolden Delicious"
+        );
+        assert_eq!(
+            query(
+                "SELECT name AS n FROM oranges WHERE name < 'n' COLLATE NOCASE ORDER BY n",
+                db
+            ),
+            "Clementine\nMandarin"
+        );
+        assert!(run(db, "SELECT id FROM oranges ORDER BY 2").is_err());
+    }
+
+    #[test]
+    fn test_split_statements() {
+        assert_eq!(split_statements("SELECT 1"), (vec![], Some("SELECT 1")));
+        assert_eq!(
+            split_statements("SELECT 1; SELECT\n2;\n"),
+            (vec!["SELECT 1;", " SELECT\n2;"], None)
+        );
+        assert_eq!(
+            split_statements("SELECT ';' -- ;\n, \"a;\", [b;];"),
+            (vec!["SELECT ';' -- ;\n, \"a;\", [b;];"], None)
+        );
+        assert_eq!(
+            split_statements("SELECT 'it''s;"),
+            (vec![], Some("SELECT 'it''s;"))
+        );
+        assert_eq!(split_statements(";  -- done\n"), (vec![], None));
+        assert_eq!(split_statements("/* ; "), (vec![], Some("/* ; ")));
+    }
+
+    #[test]
+    fn test_dot_commands() {
+        assert_eq!(
+            run("sample.db", ".dbinfo").unwrap(),
+            "database page size: 4096\nnumber of tables: 4"
+        );
+        assert_eq!(run("sample.db", "SELECT 1; SELECT 2").unwrap(), "1\n2");
+        assert!(run("sample.db", ".nope").is_err());
+        assert!(run("sample.db", ".tables a b").is_err());
+
+        let mut shell = Shell::new(Connection::open("sample.db").unwrap());
+        assert_eq!(shell.execute(".exit 3").unwrap(), Action::Exit(3));
+        assert_eq!(shell.execute(".quit").unwrap(), Action::Exit(0));
+        assert_eq!(
+            split_arguments(r#"import 'a b.csv' "t" x"#),
+            ["import", "a b.csv", "t", "x"]
+        );
+        assert_eq!(
+            split_arguments(r#"separator "\t\"" '\t' \n "\101\\""#),
+            ["separator", "\t\"", "\\t", "\\n", "A\\"]
+        );
+        assert_eq!(
+            split_arguments(r#"separator "a\x41" "\x4142" "\x4" "\X41""#),
+            ["separator", "aA", "A42", "\x04", "X41"]
+        );
+    }
+
+    #[test]
+    fn test_output_settings() {
+        let capture = Capture::default();
+        let mut shell = Shell::new(Connection::open("sample.db").unwrap());
+        shell.out = Box::new(capture.clone());
+        let sql = "SELECT id, name FROM apples WHERE id < 3";
+        for input in [".separator \" - \" \";\\n\"", sql] {
+            shell.execute(input).unwrap();
+        }
+        assert_eq!(capture.0.take(), b"1 - Granny Smith;\n2 - Fuji;\n");
+        for input in [".mode column", ".width 1 -5", sql, ".width", sql] {
+            shell.execute(input).unwrap();
+        }
+        // column mode turns headers on, unless they were set
+        assert_eq!(
+            String::from_utf8(capture.0.take()).unwrap(),
+            "i   name\n-  -----\n1  Grann\n   y Smi\n      th\n\n2   Fuji\n\
+             id  name        \n--  ------------\n1   Granny Smith\n2   Fuji        \n"
+        );
+        for input in [".headers off", ".mode list", ".mode column", sql] {
+            shell.execute(input).unwrap();
+        }
+        assert_eq!(capture.0.take(), b"1   Granny Smith\n2   Fuji        \n");
+        assert!(shell.execute(".separator").is_err());
+        assert!(shell.execute(".nullvalue").is_err());
+    }
+    #[test]
+    fn test_schema() {
+        assert_eq!(
+            run("sample.db", ".schema --indent o%").unwrap(),
+            "CREATE TABLE oranges(\n  id integer primary key autoincrement,\n  name text,\n  \
+             description text\n);\nCREATE INDEX name_index on oranges(name);"
+        );
+        assert_eq!(run("sample.db", ".schema --nosys O*").unwrap(), "");
+        assert!(run("sample.db", ".schema a b").is_err());
+        assert!(run("sample.db", ".schema sqlite_master")
+            .unwrap()
+            .starts_with("CREATE TABLE sqlite_master (\n  type text,"));
+
+        assert_eq!(
+            schema_line("CREATE TABLE \"t\"(a -- the key", ";\n"),
+            "CREATE TABLE IF NOT EXISTS \"t\"(a -- the key\n;\n"
+        );
+        assert_eq!(
+            schema_line("CREATE VIEW v AS SELECT 1\n/* v(\"1\") */", ";\n"),
+            "CREATE VIEW v AS SELECT 1\n/* v(\"1\") */;\n"
+        );
+        assert_eq!(
+            indent_schema("CREATE TABLE t ( a ,\n  b )"),
+            "CREATE TABLE t(a ,\nb);\n"
+        );
+        assert_eq!(
+            indent_schema(
+                "CREATE INDEX long_index_name ON long_table_name (first, second) \
+                 WHERE first > 0 AND (second < 1 AND first < 9)"
+            ),
+            "CREATE INDEX long_index_name ON long_table_name(\n  first,\n  second\n) \
+             WHERE first > 0 \n    AND(second < 1 AND first < 9);\n"
+        );
+    }
+    #[test]
+    fn test_listings() {
+        assert_eq!(run("sample.db", ".tables").unwrap(), "apples   oranges");
+        assert_eq!(run("sample.db", ".tables O%").unwrap(), "oranges");
+        assert_eq!(run("sample.db", ".indexes").unwrap(), "name_index");
+        assert_eq!(run("sample.db", ".indexes apples").unwrap(), "");
+        assert!(run("sample.db", ".fullschema").unwrap().ends_with(
+            ";\nCREATE INDEX name_index on oranges(name);\n/* No STAT tables available */"
+        ));
+
+        let capture = Capture::default();
+        let mut shell = Shell::new(Connection::open("sample.db").unwrap());
+        shell.out = Box::new(capture.clone());
+        let names = (1..=8).map(|n| "x".repeat(n * 3)).collect::<Vec<_>>();
+        shell.write_columns(&names).unwrap();
+        assert_eq!(
+            String::from_utf8(capture.0.take()).unwrap(),
+            [
+                "xxx                       xxxxxxxxxxxx              xxxxxxxxxxxxxxxxxxxxx   ",
+                "xxxxxx                    xxxxxxxxxxxxxxx           xxxxxxxxxxxxxxxxxxxxxxxx",
+                "xxxxxxxxx                 xxxxxxxxxxxxxxxxxx      \n",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_read_and_output() {
+        let dir = std::env::temp_dir();
+        let script = dir.join("codecrafters_sqlite_script.sql");
+        let (once, output) = (dir.join("once.txt"), dir.join("output.txt"));
+        let script_text = format!(
+            ".once {}\nSELECT 1;\nSELECT 2; SELECT\n  3;\n\
+             SELECT * FROM pears;\n.output {}\nSELECT 4\n",
+            once.display(),
+            output.display()
+        );
+        std::fs::write(&script, script_text).unwrap();
+        let capture = Capture::default();
+        let mut shell = Shell::new(Connection::open("sample.db").unwrap());
+        shell.out = Box::new(capture.clone());
+        let read = format!(".read {}", script.display());
+        assert_eq!(shell.execute(&read).unwrap(), Action::Continue);
+        assert_eq!(capture.0.take(), b"2\n3\n");
+        assert_eq!(std::fs::read_to_string(&once).unwrap(), "1\n");
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "4\n");
+
+        // a script can end the shell
+        std::fs::write(&script, "SELECT 5;\n.exit 3\nSELECT 6;").unwrap();
+        shell.out = Box::new(capture.clone());
+        assert_eq!(shell.execute(&read).unwrap(), Action::Exit(3));
+        assert_eq!(capture.0.take(), b"5\n");
+        assert!(shell.execute(".read missing.sql").is_err());
+
+        // a script that reads itself stops at the nesting limit
+        std::fs::write(&script, format!("SELECT 7;\n{read}\nSELECT 8;")).unwrap();
+        shell.out = Box::new(capture.clone());
+        assert_eq!(shell.execute(&read).unwrap(), Action::Continue);
+        let printed = String::from_utf8(capture.0.take()).unwrap();
+        assert_eq!(printed, "7\n".repeat(25) + &"8\n".repeat(25));
+        assert!(shell.failed);
+        shell.set_bail(true);
+        shell.out = Box::new(capture.clone());
+        assert_eq!(shell.execute(&read).unwrap(), Action::Exit(1));
+        assert_eq!(capture.0.take(), "7\n".repeat(25).as_bytes());
+        for file in [script, once, output] {
+            std::fs::remove_file(file).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_script_errors() {
+        let capture = Capture::default();
+        let mut shell = Shell::new(Connection::open("sample.db").unwrap());
+        shell.out = Box::new(capture.clone());
+        let script = "SELECT 1;\nSELECT x;\n.nonsense\nSELECT 2; SELECT\nnull;";
+        assert_eq!(shell.run_script(script).unwrap(), Action::Continue);
+        assert!(shell.failed());
+        assert_eq!(capture.0.take(), b"1\n2\n\n");
+
+        shell.set_echo(true);
+        shell.set_bail(true);
+        shell.set_null_value("-");
+        assert_eq!(shell.run_script(script).unwrap(), Action::Exit(1));
+        assert_eq!(capture.0.take(), b"SELECT 1;\n1\nSELECT x;\n");
+        shell.execute("SELECT NULL").unwrap();
+        assert_eq!(capture.0.take(), b"SELECT NULL\n-\n");
+    }
+
+    #[test]
+    fn test_timer_and_stats() {
+        let capture = Capture::default();
+        let mut shell = Shell::new(Connection::open("sample.db").unwrap());
+        shell.out = Box::new(capture.clone());
+        for input in [
+            ".timer on",
+            "SELECT 1",
+            ".timer off",
+            ".stats on",
+            "SELECT id FROM apples",
+        ] {
+            shell.execute(input).unwrap();
+        }
+        let out = String::from_utf8(capture.0.take()).unwrap();
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "1");
+        assert!(lines[1].starts_with("Run Time: real "));
+        assert_eq!(&lines[2..6], ["1", "2", "3", "4"]);
+        assert_eq!(lines[6], "Pages read:                          0");
+        assert_eq!(lines[7], "Page cache hits:                     0");
+        assert_eq!(lines[8], "Page cache misses:                   0");
+        assert_eq!(lines[9], "Rows scanned:                        4");
+        assert_eq!(lines[10], "Rows returned:                       4");
+
+        // a failed statement is reported too
+        shell.execute(".stats off").unwrap();
+        shell.execute(".timer on").unwrap();
+        assert!(shell.execute("SELECT nosuch()").is_err());
+        assert!(capture.0.take().starts_with(b"Run Time: "));
+        assert!(shell.execute(".timer").is_err());
+    }
+
+    #[test]
+    fn test_query_plan() {
+        let capture = Capture::default();
+        let mut shell = Shell::new(Connection::open("sample.db").unwrap());
+        shell.out = Box::new(capture.clone());
+        shell.set_mode("csv").unwrap();
+        shell
+            .execute("EXPLAIN QUERY PLAN SELECT * FROM apples ORDER BY name")
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(capture.0.take()).unwrap(),
+            "QUERY PLAN\n|--SCAN apples\n`--USE TEMP B-TREE FOR ORDER BY\n"
+        );
+
+        let steps = [
+            (1, 0, "a".to_string()),
+            (2, 1, "b".to_string()),
+            (3, 1, "c".to_string()),
+            (4, 0, "d".to_string()),
+        ];
+        let mut out = vec![];
+        write_plan_steps(&mut out, &steps, 0, "").unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "|--a\n|  |--b\n|  `--c\n`--d\n"
+        );
+    }
+
+    #[test]
+    fn test_import() {
+        let dir = std::env::temp_dir();
+        let (db, csv) = (
+            dir.join("codecrafters_sqlite_import.db"),
+            dir.join("import.csv"),
+        );
+        std::fs::copy("sample.db", &db).unwrap();
+        std::fs::write(&csv, "id,name,score\n1,\"Ann, Jr\",3.5\n2,Bob,\n3,Cy,4\n").unwrap();
+        let capture = Capture::default();
+        let mut shell = Shell::new(Connection::open(&db).unwrap());
+        shell.out = Box::new(capture.clone());
+        let import = format!(".import --csv --infer-types {} scores", csv.display());
+        shell.execute(&import).unwrap();
+        shell.execute(".schema scores").unwrap();
+        shell.execute(".mode quote").unwrap();
+        shell.execute("SELECT * FROM scores").unwrap();
+        // into an existing table the header is a row unless skipped; these
+        // ids are taken, so each row is reported and left out
+        let import = format!(".import --csv --skip 1 {} apples", csv.display());
+        assert!(shell.execute(&import).is_ok());
+        shell.execute("SELECT count(*) FROM apples").unwrap();
+        assert_eq!(
+            String::from_utf8(capture.0.take()).unwrap(),
+            [
+                "CREATE TABLE IF NOT EXISTS \"scores\"(",
+                "\"id\" INTEGER, \"name\" TEXT, \"score\" REAL);",
+                "1,'Ann, Jr',3.5",
+                "2,'Bob',NULL",
+                "3,'Cy',4.0",
+                "4",
+                "",
+            ]
+            .join("\n")
+        );
+        assert!(shell.execute(".import --ascii missing.csv t").is_err());
+        assert!(shell.execute(".import --csv only_file.csv").is_err());
+        std::fs::remove_file(db).unwrap();
+        std::fs::remove_file(csv).unwrap();
+    }
+
+    #[test]
+    fn test_dump() {
+        let dump = run("sample.db", ".dump").unwrap();
+        let lines = dump.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[..3],
+            [
+                "PRAGMA foreign_keys=OFF;",
+                "BEGIN TRANSACTION;",
+                "CREATE TABLE apples"
+            ]
+        );
+        assert_eq!(
+            lines[8],
+            "INSERT INTO apples VALUES(1,'Granny Smith','Light Green');"
+        );
+        assert_eq!(
+            lines[lines.len() - 8..],
+            [
+                "PRAGMA writable_schema=ON;",
+                "CREATE TABLE IF NOT EXISTS sqlite_sequence(name,seq);",
+                "DELETE FROM sqlite_sequence;",
+                "INSERT INTO sqlite_sequence VALUES('apples',4);",
+                "INSERT INTO sqlite_sequence VALUES('oranges',6);",
+                "CREATE INDEX name_index on oranges(name);",
+                "PRAGMA writable_schema=OFF;",
+                "COMMIT;",
+            ]
+        );
+
+        assert_eq!(
+            run("sample.db", ".dump --data-only --nosys app%").unwrap(),
+            [
+                "INSERT INTO apples VALUES(1,'Granny Smith','Light Green');",
+                "INSERT INTO apples VALUES(2,'Fuji','Red');",
+                "INSERT INTO apples VALUES(3,'Honeycrisp','Blush Red');",
+                "INSERT INTO apples VALUES(4,'Golden Delicious','Yellow');",
+            ]
+            .join("\n")
+        );
+        assert_eq!(
+            run("sample.db", ".dump none").unwrap(),
+            "PRAGMA foreign_keys=OFF;\nBEGIN TRANSACTION;\nCOMMIT;"
+        );
+        assert!(run("sample.db", ".dump --bogus").is_err());
+
+        // generated columns are computed again when the script runs
+        assert_eq!(
+            run("generated.db", ".dump --data-only").unwrap(),
+            [
+                "INSERT INTO g VALUES(2,'x');",
+                "INSERT INTO g VALUES(5,NULL);",
+                "INSERT INTO w VALUES('y',4);",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_sql_literal() {
+        let literals = [
+            Value::Null,
+            Value::Integer(-7),
+            Value::Text("it's".into()),
+            Value::Blob(vec![0, 0xff]),
+            Value::Real(3.0),
+            Value::Real(-0.1),
+            Value::Real(1.5e-5),
+            Value::Real(1e20),
+            Value::Real(1e19),
+            Value::Real(123456789.125),
+            Value::Real(f64::NEG_INFINITY),
+            Value::Text("a\nb".into()),
+            Value::Text("\\n\r\n".into()),
+        ]
+        .iter()
+        .map(sql_literal)
+        .collect::<Vec<_>>();
+        assert_eq!(
+            literals,
+            [
+                "NULL",
+                "-7",
+                "'it''s'",
+                "X'00ff'",
+                "3.0",
+                "-0.1",
+                "1.5e-05",
+                "1.0e+20",
+                "10000000000000000000.0",
+                "123456789.125",
+                "-9.0e+999",
+                "replace('a\\nb','\\n',char(10))",
+                "replace(replace('\\n\\r\\012','\\r',char(13)),'\\012',char(10))",
+            ]
+        );
+
+        assert_eq!(quote_name("fruit_1"), "fruit_1");
+        assert_eq!(quote_name("order"), "\"order\"");
+        assert_eq!(quote_name("a \"b\""), "\"a \"\"b\"\"\"");
+    }
+}
@@ -0,0 +1,210 @@
+//! Type conversions SQLite applies to the operands of a comparison before
+//! comparing them. A column has the affinity of its declared type and a
+//! CAST the affinity of its target type; any other expression has none.
+//!
+//! * When both operands have an affinity and one of them is numeric, both
+//!   are compared as NUMERIC.
+//! * When only one operand has an affinity, it is applied to the other.
+//! * BLOB affinity, and two non numeric affinities, convert nothing.
+//!
+//! So with `id INTEGER`, `id = '5'` compares 5 with 5 while `'5' = 5` is
+//! false, and with `name TEXT`, `name = 5` compares against `'5'`.
+
+use crate::sql::ast::Expr;
+use crate::sql::value::{Affinity, Value};
+
+/// Wraps the operands of every comparison in `expr` that need a conversion
+/// in `Expr::Affinity`, given the affinity of each column of the table.
+/// Literals are converted right away, so the planner still sees constants.
+pub fn bind_affinities(expr: &mut Expr, columns: &[(String, Affinity)]) {
+    for child in expr.children_mut() {
+        bind_affinities(child, columns);
+    }
+    match expr {
+        Expr::Binary(left, op, right) if op.is_comparison() => {
+            let affinity =
+                comparison_affinity(expr_affinity(left, columns), expr_affinity(right, columns));
+            if let Some(affinity) = affinity {
+                apply(left, affinity, columns);
+                apply(right, affinity, columns);
+            }
+        }
+        // the left operand's affinity, if any, applies to every list item
+        Expr::InList { expr, list, .. } => {
+            if let Some(affinity) = expr_affinity(expr, columns) {
+                for item in list {
+                    apply(item, affinity, columns);
+                }
+            }
+        }
+        Expr::Between {
+            expr, low, high, ..
+        } => {
+            let operand = expr_affinity(expr, columns);
+            for bound in [low, high] {
+                if let Some(affinity) = comparison_affinity(operand, expr_affinity(bound, columns))
+                {
+                    apply(bound, affinity, columns);
+                }
+            }
+        }
+        Expr::Case {
+            operand: Some(operand),
+            branches,
+            ..
+        } => {
+            let operand = expr_affinity(operand, columns);
+            for (when, _) in branches {
+                if let Some(affinity) = comparison_affinity(operand, expr_affinity(when, columns)) {
+                    apply(when, affinity, columns);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn expr_affinity(expr: &Expr, columns: &[(String, Affinity)]) -> Option<Affinity> {
+    match expr {
+        Expr::Column(name) => columns
+            .iter()
+            .find(|(column, _)| column.eq_ignore_ascii_case(name))
+            .map(|(_, affinity)| *affinity),
+        Expr::Cast { type_name, .. } => Some(Affinity::from_type_name(type_name)),
+        Expr::Affinity(affinity, _) => Some(*affinity),
+        Expr::Collate(expr, _) => expr_affinity(expr, columns),
+        _ => None,
+    }
+}
+
+fn comparison_affinity(left: Option<Affinity>, right: Option<Affinity>) -> Option<Affinity> {
+    match (left, right) {
+        (Some(left), Some(right)) if left.is_numeric() || right.is_numeric() => {
+            Some(Affinity::Numeric)
+        }
+        (Some(_), Some(_)) => None,
+        (Some(affinity), None) | (None, Some(affinity)) if affinity != Affinity::Blob => {
+            Some(affinity)
+        }
+        _ => None,
+    }
+}
+
+/// Applies `affinity` to an operand, unless its values already have it.
+fn apply(expr: &mut Expr, affinity: Affinity, columns: &[(String, Affinity)]) {
+    let converted = match expr_affinity(expr, columns) {
+        Some(own) => own == affinity || (own.is_numeric() && affinity.is_numeric()),
+        None => false,
+    };
+    if converted || affinity == Affinity::Blob {
+        return;
+    }
+    *expr = match std::mem::replace(expr, Expr::Literal(Value::Null)) {
+        Expr::Literal(value) => Expr::Literal(value.apply_affinity(affinity)),
+        expr => Expr::Affinity(affinity, Box::new(expr)),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_select;
+    use crate::sql::ast::BinaryOp;
+    use crate::sql::eval::eval;
+
+    fn columns() -> Vec<(String, Affinity)> {
+        vec![
+            ("id".into(), Affinity::Integer),
+            ("name".into(), Affinity::Text),
+            ("price".into(), Affinity::Real),
+            ("data".into(), Affinity::Blob),
+        ]
+    }
+
+    fn bound(condition: &str) -> Expr {
+        let select = parse_select(&format!("SELECT * FROM t WHERE {condition}")).unwrap();
+        let mut expr = select.where_clause.unwrap();
+        bind_affinities(&mut expr, &columns());
+        expr
+    }
+
+    fn eval_where(condition: &str, row: &[(String, Value)]) -> Value {
+        eval(&bound(condition), row).unwrap()
+    }
+
+    #[test]
+    fn test_bind_literals() {
+        let id = || Box::new(Expr::Column("id".into()));
+        assert_eq!(
+            bound("id = '5'"),
+            Expr::Binary(
+                id(),
+                BinaryOp::Eq,
+                Box::new(Expr::Literal(Value::Integer(5)))
+            )
+        );
+        assert_eq!(
+            bound("name > 5"),
+            Expr::binary(
+                Expr::Column("name".into()),
+                BinaryOp::Gt,
+                Expr::Literal(Value::Text("5".into()))
+            )
+        );
+        // no affinity on either side, or only BLOB: nothing to convert
+        assert_eq!(
+            bound("'5' = 5"),
+            parse_select("SELECT * FROM t WHERE '5' = 5")
+                .unwrap()
+                .where_clause
+                .unwrap()
+        );
+        assert_eq!(
+            bound("data = '5'"),
+            Expr::binary(
+                Expr::Column("data".into()),
+                BinaryOp::Eq,
+                Expr::Literal(Value::Text("5".into()))
+            )
+        );
+        assert_eq!(
+            bound("id IN ('1', 2.0, 'x')"),
+            Expr::InList {
+                expr: id(),
+                list: vec![
+                    Expr::Literal(Value::Integer(1)),
+                    Expr::Literal(Value::Integer(2)),
+                    Expr::Literal(Value::Text("x".into())),
+                ],
+                negated: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_bind_comparisons() {
+        let row = vec![
+            ("id".to_string(), Value::Integer(5)),
+            ("name".to_string(), Value::Text("10".into())),
+            ("price".to_string(), Value::Real(10.0)),
+            ("data".to_string(), Value::Text("5".into())),
+        ];
+        let yes = Value::Integer(1);
+        let no = Value::Integer(0);
+        assert_eq!(eval_where("id = '5'", &row), yes);
+        assert_eq!(eval_where("'5' = id", &row), yes);
+        assert_eq!(eval_where("+id = '5'", &row), no);
+        assert_eq!(eval_where("id = data", &row), yes);
+        assert_eq!(eval_where("data = 5", &row), no);
+        // TEXT against REAL compares numerically, TEXT against a literal as text
+        assert_eq!(eval_where("name = price", &row), yes);
+        assert_eq!(eval_where("name > 9", &row), no);
+        assert_eq!(eval_where("CAST(name AS INTEGER) > '9'", &row), yes);
+        assert_eq!(eval_where("id BETWEEN '1' AND '10'", &row), yes);
+        assert_eq!(eval_where("name IN (10, 20)", &row), yes);
+        assert_eq!(
+            eval_where("CASE id WHEN '5' THEN 'five' END", &row),
+            Value::Text("five".into())
+        );
+    }
+}
@@ -0,0 +1,280 @@
+use crate::sql::value::{Affinity, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Negate,
+    Plus,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    NotEq,
+    Is,
+    IsNot,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Concat,
+}
+
+impl BinaryOp {
+    pub fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            BinaryOp::Eq
+                | BinaryOp::NotEq
+                | BinaryOp::Is
+                | BinaryOp::IsNot
+                | BinaryOp::Lt
+                | BinaryOp::LtEq
+                | BinaryOp::Gt
+                | BinaryOp::GtEq
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PatternOp {
+    Like,
+    Glob,
+    Regexp,
+}
+
+/// A placeholder for a value bound to a prepared statement. `?` and names
+/// are numbered when the statement is prepared, see
+/// `parameter::number_parameters`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Parameter {
+    /// `?`
+    Anonymous,
+    /// `?NNN`, or any parameter once numbered
+    Numbered(usize),
+    /// `:name`, `@name` or `$name`, prefix included
+    Named(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(Value),
+    Column(String),
+    Parameter(Parameter),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(Box<Expr>, BinaryOp, Box<Expr>),
+    /// `name(args)`; `star` is set for the `count(*)` form.
+    Function {
+        name: String,
+        args: Vec<Expr>,
+        star: bool,
+    },
+    /// `expr [NOT] LIKE|GLOB|REGEXP pattern [ESCAPE escape]`
+    Pattern {
+        op: PatternOp,
+        negated: bool,
+        expr: Box<Expr>,
+        pattern: Box<Expr>,
+        escape: Option<Box<Expr>>,
+    },
+    /// `expr [NOT] IN (list)`
+    InList {
+        expr: Box<Expr>,
+        list: Vec<Expr>,
+        negated: bool,
+    },
+    /// `expr [NOT] BETWEEN low AND high`
+    Between {
+        expr: Box<Expr>,
+        low: Box<Expr>,
+        high: Box<Expr>,
+        negated: bool,
+    },
+    /// `CASE [operand] WHEN .. THEN .. [ELSE ..] END`; without an operand
+    /// each WHEN is a condition, with one it's a value compared to it.
+    Case {
+        operand: Option<Box<Expr>>,
+        branches: Vec<(Expr, Expr)>,
+        else_expr: Option<Box<Expr>>,
+    },
+    /// `CAST(expr AS type_name)`
+    Cast {
+        expr: Box<Expr>,
+        type_name: String,
+    },
+    /// `expr COLLATE name`: the value of `expr`, compared with the named
+    /// collating sequence.
+    Collate(Box<Expr>, String),
+    /// Conversion of a comparison operand to the affinity of the other side.
+    /// Never written in SQL, see `affinity::bind_affinities`.
+    Affinity(Affinity, Box<Expr>),
+}
+
+impl Expr {
+    pub fn binary(left: Expr, op: BinaryOp, right: Expr) -> Expr {
+        Expr::Binary(Box::new(left), op, Box::new(right))
+    }
+
+    pub fn unary(op: UnaryOp, expr: Expr) -> Expr {
+        Expr::Unary(op, Box::new(expr))
+    }
+
+    pub fn function(name: &str, args: Vec<Expr>) -> Expr {
+        Expr::Function {
+            name: name.to_ascii_lowercase(),
+            args,
+            star: false,
+        }
+    }
+
+    /// The direct sub expressions, in evaluation order.
+    pub fn children(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Literal(_) | Expr::Column(_) | Expr::Parameter(_) => vec![],
+            Expr::Unary(_, expr)
+            | Expr::Cast { expr, .. }
+            | Expr::Collate(expr, _)
+            | Expr::Affinity(_, expr) => vec![expr],
+            Expr::Binary(left, _, right) => vec![left, right],
+            Expr::Function { args, .. } => args.iter().collect(),
+            Expr::Pattern {
+                expr,
+                pattern,
+                escape,
+                ..
+            } => [Some(expr), Some(pattern), escape.as_ref()]
+                .into_iter()
+                .flatten()
+                .map(|e| e.as_ref())
+                .collect(),
+            Expr::InList { expr, list, .. } => [expr.as_ref()].into_iter().chain(list).collect(),
+            Expr::Between {
+                expr, low, high, ..
+            } => vec![expr, low, high],
+            Expr::Case {
+                operand,
+                branches,
+                else_expr,
+            } => operand
+                .iter()
+                .map(|e| e.as_ref())
+                .chain(branches.iter().flat_map(|(when, then)| [when, then]))
+                .chain(else_expr.iter().map(|e| e.as_ref()))
+                .collect(),
+        }
+    }
+
+    /// Mutable access to the direct sub expressions, in evaluation order.
+    pub fn children_mut(&mut self) -> Vec<&mut Expr> {
+        match self {
+            Expr::Literal(_) | Expr::Column(_) | Expr::Parameter(_) => vec![],
+            Expr::Unary(_, expr)
+            | Expr::Cast { expr, .. }
+            | Expr::Collate(expr, _)
+            | Expr::Affinity(_, expr) => vec![expr],
+            Expr::Binary(left, _, right) => vec![left, right],
+            Expr::Function { args, .. } => args.iter_mut().collect(),
+            Expr::Pattern {
+                expr,
+                pattern,
+                escape,
+                ..
+            } => [Some(expr), Some(pattern), escape.as_mut()]
+                .into_iter()
+                .flatten()
+                .map(|e| e.as_mut())
+                .collect(),
+            Expr::InList { expr, list, .. } => {
+                [expr.as_mut()].into_iter().chain(list.iter_mut()).collect()
+            }
+            Expr::Between {
+                expr, low, high, ..
+            } => vec![expr, low, high],
+            Expr::Case {
+                operand,
+                branches,
+                else_expr,
+            } => operand
+                .iter_mut()
+                .map(|e| e.as_mut())
+                .chain(branches.iter_mut().flat_map(|(when, then)| [when, then]))
+                .chain(else_expr.iter_mut().map(|e| e.as_mut()))
+                .collect(),
+        }
+    }
+
+    pub fn is_aggregate_function(name: &str) -> bool {
+        matches!(name, "count" | "min" | "max" | "sum" | "avg" | "total")
+    }
+
+    /// Whether the expression (or any sub expression) is an aggregate call.
+    pub fn is_aggregate(&self) -> bool {
+        match self {
+            Expr::Function { name, .. } if Expr::is_aggregate_function(name) => true,
+            _ => self.children().into_iter().any(Expr::is_aggregate),
+        }
+    }
+
+    /// Whether the expression refers to any column, i.e. whether its value
+    /// can change from one row to the next.
+    pub fn has_column(&self) -> bool {
+        matches!(self, Expr::Column(_)) || self.children().into_iter().any(Expr::has_column)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResultColumn {
+    Star,
+    /// `text` is the expression as written, used as the column header.
+    Expr {
+        expr: Expr,
+        alias: Option<String>,
+        text: String,
+    },
+}
+
+impl ResultColumn {
+    pub fn name(&self) -> String {
+        match self {
+            ResultColumn::Star => "*".into(),
+            ResultColumn::Expr { alias, text, .. } => alias.clone().unwrap_or(text.clone()),
+        }
+    }
+}
+
+/// A term of the ORDER BY clause.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderingTerm {
+    pub expr: Expr,
+    pub descending: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Select {
+    pub columns: Vec<ResultColumn>,
+    pub from: Option<String>,
+    pub where_clause: Option<Expr>,
+    pub order_by: Vec<OrderingTerm>,
+}
+
+impl Select {
+    /// The result column, WHERE and ORDER BY expressions, in the order they
+    /// are written.
+    pub fn exprs_mut(&mut self) -> impl Iterator<Item = &mut Expr> {
+        let result_exprs = self.columns.iter_mut().filter_map(|c| match c {
+            ResultColumn::Expr { expr, .. } => Some(expr),
+            _ => None,
+        });
+        let order_exprs = self.order_by.iter_mut().map(|term| &mut term.expr);
+        result_exprs
+            .chain(self.where_clause.iter_mut())
+            .chain(order_exprs)
+    }
+}
@@ -0,0 +1,320 @@
+//! Collating sequences, which decide how two text values compare. BINARY,
+//! NOCASE and RTRIM are built in; others can be registered by name.
+//!
+//! The collation of a comparison comes from an explicit `COLLATE` on the
+//! left operand, then on the right one, then from the declared collation of
+//! a column on the left, then on the right, otherwise it is BINARY.
+
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::db::record::TextEncoding;
+use crate::error::{Error, Result};
+use crate::sql::ast::{Expr, UnaryOp};
+use crate::sql::value::Value;
+
+type CompareFn = Rc<dyn Fn(&str, &str) -> Ordering>;
+
+thread_local! {
+    static CUSTOM_COLLATIONS: RefCell<HashMap<String, CompareFn>> = RefCell::new(HashMap::new());
+    static TEXT_ENCODING: Cell<TextEncoding> = const { Cell::new(TextEncoding::Utf8) };
+}
+
+/// Sets the encoding of the database whose statement runs on this thread.
+/// BINARY compares text as its bytes in that encoding, which is also the
+/// order of the indexes in the file. The other collations compare UTF-8.
+pub(crate) fn set_text_encoding(encoding: TextEncoding) {
+    TEXT_ENCODING.set(encoding);
+}
+
+/// The encoding set by `set_text_encoding`, UTF-8 until then.
+pub(crate) fn text_encoding() -> TextEncoding {
+    TEXT_ENCODING.get()
+}
+
+/// Compares text by its bytes in the database encoding.
+fn compare_encoded(a: &str, b: &str) -> Ordering {
+    match text_encoding() {
+        TextEncoding::Utf8 => a.as_bytes().cmp(b.as_bytes()),
+        TextEncoding::Utf16Le => a
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .cmp(b.encode_utf16().flat_map(u16::to_le_bytes)),
+        TextEncoding::Utf16Be => a.encode_utf16().cmp(b.encode_utf16()),
+    }
+}
+
+/// Registers a collating sequence usable as `COLLATE name`, in column
+/// definitions and in indexes. Names are case insensitive and replace any
+/// custom collation registered before under the same name.
+pub fn register_collation<F>(name: &str, compare: F)
+where
+    F: Fn(&str, &str) -> Ordering + 'static,
+{
+    CUSTOM_COLLATIONS.with(|collations| {
+        collations
+            .borrow_mut()
+            .insert(name.to_ascii_uppercase(), Rc::new(compare))
+    });
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Collation {
+    /// Byte by byte comparison
+    #[default]
+    Binary,
+    /// Like BINARY, with ASCII upper case letters folded to lower case
+    NoCase,
+    /// Like BINARY, ignoring trailing spaces
+    RTrim,
+    Custom(String),
+}
+
+impl Collation {
+    pub fn from_name(name: &str) -> Result<Collation> {
+        let key = name.to_ascii_uppercase();
+        Ok(match key.as_str() {
+            "BINARY" => Collation::Binary,
+            "NOCASE" => Collation::NoCase,
+            "RTRIM" => Collation::RTrim,
+            _ if CUSTOM_COLLATIONS.with(|c| c.borrow().contains_key(&key)) => {
+                Collation::Custom(key)
+            }
+            _ => return Err(Error::Sql(format!("no such collation sequence: {name}"))),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Collation::Binary => "BINARY",
+            Collation::NoCase => "NOCASE",
+            Collation::RTrim => "RTRIM",
+            Collation::Custom(name) => name,
+        }
+    }
+
+    pub fn compare_text(&self, a: &str, b: &str) -> Ordering {
+        match self {
+            Collation::Binary => compare_encoded(a, b),
+            Collation::NoCase => {
+                let fold = |s: &str| {
+                    s.bytes()
+                        .map(|b| b.to_ascii_lowercase())
+                        .collect::<Vec<_>>()
+                };
+                fold(a).cmp(&fold(b))
+            }
+            Collation::RTrim => a.trim_end_matches(' ').cmp(b.trim_end_matches(' ')),
+            Collation::Custom(name) => {
+                let compare = CUSTOM_COLLATIONS.with(|c| c.borrow().get(name).cloned());
+                match compare {
+                    Some(compare) => compare(a, b),
+                    None => a.as_bytes().cmp(b.as_bytes()),
+                }
+            }
+        }
+    }
+
+    /// `Value::compare`, with text values compared by this collation.
+    pub fn compare(&self, a: &Value, b: &Value) -> Ordering {
+        match (self, a, b) {
+            (_, Value::Text(a), Value::Text(b)) => self.compare_text(a, b),
+            _ => a.compare(b),
+        }
+    }
+}
+
+/// The collation named by a `COLLATE` clause on the expression, if any.
+pub fn explicit_collation(expr: &Expr) -> Option<&str> {
+    match expr {
+        Expr::Collate(_, name) => Some(name),
+        Expr::Affinity(_, expr) => explicit_collation(expr),
+        _ => None,
+    }
+}
+
+/// Collation of a comparison once `bind_collations` made column collations
+/// explicit: the left operand's `COLLATE`, then the right one's.
+pub fn comparison_collation(left: &Expr, right: &Expr) -> Result<Collation> {
+    match explicit_collation(left).or(explicit_collation(right)) {
+        Some(name) => Collation::from_name(name),
+        None => Ok(Collation::Binary),
+    }
+}
+
+/// Collation an expression is sorted by: its `COLLATE` clause, or the
+/// declared collation of the column it reads.
+pub fn expr_collation(expr: &Expr, columns: &[(String, Collation)]) -> Result<Collation> {
+    match explicit_collation(expr) {
+        Some(name) => Collation::from_name(name),
+        None => Ok(column_collation(expr, columns).unwrap_or_default()),
+    }
+}
+
+fn column_collation(expr: &Expr, columns: &[(String, Collation)]) -> Option<Collation> {
+    match expr {
+        Expr::Column(name) => columns
+            .iter()
+            .find(|(column, _)| column.eq_ignore_ascii_case(name))
+            .map(|(_, collation)| collation.clone()),
+        Expr::Unary(UnaryOp::Plus, expr) | Expr::Cast { expr, .. } | Expr::Affinity(_, expr) => {
+            column_collation(expr, columns)
+        }
+        _ => None,
+    }
+}
+
+/// Adds a `COLLATE` clause with the column's declared collation to the left
+/// operand of comparisons, `IN`, `BETWEEN`, `CASE` and `min()`/`max()`
+/// where no operand has one, so evaluation only has to look at explicit
+/// clauses.
+pub fn bind_collations(expr: &mut Expr, columns: &[(String, Collation)]) {
+    for child in expr.children_mut() {
+        bind_collations(child, columns);
+    }
+    match expr {
+        Expr::Binary(left, op, right)
+            if op.is_comparison()
+                && explicit_collation(left).is_none()
+                && explicit_collation(right).is_none() =>
+        {
+            let declared = column_collation(left, columns).or(column_collation(right, columns));
+            collate(left, declared);
+        }
+        Expr::InList { expr, .. } if explicit_collation(expr).is_none() => {
+            let declared = column_collation(expr, columns);
+            collate(expr, declared);
+        }
+        Expr::Between {
+            expr, low, high, ..
+        } if [&*expr, &*low, &*high]
+            .iter()
+            .all(|e| explicit_collation(e).is_none()) =>
+        {
+            let declared = column_collation(expr, columns);
+            collate(expr, declared);
+        }
+        Expr::Case {
+            operand: Some(operand),
+            branches,
+            ..
+        } if explicit_collation(operand).is_none()
+            && branches
+                .iter()
+                .all(|(when, _)| explicit_collation(when).is_none()) =>
+        {
+            let declared = column_collation(operand, columns);
+            collate(operand, declared);
+        }
+        Expr::Function { name, args, .. } if matches!(name.as_str(), "min" | "max") => {
+            if let [arg] = args.as_mut_slice() {
+                if explicit_collation(arg).is_none() {
+                    let declared = column_collation(arg, columns);
+                    collate(arg, declared);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn collate(expr: &mut Expr, collation: Option<Collation>) {
+    if let Some(collation) = collation.filter(|c| *c != Collation::Binary) {
+        let inner = std::mem::replace(expr, Expr::Literal(Value::Null));
+        *expr = Expr::Collate(Box::new(inner), collation.name().to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_select;
+    use crate::sql::eval::eval;
+
+    fn eval_where(condition: &str, row: &[(String, Value)]) -> Value {
+        let columns = vec![
+            ("a".to_string(), Collation::Binary),
+            ("b".to_string(), Collation::NoCase),
+        ];
+        let select = parse_select(&format!("SELECT * FROM t WHERE {condition}")).unwrap();
+        let mut expr = select.where_clause.unwrap();
+        bind_collations(&mut expr, &columns);
+        eval(&expr, row).unwrap()
+    }
+
+    #[test]
+    fn test_builtin_collations() {
+        assert_eq!(
+            Collation::NoCase.compare_text("ABC", "abc"),
+            Ordering::Equal
+        );
+        assert_eq!(Collation::NoCase.compare_text("Ä", "ä"), Ordering::Less);
+        assert_eq!(
+            Collation::RTrim.compare_text("abc  ", "abc"),
+            Ordering::Equal
+        );
+        assert_eq!(Collation::RTrim.compare_text(" abc", "abc"), Ordering::Less);
+        assert_eq!(Collation::from_name("nocase").unwrap(), Collation::NoCase);
+        assert!(Collation::from_name("nosuch").is_err());
+        assert_eq!(
+            Collation::NoCase.compare(&Value::Integer(1), &Value::Text("a".into())),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn test_utf16_binary_order() {
+        // U+0101 is 01 01 in UTF-16le, before the 61 00 of "a"
+        set_text_encoding(TextEncoding::Utf16Le);
+        assert_eq!(Collation::Binary.compare_text("ā", "a"), Ordering::Less);
+        assert_eq!(Collation::NoCase.compare_text("ā", "a"), Ordering::Greater);
+        assert_eq!(Collation::RTrim.compare_text("ā", "a "), Ordering::Greater);
+        set_text_encoding(TextEncoding::Utf16Be);
+        // surrogate pairs sort before U+E000 and up
+        assert_eq!(
+            Collation::Binary.compare_text("\u{10000}", "\u{e000}"),
+            Ordering::Less
+        );
+        set_text_encoding(TextEncoding::Utf8);
+        assert_eq!(Collation::Binary.compare_text("ā", "a"), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_comparison_collation() {
+        let row = vec![
+            ("a".to_string(), Value::Text("Abc".into())),
+            ("b".to_string(), Value::Text("Abc".into())),
+        ];
+        let yes = Value::Integer(1);
+        let no = Value::Integer(0);
+        assert_eq!(eval_where("a = 'abc'", &row), no);
+        assert_eq!(eval_where("a = 'abc' COLLATE NOCASE", &row), yes);
+        assert_eq!(eval_where("b = 'abc'", &row), yes);
+        assert_eq!(eval_where("'abc' = b", &row), yes);
+        assert_eq!(eval_where("b = 'abc' COLLATE BINARY", &row), no);
+        assert_eq!(eval_where("a = b", &row), yes);
+        assert_eq!(eval_where("b IN ('x', 'ABC')", &row), yes);
+        assert_eq!(eval_where("b BETWEEN 'a' AND 'b'", &row), yes);
+        assert_eq!(eval_where("a BETWEEN 'a' AND 'b'", &row), no);
+        assert_eq!(eval_where("'abc   ' = a COLLATE rtrim", &row), no);
+        assert_eq!(eval_where("'Abc   ' = a COLLATE rtrim", &row), yes);
+    }
+
+    #[test]
+    fn test_custom_collation() {
+        register_collation("reverse", |a, b| b.cmp(a));
+        let row = vec![("a".to_string(), Value::Text("b".into()))];
+        assert_eq!(
+            eval_where("a < 'a' COLLATE REVERSE", &row),
+            Value::Integer(1)
+        );
+        assert_eq!(eval_where("a < 'a'", &row), Value::Integer(0));
+        let select = parse_select("SELECT 'a' = 'b' COLLATE nosuch").unwrap();
+        match &select.columns[0] {
+            crate::sql::ast::ResultColumn::Expr { expr, .. } => assert!(eval(expr, &[]).is_err()),
+            _ => panic!("expected an expression"),
+        }
+    }
+}
@@ -0,0 +1,1120 @@
+//! Date and time functions: `date`, `time`, `datetime`, `julianday`,
+//! `unixepoch`, `strftime` and `timediff`.
+//!
+//! This follows SQLite's `date.c`: every value is turned into a julian day
+//! number counted in milliseconds and the broken down date/time fields are
+//! derived from it on demand. The `localtime`/`utc` modifiers use a fixed UTC
+//! offset (see [`set_localtime_offset`]) instead of the system time zone.
+
+use std::cell::Cell;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{Error, Result};
+use crate::sql::value::{parse_real, Value};
+
+/// Milliseconds between the julian day epoch and 1970-01-01.
+const UNIX_EPOCH_JD_MS: i64 = 210_866_760_000_000;
+const MS_PER_DAY: i64 = 86_400_000;
+/// Largest julian day (in ms) that still formats as a 4 digit year.
+const MAX_JD_MS: i64 = 464_269_060_799_999;
+
+thread_local! {
+    static LOCALTIME_OFFSET_MINUTES: Cell<i32> = const { Cell::new(0) };
+}
+
+/// Sets the offset from UTC, in minutes, applied by the `localtime`
+/// modifier and removed by the `utc` modifier in statements run on this
+/// thread. It is 0 until set, so `localtime` is UTC.
+pub fn set_localtime_offset(minutes: i32) {
+    LOCALTIME_OFFSET_MINUTES.set(minutes);
+}
+
+pub fn localtime_offset() -> i32 {
+    LOCALTIME_OFFSET_MINUTES.get()
+}
+
+#[derive(Debug, Clone, Default)]
+struct DateTime {
+    jd: i64, // julian day number times 86400000
+    year: i32,
+    month: i32,
+    day: i32,
+    hour: i32,
+    minute: i32,
+    second: f64,
+    tz: i32, // timezone offset in minutes
+    valid_jd: bool,
+    valid_ymd: bool,
+    valid_hms: bool,
+    valid_tz: bool,
+    raw_s: bool, // `second` holds the raw numeric argument
+    is_error: bool,
+    use_subsec: bool,
+    is_utc: bool,
+    is_local: bool,
+    n_floor: i32, // days to subtract for the `floor` modifier
+}
+
+impl DateTime {
+    fn now() -> Self {
+        let unix_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(0);
+        DateTime {
+            jd: unix_ms + UNIX_EPOCH_JD_MS,
+            valid_jd: true,
+            is_utc: true,
+            ..Default::default()
+        }
+    }
+
+    fn from_number(r: f64) -> Self {
+        let mut p = DateTime {
+            second: r,
+            raw_s: true,
+            ..Default::default()
+        };
+        if (0.0..5_373_484.5).contains(&r) {
+            p.jd = (r * MS_PER_DAY as f64 + 0.5) as i64;
+            p.valid_jd = true;
+        }
+        p
+    }
+
+    fn clear_ymd_hms_tz(&mut self) {
+        self.valid_ymd = false;
+        self.valid_hms = false;
+        self.valid_tz = false;
+        self.tz = 0;
+    }
+
+    fn set_error(&mut self) {
+        *self = DateTime {
+            is_error: true,
+            ..Default::default()
+        };
+    }
+
+    fn compute_jd(&mut self) {
+        if self.valid_jd {
+            return;
+        }
+        let (mut y, mut m, d) = match self.valid_ymd {
+            true => (self.year, self.month, self.day),
+            _ => (2000, 1, 1),
+        };
+        if !(-4713..=9999).contains(&y) || self.raw_s {
+            self.set_error();
+            return;
+        }
+        if m <= 2 {
+            y -= 1;
+            m += 12;
+        }
+        let a = (y + 4800) / 100;
+        let b = 38 - a + (a / 4);
+        let x1 = 36525 * (y + 4716) / 100;
+        let x2 = 306001 * (m + 1) / 10000;
+        self.jd = (((x1 + x2 + d + b) as f64 - 1524.5) * MS_PER_DAY as f64) as i64;
+        self.valid_jd = true;
+        if self.valid_hms {
+            self.jd += self.hour as i64 * 3_600_000
+                + self.minute as i64 * 60_000
+                + (self.second * 1000.0 + 0.5) as i64;
+            if self.valid_tz {
+                self.jd -= self.tz as i64 * 60_000;
+                self.valid_ymd = false;
+                self.valid_hms = false;
+                self.valid_tz = false;
+            }
+        }
+    }
+
+    fn compute_ymd(&mut self) {
+        if self.valid_ymd {
+            return;
+        }
+        if !self.valid_jd {
+            self.year = 2000;
+            self.month = 1;
+            self.day = 1;
+        } else if !(0..=MAX_JD_MS).contains(&self.jd) {
+            self.set_error();
+            return;
+        } else {
+            let z = ((self.jd + 43_200_000) / MS_PER_DAY) as i32;
+            let a = ((z as f64 - 1_867_216.25) / 36_524.25) as i32;
+            let a = z + 1 + a - (a / 4);
+            let b = a + 1524;
+            let c = ((b as f64 - 122.1) / 365.25) as i32;
+            let d = (36525 * (c & 32767)) / 100;
+            let e = ((b - d) as f64 / 30.6001) as i32;
+            let x1 = (30.6001 * e as f64) as i32;
+            self.day = b - d - x1;
+            self.month = if e < 14 { e - 1 } else { e - 13 };
+            self.year = if self.month > 2 { c - 4716 } else { c - 4715 };
+        }
+        self.valid_ymd = true;
+    }
+
+    fn compute_hms(&mut self) {
+        if self.valid_hms {
+            return;
+        }
+        self.compute_jd();
+        let day_ms = (self.jd + 43_200_000) % MS_PER_DAY;
+        self.second = (day_ms % 60_000) as f64 / 1000.0;
+        let day_min = (day_ms / 60_000) as i32;
+        self.minute = day_min % 60;
+        self.hour = day_min / 60;
+        self.raw_s = false;
+        self.valid_hms = true;
+    }
+
+    fn compute_ymd_hms(&mut self) {
+        self.compute_ymd();
+        self.compute_hms();
+    }
+
+    /// Remembers how far past the end of the month the current day is, so
+    /// the `floor` modifier can undo the overflow of `+N months`.
+    fn compute_floor(&mut self) {
+        self.n_floor = if self.day <= 28 || (1 << self.month) & 0x15aa != 0 {
+            0
+        } else if self.month != 2 {
+            (self.day == 31) as i32
+        } else if self.year % 4 != 0 || (self.year % 100 == 0 && self.year % 400 != 0) {
+            self.day - 28
+        } else {
+            self.day - 29
+        };
+    }
+
+    fn days_after_jan01(&self) -> i32 {
+        let mut jan01 = self.clone();
+        jan01.valid_jd = false;
+        jan01.month = 1;
+        jan01.day = 1;
+        jan01.compute_jd();
+        ((self.jd - jan01.jd + 43_200_000) / MS_PER_DAY) as i32
+    }
+
+    fn days_after_monday(&self) -> i32 {
+        (((self.jd + 43_200_000) / MS_PER_DAY) % 7) as i32
+    }
+
+    fn days_after_sunday(&self) -> i32 {
+        (((self.jd + 129_600_000) / MS_PER_DAY) % 7) as i32
+    }
+
+    /// The Thursday of the ISO-8601 week that contains this date.
+    fn iso_week_thursday(&self) -> DateTime {
+        let mut y = self.clone();
+        y.jd += (3 - self.days_after_monday()) as i64 * MS_PER_DAY;
+        y.valid_ymd = false;
+        y.compute_ymd();
+        y
+    }
+}
+
+/// Reads exactly `width` digits from the start of `s` into a number in
+/// `min..=max`.
+fn digits(s: &[u8], width: usize, min: i32, max: i32) -> Option<i32> {
+    if s.len() < width || !s[..width].iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    let value = s[..width]
+        .iter()
+        .fold(0, |acc, c| acc * 10 + (c - b'0') as i32);
+    (min..=max).contains(&value).then_some(value)
+}
+
+fn skip_spaces(s: &[u8]) -> &[u8] {
+    let start = s
+        .iter()
+        .position(|c| !c.is_ascii_whitespace())
+        .unwrap_or(s.len());
+    &s[start..]
+}
+
+/// Parses a `[+-]HH:MM` or `Z` timezone suffix. Returns false on trailing
+/// garbage.
+fn parse_timezone(s: &[u8], p: &mut DateTime) -> bool {
+    let mut s = skip_spaces(s);
+    p.tz = 0;
+    let sign = match s.first() {
+        Some(b'-') => -1,
+        Some(b'+') => 1,
+        Some(b'Z') | Some(b'z') => {
+            p.is_local = false;
+            p.is_utc = true;
+            return skip_spaces(&s[1..]).is_empty();
+        }
+        Some(_) => return false,
+        None => return true,
+    };
+    s = &s[1..];
+    let (hours, minutes) = match (
+        digits(s, 2, 0, 14),
+        s.get(2),
+        digits(s.get(3..).unwrap_or(&[]), 2, 0, 59),
+    ) {
+        (Some(h), Some(b':'), Some(m)) => (h, m),
+        _ => return false,
+    };
+    p.tz = sign * (minutes + hours * 60);
+    skip_spaces(&s[5..]).is_empty()
+}
+
+/// Parses `HH:MM[:SS[.SSS]]` followed by an optional timezone.
+fn parse_hh_mm_ss(s: &[u8], p: &mut DateTime) -> bool {
+    let (hour, minute) = match (
+        digits(s, 2, 0, 24),
+        s.get(2),
+        digits(s.get(3..).unwrap_or(&[]), 2, 0, 59),
+    ) {
+        (Some(h), Some(b':'), Some(m)) => (h, m),
+        _ => return false,
+    };
+    let mut rest = &s[5..];
+    let mut second = 0.0;
+    if rest.first() == Some(&b':') {
+        second = match digits(&rest[1..], 2, 0, 59) {
+            Some(sec) => sec as f64,
+            None => return false,
+        };
+        rest = &rest[3..];
+        if rest.first() == Some(&b'.') && rest.get(1).is_some_and(u8::is_ascii_digit) {
+            let fraction_len = rest[1..].iter().take_while(|c| c.is_ascii_digit()).count();
+            let (mut ms, mut scale) = (0.0, 1.0);
+            for c in &rest[1..=fraction_len] {
+                ms = ms * 10.0 + (c - b'0') as f64;
+                scale *= 10.0;
+            }
+            // truncate to avoid sub-millisecond rounding up to the next second
+            second += (ms / scale).min(0.999);
+            rest = &rest[1 + fraction_len..];
+        }
+    }
+    p.valid_jd = false;
+    p.raw_s = false;
+    p.valid_hms = true;
+    p.hour = hour;
+    p.minute = minute;
+    p.second = second;
+    if !parse_timezone(rest, p) {
+        return false;
+    }
+    p.valid_tz = p.tz != 0;
+    true
+}
+
+/// Parses `[-]YYYY-MM-DD` optionally followed by a time.
+fn parse_yyyy_mm_dd(s: &[u8], p: &mut DateTime) -> bool {
+    let (negative, s) = match s.first() {
+        Some(b'-') => (true, &s[1..]),
+        _ => (false, s),
+    };
+    let (year, month, day) = match (
+        digits(s, 4, 0, 9999),
+        s.get(4),
+        digits(s.get(5..).unwrap_or(&[]), 2, 1, 12),
+        s.get(7),
+        digits(s.get(8..).unwrap_or(&[]), 2, 1, 31),
+    ) {
+        (Some(y), Some(b'-'), Some(m), Some(b'-'), Some(d)) => (y, m, d),
+        _ => return false,
+    };
+    let rest = &s[10..];
+    let start = rest
+        .iter()
+        .position(|c| !c.is_ascii_whitespace() && *c != b'T')
+        .unwrap_or(rest.len());
+    let rest = &rest[start..];
+    if !parse_hh_mm_ss(rest, p) {
+        if !rest.is_empty() {
+            return false;
+        }
+        p.valid_hms = false;
+    }
+    p.valid_jd = false;
+    p.valid_ymd = true;
+    p.year = if negative { -year } else { year };
+    p.month = month;
+    p.day = day;
+    p.compute_floor();
+    if p.tz != 0 {
+        p.compute_jd();
+    }
+    true
+}
+
+fn parse_date_or_time(s: &str) -> Option<DateTime> {
+    let mut p = DateTime::default();
+    if parse_yyyy_mm_dd(s.as_bytes(), &mut p) {
+        return Some(p);
+    }
+    let mut p = DateTime::default();
+    if parse_hh_mm_ss(s.as_bytes(), &mut p) {
+        return Some(p);
+    }
+    if s.eq_ignore_ascii_case("now") {
+        return Some(DateTime::now());
+    }
+    if let Some(r) = parse_real(s) {
+        return Some(DateTime::from_number(r));
+    }
+    if s.eq_ignore_ascii_case("subsec") || s.eq_ignore_ascii_case("subsecond") {
+        let mut p = DateTime::now();
+        p.use_subsec = true;
+        return Some(p);
+    }
+    None
+}
+
+/// Applies the `index`-th modifier (1 based, the time value is 0) to `p`.
+/// Returns false when the modifier is not recognised or not applicable.
+fn apply_modifier(modifier: &str, p: &mut DateTime, index: usize) -> bool {
+    let z = modifier.to_ascii_lowercase();
+    match z.as_str() {
+        "auto" => {
+            if index > 1 {
+                return false;
+            }
+            if !p.raw_s || p.valid_jd {
+                p.raw_s = false;
+            } else if (-210_866_760_000.0..=253_402_300_799.0).contains(&p.second) {
+                let r = p.second * 1000.0 + UNIX_EPOCH_JD_MS as f64;
+                p.clear_ymd_hms_tz();
+                p.jd = (r + 0.5) as i64;
+                p.valid_jd = true;
+                p.raw_s = false;
+            }
+            true
+        }
+        "ceiling" => {
+            p.compute_jd();
+            p.clear_ymd_hms_tz();
+            p.n_floor = 0;
+            true
+        }
+        "floor" => {
+            p.compute_jd();
+            p.jd -= p.n_floor as i64 * MS_PER_DAY;
+            p.clear_ymd_hms_tz();
+            true
+        }
+        "julianday" => {
+            if index > 1 {
+                return false;
+            }
+            match p.valid_jd && p.raw_s {
+                true => {
+                    p.raw_s = false;
+                    true
+                }
+                _ => false,
+            }
+        }
+        "localtime" => {
+            if !p.is_local {
+                p.compute_jd();
+                p.jd += localtime_offset() as i64 * 60_000;
+                p.clear_ymd_hms_tz();
+                p.raw_s = false;
+            }
+            p.is_utc = false;
+            p.is_local = true;
+            true
+        }
+        "unixepoch" if p.raw_s => {
+            if index > 1 {
+                return false;
+            }
+            let r = p.second * 1000.0 + UNIX_EPOCH_JD_MS as f64;
+            match (0.0..464_269_060_800_000.0).contains(&r) {
+                true => {
+                    p.clear_ymd_hms_tz();
+                    p.jd = (r + 0.5) as i64;
+                    p.valid_jd = true;
+                    p.raw_s = false;
+                    true
+                }
+                _ => false,
+            }
+        }
+        "utc" => {
+            if !p.is_utc {
+                p.compute_jd();
+                let jd = p.jd - localtime_offset() as i64 * 60_000;
+                *p = DateTime {
+                    jd,
+                    valid_jd: true,
+                    is_utc: true,
+                    ..Default::default()
+                };
+            }
+            true
+        }
+        "subsec" | "subsecond" => {
+            p.use_subsec = true;
+            true
+        }
+        _ if z.starts_with("weekday ") => {
+            let n = match parse_real(&z[8..]) {
+                Some(r) if (0.0..7.0).contains(&r) && r == r.trunc() => r as i64,
+                _ => return false,
+            };
+            p.compute_ymd_hms();
+            p.valid_tz = false;
+            p.tz = 0;
+            p.valid_jd = false;
+            p.compute_jd();
+            let mut day = ((p.jd + 129_600_000) / MS_PER_DAY) % 7;
+            if day > n {
+                day -= 7;
+            }
+            p.jd += (n - day) * MS_PER_DAY;
+            p.clear_ymd_hms_tz();
+            true
+        }
+        _ if z.starts_with("start of ") => {
+            if !p.valid_jd && !p.valid_ymd && !p.valid_hms {
+                return false;
+            }
+            let unit = &z[9..];
+            if !matches!(unit, "month" | "year" | "day") {
+                return false;
+            }
+            p.compute_ymd();
+            p.valid_hms = true;
+            p.hour = 0;
+            p.minute = 0;
+            p.second = 0.0;
+            p.raw_s = false;
+            p.valid_tz = false;
+            p.tz = 0;
+            p.valid_jd = false;
+            match unit {
+                "month" => p.day = 1,
+                "year" => {
+                    p.month = 1;
+                    p.day = 1;
+                }
+                _ => {}
+            }
+            true
+        }
+        _ if z.starts_with(['+', '-']) || z.starts_with(|c: char| c.is_ascii_digit()) => {
+            apply_offset_modifier(&z, p)
+        }
+        _ => false,
+    }
+}
+
+/// Handles `±NNN units`, `±HH:MM[:SS[.SSS]]` and `±YYYY-MM-DD[ HH:MM[:SS]]`.
+fn apply_offset_modifier(z: &str, p: &mut DateTime) -> bool {
+    let bytes = z.as_bytes();
+    // the number ends at a ':' or space, or at the '-' of a ±YYYY-MM-DD
+    let mut n = 1;
+    while n < bytes.len() && bytes[n] != b':' && !bytes[n].is_ascii_whitespace() {
+        if bytes[n] == b'-' && (n == 5 || n == 6) && bytes[1..n].iter().all(u8::is_ascii_digit) {
+            break;
+        }
+        n += 1;
+    }
+
+    if bytes.get(n) == Some(&b'-') {
+        return apply_ymd_offset(z, p);
+    }
+    let r = match parse_real(&z[..n]) {
+        Some(r) => r,
+        None => return false,
+    };
+    if bytes.get(n) == Some(&b':') {
+        let digits_start = match bytes[0].is_ascii_digit() {
+            true => 0,
+            _ => 1,
+        };
+        let mut tx = DateTime::default();
+        if !parse_hh_mm_ss(&bytes[digits_start..], &mut tx) {
+            return false;
+        }
+        tx.compute_jd();
+        tx.jd -= 43_200_000;
+        let day = tx.jd / MS_PER_DAY;
+        tx.jd -= day * MS_PER_DAY;
+        if bytes[0] == b'-' {
+            tx.jd = -tx.jd;
+        }
+        p.compute_jd();
+        p.clear_ymd_hms_tz();
+        p.jd += tx.jd;
+        return true;
+    }
+
+    let unit = z[n..].trim_start();
+    let unit = unit.strip_suffix('s').unwrap_or(unit);
+    // unit, largest absolute amount, seconds per unit
+    const UNITS: [(&str, f64, f64); 6] = [
+        ("second", 4.6427e+14, 1.0),
+        ("minute", 7.7379e+12, 60.0),
+        ("hour", 1.2897e+11, 3600.0),
+        ("day", 5_373_485.0, 86_400.0),
+        ("month", 176_546.0, 2_592_000.0),
+        ("year", 14_713.0, 31_536_000.0),
+    ];
+    let (name, _, seconds) = match UNITS
+        .iter()
+        .find(|(name, limit, _)| *name == unit && r > -limit && r < *limit)
+    {
+        Some(found) => *found,
+        None => return false,
+    };
+    p.compute_jd();
+    let mut r = r;
+    match name {
+        "month" => {
+            p.compute_ymd_hms();
+            p.month += r as i32;
+            let x = match p.month > 0 {
+                true => (p.month - 1) / 12,
+                _ => (p.month - 12) / 12,
+            };
+            p.year += x;
+            p.month -= x * 12;
+            p.compute_floor();
+            p.valid_jd = false;
+            r -= (r as i32) as f64;
+        }
+        "year" => {
+            p.compute_ymd_hms();
+            p.year += r as i32;
+            p.compute_floor();
+            p.valid_jd = false;
+            r -= (r as i32) as f64;
+        }
+        _ => {}
+    }
+    p.compute_jd();
+    let rounder = if r < 0.0 { -0.5 } else { 0.5 };
+    p.jd += (r * 1000.0 * seconds + rounder) as i64;
+    p.clear_ymd_hms_tz();
+    true
+}
+
+/// `±YYYY-MM-DD` adds years, months (0-11) and days (0-30), optionally
+/// followed by a `HH:MM[:SS]` time offset.
+fn apply_ymd_offset(z: &str, p: &mut DateTime) -> bool {
+    let bytes = z.as_bytes();
+    let sign = match bytes[0] {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return false,
+    };
+    let year_digits = bytes[1..].iter().take_while(|c| c.is_ascii_digit()).count();
+    if !(4..=5).contains(&year_digits) {
+        return false;
+    }
+    let date = &bytes[1 + year_digits..];
+    let (years, months, days) = match (
+        digits(&bytes[1..], year_digits, 0, 99999),
+        date.first(),
+        digits(date.get(1..).unwrap_or(&[]), 2, 0, 11),
+        date.get(3),
+        digits(date.get(4..).unwrap_or(&[]), 2, 0, 30),
+    ) {
+        (Some(y), Some(b'-'), Some(m), Some(b'-'), Some(d)) => (y, m, d),
+        _ => return false,
+    };
+    let rest = &date[6..];
+    p.compute_ymd_hms();
+    p.valid_jd = false;
+    p.year += sign * years;
+    p.month += sign * months;
+    let x = match p.month > 0 {
+        true => (p.month - 1) / 12,
+        _ => (p.month - 12) / 12,
+    };
+    p.year += x;
+    p.month -= x * 12;
+    p.compute_floor();
+    p.compute_jd();
+    p.valid_hms = false;
+    p.valid_ymd = false;
+    p.jd += (sign * days) as i64 * MS_PER_DAY;
+    if rest.is_empty() {
+        return true;
+    }
+    if !rest[0].is_ascii_whitespace() {
+        return false;
+    }
+    let time = String::from_utf8_lossy(skip_spaces(rest));
+    let time = match sign {
+        1 => format!("+{time}"),
+        _ => format!("-{time}"),
+    };
+    apply_offset_modifier(&time, p)
+}
+
+/// Builds the DateTime for the time value and modifiers in `args`; `None`
+/// means the result of the function is NULL.
+fn is_date(args: &[Value]) -> Option<DateTime> {
+    let mut p = match args.first() {
+        None => DateTime::now(),
+        Some(Value::Integer(i)) => DateTime::from_number(*i as f64),
+        Some(Value::Real(r)) => DateTime::from_number(*r),
+        Some(value) => parse_date_or_time(&value.as_text()?)?,
+    };
+    for (index, modifier) in args.iter().enumerate().skip(1) {
+        if !apply_modifier(&modifier.as_text()?, &mut p, index) {
+            return None;
+        }
+    }
+    p.compute_jd();
+    if p.is_error || !(0..=MAX_JD_MS).contains(&p.jd) {
+        return None;
+    }
+    if args.len() == 1 && p.valid_ymd && p.day > 28 {
+        // normalise dates like 2023-02-31 into 2023-03-03
+        p.valid_ymd = false;
+    }
+    Some(p)
+}
+
+fn format_date(p: &DateTime) -> String {
+    match p.year < 0 {
+        true => format!("-{:04}-{:02}-{:02}", -p.year, p.month, p.day),
+        _ => format!("{:04}-{:02}-{:02}", p.year, p.month, p.day),
+    }
+}
+
+fn format_time(p: &DateTime) -> String {
+    match p.use_subsec {
+        true => {
+            let ms = (1000.0 * p.second + 0.5) as i32;
+            format!(
+                "{:02}:{:02}:{:02}.{:03}",
+                p.hour,
+                p.minute,
+                ms / 1000,
+                ms % 1000
+            )
+        }
+        _ => format!("{:02}:{:02}:{:02}", p.hour, p.minute, p.second as i32),
+    }
+}
+
+fn unix_seconds(p: &DateTime) -> Value {
+    match p.use_subsec {
+        true => Value::Real((p.jd - UNIX_EPOCH_JD_MS) as f64 / 1000.0),
+        _ => Value::Integer(p.jd / 1000 - UNIX_EPOCH_JD_MS / 1000),
+    }
+}
+
+fn strftime(format: &str, p: &mut DateTime) -> Option<String> {
+    p.compute_jd();
+    p.compute_ymd_hms();
+    let mut out = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'd' => out.push_str(&format!("{:02}", p.day)),
+            'e' => out.push_str(&format!("{:2}", p.day)),
+            'f' => out.push_str(&format!("{:06.3}", p.second.min(59.999))),
+            'F' => out.push_str(&format!("{:04}-{:02}-{:02}", p.year, p.month, p.day)),
+            'G' => out.push_str(&format!("{:04}", p.iso_week_thursday().year)),
+            'g' => out.push_str(&format!("{:02}", p.iso_week_thursday().year % 100)),
+            'H' => out.push_str(&format!("{:02}", p.hour)),
+            'k' => out.push_str(&format!("{:2}", p.hour)),
+            c @ ('I' | 'l') => {
+                let hour = match p.hour {
+                    0 => 12,
+                    h if h > 12 => h - 12,
+                    h => h,
+                };
+                match c {
+                    'I' => out.push_str(&format!("{:02}", hour)),
+                    _ => out.push_str(&format!("{:2}", hour)),
+                }
+            }
+            'j' => out.push_str(&format!("{:03}", p.days_after_jan01() + 1)),
+            'J' => out.push_str(&format_g16(p.jd as f64 / MS_PER_DAY as f64)),
+            'm' => out.push_str(&format!("{:02}", p.month)),
+            'M' => out.push_str(&format!("{:02}", p.minute)),
+            'p' => out.push_str(if p.hour >= 12 { "PM" } else { "AM" }),
+            'P' => out.push_str(if p.hour >= 12 { "pm" } else { "am" }),
+            'R' => out.push_str(&format!("{:02}:{:02}", p.hour, p.minute)),
+            's' => match unix_seconds(p) {
+                Value::Real(r) => out.push_str(&format!("{:.3}", r)),
+                seconds => out.push_str(&seconds.to_string()),
+            },
+            'S' => out.push_str(&format!("{:02}", p.second as i32)),
+            'T' => out.push_str(&format!(
+                "{:02}:{:02}:{:02}",
+                p.hour, p.minute, p.second as i32
+            )),
+            'u' => out.push_str(&match p.days_after_sunday() {
+                0 => 7.to_string(),
+                d => d.to_string(),
+            }),
+            'w' => out.push_str(&p.days_after_sunday().to_string()),
+            'U' => out.push_str(&format!(
+                "{:02}",
+                (p.days_after_jan01() - p.days_after_sunday() + 7) / 7
+            )),
+            'V' => out.push_str(&format!(
+                "{:02}",
+                p.iso_week_thursday().days_after_jan01() / 7 + 1
+            )),
+            'W' => out.push_str(&format!(
+                "{:02}",
+                (p.days_after_jan01() - p.days_after_monday() + 7) / 7
+            )),
+            'Y' => out.push_str(&format!("{:04}", p.year)),
+            '%' => out.push('%'),
+            _ => return None,
+        }
+    }
+    Some(out)
+}
+
+/// `printf("%.16g")`, used by the `%J` conversion.
+fn format_g16(r: f64) -> String {
+    let scientific = format!("{:.15e}", r);
+    let exponent = scientific
+        .split_once('e')
+        .and_then(|(_, e)| e.parse::<i32>().ok())
+        .unwrap_or(0);
+    if !(-4..16).contains(&exponent) {
+        return scientific;
+    }
+    let fixed = format!("{:.*}", (15 - exponent).max(0) as usize, r);
+    match fixed.contains('.') {
+        true => fixed
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_string(),
+        _ => fixed,
+    }
+}
+
+fn timediff(args: &[Value]) -> Option<String> {
+    let mut d1 = is_date(&args[..1])?;
+    let mut d2 = is_date(&args[1..2])?;
+    d1.compute_ymd_hms();
+    d2.compute_ymd_hms();
+    let sign;
+    let (mut y, mut m);
+    if d1.jd >= d2.jd {
+        sign = '+';
+        y = d1.year - d2.year;
+        if y != 0 {
+            d2.year = d1.year;
+            d2.valid_jd = false;
+            d2.compute_jd();
+        }
+        m = d1.month - d2.month;
+        if m < 0 {
+            y -= 1;
+            m += 12;
+        }
+        if m != 0 {
+            d2.month = d1.month;
+            d2.valid_jd = false;
+            d2.compute_jd();
+        }
+        while d1.jd < d2.jd {
+            m -= 1;
+            if m < 0 {
+                m = 11;
+                y -= 1;
+            }
+            d2.month -= 1;
+            if d2.month < 1 {
+                d2.month = 12;
+                d2.year -= 1;
+            }
+            d2.valid_jd = false;
+            d2.compute_jd();
+        }
+        d1.jd -= d2.jd;
+    } else {
+        sign = '-';
+        y = d2.year - d1.year;
+        if y != 0 {
+            d2.year = d1.year;
+            d2.valid_jd = false;
+            d2.compute_jd();
+        }
+        m = d2.month - d1.month;
+        if m < 0 {
+            y -= 1;
+            m += 12;
+        }
+        if m != 0 {
+            d2.month = d1.month;
+            d2.valid_jd = false;
+            d2.compute_jd();
+        }
+        while d1.jd > d2.jd {
+            m -= 1;
+            if m < 0 {
+                m = 11;
+                y -= 1;
+            }
+            d2.month += 1;
+            if d2.month > 12 {
+                d2.month = 1;
+                d2.year += 1;
+            }
+            d2.valid_jd = false;
+            d2.compute_jd();
+        }
+        d1.jd = d2.jd - d1.jd;
+    }
+    // anchor the remaining difference at 0000-01-01 00:00:00
+    d1.jd += 148_699_540_800_000;
+    d1.clear_ymd_hms_tz();
+    d1.compute_ymd_hms();
+    Some(format!(
+        "{}{:04}-{:02}-{:02} {:02}:{:02}:{:06.3}",
+        sign,
+        y,
+        m,
+        d1.day - 1,
+        d1.hour,
+        d1.minute,
+        d1.second
+    ))
+}
+
+/// Entry point used by the expression evaluator.
+pub fn call(name: &str, args: &[Value]) -> Result<Value> {
+    let text = |s: Option<String>| s.map(Value::Text).unwrap_or(Value::Null);
+    Ok(match name {
+        "date" => text(is_date(args).map(|mut p| {
+            p.compute_ymd();
+            format_date(&p)
+        })),
+        "time" => text(is_date(args).map(|mut p| {
+            p.compute_hms();
+            format_time(&p)
+        })),
+        "datetime" => text(is_date(args).map(|mut p| {
+            p.compute_ymd_hms();
+            format!("{} {}", format_date(&p), format_time(&p))
+        })),
+        "julianday" => is_date(args)
+            .map(|p| Value::Real(p.jd as f64 / MS_PER_DAY as f64))
+            .unwrap_or(Value::Null),
+        "unixepoch" => is_date(args)
+            .map(|p| unix_seconds(&p))
+            .unwrap_or(Value::Null),
+        "strftime" => match args.split_first() {
+            Some((format, rest)) => text(
+                format
+                    .as_text()
+                    .and_then(|format| strftime(&format, &mut is_date(rest)?)),
+            ),
+            _ => {
+                return Err(Error::Sql(
+                    "wrong number of arguments to function strftime()".to_string(),
+                ))
+            }
+        },
+        "timediff" => match args.len() {
+            2 => text(timediff(args)),
+            _ => {
+                return Err(Error::Sql(
+                    "wrong number of arguments to function timediff()".to_string(),
+                ))
+            }
+        },
+        _ => return Err(Error::Sql(format!("no such function: {name}"))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call_text(name: &str, args: &[&str]) -> Value {
+        let args = args
+            .iter()
+            .map(|a| Value::Text(a.to_string()))
+            .collect::<Vec<_>>();
+        call(name, &args).expect("date function failed")
+    }
+
+    fn text(s: &str) -> Value {
+        Value::Text(s.into())
+    }
+
+    #[test]
+    fn test_date_formats() {
+        assert_eq!(
+            call_text("date", &["2024-03-05 12:34:56"]),
+            text("2024-03-05")
+        );
+        assert_eq!(
+            call_text("time", &["2024-03-05T12:34:56.789"]),
+            text("12:34:56")
+        );
+        assert_eq!(
+            call_text("datetime", &["2024-03-05 12:34:56+02:00"]),
+            text("2024-03-05 10:34:56")
+        );
+        assert_eq!(call_text("date", &["2023-02-31"]), text("2023-03-03"));
+        assert_eq!(call_text("time", &["12:30"]), text("12:30:00"));
+        assert_eq!(call_text("date", &["not a date"]), Value::Null);
+        assert_eq!(
+            call_text("julianday", &["2000-01-01"]),
+            Value::Real(2451544.5)
+        );
+        assert_eq!(
+            call("datetime", &[Value::Real(2451544.5)]).unwrap(),
+            text("2000-01-01 00:00:00")
+        );
+    }
+
+    #[test]
+    fn test_unixepoch() {
+        assert_eq!(
+            call("datetime", &[Value::Integer(1709642096), text("unixepoch")]).unwrap(),
+            text("2024-03-05 12:34:56")
+        );
+        assert_eq!(
+            call_text("unixepoch", &["2024-03-05 12:34:56"]),
+            Value::Integer(1709642096)
+        );
+        assert_eq!(
+            call_text("unixepoch", &["2024-03-05 12:34:56.789", "subsec"]),
+            Value::Real(1709642096.789)
+        );
+        assert_eq!(
+            call(
+                "date",
+                &[
+                    Value::Integer(1709642096),
+                    text("start of day"),
+                    text("unixepoch")
+                ]
+            )
+            .unwrap(),
+            Value::Null
+        );
+        assert_eq!(
+            call("datetime", &[Value::Integer(1709642096), text("auto")]).unwrap(),
+            text("2024-03-05 12:34:56")
+        );
+    }
+
+    #[test]
+    fn test_modifiers() {
+        assert_eq!(
+            call_text("date", &["2024-01-31", "+1 month"]),
+            text("2024-03-02")
+        );
+        assert_eq!(
+            call_text("date", &["2024-01-31", "+1 month", "floor"]),
+            text("2024-02-29")
+        );
+        assert_eq!(
+            call_text("date", &["2024-03-05", "-7 days"]),
+            text("2024-02-27")
+        );
+        assert_eq!(
+            call_text("datetime", &["2024-03-05 10:00", "+90 minutes"]),
+            text("2024-03-05 11:30:00")
+        );
+        assert_eq!(
+            call_text("datetime", &["2024-03-05 10:00", "+01:30"]),
+            text("2024-03-05 11:30:00")
+        );
+        assert_eq!(
+            call_text("date", &["2024-03-05", "+0001-02-03"]),
+            text("2025-05-08")
+        );
+        assert_eq!(
+            call_text("date", &["2024-03-15", "start of month"]),
+            text("2024-03-01")
+        );
+        assert_eq!(
+            call_text("date", &["2024-03-15", "start of year"]),
+            text("2024-01-01")
+        );
+        assert_eq!(
+            call_text("datetime", &["2024-03-15 08:00", "start of day"]),
+            text("2024-03-15 00:00:00")
+        );
+        assert_eq!(
+            call_text("date", &["2024-03-05", "weekday 0"]),
+            text("2024-03-10")
+        );
+        assert_eq!(
+            call_text(
+                "date",
+                &["2024-03-05", "start of month", "+1 month", "-1 day"]
+            ),
+            text("2024-03-31")
+        );
+        assert_eq!(
+            call_text("date", &["2024-03-05", "+1 fortnight"]),
+            Value::Null
+        );
+    }
+
+    #[test]
+    fn test_localtime_fixed_offset() {
+        set_localtime_offset(120);
+        assert_eq!(
+            call_text("datetime", &["2024-03-05 23:30", "localtime"]),
+            text("2024-03-06 01:30:00")
+        );
+        assert_eq!(
+            call_text("datetime", &["2024-03-06 01:30", "utc"]),
+            text("2024-03-05 23:30:00")
+        );
+        // the offset is only set for this thread
+        let other =
+            std::thread::spawn(|| call_text("datetime", &["2024-03-05 23:30", "localtime"]));
+        assert_eq!(other.join().unwrap(), text("2024-03-05 23:30:00"));
+    }
+
+    #[test]
+    fn test_strftime() {
+        assert_eq!(
+            call_text(
+                "strftime",
+                &["%V %G %U %W %j %J %f %s", "2024-03-05 12:34:56.789"]
+            ),
+            text("10 2024 09 10 065 2460375.024268391 56.789 1709642096")
+        );
+        assert_eq!(
+            call_text(
+                "strftime",
+                &["%Y-%m-%d %H:%M:%S %w %u %I%p", "2023-01-01 00:05:00"]
+            ),
+            text("2023-01-01 00:05:00 0 7 12AM")
+        );
+        assert_eq!(call_text("strftime", &["%Q", "2024-03-05"]), Value::Null);
+    }
+
+    #[test]
+    fn test_timediff() {
+        assert_eq!(
+            call_text("timediff", &["2023-02-15", "2023-03-01"]),
+            text("-0000-00-14 00:00:00.000")
+        );
+        assert_eq!(
+            call_text("timediff", &["2024-03-05 12:00:00", "2023-01-01 00:00:00"]),
+            text("+0001-02-04 12:00:00.000")
+        );
+    }
+}
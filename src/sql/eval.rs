@@ -0,0 +1,510 @@
+use std::cmp::Ordering;
+
+use crate::error::{Error, Result};
+use crate::sql::ast::{BinaryOp, Expr, PatternOp, UnaryOp};
+use crate::sql::collation::{comparison_collation, expr_collation, Collation};
+use crate::sql::datetime;
+use crate::sql::pattern;
+use crate::sql::value::{Affinity, Value};
+
+/// A row as seen by the evaluator: column name and value pairs.
+pub type Row = [(String, Value)];
+
+pub fn eval(expr: &Expr, row: &Row) -> Result<Value> {
+    match expr {
+        Expr::Literal(value) => Ok(value.clone()),
+        // parameters are replaced by their values before the statement
+        // runs; one without a value is NULL
+        Expr::Parameter(_) => Ok(Value::Null),
+        Expr::Column(name) => match row.iter().find(|(col, _)| col.eq_ignore_ascii_case(name)) {
+            Some((_, value)) => Ok(value.clone()),
+            _ => Err(Error::NoSuchColumn(name.clone())),
+        },
+        Expr::Unary(op, expr) => Ok(eval_unary(*op, eval(expr, row)?)),
+        Expr::Binary(left, op, right) => {
+            let collation = comparison_collation(left, right)?;
+            eval_binary(eval(left, row)?, *op, eval(right, row)?, &collation)
+        }
+        Expr::Function { name, args, .. } => {
+            let args = args
+                .iter()
+                .map(|arg| eval(arg, row))
+                .collect::<Result<Vec<_>>>()?;
+            call_function(name, &args)
+        }
+        Expr::Pattern {
+            op,
+            negated,
+            expr,
+            pattern,
+            escape,
+        } => {
+            let escape = match escape {
+                Some(escape) => Some(eval(escape, row)?),
+                _ => None,
+            };
+            eval_pattern(*op, *negated, eval(expr, row)?, eval(pattern, row)?, escape)
+        }
+        Expr::InList {
+            expr,
+            list,
+            negated,
+        } => {
+            // only the left operand's collation counts for a list
+            let collation = expr_collation(expr, &[])?;
+            eval_in_list(eval(expr, row)?, list, *negated, row, &collation)
+        }
+        Expr::Between {
+            expr,
+            low,
+            high,
+            negated,
+        } => {
+            let value = eval(expr, row)?;
+            let (low_collation, high_collation) = (
+                comparison_collation(expr, low)?,
+                comparison_collation(expr, high)?,
+            );
+            let above = eval_binary(
+                value.clone(),
+                BinaryOp::GtEq,
+                eval(low, row)?,
+                &low_collation,
+            )?;
+            let below = eval_binary(value, BinaryOp::LtEq, eval(high, row)?, &high_collation)?;
+            let between = eval_binary(above, BinaryOp::And, below, &Collation::Binary)?;
+            Ok(match negated {
+                true => eval_unary(UnaryOp::Not, between),
+                _ => between,
+            })
+        }
+        Expr::Case {
+            operand,
+            branches,
+            else_expr,
+        } => {
+            let operand_value = match operand {
+                Some(operand) => Some(eval(operand, row)?),
+                _ => None,
+            };
+            // branches are evaluated lazily, up to the first match
+            for (when, then) in branches {
+                let when_value = eval(when, row)?;
+                let matched = match (operand, &operand_value) {
+                    (Some(operand), Some(value)) => {
+                        let collation = comparison_collation(operand, when)?;
+                        eval_binary(value.clone(), BinaryOp::Eq, when_value, &collation)?
+                    }
+                    _ => when_value,
+                };
+                if matched.as_bool() == Some(true) {
+                    return eval(then, row);
+                }
+            }
+            match else_expr {
+                Some(else_expr) => eval(else_expr, row),
+                _ => Ok(Value::Null),
+            }
+        }
+        Expr::Cast { expr, type_name } => {
+            Ok(eval(expr, row)?.cast(Affinity::from_type_name(type_name)))
+        }
+        Expr::Collate(expr, name) => {
+            Collation::from_name(name)?;
+            eval(expr, row)
+        }
+        Expr::Affinity(affinity, expr) => Ok(eval(expr, row)?.apply_affinity(*affinity)),
+    }
+}
+
+/// `value [NOT] IN (list)`: NULL when there is no match but either side of
+/// a comparison was NULL. An empty list never matches, even a NULL.
+fn eval_in_list(
+    value: Value,
+    list: &[Expr],
+    negated: bool,
+    row: &Row,
+    collation: &Collation,
+) -> Result<Value> {
+    let truth = |b: bool| Value::Integer((b != negated) as i64);
+    if list.is_empty() {
+        return Ok(truth(false));
+    }
+    if value.is_null() {
+        return Ok(Value::Null);
+    }
+    let mut saw_null = false;
+    for item in list {
+        match eval(item, row)? {
+            Value::Null => saw_null = true,
+            item if collation.compare(&value, &item) == Ordering::Equal => return Ok(truth(true)),
+            _ => {}
+        }
+    }
+    Ok(match saw_null {
+        true => Value::Null,
+        _ => truth(false),
+    })
+}
+
+/// Evaluates a select list expression that contains aggregate calls over the
+/// whole set of (already filtered) rows. Bare columns take their value from
+/// the last row, as in SQLite, or are NULL when there are no rows.
+pub fn eval_aggregate(expr: &Expr, rows: &[Vec<(String, Value)>]) -> Result<Value> {
+    let mut expr = expr.clone();
+    resolve_aggregates(&mut expr, rows)?;
+    eval(&expr, rows.last().map_or(&[], |row| row.as_slice()))
+}
+
+/// Replaces every aggregate call by a literal holding its value over `rows`.
+fn resolve_aggregates(expr: &mut Expr, rows: &[Vec<(String, Value)>]) -> Result<()> {
+    match expr {
+        Expr::Function { name, args, star } if Expr::is_aggregate_function(name) => {
+            let collation = match args.first() {
+                Some(arg) => expr_collation(arg, &[])?,
+                None => Collation::Binary,
+            };
+            let values = match (*star, args.first()) {
+                (true, _) | (false, None) => rows.iter().map(|_| Value::Integer(1)).collect(),
+                (false, Some(arg)) => rows
+                    .iter()
+                    .map(|row| eval(arg, row))
+                    .collect::<Result<Vec<_>>>()?,
+            };
+            *expr = Expr::Literal(aggregate(name, values, &collation));
+        }
+        Expr::Column(_) if rows.is_empty() => *expr = Expr::Literal(Value::Null),
+        _ => {
+            for child in expr.children_mut() {
+                resolve_aggregates(child, rows)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn aggregate(name: &str, values: Vec<Value>, collation: &Collation) -> Value {
+    let values = values.into_iter().filter(|v| !v.is_null());
+    match name {
+        "count" => Value::Integer(values.count() as i64),
+        "min" => values
+            .min_by(|a, b| collation.compare(a, b))
+            .unwrap_or(Value::Null),
+        "max" => values
+            .max_by(|a, b| collation.compare(a, b))
+            .unwrap_or(Value::Null),
+        "sum" | "total" | "avg" => {
+            let numbers = values.map(|v| v.to_numeric()).collect::<Vec<_>>();
+            let all_integers = numbers.iter().all(|v| matches!(v, Value::Integer(_)));
+            let total = numbers.iter().filter_map(Value::as_real).sum::<f64>();
+            match name {
+                "total" => Value::Real(total),
+                _ if numbers.is_empty() => Value::Null,
+                "avg" => Value::Real(total / numbers.len() as f64),
+                _ if all_integers => numbers
+                    .iter()
+                    .filter_map(Value::as_integer)
+                    .try_fold(0i64, |acc, i| acc.checked_add(i))
+                    .map(Value::Integer)
+                    .unwrap_or(Value::Real(total)),
+                _ => Value::Real(total),
+            }
+        }
+        _ => Value::Null,
+    }
+}
+
+fn eval_unary(op: UnaryOp, value: Value) -> Value {
+    match op {
+        UnaryOp::Plus => value,
+        UnaryOp::Negate => match value.to_numeric() {
+            Value::Integer(i) => match i.checked_neg() {
+                Some(i) => Value::Integer(i),
+                _ => Value::Real(-(i as f64)),
+            },
+            Value::Real(r) => Value::Real(-r),
+            other => other,
+        },
+        UnaryOp::Not => match value.as_bool() {
+            Some(b) => Value::Integer(!b as i64),
+            _ => Value::Null,
+        },
+    }
+}
+
+/// Applies a binary operator; comparisons of text use `collation`.
+fn eval_binary(left: Value, op: BinaryOp, right: Value, collation: &Collation) -> Result<Value> {
+    let truth = |b: bool| Value::Integer(b as i64);
+    match op {
+        BinaryOp::Is => return Ok(truth(is_same(&left, &right, collation))),
+        BinaryOp::IsNot => return Ok(truth(!is_same(&left, &right, collation))),
+        // three valued logic: FALSE AND NULL is FALSE, TRUE OR NULL is TRUE
+        BinaryOp::And => {
+            return Ok(match (left.as_bool(), right.as_bool()) {
+                (Some(false), _) | (_, Some(false)) => truth(false),
+                (Some(true), Some(true)) => truth(true),
+                _ => Value::Null,
+            })
+        }
+        BinaryOp::Or => {
+            return Ok(match (left.as_bool(), right.as_bool()) {
+                (Some(true), _) | (_, Some(true)) => truth(true),
+                (Some(false), Some(false)) => truth(false),
+                _ => Value::Null,
+            })
+        }
+        _ => {}
+    }
+    if left.is_null() || right.is_null() {
+        return Ok(Value::Null);
+    }
+    let compare = || collation.compare(&left, &right);
+    Ok(match op {
+        BinaryOp::Eq => truth(compare() == Ordering::Equal),
+        BinaryOp::NotEq => truth(compare() != Ordering::Equal),
+        BinaryOp::Lt => truth(compare() == Ordering::Less),
+        BinaryOp::LtEq => truth(compare() != Ordering::Greater),
+        BinaryOp::Gt => truth(compare() == Ordering::Greater),
+        BinaryOp::GtEq => truth(compare() != Ordering::Less),
+        BinaryOp::Concat => Value::Text(format!(
+            "{}{}",
+            left.as_text().unwrap_or_default(),
+            right.as_text().unwrap_or_default()
+        )),
+        BinaryOp::Add
+        | BinaryOp::Subtract
+        | BinaryOp::Multiply
+        | BinaryOp::Divide
+        | BinaryOp::Modulo => arithmetic(left.to_numeric(), op, right.to_numeric()),
+        BinaryOp::And | BinaryOp::Or | BinaryOp::Is | BinaryOp::IsNot => unreachable!(),
+    })
+}
+
+fn eval_pattern(
+    op: PatternOp,
+    negated: bool,
+    value: Value,
+    pattern: Value,
+    escape: Option<Value>,
+) -> Result<Value> {
+    let escape = match escape {
+        Some(Value::Null) => return Ok(Value::Null),
+        Some(escape) => {
+            let escape = escape.as_text().unwrap_or_default();
+            let mut chars = escape.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Some(c),
+                _ => {
+                    return Err(Error::Sql(
+                        "ESCAPE expression must be a single character".to_string(),
+                    ))
+                }
+            }
+        }
+        None => None,
+    };
+    let (value, pattern) = match (value.as_text(), pattern.as_text()) {
+        (Some(value), Some(pattern)) => (value, pattern),
+        _ => return Ok(Value::Null),
+    };
+    let matched = match op {
+        PatternOp::Like => pattern::like(&pattern, &value, escape),
+        PatternOp::Glob => pattern::glob(&pattern, &value),
+        PatternOp::Regexp => pattern::regexp(&pattern, &value)?,
+    };
+    Ok(Value::Integer((matched != negated) as i64))
+}
+
+fn is_same(left: &Value, right: &Value, collation: &Collation) -> bool {
+    match (left, right) {
+        (Value::Null, Value::Null) => true,
+        (Value::Null, _) | (_, Value::Null) => false,
+        _ => collation.compare(left, right) == Ordering::Equal,
+    }
+}
+
+fn arithmetic(left: Value, op: BinaryOp, right: Value) -> Value {
+    if let (Value::Integer(a), Value::Integer(b)) = (&left, &right) {
+        let (a, b) = (*a, *b);
+        let result = match op {
+            BinaryOp::Add => a.checked_add(b),
+            BinaryOp::Subtract => a.checked_sub(b),
+            BinaryOp::Multiply => a.checked_mul(b),
+            BinaryOp::Divide if b == 0 => return Value::Null,
+            BinaryOp::Divide => a.checked_div(b),
+            BinaryOp::Modulo if b == 0 => return Value::Null,
+            _ => Some(a.checked_rem(b).unwrap_or(0)),
+        };
+        if let Some(result) = result {
+            return Value::Integer(result);
+        }
+    }
+    let (a, b) = match (left.as_real(), right.as_real()) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return Value::Null,
+    };
+    match op {
+        BinaryOp::Add => Value::Real(a + b),
+        BinaryOp::Subtract => Value::Real(a - b),
+        BinaryOp::Multiply => Value::Real(a * b),
+        BinaryOp::Divide if b == 0.0 => Value::Null,
+        BinaryOp::Divide => Value::Real(a / b),
+        _ => {
+            // % works on the integer parts even when an operand is REAL
+            let (a, b) = (a as i64, b as i64);
+            match b {
+                0 => Value::Null,
+                _ => Value::Real(a.checked_rem(b).unwrap_or(0) as f64),
+            }
+        }
+    }
+}
+
+fn call_function(name: &str, args: &[Value]) -> Result<Value> {
+    match name {
+        "date" | "time" | "datetime" | "julianday" | "unixepoch" | "strftime" | "timediff" => {
+            datetime::call(name, args)
+        }
+        // like(X, Y) is `Y LIKE X`, with the optional escape as third argument
+        "like" | "glob" | "regexp" => {
+            let op = match name {
+                "like" => PatternOp::Like,
+                "glob" => PatternOp::Glob,
+                _ => PatternOp::Regexp,
+            };
+            match (args, op) {
+                ([pattern, value], _) => {
+                    eval_pattern(op, false, value.clone(), pattern.clone(), None)
+                }
+                ([pattern, value, escape], PatternOp::Like) => eval_pattern(
+                    op,
+                    false,
+                    value.clone(),
+                    pattern.clone(),
+                    Some(escape.clone()),
+                ),
+                _ => Err(Error::Sql(format!(
+                    "wrong number of arguments to function {name}()"
+                ))),
+            }
+        }
+        _ => Err(Error::Sql(format!("no such function: {name}"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_select;
+    use crate::sql::ast::ResultColumn;
+
+    fn eval_sql(expression: &str) -> Value {
+        let select = parse_select(&format!("SELECT {expression}")).expect("parse failed");
+        match &select.columns[0] {
+            ResultColumn::Expr { expr, .. } => eval(expr, &[]).expect("eval failed"),
+            _ => panic!("expected an expression"),
+        }
+    }
+
+    #[test]
+    fn test_eval_arithmetic() {
+        assert_eq!(eval_sql("1 + 2 * 3"), Value::Integer(7));
+        assert_eq!(eval_sql("7 / 2"), Value::Integer(3));
+        assert_eq!(eval_sql("7 / 2.0"), Value::Real(3.5));
+        assert_eq!(eval_sql("1 / 0"), Value::Null);
+        assert_eq!(eval_sql("'12abc' + 1"), Value::Integer(13));
+        assert_eq!(
+            eval_sql("9223372036854775807 + 1"),
+            Value::Real(9.223372036854776e18)
+        );
+        assert_eq!(eval_sql("'a' || 1 || NULL"), Value::Null);
+        assert_eq!(eval_sql("'a' || 1.5"), Value::Text("a1.5".into()));
+    }
+
+    #[test]
+    fn test_eval_logic_and_null() {
+        assert_eq!(eval_sql("NULL = NULL"), Value::Null);
+        assert_eq!(eval_sql("NULL IS NULL"), Value::Integer(1));
+        assert_eq!(eval_sql("1 IS NOT NULL"), Value::Integer(1));
+        assert_eq!(eval_sql("NULL AND 0"), Value::Integer(0));
+        assert_eq!(eval_sql("NULL OR 1"), Value::Integer(1));
+        assert_eq!(eval_sql("NOT NULL"), Value::Null);
+        assert_eq!(eval_sql("2 > 1 AND 'b' > 'a'"), Value::Integer(1));
+    }
+
+    #[test]
+    fn test_eval_patterns() {
+        assert_eq!(eval_sql("'Apple' LIKE 'a%'"), Value::Integer(1));
+        assert_eq!(eval_sql("'Apple' NOT LIKE 'a%'"), Value::Integer(0));
+        assert_eq!(eval_sql("'Apple' GLOB 'a*'"), Value::Integer(0));
+        assert_eq!(eval_sql("'10%' LIKE '10!%' ESCAPE '!'"), Value::Integer(1));
+        assert_eq!(eval_sql("NULL LIKE 'a%'"), Value::Null);
+        assert_eq!(eval_sql("123 LIKE '12_'"), Value::Integer(1));
+        assert_eq!(eval_sql("'abc' REGEXP 'b.$'"), Value::Integer(1));
+        assert_eq!(eval_sql("like('a%', 'abc')"), Value::Integer(1));
+        assert_eq!(eval_sql("glob('[0-9]*', '7up')"), Value::Integer(1));
+        let select = parse_select("SELECT 'a' LIKE 'a' ESCAPE 'xy'").unwrap();
+        match &select.columns[0] {
+            ResultColumn::Expr { expr, .. } => assert!(eval(expr, &[]).is_err()),
+            _ => panic!("expected an expression"),
+        }
+    }
+
+    #[test]
+    fn test_eval_in_between_case_cast() {
+        assert_eq!(eval_sql("2 IN (1, 2, 3)"), Value::Integer(1));
+        assert_eq!(eval_sql("3 IN (1, NULL)"), Value::Null);
+        assert_eq!(eval_sql("3 NOT IN (1, 2)"), Value::Integer(1));
+        assert_eq!(eval_sql("NULL NOT IN ()"), Value::Integer(1));
+        assert_eq!(eval_sql("5 BETWEEN 1 AND 10"), Value::Integer(1));
+        assert_eq!(eval_sql("5 NOT BETWEEN 6 AND NULL"), Value::Integer(1));
+        assert_eq!(eval_sql("'b' BETWEEN 'a' AND 'c' AND 1"), Value::Integer(1));
+        assert_eq!(
+            eval_sql("CASE 2 WHEN 1 THEN 'one' WHEN 2 THEN 'two' END"),
+            Value::Text("two".into())
+        );
+        assert_eq!(
+            eval_sql("CASE NULL WHEN NULL THEN 'x' ELSE 'y' END"),
+            Value::Text("y".into())
+        );
+        assert_eq!(eval_sql("CASE WHEN 0 THEN 1 END"), Value::Null);
+        // the branch that isn't taken is never evaluated
+        assert_eq!(
+            eval_sql("CASE WHEN 1 THEN 'ok' ELSE nosuchfunc() END"),
+            Value::Text("ok".into())
+        );
+        assert_eq!(eval_sql("CAST('12.7abc' AS INTEGER)"), Value::Integer(12));
+        assert_eq!(eval_sql("CAST('1e3' AS NUMERIC)"), Value::Integer(1000));
+        assert_eq!(
+            eval_sql("CAST(10 AS VARCHAR(5)) || 'x'"),
+            Value::Text("10x".into())
+        );
+        assert_eq!(eval_sql("CAST(1 AS DOUBLE PRECISION)"), Value::Real(1.0));
+    }
+
+    #[test]
+    fn test_eval_aggregate() {
+        let rows = (1..=4)
+            .map(|i| vec![("n".to_string(), Value::Integer(i))])
+            .collect::<Vec<_>>();
+        let select =
+            parse_select("SELECT count(*), sum(n), avg(n), max(n) - min(n) FROM t").unwrap();
+        let values = select
+            .columns
+            .iter()
+            .map(|c| match c {
+                ResultColumn::Expr { expr, .. } => eval_aggregate(expr, &rows).unwrap(),
+                _ => panic!("expected an expression"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            vec![
+                Value::Integer(4),
+                Value::Integer(10),
+                Value::Real(2.5),
+                Value::Integer(3)
+            ]
+        );
+    }
+}
@@ -0,0 +1,10 @@
+pub mod affinity;
+pub mod ast;
+pub mod collation;
+pub mod datetime;
+pub mod eval;
+pub mod parameter;
+pub mod pattern;
+pub mod planner;
+pub mod schema;
+pub mod value;
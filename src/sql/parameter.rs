@@ -0,0 +1,102 @@
+//! Numbering of the parameters of a statement and substitution of the
+//! values bound to them.
+//!
+//! As in SQLite, `?NNN` is parameter NNN, `?` is one more than the largest
+//! number so far, and a name gets the next number the first time it appears
+//! and the same one after that. Parameters without a bound value are NULL.
+
+use crate::sql::ast::{Expr, Parameter};
+use crate::sql::value::Value;
+
+/// Replaces every parameter of `expr` by its number. `names` holds the name
+/// of each parameter so far, None for unnamed ones, and is extended with
+/// the new ones.
+pub fn number_parameters(expr: &mut Expr, names: &mut Vec<Option<String>>) {
+    if let Expr::Parameter(parameter) = expr {
+        let index = match parameter {
+            Parameter::Anonymous => names.len() + 1,
+            Parameter::Numbered(index) => *index,
+            Parameter::Named(name) => names
+                .iter()
+                .position(|n| n.as_ref() == Some(name))
+                .map_or(names.len() + 1, |i| i + 1),
+        };
+        if names.len() < index {
+            names.resize(index, None);
+        }
+        if let Parameter::Named(name) = parameter {
+            names[index - 1] = Some(name.clone());
+        }
+        *parameter = Parameter::Numbered(index);
+    }
+    for child in expr.children_mut() {
+        number_parameters(child, names);
+    }
+}
+
+/// Replaces the numbered parameters of `expr` by the values bound to them,
+/// `values[0]` being parameter 1.
+pub fn substitute_parameters(expr: &mut Expr, values: &[Value]) {
+    if let Expr::Parameter(Parameter::Numbered(index)) = expr {
+        let value = values.get(*index - 1).cloned().unwrap_or_default();
+        *expr = Expr::Literal(value);
+        return;
+    }
+    for child in expr.children_mut() {
+        substitute_parameters(child, values);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_select;
+    use crate::sql::ast::BinaryOp;
+
+    fn numbered(sql: &str) -> (Vec<Expr>, Vec<Option<String>>) {
+        let mut select = parse_select(sql).unwrap();
+        let mut names = vec![];
+        for expr in select.exprs_mut() {
+            number_parameters(expr, &mut names);
+        }
+        (select.exprs_mut().map(|e| e.clone()).collect(), names)
+    }
+
+    #[test]
+    fn test_number_parameters() {
+        let name = |n: &str| Some(n.to_string());
+        let (_, names) = numbered("SELECT ?, :a, ?, @b, :a, ?");
+        assert_eq!(names, [None, name(":a"), None, name("@b"), None]);
+
+        // `?` continues after the largest number so far
+        let (_, names) = numbered("SELECT ?3, ?, $x WHERE ?1 = :y ORDER BY ?5");
+        assert_eq!(names, [None, None, None, None, name("$x"), name(":y")]);
+
+        // a numbered parameter can share the number of a named one
+        let (exprs, names) = numbered("SELECT :a + ?1 + :a");
+        assert_eq!(names, [name(":a")]);
+        let parameter = Expr::Parameter(Parameter::Numbered(1));
+        assert_eq!(
+            exprs[0],
+            Expr::binary(
+                Expr::binary(parameter.clone(), BinaryOp::Add, parameter.clone()),
+                BinaryOp::Add,
+                parameter
+            )
+        );
+    }
+
+    #[test]
+    fn test_substitute_parameters() {
+        let (mut exprs, _) = numbered("SELECT ? || ?3");
+        substitute_parameters(&mut exprs[0], &[Value::Text("a".into())]);
+        assert_eq!(
+            exprs[0],
+            Expr::binary(
+                Expr::Literal(Value::Text("a".into())),
+                BinaryOp::Concat,
+                Expr::Literal(Value::Null)
+            )
+        );
+    }
+}
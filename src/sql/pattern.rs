@@ -0,0 +1,216 @@
+//! Pattern matching used by the `LIKE`, `GLOB` and `REGEXP` operators.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use regex::Regex;
+
+use crate::error::{Error, Result};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// `%` in LIKE, `*` in GLOB
+    Any,
+    /// `_` in LIKE, `?` in GLOB
+    One,
+    /// `[...]` in GLOB: ranges of characters and whether the set is negated
+    Class(Vec<(char, char)>, bool),
+    Literal(char),
+}
+
+impl Token {
+    fn matches(&self, c: char, no_case: bool) -> bool {
+        match self {
+            Token::Any | Token::One => true,
+            Token::Class(ranges, negated) => {
+                ranges.iter().any(|(low, high)| (*low..=*high).contains(&c)) != *negated
+            }
+            Token::Literal(l) if no_case => l.eq_ignore_ascii_case(&c),
+            Token::Literal(l) => *l == c,
+        }
+    }
+}
+
+/// Splits a LIKE pattern into tokens. `None` means the pattern can't match
+/// anything (it ends with a dangling escape character).
+fn like_tokens(pattern: &str, escape: Option<char>) -> Option<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        tokens.push(match c {
+            c if Some(c) == escape => Token::Literal(chars.next()?),
+            '%' => Token::Any,
+            '_' => Token::One,
+            c => Token::Literal(c),
+        });
+    }
+    Some(tokens)
+}
+
+/// Splits a GLOB pattern into tokens. `None` means the pattern has an
+/// unterminated `[` class and can't match anything.
+fn glob_tokens(pattern: &str) -> Option<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        tokens.push(match c {
+            '*' => Token::Any,
+            '?' => Token::One,
+            '[' => {
+                let negated = chars.next_if_eq(&'^').is_some();
+                let mut ranges = vec![];
+                // a ']' right after the opening bracket is a literal
+                if let Some(c) = chars.next_if_eq(&']') {
+                    ranges.push((c, c));
+                }
+                loop {
+                    match chars.next()? {
+                        ']' => break,
+                        low => match (chars.peek(), ranges.is_empty()) {
+                            (Some('-'), _) => {
+                                chars.next();
+                                match chars.next()? {
+                                    ']' => {
+                                        ranges.push((low, low));
+                                        ranges.push(('-', '-'));
+                                        break;
+                                    }
+                                    high => ranges.push((low, high)),
+                                }
+                            }
+                            _ => ranges.push((low, low)),
+                        },
+                    }
+                }
+                Token::Class(ranges, negated)
+            }
+            c => Token::Literal(c),
+        });
+    }
+    Some(tokens)
+}
+
+/// Wildcard matching with backtracking to the most recent `Any` token.
+fn tokens_match(tokens: &[Token], text: &str, no_case: bool) -> bool {
+    let text = text.chars().collect::<Vec<_>>();
+    let (mut t, mut p) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match tokens.get(p) {
+            Some(Token::Any) => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(token) if token.matches(text[t], no_case) => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    tokens[p..].iter().all(|token| *token == Token::Any)
+}
+
+/// `text LIKE pattern ESCAPE escape`: case insensitive for ASCII letters.
+pub fn like(pattern: &str, text: &str, escape: Option<char>) -> bool {
+    like_tokens(pattern, escape).is_some_and(|tokens| tokens_match(&tokens, text, true))
+}
+
+/// `text GLOB pattern`: case sensitive, with `*`, `?` and `[...]` classes.
+pub fn glob(pattern: &str, text: &str) -> bool {
+    glob_tokens(pattern).is_some_and(|tokens| tokens_match(&tokens, text, false))
+}
+
+thread_local! {
+    static REGEX_CACHE: RefCell<HashMap<String, Regex>> = RefCell::new(HashMap::new());
+}
+
+/// `text REGEXP pattern`: true when the regular expression matches anywhere
+/// in `text`. Compiled expressions are cached since the pattern is usually
+/// the same for every row.
+pub fn regexp(pattern: &str, text: &str) -> Result<bool> {
+    REGEX_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if !cache.contains_key(pattern) {
+            let regex = Regex::new(pattern)
+                .map_err(|e| Error::Sql(format!("invalid regular expression: {e}")))?;
+            cache.insert(pattern.to_string(), regex);
+        }
+        Ok(cache[pattern].is_match(text))
+    })
+}
+
+/// The literal text every match of the pattern must start with, if any.
+pub fn like_prefix(pattern: &str, escape: Option<char>) -> String {
+    literal_prefix(like_tokens(pattern, escape).unwrap_or_default())
+}
+
+pub fn glob_prefix(pattern: &str) -> String {
+    literal_prefix(glob_tokens(pattern).unwrap_or_default())
+}
+
+fn literal_prefix(tokens: Vec<Token>) -> String {
+    tokens
+        .into_iter()
+        .map_while(|token| match token {
+            Token::Literal(c) => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_like() {
+        assert!(like("a%", "Apple", None));
+        assert!(like("%PLE", "apple", None));
+        assert!(like("a_p%e", "apple", None));
+        assert!(!like("a_p", "apple", None));
+        assert!(like("%", "", None));
+        assert!(like("%a%b%", "xxaxxbxx", None));
+        assert!(!like("%a%b", "xxaxxbxx", None));
+        assert!(!like("100\\%", "100%", None));
+        assert!(!like("ä%", "Äpfel", None));
+    }
+
+    #[test]
+    fn test_like_escape() {
+        assert!(like("10!%", "10%", Some('!')));
+        assert!(!like("10!%", "100", Some('!')));
+        assert!(like("a!_b", "a_b", Some('!')));
+        assert!(!like("abc!", "abc", Some('!')));
+    }
+
+    #[test]
+    fn test_glob() {
+        assert!(glob("a*", "apple"));
+        assert!(!glob("a*", "Apple"));
+        assert!(glob("?pple", "apple"));
+        assert!(glob("[a-c]*", "banana"));
+        assert!(!glob("[^a-c]*", "banana"));
+        assert!(glob("[]x]", "]"));
+        assert!(glob("[a-]", "-"));
+        assert!(!glob("[abc", "a"));
+        assert!(glob("*[0-9]", "room 7"));
+    }
+
+    #[test]
+    fn test_regexp_and_prefix() {
+        assert!(regexp("^[a-z]+@example\\.com$", "bob@example.com").unwrap());
+        assert!(regexp("an", "banana").unwrap());
+        assert!(regexp("(", "x").is_err());
+        assert_eq!(like_prefix("abc%d_", None), "abc");
+        assert_eq!(like_prefix("a!%b%", Some('!')), "a%b");
+        assert_eq!(glob_prefix("ab[cd]*"), "ab");
+    }
+}
@@ -0,0 +1,518 @@
+//! Chooses how the rows of a table are read: a full scan of the table B-tree,
+//! a lookup of the rowid an AND-ed term of the WHERE clause sets, or else a
+//! range scan over an index whose first column is constrained by one of the
+//! terms. The WHERE clause is still evaluated
+//! against every row returned, so a range only has to contain all matches.
+//! A comparison only uses an index built with the collation it compares by.
+
+use std::cmp::Ordering;
+use std::ops::Bound;
+
+use crate::db::record::TextEncoding;
+use crate::sql::ast::{BinaryOp, Expr, PatternOp};
+use crate::sql::collation::{self, comparison_collation, Collation};
+use crate::sql::eval::eval;
+use crate::sql::pattern;
+use crate::sql::value::{Affinity, Value};
+
+/// An index as declared by its `CREATE INDEX` statement: its name, the
+/// indexed columns with the collation the keys are sorted by, and whether
+/// they're sorted by the first column in descending order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexInfo {
+    pub name: String,
+    pub columns: Vec<(String, Collation)>,
+    pub descending: bool,
+}
+
+/// Bounds on the first column of an index key, compared with the collation
+/// of that column in the index. In a descending index the keys above the
+/// range come first, so they're the ones before it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyRange {
+    pub lower: Bound<Value>,
+    pub upper: Bound<Value>,
+    pub collation: Collation,
+    pub descending: bool,
+}
+
+impl KeyRange {
+    /// Whether `key` comes before the start of the range in the index.
+    pub fn is_before(&self, key: &Value) -> bool {
+        match self.descending {
+            true => self.is_above(key),
+            _ => self.is_below(key),
+        }
+    }
+
+    /// Whether `key` comes after the end of the range in the index.
+    pub fn is_after(&self, key: &Value) -> bool {
+        match self.descending {
+            true => self.is_below(key),
+            _ => self.is_above(key),
+        }
+    }
+
+    pub fn contains(&self, key: &Value) -> bool {
+        !self.is_below(key) && !self.is_above(key)
+    }
+
+    fn is_below(&self, key: &Value) -> bool {
+        match &self.lower {
+            Bound::Included(lower) => self.collation.compare(key, lower) == Ordering::Less,
+            Bound::Excluded(lower) => self.collation.compare(key, lower) != Ordering::Greater,
+            Bound::Unbounded => false,
+        }
+    }
+
+    fn is_above(&self, key: &Value) -> bool {
+        match &self.upper {
+            Bound::Included(upper) => self.collation.compare(key, upper) == Ordering::Greater,
+            Bound::Excluded(upper) => self.collation.compare(key, upper) != Ordering::Less,
+            Bound::Unbounded => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScanPlan {
+    FullScan,
+    Rowid(i64),
+    IndexRange { index: String, range: KeyRange },
+}
+
+/// Picks the scan for a table given its columns (name and affinity), the
+/// column that is an alias for the rowid if any, and its indexes. A rowid
+/// is preferred over an index, and an equality term over a range.
+pub fn plan_scan(
+    columns: &[(String, Affinity)],
+    rowid_alias: Option<&str>,
+    indexes: &[IndexInfo],
+    condition: Option<&Expr>,
+) -> ScanPlan {
+    let mut terms = vec![];
+    if let Some(condition) = condition {
+        conjuncts(condition, &mut terms);
+    }
+    if let Some(rowid) = terms.iter().find_map(|term| rowid_term(term, rowid_alias?)) {
+        return ScanPlan::Rowid(rowid);
+    }
+    let candidates = terms
+        .into_iter()
+        .filter_map(|term| index_term(term, columns))
+        .collect::<Vec<_>>();
+
+    let mut best: Option<(bool, ScanPlan)> = None;
+    for (column, collation, range) in candidates {
+        let is_equality = matches!(
+            (&range.lower, &range.upper),
+            (Bound::Included(a), Bound::Included(b)) if a == b
+        );
+        let index = indexes
+            .iter()
+            .find_map(|index| match index.columns.first() {
+                Some((first, index_collation))
+                    if first.eq_ignore_ascii_case(&column)
+                        && collation.as_ref().map_or(
+                            matches!(index_collation, Collation::Binary | Collation::NoCase),
+                            |collation| collation == index_collation,
+                        ) =>
+                {
+                    Some((index, index_collation))
+                }
+                _ => None,
+            });
+        if let Some((index, index_collation)) = index {
+            if best.as_ref().map_or(true, |(eq, _)| is_equality && !eq) {
+                let plan = ScanPlan::IndexRange {
+                    index: index.name.clone(),
+                    range: KeyRange {
+                        collation: index_collation.clone(),
+                        descending: index.descending,
+                        ..range
+                    },
+                };
+                best = Some((is_equality, plan));
+            }
+        }
+    }
+    best.map(|(_, plan)| plan).unwrap_or(ScanPlan::FullScan)
+}
+
+fn conjuncts<'a>(expr: &'a Expr, terms: &mut Vec<&'a Expr>) {
+    match expr {
+        Expr::Binary(left, BinaryOp::And, right) => {
+            conjuncts(left, terms);
+            conjuncts(right, terms);
+        }
+        _ => terms.push(expr),
+    }
+}
+
+/// Value of an expression that is the same for every row.
+fn constant(expr: &Expr) -> Option<Value> {
+    match expr.has_column() || expr.is_aggregate() {
+        true => None,
+        _ => eval(expr, &[]).ok().filter(|value| !value.is_null()),
+    }
+}
+
+/// The rowid a term `alias = value` sets, when the value is an integer once
+/// converted to the alias's INTEGER affinity.
+fn rowid_term(term: &Expr, alias: &str) -> Option<i64> {
+    let Expr::Binary(left, BinaryOp::Eq, right) = term else {
+        return None;
+    };
+    let value = match (column_of(left), column_of(right)) {
+        (Some(column), _) if column.eq_ignore_ascii_case(alias) => constant(right)?,
+        (_, Some(column)) if column.eq_ignore_ascii_case(alias) => constant(left)?,
+        _ => return None,
+    };
+    match value.apply_affinity(Affinity::Integer) {
+        Value::Integer(rowid) => Some(rowid),
+        _ => None,
+    }
+}
+
+/// The column a term constrains, the collation an index on it must use
+/// (`None` when either BINARY or NOCASE will do) and the range of values the
+/// term allows.
+fn index_term(
+    term: &Expr,
+    columns: &[(String, Affinity)],
+) -> Option<(String, Option<Collation>, KeyRange)> {
+    let affinity = |name: &str| {
+        columns
+            .iter()
+            .find(|(column, _)| column.eq_ignore_ascii_case(name))
+            .map(|(_, affinity)| *affinity)
+    };
+    match term {
+        Expr::Binary(left, op, right) => {
+            let collation = comparison_collation(left, right).ok()?;
+            let (column, op, value) = match (column_of(left), column_of(right)) {
+                (Some(column), _) => (column, *op, constant(right)?),
+                (_, Some(column)) => (column, reverse(*op)?, constant(left)?),
+                _ => return None,
+            };
+            affinity(column)?;
+            let null = Bound::Excluded(Value::Null);
+            let (lower, upper) = match op {
+                BinaryOp::Eq => (Bound::Included(value.clone()), Bound::Included(value)),
+                BinaryOp::Lt => (null, Bound::Excluded(value)),
+                BinaryOp::LtEq => (null, Bound::Included(value)),
+                BinaryOp::Gt => (Bound::Excluded(value), Bound::Unbounded),
+                BinaryOp::GtEq => (Bound::Included(value), Bound::Unbounded),
+                _ => return None,
+            };
+            let range = KeyRange {
+                lower,
+                upper,
+                collation: Collation::Binary,
+                descending: false,
+            };
+            Some((column.clone(), Some(collation), range))
+        }
+        Expr::Between {
+            expr,
+            low,
+            high,
+            negated: false,
+        } => {
+            let column = column_of(expr)?;
+            affinity(column)?;
+            let collation = comparison_collation(expr, low).ok()?;
+            if comparison_collation(expr, high).ok()? != collation {
+                return None;
+            }
+            let range = KeyRange {
+                lower: Bound::Included(constant(low)?),
+                upper: Bound::Included(constant(high)?),
+                collation: Collation::Binary,
+                descending: false,
+            };
+            Some((column.clone(), Some(collation), range))
+        }
+        Expr::Pattern {
+            op,
+            negated: false,
+            expr,
+            pattern,
+            escape,
+        } => {
+            let column = column_of(expr)?;
+            // numbers stored in the column would sort before any text bound
+            if affinity(column)? != Affinity::Text {
+                return None;
+            }
+            let pattern = constant(pattern)?.as_text()?;
+            let range = match op {
+                PatternOp::Like => {
+                    let escape = match escape {
+                        Some(escape) => Some(single_char(&constant(escape)?.as_text()?)?),
+                        None => None,
+                    };
+                    let prefix = pattern::like_prefix(&pattern, escape);
+                    // every ASCII case variant of the prefix sorts between
+                    // its all upper case and all lower case spellings
+                    prefix_range(prefix.to_ascii_uppercase(), prefix.to_ascii_lowercase())?
+                }
+                PatternOp::Glob => {
+                    let prefix = pattern::glob_prefix(&pattern);
+                    prefix_range(prefix.clone(), prefix)?
+                }
+                PatternOp::Regexp => return None,
+            };
+            // the range holds every match under both BINARY and NOCASE
+            Some((column.clone(), None, range))
+        }
+        _ => None,
+    }
+}
+
+/// The column an operand reads, possibly with a `COLLATE` clause.
+fn column_of(expr: &Expr) -> Option<&String> {
+    match expr {
+        Expr::Column(column) => Some(column),
+        Expr::Collate(expr, _) => column_of(expr),
+        _ => None,
+    }
+}
+
+/// The operator to use once the operands of a comparison are swapped.
+fn reverse(op: BinaryOp) -> Option<BinaryOp> {
+    match op {
+        BinaryOp::Eq => Some(BinaryOp::Eq),
+        BinaryOp::Lt => Some(BinaryOp::Gt),
+        BinaryOp::LtEq => Some(BinaryOp::GtEq),
+        BinaryOp::Gt => Some(BinaryOp::Lt),
+        BinaryOp::GtEq => Some(BinaryOp::LtEq),
+        _ => None,
+    }
+}
+
+fn single_char(text: &str) -> Option<char> {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+/// Range of text values starting with a prefix between `low` and `high`.
+fn prefix_range(low: String, high: String) -> Option<KeyRange> {
+    if low.is_empty() {
+        return None;
+    }
+    let upper = match successor(&high) {
+        Some(next) => Bound::Excluded(Value::Text(next)),
+        None => Bound::Unbounded,
+    };
+    Some(KeyRange {
+        lower: Bound::Included(Value::Text(low)),
+        upper,
+        collation: Collation::Binary,
+        descending: false,
+    })
+}
+
+/// The smallest string greater than every string starting with `prefix`.
+/// In UTF-16 databases the next character can encode to smaller bytes past
+/// ASCII, so such a last character is dropped to widen the range instead.
+fn successor(prefix: &str) -> Option<String> {
+    let utf16 = collation::text_encoding() != TextEncoding::Utf8;
+    let mut chars = prefix.chars().collect::<Vec<_>>();
+    while let Some(last) = chars.pop() {
+        if utf16 && !last.is_ascii() {
+            continue;
+        }
+        let next = (last as u32 + 1..=char::MAX as u32).find_map(char::from_u32);
+        if let Some(next) = next {
+            chars.push(next);
+            return Some(chars.into_iter().collect());
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_select;
+
+    fn plan(sql: &str) -> ScanPlan {
+        let columns = vec![
+            ("id".to_string(), Affinity::Integer),
+            ("name".to_string(), Affinity::Text),
+            ("country".to_string(), Affinity::Text),
+        ];
+        let indexes = vec![
+            IndexInfo {
+                name: "idx_name".into(),
+                columns: vec![("name".into(), Collation::Binary)],
+                descending: false,
+            },
+            IndexInfo {
+                name: "idx_country".into(),
+                columns: vec![
+                    ("country".into(), Collation::NoCase),
+                    ("name".into(), Collation::Binary),
+                ],
+                descending: false,
+            },
+        ];
+        let select = parse_select(sql).unwrap();
+        plan_scan(&columns, None, &indexes, select.where_clause.as_ref())
+    }
+
+    fn text(s: &str) -> Value {
+        Value::Text(s.into())
+    }
+
+    #[test]
+    fn test_plan_equality_and_range() {
+        assert_eq!(plan("SELECT * FROM t WHERE id = 1"), ScanPlan::FullScan);
+        assert_eq!(
+            plan("SELECT * FROM t WHERE name > 'b' AND 'fr' = country COLLATE NOCASE"),
+            ScanPlan::IndexRange {
+                index: "idx_country".into(),
+                range: KeyRange {
+                    lower: Bound::Included(text("fr")),
+                    upper: Bound::Included(text("fr")),
+                    collation: Collation::NoCase,
+                    descending: false,
+                },
+            }
+        );
+        assert_eq!(
+            plan("SELECT * FROM t WHERE 'm' > name OR id = 1"),
+            ScanPlan::FullScan
+        );
+        // idx_country is sorted with NOCASE
+        assert_eq!(
+            plan("SELECT * FROM t WHERE country = 'fr'"),
+            ScanPlan::FullScan
+        );
+        assert_eq!(
+            plan("SELECT * FROM t WHERE name BETWEEN 'a' AND 'c'"),
+            ScanPlan::IndexRange {
+                index: "idx_name".into(),
+                range: KeyRange {
+                    lower: Bound::Included(text("a")),
+                    upper: Bound::Included(text("c")),
+                    collation: Collation::Binary,
+                    descending: false,
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_plan_rowid() {
+        let columns = vec![("id".to_string(), Affinity::Integer)];
+        let indexes = vec![IndexInfo {
+            name: "idx_id".into(),
+            columns: vec![("id".into(), Collation::Binary)],
+            descending: false,
+        }];
+        let plan = |sql: &str| {
+            let select = parse_select(sql).unwrap();
+            plan_scan(&columns, Some("id"), &indexes, select.where_clause.as_ref())
+        };
+        assert_eq!(plan("SELECT * FROM t WHERE '7' = id"), ScanPlan::Rowid(7));
+        assert_eq!(
+            plan("SELECT * FROM t WHERE id > 1 AND ID = 2"),
+            ScanPlan::Rowid(2)
+        );
+        assert!(matches!(
+            plan("SELECT * FROM t WHERE id = 2.5"),
+            ScanPlan::IndexRange { .. }
+        ));
+        assert_eq!(
+            plan("SELECT * FROM t WHERE id = 1 OR id = 2"),
+            ScanPlan::FullScan
+        );
+    }
+
+    #[test]
+    fn test_plan_prefix() {
+        assert_eq!(
+            plan("SELECT * FROM t WHERE name GLOB 'ab*'"),
+            ScanPlan::IndexRange {
+                index: "idx_name".into(),
+                range: KeyRange {
+                    lower: Bound::Included(text("ab")),
+                    upper: Bound::Excluded(text("ac")),
+                    collation: Collation::Binary,
+                    descending: false,
+                },
+            }
+        );
+        assert_eq!(
+            plan("SELECT * FROM t WHERE name LIKE 'Ab!_%' ESCAPE '!'"),
+            ScanPlan::IndexRange {
+                index: "idx_name".into(),
+                range: KeyRange {
+                    lower: Bound::Included(text("AB_")),
+                    upper: Bound::Excluded(text("ab`")),
+                    collation: Collation::Binary,
+                    descending: false,
+                },
+            }
+        );
+        assert_eq!(
+            plan("SELECT * FROM t WHERE name LIKE '%b'"),
+            ScanPlan::FullScan
+        );
+        assert_eq!(
+            plan("SELECT * FROM t WHERE name NOT LIKE 'a%'"),
+            ScanPlan::FullScan
+        );
+        assert_eq!(
+            plan("SELECT * FROM t WHERE country GLOB 'F*'"),
+            ScanPlan::IndexRange {
+                index: "idx_country".into(),
+                range: KeyRange {
+                    lower: Bound::Included(text("F")),
+                    upper: Bound::Excluded(text("G")),
+                    collation: Collation::NoCase,
+                    descending: false,
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_key_range() {
+        let range = KeyRange {
+            lower: Bound::Excluded(Value::Null),
+            upper: Bound::Excluded(Value::Integer(5)),
+            collation: Collation::Binary,
+            descending: false,
+        };
+        assert!(range.is_before(&Value::Null));
+        assert!(range.contains(&Value::Real(4.5)));
+        assert!(range.is_after(&Value::Integer(5)));
+        assert!(range.is_after(&text("1")));
+
+        let range = KeyRange {
+            lower: Bound::Included(text("b")),
+            upper: Bound::Excluded(text("c")),
+            collation: Collation::NoCase,
+            descending: false,
+        };
+        assert!(range.contains(&text("BOB")));
+        assert!(range.is_after(&text("C")));
+
+        // a descending index meets the keys above the range first
+        let range = KeyRange {
+            lower: Bound::Excluded(Value::Null),
+            upper: Bound::Included(Value::Integer(5)),
+            collation: Collation::Binary,
+            descending: true,
+        };
+        assert!(range.is_before(&Value::Integer(6)));
+        assert!(range.contains(&Value::Integer(5)));
+        assert!(range.is_after(&Value::Null));
+    }
+}
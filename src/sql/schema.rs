@@ -0,0 +1,398 @@
+//! Table definitions as read from the `CREATE TABLE` statements stored in
+//! the schema table.
+
+use crate::error::Result;
+use crate::sql::ast::Expr;
+use crate::sql::collation::Collation;
+use crate::sql::eval::eval;
+use crate::sql::value::{Affinity, Value};
+
+/// A row of the schema table: a table, index, view or trigger.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaEntry {
+    kind: String,
+    name: String,
+    table_name: String,
+    sql: Option<String>,
+}
+
+impl SchemaEntry {
+    pub(crate) fn new(kind: String, name: String, table_name: String, sql: Option<String>) -> Self {
+        SchemaEntry {
+            kind,
+            name,
+            table_name,
+            sql,
+        }
+    }
+
+    /// `table`, `index`, `view` or `trigger`.
+    pub fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The table an index or trigger belongs to; tables and views have
+    /// their own name.
+    pub fn table_name(&self) -> &str {
+        &self.table_name
+    }
+
+    /// The `CREATE` statement as written; None for the indexes backing
+    /// `UNIQUE` and `PRIMARY KEY` constraints.
+    pub fn sql(&self) -> Option<&str> {
+        self.sql.as_deref()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableSchema {
+    pub name: String,
+    pub columns: Vec<ColumnDef>,
+    pub constraints: Vec<TableConstraint>,
+    pub without_rowid: bool,
+    pub strict: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnDef {
+    pub name: String,
+    /// The declared type as written, e.g. `VARCHAR(255)`; empty when none.
+    pub type_name: String,
+    pub constraints: Vec<ColumnConstraint>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnConstraint {
+    PrimaryKey {
+        descending: bool,
+        autoincrement: bool,
+    },
+    NotNull,
+    Unique,
+    Check(Expr),
+    Default(Expr),
+    Collate(String),
+    References(ForeignKey),
+    /// `[GENERATED ALWAYS] AS (expr) [STORED | VIRTUAL]`
+    Generated {
+        expr: Expr,
+        stored: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableConstraint {
+    PrimaryKey(Vec<IndexedColumn>),
+    Unique(Vec<IndexedColumn>),
+    Check(Expr),
+    ForeignKey {
+        columns: Vec<String>,
+        references: ForeignKey,
+    },
+}
+
+/// A column of a `PRIMARY KEY` or `UNIQUE` table constraint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexedColumn {
+    pub name: String,
+    pub collation: Option<String>,
+    pub descending: bool,
+}
+
+/// `REFERENCES table (columns)` with its `ON DELETE` / `ON UPDATE` actions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForeignKey {
+    pub table: String,
+    pub columns: Vec<String>,
+    pub on_delete: Option<String>,
+    pub on_update: Option<String>,
+}
+
+impl TableSchema {
+    /// The schema table itself, which has no `CREATE TABLE` statement.
+    pub fn sqlite_schema() -> TableSchema {
+        let column = |name: &str, type_name: &str| ColumnDef {
+            name: name.into(),
+            type_name: type_name.into(),
+            constraints: vec![],
+        };
+        TableSchema {
+            name: "sqlite_schema".into(),
+            columns: vec![
+                column("type", "text"),
+                column("name", "text"),
+                column("tbl_name", "text"),
+                column("rootpage", "integer"),
+                column("sql", "text"),
+            ],
+            constraints: vec![],
+            without_rowid: false,
+            strict: false,
+        }
+    }
+
+    pub fn column_names(&self) -> Vec<String> {
+        self.columns.iter().map(|c| c.name.clone()).collect()
+    }
+
+    /// Columns of the primary key, declared on a column or as a table
+    /// constraint; empty when the table has none.
+    pub fn primary_key(&self) -> Vec<IndexedColumn> {
+        let column_key = self.columns.iter().find_map(|c| {
+            c.constraints
+                .iter()
+                .find_map(|constraint| match constraint {
+                    ColumnConstraint::PrimaryKey { descending, .. } => Some(IndexedColumn {
+                        name: c.name.clone(),
+                        collation: None,
+                        descending: *descending,
+                    }),
+                    _ => None,
+                })
+        });
+        match column_key {
+            Some(column) => vec![column],
+            None => self
+                .constraints
+                .iter()
+                .find_map(|c| match c {
+                    TableConstraint::PrimaryKey(columns) => Some(columns.clone()),
+                    _ => None,
+                })
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Position of the column that is an alias for the rowid: the only
+    /// primary key column of a rowid table, declared with type `INTEGER`.
+    /// `INTEGER PRIMARY KEY DESC` on the column itself is not an alias.
+    pub fn rowid_alias(&self) -> Option<usize> {
+        if self.without_rowid {
+            return None;
+        }
+        let key = match self.primary_key().as_slice() {
+            [key] => key.clone(),
+            _ => return None,
+        };
+        let position = self
+            .columns
+            .iter()
+            .position(|c| c.name.eq_ignore_ascii_case(&key.name))?;
+        let column = &self.columns[position];
+        let column_key_descending = column.constraints.iter().any(|c| {
+            matches!(
+                c,
+                ColumnConstraint::PrimaryKey {
+                    descending: true,
+                    ..
+                }
+            )
+        });
+        match column.type_name.eq_ignore_ascii_case("INTEGER") && !column_key_descending {
+            true => Some(position),
+            _ => None,
+        }
+    }
+
+    /// Column names in the order their values are stored in a record. A
+    /// `WITHOUT ROWID` table is an index on its primary key, so the key
+    /// columns come first, followed by the others in declaration order.
+    /// VIRTUAL generated columns aren't stored.
+    pub fn record_columns(&self) -> Vec<String> {
+        if !self.without_rowid {
+            let stored = self.columns.iter().filter(|c| c.virtual_expr().is_none());
+            return stored.map(|c| c.name.clone()).collect();
+        }
+        let mut columns = vec![];
+        for key in self.primary_key() {
+            if let Some(c) = self.column(&key.name) {
+                if !columns.contains(&c.name) {
+                    columns.push(c.name.clone());
+                }
+            }
+        }
+        for c in self.columns.iter() {
+            if !columns.contains(&c.name) && c.virtual_expr().is_none() {
+                columns.push(c.name.clone());
+            }
+        }
+        columns
+    }
+
+    /// Sets the values of the VIRTUAL generated columns of `row`, a row of
+    /// the table with the stored values read. One may use another, so each
+    /// pass computes at least one more of them correctly.
+    pub fn compute_virtual_columns(&self, row: &mut [(String, Value)]) -> Result<()> {
+        let generated = self
+            .columns
+            .iter()
+            .enumerate()
+            .filter_map(|(i, c)| Some((i, c, c.virtual_expr()?)))
+            .collect::<Vec<_>>();
+        for _ in 0..generated.len() {
+            for (i, column, expr) in &generated {
+                row[*i].1 = eval(expr, row)?.apply_affinity(column.affinity());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn column(&self, name: &str) -> Option<&ColumnDef> {
+        self.columns
+            .iter()
+            .find(|c| c.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Columns with the affinity of their declared type.
+    pub fn affinities(&self) -> Vec<(String, Affinity)> {
+        self.columns
+            .iter()
+            .map(|c| (c.name.clone(), c.affinity()))
+            .collect()
+    }
+
+    /// Columns with their collation, failing on an unknown collation name.
+    pub fn collations(&self) -> Result<Vec<(String, Collation)>> {
+        self.columns
+            .iter()
+            .map(|c| Ok((c.name.clone(), c.collation()?)))
+            .collect()
+    }
+}
+
+impl ColumnDef {
+    pub fn affinity(&self) -> Affinity {
+        Affinity::from_type_name(&self.type_name)
+    }
+
+    /// Whether the column is generated, STORED or VIRTUAL: an INSERT gives
+    /// it no value.
+    pub fn is_generated(&self) -> bool {
+        self.constraints
+            .iter()
+            .any(|c| matches!(c, ColumnConstraint::Generated { .. }))
+    }
+
+    /// The expression of a VIRTUAL generated column, whose value is computed
+    /// when the row is read rather than stored.
+    pub fn virtual_expr(&self) -> Option<&Expr> {
+        self.constraints.iter().find_map(|c| match c {
+            ColumnConstraint::Generated {
+                expr,
+                stored: false,
+            } => Some(expr),
+            _ => None,
+        })
+    }
+
+    /// `value` as read from a record. SQLite stores REAL values without a
+    /// fractional part as integers, which read back as REAL in a column
+    /// with REAL affinity.
+    pub fn stored_value(&self, value: Value) -> Value {
+        match value {
+            Value::Integer(i) if self.affinity() == Affinity::Real => Value::Real(i as f64),
+            value => value,
+        }
+    }
+
+    /// Value of the column in rows stored before `ALTER TABLE ADD COLUMN`
+    /// added it: its `DEFAULT` converted to the column's affinity, or NULL.
+    pub fn default_value(&self) -> Value {
+        let default = self.constraints.iter().find_map(|c| match c {
+            ColumnConstraint::Default(expr) => Some(expr),
+            _ => None,
+        });
+        match default.map(|expr| eval(expr, &[])) {
+            Some(Ok(value)) => value.apply_affinity(self.affinity()),
+            _ => Value::Null,
+        }
+    }
+
+    /// The `COLLATE` clause of the column, BINARY by default.
+    pub fn collation(&self) -> Result<Collation> {
+        let name = self.constraints.iter().find_map(|c| match c {
+            ColumnConstraint::Collate(name) => Some(name),
+            _ => None,
+        });
+        match name {
+            Some(name) => Collation::from_name(name),
+            None => Ok(Collation::Binary),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_create_table;
+    use crate::sql::value::Value;
+
+    #[test]
+    fn test_primary_key() {
+        let schema = parse_create_table("CREATE TABLE t (a, b INTEGER PRIMARY KEY DESC)").unwrap();
+        let key = schema.primary_key();
+        assert_eq!(key.len(), 1);
+        assert_eq!(key[0].name, "b");
+        assert!(key[0].descending);
+
+        let schema =
+            parse_create_table("CREATE TABLE t (a, b, PRIMARY KEY (b COLLATE nocase, a))").unwrap();
+        let key = schema.primary_key();
+        let names = key.iter().map(|c| c.name.as_str());
+        assert!(names.eq(["b", "a"]));
+        assert_eq!(key[0].collation.as_deref(), Some("nocase"));
+        assert!(parse_create_table("CREATE TABLE t (a)")
+            .unwrap()
+            .primary_key()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_default_value() {
+        let schema = parse_create_table(
+            "CREATE TABLE t (a INTEGER DEFAULT '5', b TEXT DEFAULT (-3), c, d REAL DEFAULT 2)",
+        )
+        .unwrap();
+        let defaults = schema.columns.iter().map(|c| c.default_value());
+        assert!(defaults.eq([
+            Value::Integer(5),
+            Value::Text("-3".into()),
+            Value::Null,
+            Value::Real(2.0),
+        ]));
+    }
+
+    #[test]
+    fn test_rowid_alias() {
+        let alias = |sql: &str| parse_create_table(sql).unwrap().rowid_alias();
+        assert_eq!(alias("CREATE TABLE t (a, id integer primary key)"), Some(1));
+        assert_eq!(
+            alias("CREATE TABLE t (id INTEGER, PRIMARY KEY (id DESC))"),
+            Some(0)
+        );
+        assert_eq!(alias("CREATE TABLE t (id INTEGER PRIMARY KEY DESC)"), None);
+        assert_eq!(alias("CREATE TABLE t (id INT PRIMARY KEY)"), None);
+        assert_eq!(
+            alias("CREATE TABLE t (id INTEGER, b, PRIMARY KEY (id, b))"),
+            None
+        );
+        assert_eq!(
+            alias("CREATE TABLE t (id INTEGER PRIMARY KEY) WITHOUT ROWID"),
+            None
+        );
+        assert_eq!(alias("CREATE TABLE t (a, b)"), None);
+    }
+
+    #[test]
+    fn test_record_columns() {
+        let sql = "CREATE TABLE t (a, b, c, d, PRIMARY KEY (c, A))";
+        let schema = parse_create_table(sql).unwrap();
+        assert_eq!(schema.record_columns(), ["a", "b", "c", "d"]);
+        let schema = parse_create_table(&format!("{sql} WITHOUT ROWID")).unwrap();
+        assert!(schema.without_rowid);
+        assert_eq!(schema.record_columns(), ["c", "a", "b", "d"]);
+    }
+}
@@ -0,0 +1,520 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+/// A single SQL value, one variant per SQLite storage class.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Value {
+    #[default]
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+/// Type affinity of a column or CAST target, derived from the type name.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Affinity {
+    Integer,
+    Text,
+    Blob,
+    Real,
+    Numeric,
+}
+
+impl Affinity {
+    /// SQLite's rules, checked in order: INT, then CHAR/CLOB/TEXT, then BLOB
+    /// (or no type at all), then REAL/FLOA/DOUB, otherwise NUMERIC.
+    pub fn from_type_name(type_name: &str) -> Affinity {
+        let type_name = type_name.to_ascii_uppercase();
+        let has = |names: &[&str]| names.iter().any(|name| type_name.contains(name));
+        if has(&["INT"]) {
+            Affinity::Integer
+        } else if has(&["CHAR", "CLOB", "TEXT"]) {
+            Affinity::Text
+        } else if has(&["BLOB"]) || type_name.trim().is_empty() {
+            Affinity::Blob
+        } else if has(&["REAL", "FLOA", "DOUB"]) {
+            Affinity::Real
+        } else {
+            Affinity::Numeric
+        }
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, Affinity::Integer | Affinity::Real | Affinity::Numeric)
+    }
+}
+
+impl Value {
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    /// Text rendering used when a value is handed to a text function
+    /// (`sqlite3_value_text`). NULL has no text representation.
+    pub fn as_text(&self) -> Option<String> {
+        match self {
+            Value::Null => None,
+            Value::Integer(i) => Some(i.to_string()),
+            Value::Real(r) => Some(format_real(*r)),
+            Value::Text(s) => Some(s.clone()),
+            Value::Blob(b) => Some(String::from_utf8_lossy(b).to_string()),
+        }
+    }
+
+    /// Numeric value used by arithmetic: text is converted through its longest
+    /// numeric prefix, so `'12abc' + 1` is 13 and `'abc' + 1` is 1.
+    pub fn to_numeric(&self) -> Value {
+        match self {
+            Value::Null => Value::Null,
+            Value::Integer(_) | Value::Real(_) => self.clone(),
+            Value::Text(s) => numeric_prefix(s),
+            Value::Blob(b) => numeric_prefix(&String::from_utf8_lossy(b)),
+        }
+    }
+
+    pub fn as_real(&self) -> Option<f64> {
+        match self.to_numeric() {
+            Value::Integer(i) => Some(i as f64),
+            Value::Real(r) => Some(r),
+            _ => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        match self.to_numeric() {
+            Value::Integer(i) => Some(i),
+            Value::Real(r) => Some(real_to_integer(r)),
+            _ => None,
+        }
+    }
+
+    /// Truth value of an expression result: NULL is unknown, everything else
+    /// is true when its numeric value is non zero.
+    pub fn as_bool(&self) -> Option<bool> {
+        match self.to_numeric() {
+            Value::Null => None,
+            Value::Integer(i) => Some(i != 0),
+            Value::Real(r) => Some(r != 0.0),
+            _ => Some(false),
+        }
+    }
+
+    /// `CAST(value AS type)` for a type with the given affinity.
+    pub fn cast(&self, affinity: Affinity) -> Value {
+        match (affinity, self) {
+            (_, Value::Null) => Value::Null,
+            (Affinity::Text, value) => Value::Text(value.as_text().unwrap_or_default()),
+            (Affinity::Blob, Value::Blob(_)) => self.clone(),
+            (Affinity::Blob, value) => {
+                Value::Blob(value.as_text().unwrap_or_default().into_bytes())
+            }
+            (Affinity::Integer, Value::Integer(_)) => self.clone(),
+            (Affinity::Integer, Value::Real(r)) => Value::Integer(real_to_integer(*r)),
+            // only the integer prefix counts: CAST('1e3' AS INTEGER) is 1
+            (Affinity::Integer, value) => {
+                Value::Integer(integer_prefix(&value.as_text().unwrap_or_default()))
+            }
+            (Affinity::Real, value) => Value::Real(value.as_real().unwrap_or(0.0)),
+            (Affinity::Numeric, Value::Integer(_) | Value::Real(_)) => self.clone(),
+            // text becomes an INTEGER when its value is a whole number that fits
+            (Affinity::Numeric, value) => match value.to_numeric() {
+                Value::Real(r)
+                    if r.fract() == 0.0
+                        && (-9.223372036854776e18..9.223372036854776e18).contains(&r) =>
+                {
+                    Value::Integer(r as i64)
+                }
+                numeric => numeric,
+            },
+        }
+    }
+
+    /// The conversion applied when a value is stored in a column with this
+    /// affinity, or compared against one. Unlike CAST it never loses
+    /// information: text that isn't a well formed number stays text.
+    pub fn apply_affinity(&self, affinity: Affinity) -> Value {
+        match (affinity, self) {
+            (Affinity::Text, Value::Integer(_) | Value::Real(_)) => {
+                Value::Text(self.as_text().unwrap_or_default())
+            }
+            (Affinity::Real, Value::Integer(i)) => Value::Real(*i as f64),
+            (Affinity::Real, Value::Text(s)) => match parse_real(s) {
+                Some(r) => Value::Real(r),
+                None => self.clone(),
+            },
+            (Affinity::Integer | Affinity::Numeric, Value::Text(s)) => {
+                match s.trim().parse::<i64>() {
+                    Ok(i) => Value::Integer(i),
+                    _ => match parse_real(s) {
+                        Some(r) => Value::Real(r).apply_affinity(affinity),
+                        None => self.clone(),
+                    },
+                }
+            }
+            (Affinity::Integer | Affinity::Numeric, Value::Real(r))
+                if r.fract() == 0.0
+                    && (-9.223372036854776e18..9.223372036854776e18).contains(r) =>
+            {
+                Value::Integer(*r as i64)
+            }
+            _ => self.clone(),
+        }
+    }
+
+    fn storage_class_rank(&self) -> u8 {
+        match self {
+            Value::Null => 0,
+            Value::Integer(_) | Value::Real(_) => 1,
+            Value::Text(_) => 2,
+            Value::Blob(_) => 3,
+        }
+    }
+
+    /// Total order used by comparisons and sorting:
+    /// NULL < INTEGER/REAL < TEXT < BLOB.
+    pub fn compare(&self, other: &Value) -> Ordering {
+        match (self, other) {
+            (Value::Integer(a), Value::Integer(b)) => a.cmp(b),
+            (Value::Integer(a), Value::Real(b)) => compare_int_real(*a, *b),
+            (Value::Real(a), Value::Integer(b)) => compare_int_real(*b, *a).reverse(),
+            (Value::Real(a), Value::Real(b)) => a.partial_cmp(b).unwrap_or(Ordering::Equal),
+            (Value::Text(a), Value::Text(b)) => a.as_bytes().cmp(b.as_bytes()),
+            (Value::Blob(a), Value::Blob(b)) => a.cmp(b),
+            _ => self.storage_class_rank().cmp(&other.storage_class_rank()),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => Ok(()),
+            Value::Integer(i) => write!(f, "{i}"),
+            Value::Real(r) => write!(f, "{}", format_real(*r)),
+            Value::Text(s) => write!(f, "{s}"),
+            Value::Blob(b) => write!(f, "{}", String::from_utf8_lossy(b)),
+        }
+    }
+}
+
+impl From<i64> for Value {
+    fn from(i: i64) -> Value {
+        Value::Integer(i)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(i: i32) -> Value {
+        Value::Integer(i as i64)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Value {
+        Value::Integer(b as i64)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(r: f64) -> Value {
+        Value::Real(r)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Value {
+        Value::Text(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Value {
+        Value::Text(s)
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(b: Vec<u8>) -> Value {
+        Value::Blob(b)
+    }
+}
+
+impl From<&[u8]> for Value {
+    fn from(b: &[u8]) -> Value {
+        Value::Blob(b.to_vec())
+    }
+}
+
+/// None is NULL.
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Value {
+        value.map_or(Value::Null, Into::into)
+    }
+}
+
+fn compare_int_real(i: i64, r: f64) -> Ordering {
+    if r.is_nan() {
+        return Ordering::Greater;
+    }
+    (i as f64).partial_cmp(&r).unwrap_or(Ordering::Equal)
+}
+
+fn real_to_integer(r: f64) -> i64 {
+    if r.is_nan() {
+        0
+    } else if r <= i64::MIN as f64 {
+        i64::MIN
+    } else if r >= i64::MAX as f64 {
+        i64::MAX
+    } else {
+        r as i64
+    }
+}
+
+/// Value of the longest `[+-]digits` prefix after leading spaces,
+/// saturating at the i64 limits.
+fn integer_prefix(s: &str) -> i64 {
+    let s = s.trim_start();
+    let (negative, digits) = match s.as_bytes().first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+    let mut value: i64 = 0;
+    for digit in digits.bytes().take_while(u8::is_ascii_digit) {
+        let digit = (digit - b'0') as i64;
+        value = match value.checked_mul(10).and_then(|v| match negative {
+            true => v.checked_sub(digit),
+            _ => v.checked_add(digit),
+        }) {
+            Some(value) => value,
+            None if negative => return i64::MIN,
+            None => return i64::MAX,
+        };
+    }
+    value
+}
+
+/// Length of the longest prefix of `s` that looks like a number, together
+/// with whether that prefix needs a REAL (has a fraction or exponent).
+fn numeric_prefix_len(s: &str) -> (usize, bool) {
+    let bytes = s.as_bytes();
+    let mut i = 0;
+    if i < bytes.len() && (bytes[i] == b'+' || bytes[i] == b'-') {
+        i += 1;
+    }
+    let digits_start = i;
+    while i < bytes.len() && bytes[i].is_ascii_digit() {
+        i += 1;
+    }
+    let mut int_digits = i - digits_start;
+    let mut is_real = false;
+    if i < bytes.len() && bytes[i] == b'.' {
+        let mut j = i + 1;
+        while j < bytes.len() && bytes[j].is_ascii_digit() {
+            j += 1;
+        }
+        if int_digits > 0 || j > i + 1 {
+            int_digits += j - i - 1;
+            is_real = true;
+            i = j;
+        }
+    }
+    if int_digits == 0 {
+        return (0, false);
+    }
+    if i < bytes.len() && (bytes[i] == b'e' || bytes[i] == b'E') {
+        let mut j = i + 1;
+        if j < bytes.len() && (bytes[j] == b'+' || bytes[j] == b'-') {
+            j += 1;
+        }
+        let exp_start = j;
+        while j < bytes.len() && bytes[j].is_ascii_digit() {
+            j += 1;
+        }
+        if j > exp_start {
+            is_real = true;
+            i = j;
+        }
+    }
+    (i, is_real)
+}
+
+fn numeric_prefix(s: &str) -> Value {
+    let trimmed = s.trim_start();
+    let (len, is_real) = numeric_prefix_len(trimmed);
+    let prefix = &trimmed[..len];
+    if len == 0 {
+        return Value::Integer(0);
+    }
+    if !is_real {
+        if let Ok(i) = prefix.parse::<i64>() {
+            return Value::Integer(i);
+        }
+    }
+    Value::Real(prefix.parse::<f64>().unwrap_or(0.0))
+}
+
+/// Parses `s` as a number the way `sqlite3AtoF` does: surrounding
+/// whitespace is allowed, anything else after the number is not.
+pub fn parse_real(s: &str) -> Option<f64> {
+    let trimmed = s.trim();
+    let (len, _) = numeric_prefix_len(trimmed);
+    match len == trimmed.len() && len > 0 {
+        true => trimmed.parse::<f64>().ok(),
+        _ => None,
+    }
+}
+
+/// Renders a REAL the way sqlite3 prints it (`%!.15g`): 15 significant
+/// digits, and always a decimal point so it still reads as a REAL.
+pub fn format_real(r: f64) -> String {
+    if r.is_nan() {
+        return "NaN".into();
+    }
+    if r.is_infinite() {
+        return match r > 0.0 {
+            true => "Inf".into(),
+            _ => "-Inf".into(),
+        };
+    }
+    if r == 0.0 {
+        return "0.0".into();
+    }
+    let scientific = format!("{:.14e}", r);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let exponent = exponent.parse::<i32>().unwrap_or(0);
+
+    if !(-4..15).contains(&exponent) {
+        let mantissa = trim_fraction(mantissa);
+        let sign = if exponent < 0 { '-' } else { '+' };
+        return format!("{mantissa}e{sign}{:02}", exponent.abs());
+    }
+    let decimals = (14 - exponent).max(0) as usize;
+    trim_fraction(&format!("{:.*}", decimals, r))
+}
+
+fn trim_fraction(number: &str) -> String {
+    match number.contains('.') {
+        true => {
+            let trimmed = number.trim_end_matches('0');
+            match trimmed.ends_with('.') {
+                true => format!("{trimmed}0"),
+                _ => trimmed.to_string(),
+            }
+        }
+        _ => format!("{number}.0"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_real() {
+        assert_eq!(format_real(0.1), "0.1");
+        assert_eq!(format_real(1.0 / 3.0), "0.333333333333333");
+        assert_eq!(format_real(1e20), "1.0e+20");
+        assert_eq!(format_real(123456789012345678.0), "1.23456789012346e+17");
+        assert_eq!(format_real(1e-5), "1.0e-05");
+        assert_eq!(format_real(100.0), "100.0");
+        assert_eq!(format_real(2451544.5), "2451544.5");
+        assert_eq!(format_real(-0.0), "0.0");
+    }
+
+    #[test]
+    fn test_numeric_conversion() {
+        assert_eq!(Value::Text("12abc".into()).to_numeric(), Value::Integer(12));
+        assert_eq!(
+            Value::Text(" 1.5e2x".into()).to_numeric(),
+            Value::Real(150.0)
+        );
+        assert_eq!(Value::Text("abc".into()).to_numeric(), Value::Integer(0));
+        assert_eq!(parse_real(" 42 "), Some(42.0));
+        assert_eq!(parse_real("42 days"), None);
+        assert_eq!(parse_real("inf"), None);
+    }
+
+    #[test]
+    fn test_affinity_and_cast() {
+        assert_eq!(Affinity::from_type_name("BIGINT"), Affinity::Integer);
+        assert_eq!(Affinity::from_type_name("varchar(255)"), Affinity::Text);
+        assert_eq!(Affinity::from_type_name(""), Affinity::Blob);
+        assert_eq!(Affinity::from_type_name("DOUBLE PRECISION"), Affinity::Real);
+        assert_eq!(Affinity::from_type_name("DECIMAL(10,5)"), Affinity::Numeric);
+        // "POINT" contains "INT"
+        assert_eq!(
+            Affinity::from_type_name("FLOATING POINT"),
+            Affinity::Integer
+        );
+
+        let text = |s: &str| Value::Text(s.into());
+        assert_eq!(text("1e3").cast(Affinity::Integer), Value::Integer(1));
+        assert_eq!(text(" -0012 ").cast(Affinity::Integer), Value::Integer(-12));
+        assert_eq!(
+            text("9223372036854775808").cast(Affinity::Integer),
+            Value::Integer(i64::MAX)
+        );
+        assert_eq!(
+            Value::Real(-12.9).cast(Affinity::Integer),
+            Value::Integer(-12)
+        );
+        assert_eq!(text("1e3").cast(Affinity::Numeric), Value::Integer(1000));
+        assert_eq!(text("9.9e18").cast(Affinity::Numeric), Value::Real(9.9e18));
+        assert_eq!(text("abc").cast(Affinity::Numeric), Value::Integer(0));
+        assert_eq!(Value::Real(3.0).cast(Affinity::Numeric), Value::Real(3.0));
+        assert_eq!(text("4.5x").cast(Affinity::Real), Value::Real(4.5));
+        assert_eq!(Value::Real(1.5).cast(Affinity::Text), text("1.5"));
+        assert_eq!(
+            Value::Integer(5).cast(Affinity::Blob),
+            Value::Blob(vec![b'5'])
+        );
+    }
+
+    #[test]
+    fn test_apply_affinity() {
+        let text = |s: &str| Value::Text(s.into());
+        assert_eq!(
+            text(" 42 ").apply_affinity(Affinity::Integer),
+            Value::Integer(42)
+        );
+        assert_eq!(
+            text("1e3").apply_affinity(Affinity::Numeric),
+            Value::Integer(1000)
+        );
+        assert_eq!(
+            text("2.5").apply_affinity(Affinity::Integer),
+            Value::Real(2.5)
+        );
+        assert_eq!(
+            text("12abc").apply_affinity(Affinity::Integer),
+            text("12abc")
+        );
+        assert_eq!(text("0x10").apply_affinity(Affinity::Numeric), text("0x10"));
+        assert_eq!(text("7").apply_affinity(Affinity::Real), Value::Real(7.0));
+        assert_eq!(
+            Value::Real(3.0).apply_affinity(Affinity::Numeric),
+            Value::Integer(3)
+        );
+        assert_eq!(Value::Integer(5).apply_affinity(Affinity::Text), text("5"));
+        assert_eq!(text("5").apply_affinity(Affinity::Blob), text("5"));
+        assert_eq!(Value::Null.apply_affinity(Affinity::Integer), Value::Null);
+    }
+
+    #[test]
+    fn test_compare_storage_classes() {
+        assert_eq!(Value::Integer(2).compare(&Value::Real(2.5)), Ordering::Less);
+        assert_eq!(
+            Value::Integer(100).compare(&Value::Text("1".into())),
+            Ordering::Less
+        );
+        assert_eq!(Value::Null.compare(&Value::Integer(0)), Ordering::Less);
+        assert_eq!(
+            Value::Text("b".into()).compare(&Value::Blob(vec![0])),
+            Ordering::Less
+        );
+    }
+}
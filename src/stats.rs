@@ -0,0 +1,44 @@
+//! Counters of the work done by the statement run last on this thread, kept
+//! where pages are loaded and records decoded since those have no handle on
+//! the connection.
+
+use std::cell::Cell;
+
+/// What a statement read, counted from when it started running.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// B-tree pages read. The root page of a table or index, kept in
+    /// memory with the schema, isn't read again.
+    pub pages_read: u64,
+    /// Pages read from the page cache.
+    pub cache_hits: u64,
+    /// Pages read from the file, as they weren't in the page cache.
+    pub cache_misses: u64,
+    /// Table rows read, before WHERE filters them.
+    pub rows_scanned: u64,
+    /// Result rows produced so far.
+    pub rows_returned: u64,
+    /// Bytes of records decoded into values, overflow pages included.
+    pub bytes_decoded: u64,
+}
+
+thread_local! {
+    static STATS: Cell<Stats> = Cell::new(Stats::default());
+}
+
+/// The counts since the last reset.
+pub(crate) fn current() -> Stats {
+    STATS.get()
+}
+
+/// Sets every count back to 0, as a statement starts.
+pub(crate) fn reset() {
+    STATS.set(Stats::default());
+}
+
+/// Adds to the counts with `update`.
+pub(crate) fn record(update: impl FnOnce(&mut Stats)) {
+    let mut stats = STATS.get();
+    update(&mut stats);
+    STATS.set(stats);
+}
@@ -0,0 +1,432 @@
+//! Changes to a database: creating tables and inserting rows into them.
+//! Each change is made through a [`Pager`] and written when it completes,
+//! holding a lock on the database and with a rollback journal that undoes
+//! it if the writing is cut short.
+//!
+//! Only what needs no index is written: tables with `UNIQUE` constraints,
+//! a primary key other than `INTEGER PRIMARY KEY`, or indexes of their own
+//! are refused, as are `WITHOUT ROWID` tables, triggers, `CHECK`
+//! constraints and generated columns.
+
+use std::path::Path;
+
+use crate::db::btree::Pager;
+use crate::db::record::{decode_record, encode_record};
+use crate::error::{Error, Result};
+use crate::parser::parse_create_table;
+use crate::sql::schema::{ColumnConstraint, TableConstraint, TableSchema};
+use crate::sql::value::{Affinity, Value};
+
+const SCHEMA_ROOT: u32 = 1;
+
+/// A row of the schema table.
+struct SchemaRow {
+    kind: String,
+    name: String,
+    table_name: String,
+    root: u32,
+    sql: String,
+}
+
+fn read_schema(pager: &mut Pager) -> Result<Vec<SchemaRow>> {
+    let encoding = pager.text_encoding();
+    let rows = pager.rows(SCHEMA_ROOT)?;
+    Ok(rows
+        .into_iter()
+        .map(|(_, payload)| {
+            let values = decode_record(&payload, encoding);
+            let text = |i: usize| values.get(i).and_then(Value::as_text).unwrap_or_default();
+            SchemaRow {
+                kind: text(0),
+                name: text(1),
+                table_name: text(2),
+                root: values.get(3).and_then(Value::as_integer).unwrap_or(0) as u32,
+                sql: text(4),
+            }
+        })
+        .collect())
+}
+
+/// Adds the row of a new table to the schema table.
+fn add_table(pager: &mut Pager, name: &str, root: u32, sql: &str) -> Result<()> {
+    let rowid = pager.max_rowid(SCHEMA_ROOT)?.unwrap_or(0) + 1;
+    let values = [
+        Value::Text("table".into()),
+        Value::Text(name.into()),
+        Value::Text(name.into()),
+        Value::Integer(root as i64),
+        Value::Text(sql.into()),
+    ];
+    pager.insert(
+        SCHEMA_ROOT,
+        rowid,
+        &encode_record(&values, pager.text_encoding()),
+        false,
+    )?;
+    pager.change_schema();
+    Ok(())
+}
+
+/// Splits `CREATE TABLE [IF NOT EXISTS] [schema.]rest` into whether it has
+/// `IF NOT EXISTS` and `rest`, which follows `CREATE TABLE` in the schema
+/// table. None if `sql` starts some other way.
+fn split_create_table(sql: &str) -> Option<(bool, &str)> {
+    fn keyword<'a>(sql: &'a str, word: &str) -> Option<&'a str> {
+        let sql = sql.trim_start();
+        let end = sql
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(sql.len());
+        sql[..end].eq_ignore_ascii_case(word).then(|| &sql[end..])
+    }
+
+    let rest = keyword(sql, "CREATE").and_then(|rest| keyword(rest, "TABLE"))?;
+    let if_not_exists = keyword(rest, "IF")
+        .and_then(|rest| keyword(rest, "NOT"))
+        .and_then(|rest| keyword(rest, "EXISTS"));
+    let rest = if_not_exists.unwrap_or(rest).trim_start();
+
+    // the schema name, if any, is the identifier before a dot
+    let name_end = match rest.chars().next()? {
+        quote @ ('"' | '`' | '[') => {
+            let close = if quote == '[' { ']' } else { quote };
+            rest[1..].find(close)? + 2
+        }
+        _ => rest
+            .find(|c: char| !c.is_alphanumeric() && c != '_' && c != '$')
+            .unwrap_or(rest.len()),
+    };
+    let rest = match rest[name_end..].trim_start().strip_prefix('.') {
+        Some(table) => table.trim_start(),
+        None => rest,
+    };
+    Some((if_not_exists.is_some(), rest))
+}
+
+/// Whether rows of `table` can be written: see the module documentation.
+fn check_writable(table: &TableSchema) -> Result<()> {
+    if table.without_rowid {
+        return Err(Error::Unsupported("writing WITHOUT ROWID tables".into()));
+    }
+    let indexed = table.constraints.iter().any(|c| match c {
+        TableConstraint::Unique(_) => true,
+        TableConstraint::PrimaryKey(_) => table.rowid_alias().is_none(),
+        _ => false,
+    }) || table.columns.iter().any(|column| {
+        column.constraints.iter().any(|c| match c {
+            ColumnConstraint::Unique => true,
+            ColumnConstraint::PrimaryKey { .. } => table.rowid_alias().is_none(),
+            _ => false,
+        })
+    });
+    if indexed {
+        return Err(Error::Unsupported(
+            "writing tables with UNIQUE or PRIMARY KEY constraints other than INTEGER PRIMARY KEY"
+                .into(),
+        ));
+    }
+    let checked = table
+        .constraints
+        .iter()
+        .any(|c| matches!(c, TableConstraint::Check(_)))
+        || table.columns.iter().any(|column| {
+            column.constraints.iter().any(|c| {
+                matches!(
+                    c,
+                    ColumnConstraint::Check(_) | ColumnConstraint::Generated { .. }
+                )
+            })
+        });
+    match checked {
+        true => Err(Error::Unsupported(
+            "writing tables with CHECK constraints or generated columns".into(),
+        )),
+        false => Ok(()),
+    }
+}
+
+fn autoincrement(table: &TableSchema) -> bool {
+    table.columns.iter().any(|column| {
+        column.constraints.iter().any(|c| {
+            matches!(
+                c,
+                ColumnConstraint::PrimaryKey {
+                    autoincrement: true,
+                    ..
+                }
+            )
+        })
+    })
+}
+
+/// Runs `sql`, a `CREATE TABLE` statement, on the database at `path`. A
+/// table with an `AUTOINCREMENT` column also creates `sqlite_sequence`.
+pub fn create_table(path: &Path, sql: &str) -> Result<()> {
+    let table = parse_create_table(sql)?;
+    let (if_not_exists, definition) =
+        split_create_table(sql).ok_or_else(|| Error::Unsupported("CREATE TEMP TABLE".into()))?;
+    if table.name.len() > 7 && table.name[..7].eq_ignore_ascii_case("sqlite_") {
+        return Err(Error::Sql(format!(
+            "object name reserved for internal use: {}",
+            table.name
+        )));
+    }
+    check_writable(&table)?;
+
+    let mut pager = Pager::open(path)?;
+    let schema = read_schema(&mut pager)?;
+    let existing = schema
+        .iter()
+        .find(|row| row.name.eq_ignore_ascii_case(&table.name));
+    match existing {
+        Some(row) if row.kind == "table" || row.kind == "view" => {
+            return match if_not_exists {
+                true => Ok(()),
+                false => Err(Error::Sql(format!(
+                    "{} {} already exists",
+                    row.kind, table.name
+                ))),
+            };
+        }
+        Some(row) => {
+            return Err(Error::Sql(format!(
+                "there is already an {} named {}",
+                row.kind, table.name
+            )))
+        }
+        None => {}
+    }
+
+    let root = pager.create_tree()?;
+    let sql = format!(
+        "CREATE TABLE {}",
+        definition.trim_end().trim_end_matches(';').trim_end()
+    );
+    add_table(&mut pager, &table.name, root, &sql)?;
+    if autoincrement(&table) && !schema.iter().any(|row| row.name == "sqlite_sequence") {
+        let root = pager.create_tree()?;
+        add_table(
+            &mut pager,
+            "sqlite_sequence",
+            root,
+            "CREATE TABLE sqlite_sequence(name,seq)",
+        )?;
+    }
+    pager.commit()
+}
+
+/// Name of the storage class of `value`, as `typeof()` gives it.
+fn storage_class(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Integer(_) => "integer",
+        Value::Real(_) => "real",
+        Value::Text(_) => "text",
+        Value::Blob(_) => "blob",
+    }
+}
+
+/// The rowid and record of a row of `table`, called `name`, given a value
+/// for each column: None for the rowid if the row is to have the next one.
+fn table_record(
+    table: &TableSchema,
+    name: &str,
+    values: Vec<Value>,
+) -> Result<(Option<i64>, Vec<Value>)> {
+    if values.len() != table.columns.len() {
+        return Err(Error::Sql(format!(
+            "table {name} has {} columns but {} values were supplied",
+            table.columns.len(),
+            values.len()
+        )));
+    }
+    let mut record = Vec::with_capacity(values.len());
+    for (column, value) in table.columns.iter().zip(values) {
+        let strict_any = table.strict && column.type_name.eq_ignore_ascii_case("ANY");
+        let value = match strict_any {
+            true => value,
+            false => value.apply_affinity(column.affinity()),
+        };
+        let not_null = column
+            .constraints
+            .iter()
+            .any(|c| matches!(c, ColumnConstraint::NotNull));
+        if value.is_null() && not_null {
+            return Err(Error::Constraint(format!(
+                "NOT NULL constraint failed: {name}.{}",
+                column.name
+            )));
+        }
+        if table.strict && !value.is_null() && !strict_any {
+            let expected = match column.type_name.to_ascii_uppercase().as_str() {
+                "INT" | "INTEGER" => "integer",
+                "REAL" => "real",
+                "TEXT" => "text",
+                _ => "blob",
+            };
+            if storage_class(&value) != expected {
+                return Err(Error::Constraint(format!(
+                    "cannot store {} value in {} column {name}.{}",
+                    storage_class(&value).to_ascii_uppercase(),
+                    column.type_name.to_ascii_uppercase(),
+                    column.name
+                )));
+            }
+        }
+        // as in SQLite, a whole REAL that fits in 6 bytes is stored as an
+        // integer, and reads back as REAL because of the column's affinity
+        let value = match value {
+            Value::Real(r)
+                if column.affinity() == Affinity::Real
+                    && r.fract() == 0.0
+                    && r.abs() < (1u64 << 47) as f64 =>
+            {
+                Value::Integer(r as i64)
+            }
+            value => value,
+        };
+        record.push(value);
+    }
+
+    let Some(i) = table.rowid_alias() else {
+        return Ok((None, record));
+    };
+    // the rowid alias is stored as NULL, its value being the key
+    match std::mem::take(&mut record[i]) {
+        Value::Null => Ok((None, record)),
+        Value::Integer(rowid) => Ok((Some(rowid), record)),
+        _ => Err(Error::Sql("datatype mismatch".into())),
+    }
+}
+
+/// Inserts `rows`, each with a value for every column, into table `name`
+/// of the database at `path`, returning how many were inserted. Values
+/// take the affinity of their column; a NULL `INTEGER PRIMARY KEY` is given
+/// the next rowid. A row that breaks a constraint is passed to `failed`
+/// with its position: it's skipped if that returns Ok, and otherwise
+/// nothing is written.
+pub fn insert<I, F>(path: &Path, name: &str, rows: I, mut failed: F) -> Result<usize>
+where
+    I: IntoIterator<Item = Vec<Value>>,
+    F: FnMut(usize, Error) -> Result<()>,
+{
+    let mut pager = Pager::open(path)?;
+    let schema = read_schema(&mut pager)?;
+    let Some(entry) = schema.iter().find(|row| {
+        matches!(row.kind.as_str(), "table" | "view") && row.name.eq_ignore_ascii_case(name)
+    }) else {
+        return Err(Error::NoSuchTable(name.to_string()));
+    };
+    let name = entry.name.as_str();
+    if entry.kind == "view" {
+        return Err(Error::Sql(format!(
+            "cannot modify {name} because it is a view"
+        )));
+    }
+    if name.len() > 7 && name[..7].eq_ignore_ascii_case("sqlite_") {
+        return Err(Error::Sql(format!("table {name} may not be modified")));
+    }
+    if split_create_table(&entry.sql).is_none() {
+        return Err(Error::Unsupported("writing virtual tables".into()));
+    }
+    let table = parse_create_table(&entry.sql)?;
+    check_writable(&table)?;
+    for row in schema.iter().filter(|row| row.kind != "table") {
+        if row.table_name.eq_ignore_ascii_case(name) {
+            return Err(Error::Unsupported(format!(
+                "writing tables with an {}",
+                row.kind
+            )));
+        }
+    }
+
+    let sequence = schema
+        .iter()
+        .find(|row| row.name == "sqlite_sequence")
+        .map(|row| row.root);
+    let autoincrement = autoincrement(&table);
+    let encoding = pager.text_encoding();
+    // the largest rowid given to a row of the table so far
+    let mut sequence_row = None;
+    let mut largest = pager.max_rowid(entry.root)?.unwrap_or(0);
+    if let (true, Some(root)) = (autoincrement, sequence) {
+        for (rowid, payload) in pager.rows(root)? {
+            let values = decode_record(&payload, encoding);
+            if values.first().and_then(Value::as_text).as_deref() == Some(name) {
+                let seq = values.get(1).and_then(Value::as_integer).unwrap_or(0);
+                largest = largest.max(seq);
+                sequence_row = Some(rowid);
+            }
+        }
+    }
+    let used = largest;
+
+    let mut count = 0;
+    for (i, values) in rows.into_iter().enumerate() {
+        let inserted = table_record(&table, name, values).and_then(|(rowid, record)| {
+            let rowid = match rowid {
+                Some(rowid) => rowid,
+                None => largest
+                    .checked_add(1)
+                    .ok_or_else(|| Error::Sql("database or disk is full".into()))?,
+            };
+            let payload = encode_record(&record, encoding);
+            match pager.insert(entry.root, rowid, &payload, false)? {
+                true => Ok(rowid),
+                false => {
+                    let alias = table.rowid_alias();
+                    let column = alias.map_or("rowid", |i| table.columns[i].name.as_str());
+                    Err(Error::Constraint(format!(
+                        "UNIQUE constraint failed: {name}.{column}"
+                    )))
+                }
+            }
+        });
+        match inserted {
+            Ok(rowid) => {
+                largest = largest.max(rowid);
+                count += 1;
+            }
+            Err(e @ (Error::Constraint(_) | Error::Sql(_))) => failed(i, e)?,
+            Err(e) => return Err(e),
+        }
+    }
+
+    if let (true, Some(root), true) = (autoincrement, sequence, largest > used) {
+        let values = [Value::Text(name.to_string()), Value::Integer(largest)];
+        let payload = encode_record(&values, encoding);
+        match sequence_row {
+            Some(rowid) => pager.insert(root, rowid, &payload, true)?,
+            None => {
+                let rowid = pager.max_rowid(root)?.unwrap_or(0) + 1;
+                pager.insert(root, rowid, &payload, false)?
+            }
+        };
+    }
+    pager.commit()?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_create_table() {
+        assert_eq!(
+            split_create_table("create  table if not exists main.t(a)"),
+            Some((true, "t(a)"))
+        );
+        assert_eq!(
+            split_create_table("CREATE TABLE \"x.y\" (a)"),
+            Some((false, "\"x.y\" (a)"))
+        );
+        assert_eq!(
+            split_create_table("CREATE TABLE [main] . [t] (a)"),
+            Some((false, "[t] (a)"))
+        );
+        assert_eq!(
+            split_create_table("CREATE VIRTUAL TABLE t USING fts5(a)"),
+            None
+        );
+    }
+}